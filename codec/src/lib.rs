@@ -441,7 +441,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 45;
+pub const CODEC_VERSION: usize = 46;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -503,6 +503,8 @@ pdu! {
     GetPaneDirectionResponse: 61,
     AdjustPaneSize: 62,
     SetTabLayout: 63,
+    GetTmuxCompatSocket: 64,
+    GetTmuxCompatSocketResponse: 65,
 }
 
 impl Pdu {
@@ -888,6 +890,16 @@ pub struct GetPaneDirectionResponse {
     pub pane_id: Option<PaneId>,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetTmuxCompatSocket;
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetTmuxCompatSocketResponse {
+    /// Addresses of the server's tmux CC listeners; empty when
+    /// `enable_tmux_compat` is off.
+    pub addresses: Vec<String>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct ActivatePaneDirection {
     pub pane_id: PaneId,
@@ -1726,7 +1726,8 @@ fn default_scrollback_lines() -> usize {
     3500
 }
 
-const MAX_SCROLLBACK_LINES: usize = 999_999_999;
+/// The most lines of scrollback a pane can be given.
+pub const MAX_SCROLLBACK_LINES: usize = 999_999_999;
 fn validate_scrollback_lines(value: &usize) -> Result<(), String> {
     if *value > MAX_SCROLLBACK_LINES {
         return Err(format!(
@@ -359,6 +359,11 @@ pub struct PaneSelectArguments {
 
     #[dynamic(default)]
     pub show_pane_ids: bool,
+
+    /// Instead of `mode`, run this tmux command with `%%` replaced
+    /// by the id of the selected pane
+    #[dynamic(default)]
+    pub tmux_command: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
@@ -505,6 +510,21 @@ pub struct InputSelector {
     pub fuzzy_description: String,
 }
 
+impl InputSelector {
+    /// An InputSelector with the default alphabet and descriptions
+    pub fn new(title: String, choices: Vec<InputSelectorEntry>, action: KeyAssignment) -> Self {
+        Self {
+            action: Box::new(action),
+            title,
+            choices,
+            fuzzy: false,
+            alphabet: default_num_alphabet(),
+            description: default_description(),
+            fuzzy_description: default_fuzzy_description(),
+        }
+    }
+}
+
 fn default_num_alphabet() -> String {
     "1234567890abcdefghilmnopqrstuvwxyz".to_string()
 }
@@ -634,6 +654,7 @@ pub enum KeyAssignment {
     CopyMode(CopyModeAssignment),
     RotatePanes(RotationDirection),
     SetTabLayout(String),
+    TmuxCommand(String),
     SplitPane(SplitPane),
     PaneSelect(PaneSelectArguments),
     CharSelect(CharSelectArguments),
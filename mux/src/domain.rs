@@ -15,7 +15,7 @@ use async_trait::async_trait;
 use config::keyassignment::{SpawnCommand, SpawnTabDomain};
 use config::{configuration, ExecDomain, SerialDomain, ValueOrFunc, WslDomain};
 use downcast_rs::{impl_downcast, Downcast};
-use parking_lot::Mutex;
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
 use portable_pty::{native_pty_system, CommandBuilder, ExitStatus, MasterPty, PtySize, PtySystem};
 use std::collections::HashMap;
 use std::ffi::OsString;
@@ -148,6 +148,23 @@ pub trait Domain: Downcast + Send + Sync {
         command_dir: Option<String>,
     ) -> anyhow::Result<Arc<dyn Pane>>;
 
+    /// Start a new program in `pane`, which belongs to this domain, in
+    /// place of the one it is running or was running. The pane keeps its
+    /// id, its place in the tab and its scrollback. If `command` is `None`,
+    /// the command the pane was spawned with is run again.
+    async fn respawn_pane(
+        &self,
+        pane: &Arc<dyn Pane>,
+        _command: Option<CommandBuilder>,
+        _command_dir: Option<String>,
+    ) -> anyhow::Result<()> {
+        bail!(
+            "domain {} cannot respawn pane {}",
+            self.domain_name(),
+            pane.pane_id()
+        );
+    }
+
     /// The mux will call this method on the domain of the pane that
     /// is being moved to give the domain a chance to handle the movement.
     /// If this method returns Ok(None), then the mux will handle the
@@ -446,6 +463,22 @@ impl LocalDomain {
         Ok(())
     }
 
+    /// How a spawned command is described when its process exits.
+    fn describe_command(&self, cmd: &CommandBuilder) -> String {
+        let command_line = cmd
+            .as_unix_command_line()
+            .unwrap_or_else(|err| format!("error rendering command line: {:?}", err));
+        format!(
+            "\"{}\" in domain \"{}\"",
+            if command_line.is_empty() {
+                cmd.get_shell()
+            } else {
+                command_line
+            },
+            self.name
+        )
+    }
+
     async fn build_command(
         &self,
         command: Option<CommandBuilder>,
@@ -483,13 +516,20 @@ impl LocalDomain {
         if let Some(agent) = Mux::get().agent.as_ref() {
             cmd.env("SSH_AUTH_SOCK", agent.path());
         }
-        // Tmux compat: forward CC socket path and set TMUX + PATH
+        // Tmux compat: forward CC socket path and set TMUX + PATH.
+        // Panes spawned for a named tmux server come with its socket path.
         if config.enable_tmux_compat {
-            if let Ok(tmux_cc) = std::env::var("WEZTERM_TMUX_CC") {
+            let tmux_cc = match cmd.get_env("WEZTERM_TMUX_CC") {
+                Some(path) => Some(path.to_string_lossy().into_owned()),
+                None => std::env::var("WEZTERM_TMUX_CC").ok(),
+            };
+            if let Some(tmux_cc) = tmux_cc {
                 cmd.env("WEZTERM_TMUX_CC", &tmux_cc);
                 // Set TMUX so tools auto-detect tmux mode.
-                // Format: socket_path,pid,session (matches real tmux convention)
-                let tmux_val = format!("{},{},0", tmux_cc, std::process::id());
+                // Format: socket_path,pid,session (matches real tmux convention),
+                // then the token file of a TCP listener.
+                let tmux_val =
+                    crate::tmux_compat_server::auth::tmux_env_value(&tmux_cc, std::process::id());
                 log::debug!("tmux compat: setting TMUX={}", tmux_val);
                 cmd.env("TMUX", &tmux_val);
                 // Prepend the tmux shim directory to PATH so our shim shadows real tmux
@@ -535,7 +575,7 @@ impl LocalDomain {
 /// but the Pane trait returns a RefMut and that makes it a bit
 /// awkward at the moment.
 #[derive(Clone)]
-pub(crate) struct WriterWrapper {
+pub struct WriterWrapper {
     writer: Arc<Mutex<Box<dyn Write + Send>>>,
 }
 
@@ -545,6 +585,19 @@ impl WriterWrapper {
             writer: Arc::new(Mutex::new(writer)),
         }
     }
+
+    pub fn lock(&self) -> MappedMutexGuard<'_, dyn Write> {
+        MutexGuard::map(self.writer.lock(), |writer| {
+            let w: &mut dyn Write = writer;
+            w
+        })
+    }
+
+    /// Send everything written from now on, by every clone of this
+    /// wrapper, to `writer` instead.
+    pub fn replace(&self, writer: Box<dyn Write + Send>) {
+        *self.writer.lock() = writer;
+    }
 }
 
 impl std::io::Write for WriterWrapper {
@@ -635,6 +688,7 @@ impl Domain for LocalDomain {
         command_dir: Option<String>,
     ) -> anyhow::Result<Arc<dyn Pane>> {
         let pane_id = alloc_pane_id();
+        let spawn_command = command.clone();
         let cmd = self
             .build_command(command, command_dir, pane_id)
             .await
@@ -644,18 +698,7 @@ impl Domain for LocalDomain {
             .lock()
             .openpty(crate::terminal_size_to_pty_size(size)?)?;
 
-        let command_line = cmd
-            .as_unix_command_line()
-            .unwrap_or_else(|err| format!("error rendering command line: {:?}", err));
-        let command_description = format!(
-            "\"{}\" in domain \"{}\"",
-            if command_line.is_empty() {
-                cmd.get_shell()
-            } else {
-                command_line
-            },
-            self.name
-        );
+        let command_description = self.describe_command(&cmd);
         let child_result = pair.slave.spawn_command(cmd);
         let mut writer = WriterWrapper::new(pair.master.take_writer()?);
 
@@ -670,34 +713,36 @@ impl Domain for LocalDomain {
             terminal.enable_conpty_quirks();
         }
 
-        let pane: Arc<dyn Pane> = match child_result {
-            Ok(child) => Arc::new(LocalPane::new(
+        let pane = match child_result {
+            Ok(child) => LocalPane::new(
                 pane_id,
                 terminal,
                 child,
                 pair.master,
-                Box::new(writer),
+                writer,
                 self.id,
                 command_description,
-            )),
+            ),
             Err(err) => {
                 // Show the error to the user in the new pane
                 write!(writer, "{err:#}").ok();
 
                 // and return a dummy pane that has exited
-                Arc::new(LocalPane::new(
+                LocalPane::new(
                     pane_id,
                     terminal,
                     Box::new(FailedProcessSpawn {}),
                     Box::new(FailedSpawnPty {
                         inner: Mutex::new(pair.master),
                     }),
-                    Box::new(writer),
+                    writer,
                     self.id,
                     command_description,
-                ))
+                )
             }
         };
+        pane.set_spawn_command(spawn_command);
+        let pane: Arc<dyn Pane> = Arc::new(pane);
 
         let mux = Mux::get();
         mux.add_pane(&pane)?;
@@ -705,6 +750,31 @@ impl Domain for LocalDomain {
         Ok(pane)
     }
 
+    async fn respawn_pane(
+        &self,
+        pane: &Arc<dyn Pane>,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
+    ) -> anyhow::Result<()> {
+        let local = pane
+            .downcast_ref::<LocalPane>()
+            .ok_or_else(|| anyhow::anyhow!("pane {} is not a local pane", pane.pane_id()))?;
+        let spawn_command = command.or_else(|| local.spawn_command());
+        let cmd = self
+            .build_command(spawn_command.clone(), command_dir, pane.pane_id())
+            .await
+            .context("build_command")?;
+        let pair = self.pty_system.lock().openpty(local.pty_size()?)?;
+
+        let command_description = self.describe_command(&cmd);
+        let child = pair.slave.spawn_command(cmd)?;
+        let writer = pair.master.take_writer()?;
+        local.respawn(child, pair.master, writer, command_description);
+        local.set_spawn_command(spawn_command);
+
+        Mux::get().start_pane_reader(pane)
+    }
+
     fn domain_id(&self) -> DomainId {
         self.id
     }
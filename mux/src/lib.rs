@@ -1,10 +1,10 @@
 use crate::client::{ClientId, ClientInfo};
 use crate::pane::{CachePolicy, Pane, PaneId};
 use crate::ssh_agent::AgentProxy;
-use crate::tab::{SplitRequest, Tab, TabId};
+use crate::tab::{SplitRequest, Tab, TabId, TabPopup};
 use crate::window::{Window, WindowId};
 use anyhow::{anyhow, Context, Error};
-use config::keyassignment::SpawnTabDomain;
+use config::keyassignment::{KeyAssignment, SpawnTabDomain};
 use config::{configuration, ExitBehavior, GuiPosition};
 use domain::{Domain, DomainId, DomainState, SplitSource};
 use filedescriptor::{poll, pollfd, socketpair, AsRawSocketDescriptor, FileDescriptor, POLLIN};
@@ -96,6 +96,12 @@ pub enum MuxNotification {
         old_workspace: String,
         new_workspace: String,
     },
+    /// Asks the gui window containing the pane to perform the assignment
+    /// in it, eg: the InputSelector opened by tmux's choose-tree
+    PerformAssignment {
+        pane_id: PaneId,
+        assignment: Box<KeyAssignment>,
+    },
 }
 
 static SUB_ID: AtomicUsize = AtomicUsize::new(0);
@@ -104,15 +110,38 @@ static SUB_ID: AtomicUsize = AtomicUsize::new(0);
 // Raw output tap — allows CC protocol connections to receive raw PTY bytes
 // ---------------------------------------------------------------------------
 
+/// Where a tap sends a pane's output.
+enum OutputTap {
+    /// Falls behind by at most 256 chunks; a tap that falls further behind is
+    /// dropped.
+    Bounded(std::sync::mpsc::SyncSender<(Vec<u8>, Instant)>),
+    /// Gets every chunk, however far behind its reader is.
+    Unbounded(std::sync::mpsc::Sender<(Vec<u8>, Instant)>),
+}
+
+impl OutputTap {
+    /// Send a chunk; returns `false` if the tap should be dropped.
+    fn send(&self, data: &[u8], when: Instant) -> bool {
+        match self {
+            Self::Bounded(tx) => tx.try_send((data.to_vec(), when)).is_ok(),
+            Self::Unbounded(tx) => tx.send((data.to_vec(), when)).is_ok(),
+        }
+    }
+}
+
 /// Registry of raw output taps keyed by pane ID.
 ///
 /// Each entry is a list of senders; when a pane produces output in
 /// `parse_buffered_data`, the raw bytes (plus timestamp) are cloned to each
-/// registered sender.  Senders that fail (disconnected) are automatically
-/// pruned.
-static OUTPUT_TAPS: Mutex<
-    Option<HashMap<PaneId, Vec<std::sync::mpsc::SyncSender<(Vec<u8>, Instant)>>>>,
-> = Mutex::new(None);
+/// registered sender.  Senders that fail (disconnected, or a bounded tap
+/// that is full) are automatically pruned.
+static OUTPUT_TAPS: Mutex<Option<HashMap<PaneId, Vec<OutputTap>>>> = Mutex::new(None);
+
+fn add_output_tap(pane_id: PaneId, tap: OutputTap) {
+    let mut guard = OUTPUT_TAPS.lock();
+    let map = guard.get_or_insert_with(HashMap::new);
+    map.entry(pane_id).or_default().push(tap);
+}
 
 /// Register an output tap for a specific pane.
 ///
@@ -120,9 +149,17 @@ static OUTPUT_TAPS: Mutex<
 /// of PTY output.
 pub fn register_output_tap(pane_id: PaneId) -> std::sync::mpsc::Receiver<(Vec<u8>, Instant)> {
     let (tx, rx) = std::sync::mpsc::sync_channel(256);
-    let mut guard = OUTPUT_TAPS.lock();
-    let map = guard.get_or_insert_with(HashMap::new);
-    map.entry(pane_id).or_default().push(tx);
+    add_output_tap(pane_id, OutputTap::Bounded(tx));
+    rx
+}
+
+/// Register an output tap that is never dropped for falling behind, for
+/// consumers that must see all of a pane's output, such as `pipe-pane`.
+pub fn register_unbounded_output_tap(
+    pane_id: PaneId,
+) -> std::sync::mpsc::Receiver<(Vec<u8>, Instant)> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    add_output_tap(pane_id, OutputTap::Unbounded(tx));
     rx
 }
 
@@ -141,7 +178,7 @@ fn notify_output_taps(pane_id: PaneId, data: &[u8], when: Instant) {
     let mut guard = OUTPUT_TAPS.lock();
     if let Some(map) = guard.as_mut() {
         if let Some(senders) = map.get_mut(&pane_id) {
-            senders.retain(|tx| tx.try_send((data.to_vec(), when)).is_ok());
+            senders.retain(|tap| tap.send(data, when));
             if senders.is_empty() {
                 map.remove(&pane_id);
             }
@@ -342,8 +379,8 @@ fn read_from_pane_pty(
     // or in the main mux thread.  If `true`, this thread will terminate.
     let dead = Arc::new(AtomicBool::new(false));
 
-    let (pane_id, exit_behavior) = match pane.upgrade() {
-        Some(pane) => (pane.pane_id(), pane.exit_behavior()),
+    let (pane_id, generation) = match pane.upgrade() {
+        Some(pane) => (pane.pane_id(), pane.process_generation()),
         None => return,
     };
 
@@ -364,6 +401,7 @@ fn read_from_pane_pty(
 
     std::thread::spawn({
         let dead = Arc::clone(&dead);
+        let pane = Weak::clone(&pane);
         move || parse_buffered_data(pane, &dead, rx)
     });
 
@@ -395,6 +433,19 @@ fn read_from_pane_pty(
         }
     }
 
+    // Look at the exit behavior now rather than when the pane was spawned,
+    // since it can change while the program runs.
+    let exit_behavior = match pane.upgrade() {
+        Some(pane) if pane.process_generation() != generation => {
+            // The pane was respawned and has a new reader; leave it alone.
+            log::trace!("read_pty EOF for replaced program in pane {}", pane_id);
+            dead.store(true, Ordering::Relaxed);
+            return;
+        }
+        Some(pane) => pane.exit_behavior(),
+        None => None,
+    };
+
     match exit_behavior.unwrap_or_else(|| configuration().exit_behavior) {
         ExitBehavior::Hold | ExitBehavior::CloseOnCleanExit => {
             // We don't know if we can unilaterally close
@@ -841,13 +892,21 @@ impl Mux {
 
         self.panes.write().insert(pane.pane_id(), Arc::clone(pane));
         let pane_id = pane.pane_id();
+        self.start_pane_reader(pane)?;
+        self.recompute_pane_count();
+        self.notify(MuxNotification::PaneAdded(pane_id));
+        Ok(())
+    }
+
+    /// Start the thread that feeds the output of the program in `pane` to
+    /// its terminal. Called by `add_pane`, and again when a new program is
+    /// started in the pane.
+    pub fn start_pane_reader(&self, pane: &Arc<dyn Pane>) -> Result<(), Error> {
         if let Some(reader) = pane.reader()? {
             let banner = self.banner.read().clone();
             let pane = Arc::downgrade(pane);
             thread::spawn(move || read_from_pane_pty(pane, banner, reader));
         }
-        self.recompute_pane_count();
-        self.notify(MuxNotification::PaneAdded(pane_id));
         Ok(())
     }
 
@@ -874,6 +933,20 @@ impl Mux {
             changed = true;
         }
 
+        let popup_tab = self
+            .tabs
+            .read()
+            .values()
+            .find(|tab| {
+                tab.get_popup()
+                    .is_some_and(|popup| popup.pane.pane_id() == pane_id)
+            })
+            .map(Arc::clone);
+        if let Some(tab) = popup_tab {
+            tab.set_popup(None);
+            self.notify_popup_changed(tab.tab_id());
+        }
+
         if changed {
             self.recompute_pane_count();
         }
@@ -894,6 +967,9 @@ impl Mux {
         for pos in tab.iter_panes_ignoring_zoom() {
             pane_ids.push(pos.pane.pane_id());
         }
+        if let Some(popup) = tab.set_popup(None) {
+            pane_ids.push(popup.pane.pane_id());
+        }
         log::debug!("panes to remove: {pane_ids:?}");
         for pane_id in pane_ids {
             self.remove_pane_internal(pane_id);
@@ -949,11 +1025,57 @@ impl Mux {
         tab
     }
 
+    /// Show `popup` over the top of the tab, replacing any popup that is
+    /// already there.  The popup pane is added to the mux; it is removed
+    /// again when the popup is closed.
+    pub fn open_popup(&self, tab_id: TabId, popup: TabPopup) -> Result<(), Error> {
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab {} not found", tab_id))?;
+        self.add_pane(&popup.pane)?;
+        if let Some(prior) = tab.set_popup(Some(popup)) {
+            self.remove_pane_internal(prior.pane.pane_id());
+        }
+        self.notify_popup_changed(tab_id);
+        Ok(())
+    }
+
+    /// Close the popup shown over the tab, if any, and remove its pane.
+    /// Returns true if there was a popup to close.
+    pub fn close_popup(&self, tab_id: TabId) -> bool {
+        let popup = match self.get_tab(tab_id).and_then(|tab| tab.set_popup(None)) {
+            Some(popup) => popup,
+            None => return false,
+        };
+        self.remove_pane_internal(popup.pane.pane_id());
+        self.notify_popup_changed(tab_id);
+        true
+    }
+
+    fn notify_popup_changed(&self, tab_id: TabId) {
+        if let Some(window_id) = self.window_containing_tab(tab_id) {
+            self.notify(MuxNotification::WindowInvalidated(window_id));
+        }
+    }
+
     pub fn prune_dead_windows(&self) {
         if Activity::count() > 0 {
             log::trace!("prune_dead_windows: Activity::count={}", Activity::count());
             return;
         }
+
+        let exited_popups: Vec<TabId> = self
+            .tabs
+            .read()
+            .values()
+            .filter(|tab| tab.get_popup().is_some_and(|popup| popup.should_close()))
+            .map(|tab| tab.tab_id())
+            .collect();
+        for tab_id in exited_popups {
+            log::trace!("popup in tab {} has exited", tab_id);
+            self.close_popup(tab_id);
+        }
+
         let live_tab_ids: Vec<TabId> = self.tabs.read().keys().cloned().collect();
         let mut dead_windows = vec![];
         let dead_tab_ids: Vec<TabId>;
@@ -1,7 +1,7 @@
-use crate::domain::DomainId;
+use crate::domain::{DomainId, WriterWrapper};
 use crate::pane::{
     CachePolicy, CloseReason, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId, Pattern,
-    SearchResult, WithPaneLines,
+    RemainOnExit, SearchResult, WithPaneLines,
 };
 use crate::renderable::*;
 use crate::tmux::{TmuxDomain, TmuxDomainState};
@@ -12,7 +12,7 @@ use config::keyassignment::ScrollbackEraseMode;
 use config::{configuration, ExitBehavior, ExitBehaviorMessaging};
 use fancy_regex::Regex;
 use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
-use portable_pty::{Child, ChildKiller, ExitStatus, MasterPty, PtySize};
+use portable_pty::{Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize};
 use procinfo::LocalProcessInfo;
 use rangeset::RangeSet;
 use smol::channel::{bounded, Receiver, TryRecvError};
@@ -21,6 +21,7 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::io::{Result as IoResult, Write};
 use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use termwiz::escape::csi::{Sgr, CSI};
@@ -126,14 +127,20 @@ pub struct LocalPane {
     terminal: Mutex<Terminal>,
     process: Mutex<ProcessState>,
     pty: Mutex<Box<dyn MasterPty>>,
-    writer: Mutex<Box<dyn Write + Send>>,
+    writer: WriterWrapper,
     domain_id: DomainId,
     tmux_domain: Mutex<Option<Arc<TmuxDomainState>>>,
     proc_list: Mutex<Option<CachedProcInfo>>,
     #[cfg(unix)]
     leader: Arc<Mutex<Option<CachedLeaderInfo>>>,
-    command_description: String,
+    command_description: Mutex<String>,
+    /// The command the pane was spawned with, run again by respawn.
+    spawn_command: Mutex<Option<CommandBuilder>>,
     header: Mutex<Option<String>>,
+    remain_on_exit: Mutex<RemainOnExit>,
+    /// The exit code of the program once it has exited.
+    exit_code: Mutex<Option<u32>>,
+    process_generation: AtomicUsize,
 }
 
 #[async_trait(?Send)]
@@ -225,6 +232,10 @@ impl Pane for LocalPane {
     }
 
     fn exit_behavior(&self) -> Option<ExitBehavior> {
+        if *self.remain_on_exit.lock() == RemainOnExit::On {
+            return Some(ExitBehavior::Hold);
+        }
+
         // If we are ssh, and we've not yet fully connected,
         // then override exit_behavior so that we can show
         // connection issues
@@ -274,7 +285,8 @@ impl Pane for LocalPane {
         let mut terse = String::new();
         let mut brief = String::new();
         let mut trailer = String::new();
-        let cmd = &self.command_description;
+        let mut notify = None;
+        let cmd = self.command_description.lock().clone();
 
         match &mut *proc {
             ProcessState::Running {
@@ -289,6 +301,7 @@ impl Pane for LocalPane {
                 };
 
                 if let Some(status) = status {
+                    *self.exit_code.lock() = Some(status.exit_code());
                     let success = match status.success() {
                         true => true,
                         false => configuration()
@@ -296,12 +309,14 @@ impl Pane for LocalPane {
                             .contains(&status.exit_code()),
                     };
 
-                    match (
+                    let remain = self.remain_on_exit.lock().keeps(status.exit_code());
+                    let exit_behavior = if remain {
+                        ExitBehavior::Hold
+                    } else {
                         self.exit_behavior()
-                            .unwrap_or_else(|| configuration().exit_behavior),
-                        success,
-                        killed,
-                    ) {
+                            .unwrap_or_else(|| configuration().exit_behavior)
+                    };
+                    match (exit_behavior, success, killed) {
                         (ExitBehavior::Close, _, _) => *proc = ProcessState::Dead,
                         (ExitBehavior::CloseOnCleanExit, false, _) => {
                             brief = format!("⚠️  Process {cmd} didn't exit cleanly");
@@ -311,6 +326,14 @@ impl Pane for LocalPane {
                             *proc = ProcessState::DeadPendingClose { killed: false }
                         }
                         (ExitBehavior::CloseOnCleanExit, ..) => *proc = ProcessState::Dead,
+                        (ExitBehavior::Hold, _, false) if remain => {
+                            // Keep the pane open, like tmux's remain-on-exit
+                            notify = Some(format!(
+                                "\r\nPane is dead (status {})\r\n",
+                                status.exit_code()
+                            ));
+                            *proc = ProcessState::DeadPendingClose { killed: false }
+                        }
                         (ExitBehavior::Hold, success, false) => {
                             trailer = format!("{EXIT_BEHAVIOR}=\"Hold\"");
 
@@ -337,7 +360,6 @@ impl Pane for LocalPane {
             ProcessState::Dead => {}
         }
 
-        let mut notify = None;
         if !terse.is_empty() {
             match configuration().exit_behavior_messaging {
                 ExitBehaviorMessaging::Verbose => {
@@ -428,10 +450,7 @@ impl Pane for LocalPane {
 
     fn writer(&self) -> MappedMutexGuard<'_, dyn std::io::Write> {
         Mux::get().record_input_for_current_identity();
-        MutexGuard::map(self.writer.lock(), |writer| {
-            let w: &mut dyn std::io::Write = writer;
-            w
-        })
+        self.writer.lock()
     }
 
     fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
@@ -486,6 +505,18 @@ impl Pane for LocalPane {
         }
     }
 
+    fn erase_hyperlinks(&self) {
+        self.terminal.lock().erase_hyperlinks();
+    }
+
+    fn set_scrollback_limit(&self, limit: Option<usize>) {
+        self.terminal.lock().set_scrollback_limit(limit);
+    }
+
+    fn scrollback_limit(&self) -> Option<usize> {
+        Some(self.terminal.lock().scrollback_limit())
+    }
+
     fn focus_changed(&self, focused: bool) {
         self.terminal.lock().focus_changed(focused);
     }
@@ -832,6 +863,18 @@ impl Pane for LocalPane {
     fn set_header(&self, header: Option<String>) {
         *self.header.lock() = header;
     }
+
+    fn set_remain_on_exit(&self, remain: RemainOnExit) {
+        *self.remain_on_exit.lock() = remain;
+    }
+
+    fn exit_code(&self) -> Option<u32> {
+        *self.exit_code.lock()
+    }
+
+    fn process_generation(&self) -> usize {
+        self.process_generation.load(Ordering::Relaxed)
+    }
 }
 
 struct LocalPaneDCSHandler {
@@ -999,7 +1042,7 @@ impl LocalPane {
         mut terminal: Terminal,
         process: Box<dyn Child + Send>,
         pty: Box<dyn MasterPty>,
-        writer: Box<dyn Write + Send>,
+        writer: WriterWrapper,
         domain_id: DomainId,
         command_description: String,
     ) -> Self {
@@ -1021,17 +1064,70 @@ impl LocalPane {
                 killed: false,
             }),
             pty: Mutex::new(pty),
-            writer: Mutex::new(writer),
+            writer,
             domain_id,
             tmux_domain: Mutex::new(None),
             proc_list: Mutex::new(None),
             #[cfg(unix)]
             leader: Arc::new(Mutex::new(None)),
-            command_description,
+            command_description: Mutex::new(command_description),
+            spawn_command: Mutex::new(None),
             header: Mutex::new(None),
+            remain_on_exit: Mutex::new(RemainOnExit::Off),
+            exit_code: Mutex::new(None),
+            process_generation: AtomicUsize::new(0),
         }
     }
 
+    pub(crate) fn spawn_command(&self) -> Option<CommandBuilder> {
+        self.spawn_command.lock().clone()
+    }
+
+    pub(crate) fn set_spawn_command(&self, command: Option<CommandBuilder>) {
+        *self.spawn_command.lock() = command;
+    }
+
+    pub(crate) fn pty_size(&self) -> anyhow::Result<PtySize> {
+        self.pty.lock().get_size()
+    }
+
+    /// Replace the program in this pane with `process`, running in `pty`.
+    /// A program that is still running is killed. The terminal and its
+    /// scrollback are kept; the caller must start a reader for the new pty.
+    pub(crate) fn respawn(
+        &self,
+        process: Box<dyn Child + Send>,
+        pty: Box<dyn MasterPty>,
+        writer: Box<dyn Write + Send>,
+        command_description: String,
+    ) {
+        let (process, signaller, pid) = split_child(process);
+
+        // The reader of the old pty sees EOF once it is dropped below; bump
+        // the generation first so that it knows it was replaced.
+        self.process_generation.fetch_add(1, Ordering::Relaxed);
+        let old = std::mem::replace(
+            &mut *self.process.lock(),
+            ProcessState::Running {
+                child_waiter: process,
+                pid,
+                signaller,
+                killed: false,
+            },
+        );
+        if let ProcessState::Running { mut signaller, .. } = old {
+            let _ = signaller.kill();
+        }
+
+        self.writer.replace(writer);
+        *self.pty.lock() = pty;
+        *self.command_description.lock() = command_description;
+        *self.exit_code.lock() = None;
+        self.proc_list.lock().take();
+        #[cfg(unix)]
+        self.leader.lock().take();
+    }
+
     #[cfg(unix)]
     fn get_leader(&self, policy: CachePolicy) -> CachedLeaderInfo {
         let mut leader = self.leader.lock();
@@ -1153,3 +1249,113 @@ impl Drop for LocalPane {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use portable_pty::{native_pty_system, PtySize};
+
+    /// A program, its pty and the writer for its input.
+    type Program = (
+        Box<dyn Child + Send>,
+        Box<dyn MasterPty>,
+        Box<dyn Write + Send>,
+    );
+
+    /// Start `sh -c script` in a new pty.
+    fn run(script: &str) -> Program {
+        let pair = native_pty_system()
+            .openpty(PtySize::default())
+            .expect("openpty");
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", script]);
+        let child = pair.slave.spawn_command(cmd).expect("spawn");
+        let writer = pair.master.take_writer().expect("take_writer");
+        (child, pair.master, writer)
+    }
+
+    fn pane(script: &str, remain: RemainOnExit) -> LocalPane {
+        config::use_test_configuration();
+        // The pane reports what happens to its program on the main thread;
+        // there isn't one here, so that work is queued and never run.
+        static MAIN_THREAD: std::sync::OnceLock<promise::spawn::ScopedExecutor> =
+            std::sync::OnceLock::new();
+        MAIN_THREAD.get_or_init(promise::spawn::ScopedExecutor::new);
+        let terminal = Terminal::new(
+            TerminalSize::default(),
+            Arc::new(config::TermConfig::new()),
+            "WezTerm",
+            config::wezterm_version(),
+            Box::new(Vec::<u8>::new()),
+        );
+        let (child, pty, writer) = run(script);
+        let pane = LocalPane::new(
+            0,
+            terminal,
+            child,
+            pty,
+            WriterWrapper::new(writer),
+            0,
+            script.to_string(),
+        );
+        pane.set_remain_on_exit(remain);
+        pane
+    }
+
+    /// Wait for the program in `pane` to exit, returning whether the pane
+    /// is then dead.
+    fn wait_for_exit(pane: &LocalPane) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while pane.exit_code().is_none() {
+            assert!(Instant::now() < deadline, "the program didn't exit");
+            std::thread::sleep(Duration::from_millis(10));
+            pane.is_dead();
+        }
+        pane.is_dead()
+    }
+
+    #[test]
+    fn remain_on_exit() {
+        let kept = pane("exit 3", RemainOnExit::On);
+        assert!(!wait_for_exit(&kept));
+        assert_eq!(kept.exit_code(), Some(3));
+
+        let kept = pane("exit 0", RemainOnExit::On);
+        assert!(!wait_for_exit(&kept));
+
+        let closed = pane("exit 3", RemainOnExit::Off);
+        assert!(wait_for_exit(&closed));
+    }
+
+    #[test]
+    fn remain_on_exit_failed_keeps_failed_panes() {
+        let kept = pane("exit 3", RemainOnExit::Failed);
+        assert!(!wait_for_exit(&kept));
+        assert_eq!(kept.exit_code(), Some(3));
+
+        let closed = pane("exit 0", RemainOnExit::Failed);
+        assert!(wait_for_exit(&closed));
+        assert_eq!(closed.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn respawn_runs_a_new_program() {
+        let pane = pane("exit 3", RemainOnExit::On);
+        assert!(!wait_for_exit(&pane));
+        assert_eq!(pane.process_generation(), 0);
+
+        let (child, pty, writer) = run("sleep 30");
+        pane.respawn(child, pty, writer, "sleep 30".to_string());
+        assert_eq!(pane.process_generation(), 1);
+        assert_eq!(pane.exit_code(), None);
+        assert!(!pane.is_dead());
+        assert_eq!(*pane.command_description.lock(), "sleep 30");
+
+        // Respawning a pane whose program is still running replaces it.
+        let (child, pty, writer) = run("exit 4");
+        pane.respawn(child, pty, writer, "exit 4".to_string());
+        assert_eq!(pane.process_generation(), 2);
+        assert!(!wait_for_exit(&pane));
+        assert_eq!(pane.exit_code(), Some(4));
+    }
+}
@@ -40,6 +40,30 @@ pub enum PerformAssignmentResult {
     BlockAssignmentAndRouteToKeyDown,
 }
 
+/// Whether a pane is kept open when its program exits, as with tmux's
+/// `remain-on-exit`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RemainOnExit {
+    /// The configured exit behavior applies.
+    #[default]
+    Off,
+    /// The pane is kept, however its program exits.
+    On,
+    /// The pane is kept only if its program exits with a non-zero status.
+    Failed,
+}
+
+impl RemainOnExit {
+    /// Whether a pane whose program exited with `exit_code` is kept.
+    pub fn keeps(self, exit_code: u32) -> bool {
+        match self {
+            Self::Off => false,
+            Self::On => true,
+            Self::Failed => exit_code != 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct SearchResult {
     pub start_y: StableRowIndex,
@@ -264,6 +288,18 @@ pub trait Pane: Downcast + Send + Sync {
 
     fn erase_scrollback(&self, _erase_mode: ScrollbackEraseMode) {}
 
+    /// Remove the hyperlinks from the text of this pane, keeping the text.
+    fn erase_hyperlinks(&self) {}
+
+    /// Override the maximum number of lines of scrollback kept by this
+    /// pane, or revert to the configured `scrollback_lines` with `None`.
+    fn set_scrollback_limit(&self, _limit: Option<usize>) {}
+
+    /// The maximum number of lines of scrollback kept by this pane, if known.
+    fn scrollback_limit(&self) -> Option<usize> {
+        None
+    }
+
     /// Called to advise on whether this tab has focus
     fn focus_changed(&self, _focused: bool) {}
 
@@ -346,6 +382,23 @@ pub trait Pane: Downcast + Send + Sync {
 
     /// Sets or clears the user-set header label for this pane.
     fn set_header(&self, _header: Option<String>) {}
+
+    /// Whether the pane is kept open after its program exits, as with
+    /// tmux's `remain-on-exit`, whatever the configured exit behavior.
+    fn set_remain_on_exit(&self, _remain: RemainOnExit) {}
+
+    /// Returns the exit code of the program in this pane once it has
+    /// exited.
+    fn exit_code(&self) -> Option<u32> {
+        None
+    }
+
+    /// Counts the programs that have been started in this pane. It goes
+    /// up when the pane is respawned, which lets the reader of the old
+    /// program tell that it has been replaced.
+    fn process_generation(&self) -> usize {
+        0
+    }
 }
 impl_downcast!(Pane);
 
@@ -772,7 +772,7 @@ impl Domain for RemoteSshDomain {
             terminal,
             child,
             pty,
-            Box::new(writer),
+            writer,
             self.id,
             "RemoteSshDomain".to_string(),
         ));
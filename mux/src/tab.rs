@@ -3,8 +3,8 @@ use crate::pane::*;
 use crate::renderable::StableCursorPosition;
 use crate::{Mux, MuxNotification, WindowId};
 use bintree::PathBranch;
-use config::configuration;
 use config::keyassignment::PaneDirection;
+use config::{configuration, ExitBehavior};
 use parking_lot::Mutex;
 use rangeset::intersects_range;
 use serde::{Deserialize, Serialize};
@@ -35,6 +35,15 @@ impl Recency {
     fn score(&self, idx: usize) -> usize {
         self.by_idx.get(&idx).copied().unwrap_or(0)
     }
+
+    /// Returns the most recently tagged of `candidates`, ignoring any
+    /// that have never been tagged.
+    fn most_recent(&self, candidates: impl Iterator<Item = usize>) -> Option<usize> {
+        candidates
+            .filter_map(|idx| self.by_idx.get(&idx).map(|&score| (score, idx)))
+            .max()
+            .map(|(_, idx)| idx)
+    }
 }
 
 struct TabInner {
@@ -46,6 +55,7 @@ struct TabInner {
     zoomed: Option<Arc<dyn Pane>>,
     title: String,
     recency: Recency,
+    popup: Option<TabPopup>,
 }
 
 /// A Tab is a container of Panes
@@ -92,6 +102,83 @@ impl std::fmt::Debug for PositionedPane {
     }
 }
 
+/// A pane floating over the top of a tab, as opened by tmux's
+/// `display-popup`. The pane is not part of the tab's split tree.
+#[derive(Clone)]
+pub struct TabPopup {
+    pub pane: Arc<dyn Pane>,
+    /// The column of the tab, counting from 0 at its left edge, where the
+    /// left side of the popup border is drawn.
+    pub left: usize,
+    /// The row of the tab, counting from 0 at its top edge, where the top
+    /// side of the popup border is drawn.
+    pub top: usize,
+    /// The width of the popup in cells, including its border
+    pub width: usize,
+    /// The height of the popup in cells, including its border
+    pub height: usize,
+    pub title: String,
+    /// What to do with the popup when its program exits
+    pub exit_behavior: ExitBehavior,
+}
+
+impl std::fmt::Debug for TabPopup {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        fmt.debug_struct("TabPopup")
+            .field("left", &self.left)
+            .field("top", &self.top)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("title", &self.title)
+            .field("exit_behavior", &self.exit_behavior)
+            .field("pane_id", &self.pane.pane_id())
+            .finish()
+    }
+}
+
+impl TabPopup {
+    /// Returns the size of the area inside the border of a popup of
+    /// `width` by `height` cells shown in a tab of `tab_size`.
+    pub fn content_size(width: usize, height: usize, tab_size: &TerminalSize) -> TerminalSize {
+        let dims = cell_dimensions(tab_size);
+        let cols = width.saturating_sub(2).max(1);
+        let rows = height.saturating_sub(2).max(1);
+        TerminalSize {
+            rows,
+            cols,
+            pixel_width: cols * dims.pixel_width,
+            pixel_height: rows * dims.pixel_height,
+            dpi: dims.dpi,
+        }
+    }
+
+    /// Returns true if the program in the popup has exited and, according
+    /// to the exit behavior, the popup should now be closed.
+    pub fn should_close(&self) -> bool {
+        // Polling is_dead() is what records the exit status of the program
+        if self.pane.is_dead() {
+            return true;
+        }
+        match (self.exit_behavior, self.pane.exit_code()) {
+            (_, None) | (ExitBehavior::Hold, _) => false,
+            (ExitBehavior::Close, Some(_)) => true,
+            (ExitBehavior::CloseOnCleanExit, Some(code)) => code == 0,
+        }
+    }
+
+    /// Shrink and move the popup so that it fits inside a tab of `size`,
+    /// and resize its pane to match.
+    fn fit(&mut self, size: &TerminalSize) {
+        self.width = self.width.min(size.cols);
+        self.height = self.height.min(size.rows);
+        self.left = self.left.min(size.cols - self.width);
+        self.top = self.top.min(size.rows - self.height);
+        self.pane
+            .resize(Self::content_size(self.width, self.height, size))
+            .ok();
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum SplitDirection {
     Horizontal,
@@ -318,6 +405,30 @@ where
     }
 }
 
+/// Copy a split tree; the panes are shared.
+fn clone_tree(tree: &Tree) -> Tree {
+    match tree {
+        Tree::Empty => Tree::Empty,
+        Tree::Node { left, right, data } => Tree::Node {
+            left: Box::new(clone_tree(left)),
+            right: Box::new(clone_tree(right)),
+            data: *data,
+        },
+        Tree::Leaf(pane) => Tree::Leaf(Arc::clone(pane)),
+    }
+}
+
+fn collect_pane_ids(tree: &Tree, ids: &mut Vec<PaneId>) {
+    match tree {
+        Tree::Empty => {}
+        Tree::Node { left, right, .. } => {
+            collect_pane_ids(left, ids);
+            collect_pane_ids(right, ids);
+        }
+        Tree::Leaf(pane) => ids.push(pane.pane_id()),
+    }
+}
+
 /// Computes the minimum (x, y) size based on the panes in this portion
 /// of the tree.
 fn compute_min_size(tree: &mut Tree) -> (usize, usize) {
@@ -620,33 +731,32 @@ fn build_chain_tree(
     }
 }
 
-/// Build a right-leaning chain from a `Vec` of already-constructed sub-trees
-/// along the given `direction`, splitting the available space evenly.
-/// Takes ownership of the trees to avoid requiring `Clone` on `Tree`.
-fn build_tree_chain_from_vec(
-    mut trees: Vec<Tree>,
-    direction: SplitDirection,
+/// Build a `tiled` grid: rows of up to `num_cols` panes, stacked
+/// vertically. Each row is laid out within the size its split gives it.
+fn build_tiled_tree(
+    panes: &[Arc<dyn Pane>],
+    num_rows: usize,
+    num_cols: usize,
     size: &TerminalSize,
 ) -> Tree {
-    assert!(!trees.is_empty());
-    if trees.len() == 1 {
-        return trees.remove(0);
+    let in_row = if num_rows > 1 {
+        num_cols.min(panes.len())
+    } else {
+        panes.len()
+    };
+    let (row, rest) = panes.split_at(in_row);
+    if rest.is_empty() {
+        return build_chain_tree(row, SplitDirection::Horizontal, size);
     }
 
-    let n = trees.len();
-    let (first_size, second_size) = split_size_evenly(size, direction, 1, n);
-
-    // Pop the first tree and recurse on the rest.
-    let first = trees.remove(0);
-    let rest = build_tree_chain_from_vec(trees, direction, &second_size);
-
+    let (row_size, rest_size) = split_size_evenly(size, SplitDirection::Vertical, 1, num_rows);
     Tree::Node {
-        left: Box::new(first),
-        right: Box::new(rest),
+        left: Box::new(build_chain_tree(row, SplitDirection::Horizontal, &row_size)),
+        right: Box::new(build_tiled_tree(rest, num_rows - 1, num_cols, &rest_size)),
         data: Some(SplitDirectionAndSize {
-            direction,
-            first: first_size,
-            second: second_size,
+            direction: SplitDirection::Vertical,
+            first: row_size,
+            second: rest_size,
         }),
     }
 }
@@ -741,6 +851,23 @@ impl Tab {
         self.inner.lock().apply_layout(layout_name)
     }
 
+    /// Returns a copy of the split tree, ignoring zoom. The leaves hold the
+    /// panes themselves.
+    pub fn get_split_tree(&self) -> Tree {
+        match self.inner.lock().pane.as_ref() {
+            Some(tree) => clone_tree(tree),
+            None => Tree::Empty,
+        }
+    }
+
+    /// Replace the split tree with `tree`, which must hold exactly the
+    /// panes that are already in this tab. The tab is unzoomed, the active
+    /// pane is kept, and the split sizes in `tree` are fitted to the size
+    /// of the tab.
+    pub fn set_split_tree(&self, tree: Tree) -> Result<(), String> {
+        self.inner.lock().set_split_tree(tree)
+    }
+
     pub fn iter_splits(&self) -> Vec<PositionedSplit> {
         self.inner.lock().iter_splits()
     }
@@ -763,6 +890,24 @@ impl Tab {
         self.inner.lock().resize(size)
     }
 
+    /// Returns the popup shown over this tab, if any
+    pub fn get_popup(&self) -> Option<TabPopup> {
+        self.inner.lock().popup.clone()
+    }
+
+    /// Replaces the popup shown over this tab, returning the prior one.
+    /// The popup is fitted to the tab and its pane resized accordingly.
+    /// Use `Mux::open_popup` and `Mux::close_popup` rather than calling
+    /// this directly, so that the popup pane is managed by the mux.
+    pub fn set_popup(&self, popup: Option<TabPopup>) -> Option<TabPopup> {
+        let mut inner = self.inner.lock();
+        let mut popup = popup;
+        if let Some(popup) = popup.as_mut() {
+            popup.fit(&inner.size);
+        }
+        std::mem::replace(&mut inner.popup, popup)
+    }
+
     /// Called when running in the mux server after an individual pane
     /// has been resized.
     /// Because the split manipulation happened on the GUI we "lost"
@@ -798,6 +943,24 @@ impl Tab {
         self.inner.lock().adjust_pane_size(direction, amount)
     }
 
+    /// Adjusts the size of the pane at pane_index, the topological index
+    /// returned by iter_panes() as PositionedPane::index, in the specified
+    /// direction by the specified amount.
+    pub fn adjust_pane_size_at(&self, pane_index: usize, direction: PaneDirection, amount: usize) {
+        self.inner
+            .lock()
+            .adjust_pane_size_at(pane_index, direction, amount)
+    }
+
+    /// Moves the nearest split of the specified direction bordering the
+    /// pane at pane_index so that the pane becomes size cells wide (for
+    /// Horizontal) or tall (for Vertical).
+    pub fn resize_pane_at(&self, pane_index: usize, direction: SplitDirection, size: usize) {
+        self.inner
+            .lock()
+            .resize_pane_at(pane_index, direction, size)
+    }
+
     /// Activate an adjacent pane in the specified direction.
     /// In cases where there are multiple adjacent panes in the
     /// intended direction, we take the pane that has the largest
@@ -858,6 +1021,12 @@ impl Tab {
         self.inner.lock().set_active_idx(pane_index)
     }
 
+    /// Returns the index of the pane that was active before the current
+    /// one, if it is still present in this tab.
+    pub fn get_last_active_idx(&self) -> Option<usize> {
+        self.inner.lock().get_last_active_idx()
+    }
+
     /// Assigns the root pane.
     /// This is suitable when creating a new tab and then assigning
     /// the initial pane
@@ -922,6 +1091,7 @@ impl TabInner {
             zoomed: None,
             title: String::new(),
             recency: Recency::default(),
+            popup: None,
         }
     }
 
@@ -1214,37 +1384,7 @@ impl TabInner {
                 let n = pane_list.len();
                 let num_rows = (n as f64).sqrt().ceil() as usize;
                 let num_cols = (n + num_rows - 1) / num_rows;
-
-                // Build each row as a horizontal chain, then stack rows
-                // vertically.
-                let mut row_trees: Vec<Tree> = Vec::new();
-                let mut remaining = &pane_list[..];
-                let row_size = {
-                    let (first_row_size, _) =
-                        split_size_evenly(&self.size, SplitDirection::Vertical, 1, num_rows);
-                    first_row_size
-                };
-
-                for row_idx in 0..num_rows {
-                    let panes_in_row = if row_idx < num_rows - 1 {
-                        num_cols.min(remaining.len())
-                    } else {
-                        remaining.len()
-                    };
-                    if panes_in_row == 0 {
-                        break;
-                    }
-                    let (row_panes, rest) = remaining.split_at(panes_in_row);
-                    remaining = rest;
-                    row_trees.push(build_chain_tree(
-                        row_panes,
-                        SplitDirection::Horizontal,
-                        &row_size,
-                    ));
-                }
-
-                // Stack rows into a vertical chain (right-leaning).
-                build_tree_chain_from_vec(row_trees, SplitDirection::Vertical, &self.size)
+                build_tiled_tree(&pane_list, num_rows, num_cols, &self.size)
             }
             _ => {
                 return Err(format!("unknown layout name: {}", layout_name));
@@ -1258,6 +1398,45 @@ impl TabInner {
         Ok(())
     }
 
+    fn set_split_tree(&mut self, tree: Tree) -> Result<(), String> {
+        let mut current: Vec<PaneId> = self
+            .iter_panes_ignoring_zoom()
+            .iter()
+            .map(|p| p.pane.pane_id())
+            .collect();
+        let mut replacement = vec![];
+        collect_pane_ids(&tree, &mut replacement);
+        current.sort_unstable();
+        replacement.sort_unstable();
+        if current != replacement {
+            return Err("layout does not hold the panes of this tab".to_string());
+        }
+
+        self.set_zoomed(false);
+        let active = self.get_active_pane();
+        let size = self.size;
+        if let Tree::Node {
+            data: Some(data), ..
+        } = &tree
+        {
+            // Start from the size the layout was made for, so that resizing
+            // to the tab size adjusts the splits.
+            self.size = data.size();
+        }
+        self.pane = Some(tree);
+        if let Some(active) = active {
+            if let Some(item) = self
+                .iter_panes_ignoring_zoom()
+                .iter()
+                .find(|p| p.pane.pane_id() == active.pane_id())
+            {
+                self.active = item.index;
+            }
+        }
+        self.resize(size);
+        Ok(())
+    }
+
     fn iter_panes_impl(&mut self, respect_zoom_state: bool) -> Vec<PositionedPane> {
         let mut panes = vec![];
 
@@ -1445,6 +1624,11 @@ impl TabInner {
             apply_sizes_from_splits(self.pane.as_mut().unwrap(), &size);
         }
 
+        let size = self.size;
+        if let Some(popup) = self.popup.as_mut() {
+            popup.fit(&size);
+        }
+
         Mux::try_get().map(|mux| mux.notify(MuxNotification::TabResized(self.id)));
     }
 
@@ -1641,17 +1825,55 @@ impl TabInner {
     }
 
     fn adjust_pane_size(&mut self, direction: PaneDirection, amount: usize) {
+        self.adjust_pane_size_at(self.active, direction, amount)
+    }
+
+    fn adjust_pane_size_at(&mut self, pane_index: usize, direction: PaneDirection, amount: usize) {
+        let split_direction = match direction {
+            PaneDirection::Left | PaneDirection::Right => SplitDirection::Horizontal,
+            PaneDirection::Up | PaneDirection::Down => SplitDirection::Vertical,
+            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
+        };
+        let delta = match direction {
+            PaneDirection::Down | PaneDirection::Right => amount as isize,
+            PaneDirection::Up | PaneDirection::Left => -(amount as isize),
+            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
+        };
+        self.adjust_pane_split(pane_index, split_direction, |_, _| delta)
+    }
+
+    fn resize_pane_at(&mut self, pane_index: usize, direction: SplitDirection, size: usize) {
+        self.adjust_pane_split(pane_index, direction, |is_second, cells| {
+            // Growing the pane moves the split away from it
+            let delta = size as isize - cells as isize;
+            if is_second {
+                -delta
+            } else {
+                delta
+            }
+        })
+    }
+
+    /// Moves the nearest split of split_direction above the pane at
+    /// pane_index by the delta that compute_delta returns. It is passed
+    /// whether the pane is on the second (right/bottom) side of the split,
+    /// and the size of that side along the split direction.
+    fn adjust_pane_split(
+        &mut self,
+        pane_index: usize,
+        split_direction: SplitDirection,
+        compute_delta: impl FnOnce(bool, usize) -> isize,
+    ) {
         if self.zoomed.is_some() {
             return;
         }
-        let active_index = self.active;
         let mut cursor = self.pane.take().unwrap().cursor();
         let mut index = 0;
 
-        // Position cursor on the active leaf
+        // Position cursor on the pane's leaf
         loop {
             if cursor.is_leaf() {
-                if index == active_index {
+                if index == pane_index {
                     // Found it
                     break;
                 }
@@ -1667,25 +1889,21 @@ impl TabInner {
             }
         }
 
-        // We are on the active leaf.
+        // We are on the pane's leaf.
         // Now we go up until we find the parent node that is
         // aligned with the desired direction.
-        let split_direction = match direction {
-            PaneDirection::Left | PaneDirection::Right => SplitDirection::Horizontal,
-            PaneDirection::Up | PaneDirection::Down => SplitDirection::Vertical,
-            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
-        };
-        let delta = match direction {
-            PaneDirection::Down | PaneDirection::Right => amount as isize,
-            PaneDirection::Up | PaneDirection::Left => -(amount as isize),
-            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
-        };
         loop {
+            let is_second = cursor.is_right();
             match cursor.go_up() {
                 Ok(mut c) => {
                     if let Ok(Some(node)) = c.node_mut() {
                         if node.direction == split_direction {
-                            self.adjust_node_at_cursor(&mut c, delta);
+                            let side = if is_second { &node.second } else { &node.first };
+                            let cells = match split_direction {
+                                SplitDirection::Horizontal => side.cols,
+                                SplitDirection::Vertical => side.rows,
+                            };
+                            self.adjust_node_at_cursor(&mut c, compute_delta(is_second, cells));
                             self.cascade_size_from_cursor(c);
                             return;
                         }
@@ -2013,6 +2231,13 @@ impl TabInner {
         self.active
     }
 
+    fn get_last_active_idx(&mut self) -> Option<usize> {
+        let active = self.active;
+        let num_panes = self.count_panes();
+        self.recency
+            .most_recent((0..num_panes).filter(|&idx| idx != active))
+    }
+
     fn set_active_pane(&mut self, pane: &Arc<dyn Pane>) {
         let prior = self.get_active_pane();
 
@@ -2482,13 +2707,20 @@ mod test {
     struct FakePane {
         id: PaneId,
         size: Mutex<TerminalSize>,
+        exit_code: Option<u32>,
     }
 
     impl FakePane {
         fn new(id: PaneId, size: TerminalSize) -> Arc<dyn Pane> {
+            Self::exited(id, size, None)
+        }
+
+        /// A pane whose program has exited with `exit_code`, if any.
+        fn exited(id: PaneId, size: TerminalSize, exit_code: Option<u32>) -> Arc<dyn Pane> {
             Arc::new(Self {
                 id,
                 size: Mutex::new(size),
+                exit_code,
             })
         }
     }
@@ -2571,6 +2803,9 @@ mod test {
         fn is_dead(&self) -> bool {
             false
         }
+        fn exit_code(&self) -> Option<u32> {
+            self.exit_code
+        }
         fn palette(&self) -> ColorPalette {
             unimplemented!()
         }
@@ -2789,10 +3024,226 @@ mod test {
         assert_eq!(600, panes[2].pixel_height);
     }
 
+    #[test]
+    fn resize_pane_at_moves_nearest_split() {
+        let size = TerminalSize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi: 96,
+        };
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for (pane_id, direction) in [
+            (2, SplitDirection::Horizontal),
+            (3, SplitDirection::Vertical),
+        ] {
+            let request = SplitRequest {
+                direction,
+                ..Default::default()
+            };
+            let split_size = tab.compute_split_size(0, request).unwrap();
+            tab.split_and_insert(0, request, FakePane::new(pane_id, split_size.second))
+                .unwrap();
+        }
+        let widths_and_heights = || {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.width, p.height))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            widths_and_heights(),
+            vec![(1, 39, 11), (3, 39, 12), (2, 40, 24)]
+        );
+
+        // The right pane grows by moving the split on its left
+        tab.resize_pane_at(2, SplitDirection::Horizontal, 50);
+        assert_eq!(
+            widths_and_heights(),
+            vec![(1, 29, 11), (3, 29, 12), (2, 50, 24)]
+        );
+
+        tab.resize_pane_at(0, SplitDirection::Horizontal, 30);
+        assert_eq!(
+            widths_and_heights(),
+            vec![(1, 30, 11), (3, 30, 12), (2, 49, 24)]
+        );
+
+        // An inactive pane can be adjusted too
+        tab.adjust_pane_size_at(1, PaneDirection::Up, 2);
+        assert_eq!(
+            widths_and_heights(),
+            vec![(1, 30, 9), (3, 30, 14), (2, 49, 24)]
+        );
+
+        // There is no vertical split above the right pane
+        tab.resize_pane_at(2, SplitDirection::Vertical, 10);
+        assert_eq!(
+            widths_and_heights(),
+            vec![(1, 30, 9), (3, 30, 14), (2, 49, 24)]
+        );
+    }
+
     fn is_send_and_sync<T: Send + Sync>() -> bool {
         true
     }
 
+    #[test]
+    fn recency_most_recent() {
+        let mut recency = Recency::default();
+        assert_eq!(recency.most_recent(0..3), None);
+
+        recency.tag(0);
+        recency.tag(2);
+        recency.tag(1);
+        assert_eq!(recency.most_recent(0..3), Some(1));
+        assert_eq!(recency.most_recent(vec![0, 2].into_iter()), Some(2));
+        assert_eq!(recency.most_recent(3..4), None);
+    }
+
+    #[test]
+    fn popup_fits_inside_tab() {
+        let size = TerminalSize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi: 96,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        let popup = TabPopup {
+            pane: FakePane::new(2, size),
+            left: 50,
+            top: 20,
+            width: 40,
+            height: 10,
+            title: String::new(),
+            exit_behavior: ExitBehavior::Close,
+        };
+        assert!(tab.set_popup(Some(popup)).is_none());
+
+        let popup = tab.get_popup().unwrap();
+        assert_eq!(
+            (40, 14, 40, 10),
+            (popup.left, popup.top, popup.width, popup.height)
+        );
+        let content = TabPopup::content_size(popup.width, popup.height, &size);
+        assert_eq!(
+            (38, 8, 380, 200),
+            (
+                content.cols,
+                content.rows,
+                content.pixel_width,
+                content.pixel_height
+            )
+        );
+
+        tab.resize(TerminalSize {
+            rows: 8,
+            cols: 30,
+            pixel_width: 300,
+            pixel_height: 200,
+            dpi: 96,
+        });
+        let popup = tab.get_popup().unwrap();
+        assert_eq!(
+            (0, 0, 30, 8),
+            (popup.left, popup.top, popup.width, popup.height)
+        );
+
+        assert_eq!(2, tab.set_popup(None).unwrap().pane.pane_id());
+        assert!(tab.get_popup().is_none());
+    }
+
+    fn popup(pane: Arc<dyn Pane>, exit_behavior: ExitBehavior) -> TabPopup {
+        TabPopup {
+            pane,
+            left: 0,
+            top: 0,
+            width: 40,
+            height: 10,
+            title: String::new(),
+            exit_behavior,
+        }
+    }
+
+    #[test]
+    fn popup_closes_as_its_exit_behavior_says() {
+        let size = TerminalSize::default();
+        let cases = [
+            (ExitBehavior::Close, None, false),
+            (ExitBehavior::Close, Some(1), true),
+            (ExitBehavior::CloseOnCleanExit, Some(0), true),
+            (ExitBehavior::CloseOnCleanExit, Some(1), false),
+            (ExitBehavior::Hold, Some(0), false),
+        ];
+        for (exit_behavior, exit_code, closes) in cases {
+            let popup = popup(FakePane::exited(2, size, exit_code), exit_behavior);
+            assert_eq!(
+                popup.should_close(),
+                closes,
+                "{:?} with exit code {:?}",
+                exit_behavior,
+                exit_code
+            );
+        }
+    }
+
+    #[test]
+    fn mux_removes_popups_with_their_pane_or_tab() {
+        config::use_test_configuration();
+        let size = TerminalSize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi: 96,
+        };
+        let mux = crate::Mux::new(None);
+        let tab = Arc::new(Tab::new(&size));
+        tab.assign_pane(&FakePane::new(1, size));
+        mux.add_tab_and_active_pane(&tab).unwrap();
+        let tab_id = tab.tab_id();
+        let open = |pane_id| {
+            let pane = FakePane::new(pane_id, size);
+            mux.open_popup(tab_id, popup(pane, ExitBehavior::Close))
+                .unwrap();
+        };
+        let popup_pane = || tab.get_popup().map(|popup| popup.pane.pane_id());
+
+        open(2);
+        assert_eq!(popup_pane(), Some(2));
+        assert!(mux.get_pane(2).is_some());
+
+        // A new popup replaces the old one, whose pane goes.
+        open(3);
+        assert_eq!(popup_pane(), Some(3));
+        assert!(mux.get_pane(2).is_none());
+
+        assert!(mux.close_popup(tab_id));
+        assert_eq!(popup_pane(), None);
+        assert!(mux.get_pane(3).is_none());
+        assert!(!mux.close_popup(tab_id));
+
+        // Killing the pane of a popup closes it.
+        open(4);
+        mux.remove_pane(4);
+        assert_eq!(popup_pane(), None);
+        assert!(mux.get_pane(1).is_some());
+
+        // Closing the tab removes the pane of its popup too.
+        open(5);
+        mux.remove_tab(tab_id);
+        assert!(mux.get_pane(5).is_none());
+        assert!(mux.get_pane(1).is_none());
+        assert!(mux.get_tab(tab_id).is_none());
+    }
+
     #[test]
     fn tab_is_send_and_sync() {
         assert!(is_send_and_sync::<Tab>());
@@ -0,0 +1,530 @@
+//! Window alerts: `monitor-activity`, `monitor-bell` and `monitor-silence`.
+//!
+//! As in tmux, a monitored window is flagged when one of its panes produces
+//! output (`#` in `window_flags`, `#{window_activity_flag}`), rings the bell
+//! (`!`, `#{window_bell_flag}`) or has been quiet for `monitor-silence`
+//! seconds (`~`, `#{window_silence_flag}`), unless it is the current window
+//! of its session. The flags are cleared once the window becomes current.
+//! Each alert also fires the `alert-activity`, `alert-bell` or
+//! `alert-silence` hook when the session's `activity-action`, `bell-action`
+//! or `silence-action` applies to the window.
+//!
+//! The mux subscriber only records output and bells by pane here, as it may
+//! be called with the mux locked, noting when the output arrived. A timer
+//! thread hands those events to the windows of the panes each
+//! [`ALERT_INTERVAL`] and checks every window against its options, so flags
+//! and hooks can lag the event by that much, but silence is timed from the
+//! output itself. Each server keeps the alerts of its own windows, as it
+//! does their options. tmux has no control mode notification for alerts, so
+//! clients learn of them from the hooks or from the window flags.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use parking_lot::{MappedMutexGuard, Mutex};
+
+use crate::pane::PaneId;
+use crate::tab::TabId;
+use crate::Mux;
+
+use super::hooks::{check_died_panes, fire_hook, HookEvent};
+use super::named_server::{self, PerServer};
+use super::options::{options, OptionScope};
+
+/// How often the timer thread checks the windows.
+pub const ALERT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The alerts a window is flagged with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AlertFlags {
+    pub activity: bool,
+    pub bell: bool,
+    pub silence: bool,
+}
+
+/// The options that decide how a window's alerts are raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorOptions {
+    pub monitor_activity: bool,
+    pub monitor_bell: bool,
+    /// `monitor-silence` in seconds; zero turns it off.
+    pub monitor_silence: u64,
+    pub activity_action: String,
+    pub bell_action: String,
+    pub silence_action: String,
+}
+
+impl Default for MonitorOptions {
+    /// tmux's defaults.
+    fn default() -> Self {
+        Self {
+            monitor_activity: false,
+            monitor_bell: true,
+            monitor_silence: 0,
+            activity_action: "other".to_string(),
+            bell_action: "any".to_string(),
+            silence_action: "other".to_string(),
+        }
+    }
+}
+
+impl MonitorOptions {
+    /// Read the options of the window `tab_id` in the session `workspace`.
+    pub fn lookup(tab_id: TabId, workspace: &str) -> Self {
+        let store = options(named_server::owner(workspace).as_deref());
+        let window = OptionScope::Window(tab_id).chain(None);
+        let session = OptionScope::Session(workspace.to_string()).chain(None);
+        let flag = |name| {
+            store
+                .lookup(&window, name)
+                .is_some_and(|value| matches!(value, "on" | "yes" | "1"))
+        };
+        let action = |name| store.lookup(&session, name).unwrap_or("none").to_string();
+        Self {
+            monitor_activity: flag("monitor-activity"),
+            monitor_bell: flag("monitor-bell"),
+            monitor_silence: store
+                .lookup(&window, "monitor-silence")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0),
+            activity_action: action("activity-action"),
+            bell_action: action("bell-action"),
+            silence_action: action("silence-action"),
+        }
+    }
+}
+
+/// Whether an `*-action` of `action` lets an alert in a window that is, or
+/// isn't, `current` fire its hook.
+pub fn action_applies(action: &str, current: bool) -> bool {
+    match action {
+        "any" => true,
+        "current" => current,
+        "other" => !current,
+        _ => false,
+    }
+}
+
+/// What a pane did since the windows were last checked.
+#[derive(Debug, Default, Clone, Copy)]
+struct PaneEvents {
+    output: Option<Instant>,
+    bell: bool,
+}
+
+#[derive(Debug, Default)]
+struct WindowAlerts {
+    flags: AlertFlags,
+    /// When the silence being timed started: the last output, or when
+    /// `monitor-silence` was last changed.
+    quiet_since: Option<Instant>,
+    /// The `monitor-silence` the silence is being timed against.
+    silence_interval: u64,
+    /// Whether the current silence has already raised its alert.
+    silence_alerted: bool,
+}
+
+/// Output and bells waiting to be checked, and the alerts of each window of
+/// a server.
+#[derive(Debug, Default)]
+pub struct AlertStore {
+    panes: HashMap<PaneId, PaneEvents>,
+    windows: HashMap<TabId, WindowAlerts>,
+}
+
+impl AlertStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `pane_id` produced output at `when`.
+    pub fn pane_output(&mut self, pane_id: PaneId, when: Instant) {
+        self.panes.entry(pane_id).or_default().output = Some(when);
+    }
+
+    /// Record that `pane_id` rang the bell.
+    pub fn pane_bell(&mut self, pane_id: PaneId) {
+        self.panes.entry(pane_id).or_default().bell = true;
+    }
+
+    /// Add what `pane_id` did to what it has done since the last check.
+    fn add_events(&mut self, pane_id: PaneId, events: PaneEvents) {
+        let pane = self.panes.entry(pane_id).or_default();
+        pane.output = pane.output.max(events.output);
+        pane.bell |= events.bell;
+    }
+
+    /// The alerts the window `tab_id` is flagged with.
+    pub fn flags(&self, tab_id: TabId) -> AlertFlags {
+        self.windows
+            .get(&tab_id)
+            .map(|window| window.flags)
+            .unwrap_or_default()
+    }
+
+    /// Take what the panes of the window `tab_id` did since the last check,
+    /// update its flags and return the hooks to fire. `current` is whether
+    /// the window is the current window of its session.
+    pub fn check_window(
+        &mut self,
+        tab_id: TabId,
+        panes: &[PaneId],
+        current: bool,
+        opts: &MonitorOptions,
+        now: Instant,
+    ) -> Vec<&'static str> {
+        let mut output = None;
+        let mut bell = false;
+        for pane_id in panes {
+            if let Some(events) = self.panes.remove(pane_id) {
+                output = output.max(events.output);
+                bell |= events.bell;
+            }
+        }
+
+        let window = self.windows.entry(tab_id).or_default();
+        if current {
+            window.flags = AlertFlags::default();
+        }
+        if output.is_some() || window.silence_interval != opts.monitor_silence {
+            window.quiet_since = Some(output.unwrap_or(now));
+            window.silence_interval = opts.monitor_silence;
+            window.silence_alerted = false;
+        }
+
+        let mut hooks = Vec::new();
+        if output.is_some() && opts.monitor_activity && !window.flags.activity {
+            window.flags.activity = !current;
+            if action_applies(&opts.activity_action, current) {
+                hooks.push("alert-activity");
+            }
+        }
+        // Like tmux, a window that already has the bell flag still fires the
+        // hook for each new bell.
+        if bell && opts.monitor_bell {
+            window.flags.bell |= !current;
+            if action_applies(&opts.bell_action, current) {
+                hooks.push("alert-bell");
+            }
+        }
+        let quiet_since = *window.quiet_since.get_or_insert(now);
+        if opts.monitor_silence > 0
+            && !window.silence_alerted
+            && now.saturating_duration_since(quiet_since)
+                >= Duration::from_secs(opts.monitor_silence)
+        {
+            window.silence_alerted = true;
+            window.flags.silence = !current;
+            if action_applies(&opts.silence_action, current) {
+                hooks.push("alert-silence");
+            }
+        }
+        hooks
+    }
+
+    /// Forget the windows not in `live`, and the events of panes that no
+    /// longer belong to any window.
+    pub fn prune(&mut self, live: &[TabId]) {
+        self.windows.retain(|tab_id, _| live.contains(tab_id));
+        self.panes.clear();
+    }
+}
+
+pub(super) static ALERTS: LazyLock<PerServer<AlertStore>> = LazyLock::new(Default::default);
+
+/// Lock the alert store of `server`, or of the default server for `None`.
+pub fn alerts(server: Option<&str>) -> MappedMutexGuard<'static, AlertStore> {
+    ALERTS.lock(server)
+}
+
+/// Output and bells of panes the timer thread hasn't handed to their
+/// windows yet. Pane IDs are unique across servers, and the mux subscriber
+/// can't tell which server a pane belongs to.
+static PENDING: LazyLock<Mutex<AlertStore>> = LazyLock::new(Default::default);
+
+/// Record that `pane_id` produced output at `when`.
+pub fn pane_output(pane_id: PaneId, when: Instant) {
+    PENDING.lock().pane_output(pane_id, when);
+}
+
+/// Record that `pane_id` rang the bell.
+pub fn pane_bell(pane_id: PaneId) {
+    PENDING.lock().pane_bell(pane_id);
+}
+
+/// The alerts the window `tab_id` of `server` is flagged with, as shown in
+/// formats. A window loses its flags when it becomes current, which the
+/// timer thread may not have noticed yet.
+pub fn window_alert_flags(server: Option<&str>, tab_id: TabId, current: bool) -> AlertFlags {
+    if current {
+        AlertFlags::default()
+    } else {
+        alerts(server).flags(tab_id)
+    }
+}
+
+/// Start the thread that checks the windows for alerts, and the panes for
+/// `pane-died`, once.
+pub fn start_alert_timer() {
+    static STARTED: std::sync::Once = std::sync::Once::new();
+    STARTED.call_once(|| {
+        let spawned = std::thread::Builder::new()
+            .name("tmux-alerts".to_string())
+            .spawn(|| loop {
+                std::thread::sleep(ALERT_INTERVAL);
+                check_alerts();
+                check_died_panes();
+            });
+        if let Err(err) = spawned {
+            log::error!("tmux CC: can't start the alert timer: {}", err);
+        }
+    });
+}
+
+/// A window as seen by [`check_alerts`].
+struct WindowInfo {
+    tab_id: TabId,
+    workspace: String,
+    current: bool,
+    panes: Vec<PaneId>,
+}
+
+/// Check every window for alerts and fire their hooks.
+fn check_alerts() {
+    let Some(mux) = Mux::try_get() else {
+        return;
+    };
+    let mut windows = Vec::new();
+    for window_id in mux.iter_windows() {
+        let Some(window) = mux.get_window(window_id) else {
+            continue;
+        };
+        let active = window.get_active().map(|tab| tab.tab_id());
+        for tab in window.iter() {
+            windows.push(WindowInfo {
+                tab_id: tab.tab_id(),
+                workspace: window.get_workspace().to_string(),
+                current: active == Some(tab.tab_id()),
+                panes: tab
+                    .iter_panes_ignoring_zoom()
+                    .iter()
+                    .map(|pp| pp.pane.pane_id())
+                    .collect(),
+            });
+        }
+    }
+
+    let monitor: Vec<MonitorOptions> = windows
+        .iter()
+        .map(|info| MonitorOptions::lookup(info.tab_id, &info.workspace))
+        .collect();
+    let now = Instant::now();
+    let mut pending = std::mem::take(&mut PENDING.lock().panes);
+    let mut events = Vec::new();
+    for (info, opts) in windows.iter().zip(&monitor) {
+        let mut store = alerts(named_server::owner(&info.workspace).as_deref());
+        for pane_id in &info.panes {
+            if let Some(pane_events) = pending.remove(pane_id) {
+                store.add_events(*pane_id, pane_events);
+            }
+        }
+        for name in store.check_window(info.tab_id, &info.panes, info.current, opts, now) {
+            events.push(HookEvent::tab(name, info.tab_id));
+        }
+    }
+    let live: Vec<TabId> = windows.iter().map(|info| info.tab_id).collect();
+    ALERTS.for_each(|store| store.prune(&live));
+    for event in events {
+        fire_hook(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitoring() -> MonitorOptions {
+        MonitorOptions {
+            monitor_activity: true,
+            monitor_silence: 60,
+            ..MonitorOptions::default()
+        }
+    }
+
+    #[test]
+    fn actions() {
+        assert!(action_applies("any", true));
+        assert!(action_applies("any", false));
+        assert!(action_applies("current", true));
+        assert!(!action_applies("current", false));
+        assert!(!action_applies("other", true));
+        assert!(action_applies("other", false));
+        assert!(!action_applies("none", false));
+    }
+
+    #[test]
+    fn activity_flags_other_windows_once() {
+        let mut store = AlertStore::new();
+        let now = Instant::now();
+        let opts = monitoring();
+
+        store.pane_output(1, now);
+        assert_eq!(
+            store.check_window(7, &[1, 2], false, &opts, now),
+            vec!["alert-activity"]
+        );
+        assert!(store.flags(7).activity);
+
+        // Already flagged: no new hook until the window has been current.
+        store.pane_output(2, now);
+        assert!(store.check_window(7, &[1, 2], false, &opts, now).is_empty());
+
+        assert!(store.check_window(7, &[1, 2], true, &opts, now).is_empty());
+        assert_eq!(store.flags(7), AlertFlags::default());
+    }
+
+    #[test]
+    fn activity_is_only_monitored_when_asked() {
+        let mut store = AlertStore::new();
+        let now = Instant::now();
+        store.pane_output(1, now);
+        let opts = MonitorOptions::default();
+        assert!(store.check_window(7, &[1], false, &opts, now).is_empty());
+        assert!(!store.flags(7).activity);
+    }
+
+    #[test]
+    fn current_window_is_not_flagged() {
+        let mut store = AlertStore::new();
+        let now = Instant::now();
+        let opts = MonitorOptions {
+            activity_action: "any".to_string(),
+            ..monitoring()
+        };
+        store.pane_output(1, now);
+        store.pane_bell(1);
+        assert_eq!(
+            store.check_window(7, &[1], true, &opts, now),
+            vec!["alert-activity", "alert-bell"]
+        );
+        assert_eq!(store.flags(7), AlertFlags::default());
+    }
+
+    #[test]
+    fn every_bell_fires_unless_unmonitored() {
+        let mut store = AlertStore::new();
+        let now = Instant::now();
+        let opts = MonitorOptions::default();
+        for _ in 0..2 {
+            store.pane_bell(3);
+            assert_eq!(
+                store.check_window(7, &[3], false, &opts, now),
+                vec!["alert-bell"]
+            );
+            assert!(store.flags(7).bell);
+        }
+
+        let opts = MonitorOptions {
+            monitor_bell: false,
+            ..MonitorOptions::default()
+        };
+        store.pane_bell(3);
+        assert!(store.check_window(8, &[3], false, &opts, now).is_empty());
+        assert!(!store.flags(8).bell);
+    }
+
+    #[test]
+    fn silence_alerts_once_per_quiet_spell() {
+        let mut store = AlertStore::new();
+        let start = Instant::now();
+        let opts = MonitorOptions {
+            monitor_activity: false,
+            ..monitoring()
+        };
+        store.pane_output(1, start);
+        assert!(store.check_window(7, &[1], false, &opts, start).is_empty());
+
+        let later = start + Duration::from_secs(59);
+        assert!(store.check_window(7, &[1], false, &opts, later).is_empty());
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(
+            store.check_window(7, &[1], false, &opts, later),
+            vec!["alert-silence"]
+        );
+        assert!(store.flags(7).silence);
+        let later = start + Duration::from_secs(200);
+        assert!(store.check_window(7, &[1], false, &opts, later).is_empty());
+
+        // Output starts a new spell; the flag stays until the window is
+        // current.
+        store.pane_output(1, later);
+        assert!(store.check_window(7, &[1], false, &opts, later).is_empty());
+        assert!(store.flags(7).silence);
+        let much_later = later + Duration::from_secs(60);
+        assert!(store
+            .check_window(7, &[1], true, &opts, much_later)
+            .is_empty());
+        assert!(!store.flags(7).silence);
+    }
+
+    #[test]
+    fn changing_monitor_silence_restarts_the_timer() {
+        let mut store = AlertStore::new();
+        let start = Instant::now();
+        let off = MonitorOptions::default();
+        assert!(store.check_window(7, &[1], false, &off, start).is_empty());
+
+        let later = start + Duration::from_secs(100);
+        let opts = MonitorOptions {
+            monitor_silence: 60,
+            ..MonitorOptions::default()
+        };
+        assert!(store.check_window(7, &[1], false, &opts, later).is_empty());
+        let later = later + Duration::from_secs(60);
+        assert_eq!(
+            store.check_window(7, &[1], false, &opts, later),
+            vec!["alert-silence"]
+        );
+    }
+
+    #[test]
+    fn prune_forgets_closed_windows() {
+        let mut store = AlertStore::new();
+        let now = Instant::now();
+        store.pane_bell(1);
+        store.check_window(7, &[1], false, &MonitorOptions::default(), now);
+        store.pane_bell(9);
+        store.prune(&[8]);
+        assert_eq!(store.flags(7), AlertFlags::default());
+        assert!(store.panes.is_empty());
+    }
+
+    #[test]
+    fn each_server_keeps_its_own_alerts() {
+        let path = "/tmp/alerts-test-server";
+        let server = Some(path);
+        let now = Instant::now();
+        let events = PaneEvents {
+            output: Some(now),
+            bell: true,
+        };
+        {
+            let mut store = alerts(server);
+            store.add_events(31, events);
+            store.add_events(31, PaneEvents::default());
+            let hooks = store.check_window(30, &[31], false, &monitoring(), now);
+            assert_eq!(hooks, vec!["alert-activity", "alert-bell"]);
+        }
+        let flagged = AlertFlags {
+            activity: true,
+            bell: true,
+            silence: false,
+        };
+        assert_eq!(window_alert_flags(server, 30, false), flagged);
+        assert_eq!(window_alert_flags(server, 30, true), AlertFlags::default());
+        assert_eq!(window_alert_flags(None, 30, false), AlertFlags::default());
+        ALERTS.remove(path);
+    }
+}
@@ -0,0 +1,265 @@
+//! Authentication of CC clients.
+//!
+//! Every user of the host can connect to a TCP listener on localhost, so a
+//! client of one must first send `auth-token <token>`, where the token is a
+//! random secret that the listener writes to a file only its user can read.
+//! Panes learn the path of that file from the fourth field of `$TMUX`, which
+//! is otherwise `address,pid,session` as with tmux.
+//!
+//! Clients of a Unix socket must run as the same user as WezTerm, or as
+//! root, as tmux requires of its own clients.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+/// The command a TCP client sends with its token before anything else.
+pub const AUTH_COMMAND: &str = "auth-token";
+
+/// How long a TCP client has to present its token.
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a client that isn't let in is told before the connection closes.
+pub const ACCESS_DENIED: &str = "%exit access denied\n";
+
+/// The first line is short; a client sending more is not one of ours.
+const MAX_AUTH_LINE: usize = 256;
+
+/// The token files of the TCP listeners started by this process, by address.
+static TOKEN_FILES: LazyLock<Mutex<HashMap<String, PathBuf>>> = LazyLock::new(Default::default);
+
+/// Generate a new random token.
+pub fn new_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow::anyhow!("can't generate a token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Write `token` to a new file at `path` that only the current user can
+/// read, replacing any file already there.
+pub fn write_token_file(path: &Path, token: &str) -> anyhow::Result<()> {
+    let _ = std::fs::remove_file(path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(token.as_bytes())?;
+    Ok(())
+}
+
+/// Record that clients of the listener at `address` find its token in the
+/// file at `path`.
+pub fn register_token_file(address: &str, path: PathBuf) {
+    TOKEN_FILES.lock().insert(address.to_string(), path);
+}
+
+/// The value of `$TMUX` for panes talking to the listener at `address`,
+/// started by the process `pid`.
+pub fn tmux_env_value(address: &str, pid: u32) -> String {
+    match TOKEN_FILES.lock().get(address) {
+        Some(path) => format!("{},{},0,{}", address, pid, path.display()),
+        None => format!("{},{},0", address, pid),
+    }
+}
+
+/// Whether `line`, the first line a client sent, presents `token`.
+pub fn is_authorized(line: &str, token: &str) -> bool {
+    let presented = match line
+        .trim_end()
+        .strip_prefix(AUTH_COMMAND)
+        .and_then(|rest| rest.strip_prefix(' '))
+    {
+        Some(presented) => presented,
+        None => return false,
+    };
+    // Compare every byte, so that the time taken says nothing about how
+    // much of the token is right.
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Read the first line a client sends, a byte at a time so that nothing
+/// after it is consumed. The caller sets a read timeout.
+pub fn read_first_line(stream: &mut impl Read) -> std::io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while line.len() < MAX_AUTH_LINE {
+        if stream.read(&mut byte)? == 0 || byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Check that a TCP client presents `token` before it is sent anything,
+/// and tell it that it isn't let in otherwise.
+pub fn authenticate(stream: &mut (impl Read + Write), token: &str) -> anyhow::Result<()> {
+    let line = read_first_line(stream)?;
+    if !is_authorized(&line, token) {
+        let _ = stream.write_all(ACCESS_DENIED.as_bytes());
+        anyhow::bail!("client did not present the listener's token");
+    }
+    Ok(())
+}
+
+/// Whether the client connected to `stream`, a Unix socket, runs as the
+/// same user as us or as root.
+#[cfg(unix)]
+pub fn peer_is_allowed(stream: &impl std::os::unix::io::AsRawFd) -> bool {
+    match peer_uid(stream.as_raw_fd()) {
+        Ok(uid) => uid == 0 || uid == unsafe { libc::geteuid() },
+        Err(err) => {
+            log::warn!("tmux CC: can't get the credentials of a client: {}", err);
+            false
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(fd: std::os::unix::io::RawFd) -> std::io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn peer_uid(fd: std::os::unix::io::RawFd) -> std::io::Result<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client that sends `input` and records what it is sent.
+    struct FakeClient {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl FakeClient {
+        fn new(input: &str) -> Self {
+            Self {
+                input: std::io::Cursor::new(input.as_bytes().to_vec()),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for FakeClient {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for FakeClient {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let token = new_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, new_token().unwrap());
+    }
+
+    #[test]
+    fn token_file_is_private() {
+        let path = std::env::temp_dir().join(format!("tmux-cc-token-test-{}", std::process::id()));
+        std::fs::write(&path, "stale").unwrap();
+        write_token_file(&path, "secret").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn tmux_env_names_the_token_file() {
+        assert_eq!(
+            tmux_env_value("tcp:127.0.0.1:1", 42),
+            "tcp:127.0.0.1:1,42,0"
+        );
+        register_token_file("tcp:127.0.0.1:2", PathBuf::from("/run/tmux-cc.token"));
+        assert_eq!(
+            tmux_env_value("tcp:127.0.0.1:2", 42),
+            "tcp:127.0.0.1:2,42,0,/run/tmux-cc.token"
+        );
+    }
+
+    #[test]
+    fn authorization() {
+        assert!(is_authorized("auth-token abc\r", "abc"));
+        assert!(!is_authorized("auth-token abd", "abc"));
+        assert!(!is_authorized("auth-token ab", "abc"));
+        assert!(!is_authorized("auth-token", "abc"));
+        assert!(!is_authorized("list-sessions", "abc"));
+    }
+
+    #[test]
+    fn authenticate_reads_only_the_first_line() {
+        let mut client = FakeClient::new("auth-token abc\nlist-sessions\n");
+        authenticate(&mut client, "abc").unwrap();
+        assert!(client.output.is_empty());
+        let mut rest = String::new();
+        client.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "list-sessions\n");
+
+        let mut client = FakeClient::new("list-sessions\n");
+        assert!(authenticate(&mut client, "abc").is_err());
+        assert_eq!(client.output, ACCESS_DENIED.as_bytes());
+
+        let mut client = FakeClient::new(&"x".repeat(1000));
+        assert!(authenticate(&mut client, "abc").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn peers_of_the_same_user_are_allowed() {
+        use std::os::unix::io::AsRawFd;
+        let (a, _b) = std::os::unix::net::UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(a.as_raw_fd()).unwrap(), unsafe { libc::geteuid() });
+        assert!(peer_is_allowed(&a));
+    }
+}
@@ -0,0 +1,375 @@
+//! Choosers (`choose-tree`, `choose-buffer`, `choose-client`) and
+//! `display-panes`.
+//!
+//! tmux shows these in the client. Here the GUI window containing the target
+//! pane shows them instead: the choosers as an `InputSelector` listing the
+//! items, and `display-panes` as a `PaneSelect` labelling each pane with its
+//! index. Once the user picks something, the command's template is run with
+//! `%%` replaced by the chosen item, as if from a key binding in that pane.
+
+use config::keyassignment::{
+    InputSelector, InputSelectorEntry, KeyAssignment, PaneSelectArguments,
+};
+
+use crate::pane::PaneId;
+
+use super::format::{expand_format, is_true, FormatContext};
+use super::key_bindings::{run_binding, run_in_pane};
+
+/// The templates run for the chosen item when a command is given none.
+pub const TREE_TEMPLATE: &str = "switch-client -t '%%'";
+pub const BUFFER_TEMPLATE: &str = "paste-buffer -p -b '%%'";
+pub const CLIENT_TEMPLATE: &str = "detach-client -t '%%'";
+pub const PANES_TEMPLATE: &str = "select-pane -t '%%'";
+
+/// The line shown for each `choose-tree` item after its name.
+pub const TREE_FORMAT: &str = "#{?pane_format,\
+#{pane_current_command}#{?pane_active,*,} \"#{pane_title}\",\
+#{?window_format,\
+#{window_name}#{window_flags} (#{window_panes} panes),\
+#{session_windows} windows#{?session_attached, (attached),}}}";
+pub const BUFFER_FORMAT: &str = "#{buffer_size} bytes: \"#{buffer_sample}\"";
+pub const CLIENT_FORMAT: &str = "#{session_name}";
+
+/// `display-panes` labels the panes with their index.
+const PANE_LABELS: &str = "0123456789";
+
+/// How far down `choose-tree` lists: `-s` stops at sessions and `-w` at
+/// windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeDepth {
+    Sessions,
+    Windows,
+    Panes,
+}
+
+/// One line of a chooser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub label: String,
+    /// What `%%` in the template becomes when this line is chosen.
+    pub target: String,
+}
+
+/// Options shared by the choosers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChooseOptions<'a> {
+    /// `-F`: the format of each line, after the item's name.
+    pub format: Option<&'a str>,
+    /// `-f`: only items for which this format is true are listed.
+    pub filter: Option<&'a str>,
+    /// `-r`: list the items in reverse order.
+    pub reverse: bool,
+}
+
+impl ChooseOptions<'_> {
+    fn matches(&self, item: &FormatContext) -> bool {
+        self.filter
+            .is_none_or(|filter| is_true(&expand_format(filter, item)))
+    }
+
+    fn line(&self, name: &str, item: &FormatContext, default: &str) -> String {
+        format!(
+            "{}: {}",
+            name,
+            expand_format(self.format.unwrap_or(default), item)
+        )
+    }
+
+    fn order<T>(&self, mut items: Vec<T>) -> Vec<T> {
+        if self.reverse {
+            items.reverse();
+        }
+        items
+    }
+}
+
+/// The lines of `choose-tree`. Each session in `sessions` carries its
+/// windows in `loop_windows`, and each window its panes in `loop_panes`.
+///
+/// As in tmux, the filter applies to panes: a window is listed if any of its
+/// panes match, and a session if any of its windows are listed.
+pub fn tree_choices(
+    sessions: &[FormatContext],
+    depth: TreeDepth,
+    opts: ChooseOptions,
+) -> Vec<Choice> {
+    let mut choices = Vec::new();
+    for session in opts.order(sessions.iter().collect()) {
+        let mut windows = Vec::new();
+        for window in opts.order(session.loop_windows.iter().collect()) {
+            let panes: Vec<Choice> = opts
+                .order(window.loop_panes.iter().collect())
+                .into_iter()
+                .filter(|pane| opts.matches(pane))
+                .map(|pane| {
+                    let pane = FormatContext {
+                        format_type: "pane",
+                        ..pane.clone()
+                    };
+                    Choice {
+                        label: format!(
+                            "    {}",
+                            opts.line(&pane.pane_index.to_string(), &pane, TREE_FORMAT)
+                        ),
+                        target: format!("%{}", pane.pane_id),
+                    }
+                })
+                .collect();
+            if panes.is_empty() {
+                continue;
+            }
+            let window = FormatContext {
+                format_type: "window",
+                ..window.clone()
+            };
+            windows.push(Choice {
+                label: format!(
+                    "  {}",
+                    opts.line(&window.window_index.to_string(), &window, TREE_FORMAT)
+                ),
+                target: format!("@{}", window.window_id),
+            });
+            if depth == TreeDepth::Panes {
+                windows.extend(panes);
+            }
+        }
+        if windows.is_empty() {
+            continue;
+        }
+        let session = FormatContext {
+            format_type: "session",
+            ..session.clone()
+        };
+        choices.push(Choice {
+            label: opts.line(&session.session_name, &session, TREE_FORMAT),
+            target: format!("${}", session.session_id),
+        });
+        if depth != TreeDepth::Sessions {
+            choices.extend(windows);
+        }
+    }
+    choices
+}
+
+/// The lines of `choose-buffer`, one per buffer in `buffers`.
+pub fn buffer_choices(buffers: &[FormatContext], opts: ChooseOptions) -> Vec<Choice> {
+    named_choices(buffers, opts, BUFFER_FORMAT, |item| &item.buffer_name)
+}
+
+/// The lines of `choose-client`, one per client in `clients`.
+pub fn client_choices(clients: &[FormatContext], opts: ChooseOptions) -> Vec<Choice> {
+    named_choices(clients, opts, CLIENT_FORMAT, |item| &item.client_name)
+}
+
+fn named_choices(
+    items: &[FormatContext],
+    opts: ChooseOptions,
+    default_format: &str,
+    name: impl Fn(&FormatContext) -> &String,
+) -> Vec<Choice> {
+    opts.order(items.iter().collect())
+        .into_iter()
+        .filter(|item| opts.matches(item))
+        .map(|item| Choice {
+            label: opts.line(name(item), item, default_format),
+            target: name(item).clone(),
+        })
+        .collect()
+}
+
+/// Replace `%%` and `%1` in `template` with `value`, as tmux does with the
+/// template of a chooser. With one more `%` (`%%%` or `%1%`), the characters
+/// that are special to the command parser are escaped in `value`.
+pub fn expand_template(template: &str, value: &str) -> String {
+    let mut out = String::with_capacity(template.len() + value.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' || !matches!(chars.peek(), Some('%') | Some('1')) {
+            out.push(c);
+            continue;
+        }
+        chars.next();
+        if chars.next_if_eq(&'%').is_some() {
+            for v in value.chars() {
+                if "\"\\$;~".contains(v) {
+                    out.push('\\');
+                }
+                out.push(v);
+            }
+        } else {
+            out.push_str(value);
+        }
+    }
+    out
+}
+
+/// The `InputSelector` the GUI shows for a chooser.
+pub fn selector(title: &str, choices: Vec<Choice>, template: &str) -> KeyAssignment {
+    let choices = choices
+        .into_iter()
+        .map(|choice| InputSelectorEntry {
+            label: choice.label,
+            id: Some(choice.target),
+        })
+        .collect();
+    KeyAssignment::InputSelector(InputSelector::new(
+        title.to_string(),
+        choices,
+        KeyAssignment::TmuxCommand(template.to_string()),
+    ))
+}
+
+/// The `PaneSelect` the GUI shows for `display-panes`.
+pub fn pane_labels(template: &str) -> KeyAssignment {
+    KeyAssignment::PaneSelect(PaneSelectArguments {
+        alphabet: PANE_LABELS.to_string(),
+        tmux_command: Some(template.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Run `template` for the item chosen in a chooser shown in `pane_id`. Can
+/// be called from any thread.
+pub fn run_choice(pane_id: PaneId, template: &str, choice: &str) {
+    run_binding(pane_id, expand_template(template, choice));
+}
+
+/// Run `template` for the pane chosen with `display-panes` in the window of
+/// `pane_id`. Can be called from any thread.
+pub fn run_pane_choice(pane_id: PaneId, template: &str, chosen: PaneId) {
+    let template = template.to_string();
+    run_in_pane(pane_id, move |ctx| {
+        let tmux_id = ctx.id_map.get_or_create_tmux_pane_id(chosen);
+        expand_template(&template, &format!("%{}", tmux_id))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane(id: u64, index: u64, command: &str) -> FormatContext {
+        FormatContext {
+            pane_id: id,
+            pane_index: index,
+            pane_current_command: command.to_string(),
+            pane_title: "host".to_string(),
+            ..FormatContext::default()
+        }
+    }
+
+    fn tree() -> Vec<FormatContext> {
+        let window = |id: u64, name: &str, panes: Vec<FormatContext>| FormatContext {
+            window_id: id,
+            window_index: id,
+            window_name: name.to_string(),
+            window_panes: panes.len() as u64,
+            loop_panes: panes,
+            ..FormatContext::default()
+        };
+        vec![FormatContext {
+            session_id: 0,
+            session_name: "work".to_string(),
+            session_windows: 2,
+            session_attached: 1,
+            loop_windows: vec![
+                window(1, "edit", vec![pane(1, 0, "vim"), pane(2, 1, "bash")]),
+                window(2, "logs", vec![pane(3, 0, "tail")]),
+            ],
+            ..FormatContext::default()
+        }]
+    }
+
+    fn labels(choices: &[Choice]) -> Vec<&str> {
+        choices.iter().map(|c| c.label.as_str()).collect()
+    }
+
+    #[test]
+    fn tree_lists_sessions_windows_and_panes() {
+        let choices = tree_choices(&tree(), TreeDepth::Panes, ChooseOptions::default());
+        assert_eq!(
+            labels(&choices),
+            vec![
+                "work: 2 windows (attached)",
+                "  1: edit (2 panes)",
+                "    0: vim \"host\"",
+                "    1: bash \"host\"",
+                "  2: logs (1 panes)",
+                "    0: tail \"host\"",
+            ]
+        );
+        let targets: Vec<&str> = choices.iter().map(|c| c.target.as_str()).collect();
+        assert_eq!(targets, vec!["$0", "@1", "%1", "%2", "@2", "%3"]);
+    }
+
+    #[test]
+    fn tree_depth_format_filter_and_order() {
+        let sessions = tree();
+        let choices = tree_choices(&sessions, TreeDepth::Sessions, ChooseOptions::default());
+        assert_eq!(labels(&choices), vec!["work: 2 windows (attached)"]);
+
+        let opts = ChooseOptions {
+            format: Some("#{?window_format,#{window_name},-}"),
+            filter: Some("#{==:#{pane_current_command},tail}"),
+            reverse: false,
+        };
+        let choices = tree_choices(&sessions, TreeDepth::Windows, opts);
+        assert_eq!(labels(&choices), vec!["work: -", "  2: logs"]);
+
+        let opts = ChooseOptions {
+            format: Some("#{window_name}"),
+            reverse: true,
+            ..ChooseOptions::default()
+        };
+        let choices = tree_choices(&sessions, TreeDepth::Windows, opts);
+        assert_eq!(labels(&choices)[1..], ["  2: logs", "  1: edit"]);
+
+        let opts = ChooseOptions {
+            filter: Some("0"),
+            ..ChooseOptions::default()
+        };
+        assert!(tree_choices(&sessions, TreeDepth::Panes, opts).is_empty());
+    }
+
+    #[test]
+    fn buffer_choices_are_named() {
+        let buffer = |name: &str, data: &str| FormatContext {
+            buffer_name: name.to_string(),
+            buffer_size: data.len() as u64,
+            buffer_sample: data.to_string(),
+            ..FormatContext::default()
+        };
+        let buffers = vec![buffer("buffer1", "hello"), buffer("buffer0", "hi")];
+        let choices = buffer_choices(&buffers, ChooseOptions::default());
+        assert_eq!(
+            choices[0],
+            Choice {
+                label: "buffer1: 5 bytes: \"hello\"".to_string(),
+                target: "buffer1".to_string(),
+            }
+        );
+        let opts = ChooseOptions {
+            filter: Some("#{m:*0,#{buffer_name}}"),
+            ..ChooseOptions::default()
+        };
+        assert_eq!(
+            labels(&buffer_choices(&buffers, opts)),
+            vec!["buffer0: 2 bytes: \"hi\""]
+        );
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(expand_template("swap-pane -t %%", "%3"), "swap-pane -t %3");
+        assert_eq!(
+            expand_template("select-pane -t '%1' ; display %%", "%3"),
+            "select-pane -t '%3' ; display %3"
+        );
+        assert_eq!(
+            expand_template("say \"%%%\"", "a\"b;c"),
+            "say \"a\\\"b\\;c\""
+        );
+        assert_eq!(expand_template("100% done %2", "x"), "100% done %2");
+    }
+}
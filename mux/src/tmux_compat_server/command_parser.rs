@@ -23,6 +23,11 @@ pub enum TmuxCliCommand {
         target: Option<String>,
         literal: bool,
         hex: bool,
+        mouse: bool,
+        reset: bool,
+        /// `-X`: `keys` is a copy mode command and its arguments.
+        copy_command: bool,
+        repeat: Option<u32>,
         keys: Vec<String>,
     },
     CapturePane {
@@ -30,6 +35,8 @@ pub enum TmuxCliCommand {
         target: Option<String>,
         escape: bool,
         octal_escape: bool,
+        join: bool,
+        preserve_trailing: bool,
         start_line: Option<i64>,
         end_line: Option<i64>,
     },
@@ -68,14 +75,24 @@ pub enum TmuxCliCommand {
     },
     ResizePane {
         target: Option<String>,
-        width: Option<u64>,
-        height: Option<u64>,
+        /// `-x`/`-y`: cells, or a percentage of the window with a `%` suffix.
+        width: Option<String>,
+        height: Option<String>,
         zoom: bool,
+        /// `-L`, `-R`, `-U` or `-D`: the direction to move the pane's border in.
+        direction: Option<char>,
+        /// How many cells to move the border by, 1 if not given.
+        adjustment: Option<u64>,
+        /// `-M`: begin resizing with the mouse.
+        mouse: bool,
     },
     ResizeWindow {
         target: Option<String>,
         width: Option<u64>,
         height: Option<u64>,
+        /// `-A`: the size of the largest client, `-a` the smallest.
+        largest: bool,
+        smallest: bool,
     },
     RefreshClient {
         size: Option<String>,
@@ -118,14 +135,20 @@ pub enum TmuxCliCommand {
     },
     ShowOptions {
         global: bool,
+        server: bool,
+        window: bool,
+        pane: bool,
         value_only: bool,
         quiet: bool,
+        inherited: bool,
+        target: Option<String>,
         option_name: Option<String>,
     },
     ShowWindowOptions {
         global: bool,
         value_only: bool,
         quiet: bool,
+        target: Option<String>,
         option_name: Option<String>,
     },
     AttachSession {
@@ -134,6 +157,8 @@ pub enum TmuxCliCommand {
     DetachClient,
     SwitchClient {
         target: Option<String>,
+        /// `-T`: the key table to look the next key press up in.
+        key_table: Option<String>,
     },
     ListClients {
         format: Option<String>,
@@ -147,6 +172,7 @@ pub enum TmuxCliCommand {
         buffer_name: Option<String>,
         data: Option<String>,
         append: bool,
+        clipboard: bool,
     },
     DeleteBuffer {
         buffer_name: Option<String>,
@@ -160,12 +186,28 @@ pub enum TmuxCliCommand {
         delete_after: bool,
         bracketed: bool,
     },
+    LoadBuffer {
+        buffer_name: Option<String>,
+        path: String,
+        clipboard: bool,
+    },
+    SaveBuffer {
+        buffer_name: Option<String>,
+        path: String,
+        append: bool,
+    },
     // Phase 12.3: move commands
     MovePane {
         src: Option<String>,
         dst: Option<String>,
         horizontal: bool,
         before: bool,
+        /// `-d`: leave the active pane of the destination window alone.
+        detach: bool,
+        /// `-f`: span the full width or height of the destination window.
+        full_size: bool,
+        /// `-l`: size of the moved pane, in cells or as a percentage.
+        size: Option<String>,
     },
     MoveWindow {
         src: Option<String>,
@@ -181,10 +223,27 @@ pub enum TmuxCliCommand {
         target: Option<String>,
         option_name: Option<String>,
         value: Option<String>,
+        global: bool,
+        server: bool,
+        window: bool,
+        pane: bool,
+        append: bool,
+        unset: bool,
+        unset_panes: bool,
+        only_if_unset: bool,
+        format: bool,
+        quiet: bool,
     },
     SelectLayout {
         target: Option<String>,
+        /// A preset name or a full layout string.
         layout_name: Option<String>,
+        /// `-E`: spread the target pane and its neighbours out evenly.
+        spread: bool,
+        /// `-n`: apply the next preset layout.
+        next: bool,
+        /// `-p`: apply the previous preset layout.
+        previous: bool,
     },
     BreakPane {
         detach: bool,
@@ -193,8 +252,21 @@ pub enum TmuxCliCommand {
     },
     // Phase 17: missing commands for cleanup & orchestration
     KillServer,
+    StartServer {
+        /// `-S`: socket path of a named server to start. tmux's
+        /// `start-server` takes no flags; the shim sends this when `tmux -L`
+        /// or `tmux -S` names a server that isn't running yet.
+        socket_path: Option<String>,
+    },
     WaitFor {
         signal: bool,
+        /// `-L`: take the channel's lock.
+        lock: bool,
+        /// `-U`: release the channel's lock.
+        unlock: bool,
+        /// `-T`: give up waiting after this many seconds. This is our
+        /// extension; tmux waits for ever.
+        timeout: Option<String>,
         channel: String,
     },
     PipePane {
@@ -206,6 +278,25 @@ pub enum TmuxCliCommand {
     },
     DisplayPopup {
         target: Option<String>,
+        /// `-C`: close the popup open over the target window instead.
+        close: bool,
+        /// Number of `-E` flags: one closes the popup when the command
+        /// exits, two only when it exits successfully.
+        close_on_exit: u8,
+        /// `-w`/`-h`: cells, or a percentage of the window with a `%` suffix.
+        width: Option<String>,
+        height: Option<String>,
+        /// `-x`/`-y`: a number or one of tmux's position letters.
+        x: Option<String>,
+        y: Option<String>,
+        cwd: Option<String>,
+        title: Option<String>,
+        env: Vec<String>,
+        shell_command: Option<String>,
+    },
+    /// `display-menu`, accepted and ignored.
+    DisplayMenu {
+        target: Option<String>,
     },
     RunShell {
         background: bool,
@@ -216,8 +307,284 @@ pub enum TmuxCliCommand {
     },
     // Phase 19: diagnostic & debugging
     ServerInfo,
+    // Hooks
+    SetHook {
+        target: Option<String>,
+        global: bool,
+        append: bool,
+        unset: bool,
+        /// `-R`: run the hook now instead of changing it.
+        run: bool,
+        hook_name: String,
+        command: Option<String>,
+    },
+    ShowHooks {
+        target: Option<String>,
+        global: bool,
+    },
+    // Command lists and config files
+    IfShell {
+        background: bool,
+        /// `-F`: `condition` is a format rather than a shell command.
+        format: bool,
+        target: Option<String>,
+        condition: String,
+        /// Command list run when the condition is true. Lists are parsed
+        /// when they run, after the condition has been checked.
+        then_command: String,
+        else_command: Option<String>,
+    },
+    SourceFile {
+        /// `-F`: expand the paths as formats.
+        format: bool,
+        /// `-n`: check the syntax of the files without running them.
+        parse_only: bool,
+        /// `-q`: ignore files that do not exist.
+        quiet: bool,
+        target: Option<String>,
+        paths: Vec<String>,
+    },
+    // Rearranging panes and windows
+    SwapPane {
+        src: Option<String>,
+        dst: Option<String>,
+        /// `-d`: keep the active pane where it is rather than following
+        /// the swapped pane.
+        detach: bool,
+        /// `-U`: swap the target with the previous pane.
+        up: bool,
+        /// `-D`: swap the target with the next pane.
+        down: bool,
+        /// `-Z`: keep the window zoomed.
+        keep_zoom: bool,
+    },
+    RotateWindow {
+        target: Option<String>,
+        /// `-D`: rotate panes downwards; upwards (`-U`) is the default.
+        down: bool,
+        keep_zoom: bool,
+    },
+    LastPane {
+        target: Option<String>,
+        keep_zoom: bool,
+    },
+    LastWindow {
+        target: Option<String>,
+    },
+    NextWindow {
+        target: Option<String>,
+    },
+    PreviousWindow {
+        target: Option<String>,
+    },
+    RespawnPane {
+        target: Option<String>,
+        /// `-k`: kill the running program first.
+        kill: bool,
+        cwd: Option<String>,
+        env: Vec<String>,
+        shell_command: Option<String>,
+    },
+    RespawnWindow {
+        target: Option<String>,
+        /// `-k`: kill the running programs first.
+        kill: bool,
+        cwd: Option<String>,
+        env: Vec<String>,
+        shell_command: Option<String>,
+    },
+    // Session environments
+    SetEnvironment {
+        target: Option<String>,
+        global: bool,
+        /// `-u`: take the variable out of the environment.
+        unset: bool,
+        /// `-r`: mark the variable as removed, so that new panes don't
+        /// inherit it.
+        remove: bool,
+        /// `-h`: hide the variable from `show-environment`.
+        hidden: bool,
+        /// `-F`: expand the value as a format.
+        format: bool,
+        name: String,
+        value: Option<String>,
+    },
+    ShowEnvironment {
+        target: Option<String>,
+        global: bool,
+        /// `-h`: show the hidden variables instead.
+        hidden: bool,
+        /// `-s`: print commands for a shell to evaluate.
+        shell: bool,
+        name: Option<String>,
+    },
+    // Key bindings
+    BindKey {
+        /// `-T`, or `root` for `-n`; `prefix` by default.
+        table: String,
+        /// `-r`: the key can repeat without the prefix key.
+        repeat: bool,
+        /// `-N`: a note shown by `list-keys -N`.
+        note: Option<String>,
+        key: String,
+        command: Option<String>,
+    },
+    UnbindKey {
+        table: String,
+        /// `-a`: remove every binding in the table.
+        all: bool,
+        /// `-q`: don't complain about a missing table.
+        quiet: bool,
+        key: Option<String>,
+    },
+    ListKeys {
+        table: Option<String>,
+        /// `-N`: list the notes of the keys rather than their commands.
+        notes: bool,
+        /// `-a`: with `-N`, list keys without a note too.
+        all: bool,
+        /// `-P`: with `-N`, what to print before keys in the prefix table.
+        prefix: Option<String>,
+        key: Option<String>,
+    },
+    ClearHistory {
+        target: Option<String>,
+        /// `-H`: also remove the hyperlinks from the visible text.
+        hyperlinks: bool,
+    },
+    // Choosers
+    ChooseTree {
+        target: Option<String>,
+        /// `-F`: the format of each line.
+        format: Option<String>,
+        /// `-f`: only list the panes for which this format is true.
+        filter: Option<String>,
+        /// `-r`: list in reverse order.
+        reverse: bool,
+        /// `-s`: list only the sessions.
+        sessions: bool,
+        /// `-w`: list the sessions and windows, without their panes.
+        windows: bool,
+        /// Run with `%%` replaced by the target of the chosen item.
+        template: Option<String>,
+    },
+    ChooseBuffer {
+        target: Option<String>,
+        format: Option<String>,
+        filter: Option<String>,
+        reverse: bool,
+        template: Option<String>,
+    },
+    ChooseClient {
+        target: Option<String>,
+        format: Option<String>,
+        filter: Option<String>,
+        reverse: bool,
+        template: Option<String>,
+    },
+    DisplayPanes {
+        target: Option<String>,
+        /// Run with `%%` replaced by the id of the chosen pane.
+        template: Option<String>,
+    },
+}
+
+impl TmuxCliCommand {
+    /// The full tmux name of the command, e.g. `split-window`. Used for
+    /// `after-*` hooks.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SplitWindow { .. } => "split-window",
+            Self::SendKeys { .. } => "send-keys",
+            Self::CapturePane { .. } => "capture-pane",
+            Self::ListPanes { .. } => "list-panes",
+            Self::ListWindows { .. } => "list-windows",
+            Self::ListSessions { .. } => "list-sessions",
+            Self::NewWindow { .. } => "new-window",
+            Self::SelectWindow { .. } => "select-window",
+            Self::SelectPane { .. } => "select-pane",
+            Self::KillPane { .. } => "kill-pane",
+            Self::ResizePane { .. } => "resize-pane",
+            Self::ResizeWindow { .. } => "resize-window",
+            Self::RefreshClient { .. } => "refresh-client",
+            Self::DisplayMessage { .. } => "display-message",
+            Self::HasSession { .. } => "has-session",
+            Self::ListCommands => "list-commands",
+            Self::KillWindow { .. } => "kill-window",
+            Self::KillSession { .. } => "kill-session",
+            Self::RenameWindow { .. } => "rename-window",
+            Self::RenameSession { .. } => "rename-session",
+            Self::NewSession { .. } => "new-session",
+            Self::ShowOptions { .. } => "show-options",
+            Self::ShowWindowOptions { .. } => "show-window-options",
+            Self::AttachSession { .. } => "attach-session",
+            Self::DetachClient => "detach-client",
+            Self::SwitchClient { .. } => "switch-client",
+            Self::ListClients { .. } => "list-clients",
+            Self::ShowBuffer { .. } => "show-buffer",
+            Self::SetBuffer { .. } => "set-buffer",
+            Self::DeleteBuffer { .. } => "delete-buffer",
+            Self::ListBuffers { .. } => "list-buffers",
+            Self::PasteBuffer { .. } => "paste-buffer",
+            Self::LoadBuffer { .. } => "load-buffer",
+            Self::SaveBuffer { .. } => "save-buffer",
+            Self::MovePane { .. } => "move-pane",
+            Self::MoveWindow { .. } => "move-window",
+            Self::CopyMode { .. } => "copy-mode",
+            Self::SetOption { .. } => "set-option",
+            Self::SelectLayout { .. } => "select-layout",
+            Self::BreakPane { .. } => "break-pane",
+            Self::KillServer => "kill-server",
+            Self::StartServer { .. } => "start-server",
+            Self::WaitFor { .. } => "wait-for",
+            Self::PipePane { .. } => "pipe-pane",
+            Self::DisplayPopup { .. } => "display-popup",
+            Self::DisplayMenu { .. } => "display-menu",
+            Self::RunShell { .. } => "run-shell",
+            Self::ServerInfo => "server-info",
+            Self::SetHook { .. } => "set-hook",
+            Self::ShowHooks { .. } => "show-hooks",
+            Self::IfShell { .. } => "if-shell",
+            Self::SourceFile { .. } => "source-file",
+            Self::SwapPane { .. } => "swap-pane",
+            Self::RotateWindow { .. } => "rotate-window",
+            Self::LastPane { .. } => "last-pane",
+            Self::LastWindow { .. } => "last-window",
+            Self::NextWindow { .. } => "next-window",
+            Self::PreviousWindow { .. } => "previous-window",
+            Self::RespawnPane { .. } => "respawn-pane",
+            Self::RespawnWindow { .. } => "respawn-window",
+            Self::SetEnvironment { .. } => "set-environment",
+            Self::ShowEnvironment { .. } => "show-environment",
+            Self::BindKey { .. } => "bind-key",
+            Self::UnbindKey { .. } => "unbind-key",
+            Self::ListKeys { .. } => "list-keys",
+            Self::ClearHistory { .. } => "clear-history",
+            Self::ChooseTree { .. } => "choose-tree",
+            Self::ChooseBuffer { .. } => "choose-buffer",
+            Self::ChooseClient { .. } => "choose-client",
+            Self::DisplayPanes { .. } => "display-panes",
+        }
+    }
+}
+
+/// One entry of a parsed [`CommandList`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandListItem {
+    Command(TmuxCliCommand),
+    /// `%if` / `%elif` / `%else` / `%endif`. Each branch pairs a format with
+    /// the commands run when it expands to a true value. The formats are
+    /// expanded when the list runs, not when it is parsed.
+    Conditional {
+        branches: Vec<(String, CommandList)>,
+        otherwise: CommandList,
+    },
 }
 
+/// A sequence of commands, as sent by a client on one line (`cmd1 ; cmd2`)
+/// or read from a config file by `source-file`.
+pub type CommandList = Vec<CommandListItem>;
+
 /// Parse a tmux command line into a structured [`TmuxCliCommand`].
 ///
 /// The input `line` is the raw text sent by the client, e.g.
@@ -271,25 +638,344 @@ pub fn parse_command(line: &str) -> Result<TmuxCliCommand> {
         "delete-buffer" | "deleteb" => parse_delete_buffer(args),
         "list-buffers" | "lsb" => parse_list_buffers(args),
         "paste-buffer" | "pasteb" => parse_paste_buffer(args),
+        "load-buffer" | "loadb" => parse_load_buffer(args),
+        "save-buffer" | "saveb" => parse_save_buffer(args),
         "move-pane" | "movep" | "join-pane" | "joinp" => parse_move_pane(args),
+        "swap-pane" | "swapp" => parse_swap_pane(args),
+        "rotate-window" | "rotatew" => parse_rotate_window(args),
+        "last-pane" | "lastp" => parse_last_pane(args),
+        "last-window" | "last" => parse_window_step(args, "last-window"),
+        "next-window" | "next" => parse_window_step(args, "next-window"),
+        "previous-window" | "prev" => parse_window_step(args, "previous-window"),
+        "respawn-pane" | "respawnp" => parse_respawn(args, "respawn-pane"),
+        "respawn-window" | "respawnw" => parse_respawn(args, "respawn-window"),
         "move-window" | "movew" => parse_move_window(args),
         "copy-mode" => parse_copy_mode(args),
         // Phase 13: Claude Code agent teams compatibility
         "set-option" | "set" => parse_set_option(args),
+        "set-window-option" | "setw" => parse_set_window_option(args),
         "select-layout" | "selectl" => parse_select_layout(args),
         "break-pane" | "breakp" => parse_break_pane(args),
         // Phase 17: missing commands for cleanup & orchestration
         "kill-server" => Ok(TmuxCliCommand::KillServer),
+        "start-server" | "start" => parse_start_server(args),
         "wait-for" | "wait" => parse_wait_for(args),
         "pipe-pane" | "pipep" => parse_pipe_pane(args),
-        "display-popup" | "popup" | "display-menu" | "menu" => parse_display_popup(args),
+        "display-popup" | "popup" => parse_display_popup(args),
+        "display-menu" | "menu" => parse_display_menu(args),
         "run-shell" | "run" => parse_run_shell(args),
         // Phase 19: diagnostic & debugging
         "server-info" | "info" => Ok(TmuxCliCommand::ServerInfo),
+        "set-hook" => parse_set_hook(args),
+        "show-hooks" => parse_show_hooks(args),
+        "if-shell" | "if" => parse_if_shell(args),
+        "source-file" | "source" => parse_source_file(args),
+        "set-environment" | "setenv" => parse_set_environment(args),
+        "show-environment" | "showenv" => parse_show_environment(args),
+        "bind-key" | "bind" => parse_bind_key(args),
+        "unbind-key" | "unbind" => parse_unbind_key(args),
+        "list-keys" | "lsk" => parse_list_keys(args),
+        "clear-history" | "clearhist" => parse_clear_history(args),
+        "choose-tree" => parse_choose(args, "choose-tree"),
+        "choose-buffer" => parse_choose(args, "choose-buffer"),
+        "choose-client" => parse_choose(args, "choose-client"),
+        "display-panes" | "displayp" => parse_display_panes(args),
         other => bail!("unknown tmux command: {other:?}"),
     }
 }
 
+/// Parse a command list: commands separated by `;` (or `\;`) and newlines,
+/// `{ ... }` blocks and `%if` conditionals.
+///
+/// Unlike [`parse_config`], the whole list is rejected if any command in it
+/// fails to parse, as tmux does for a client's command line.
+pub fn parse_command_list(text: &str) -> Result<CommandList> {
+    let (list, errors) = parse_config(text)?;
+    if let Some((_, error)) = errors.into_iter().next() {
+        bail!("{error}");
+    }
+    Ok(list)
+}
+
+/// Parse the contents of a config file into a [`CommandList`].
+///
+/// Commands that fail to parse (often commands the compat server does not
+/// implement) are left out of the list and returned with their line numbers,
+/// so the rest of the file can still be run. Unterminated quotes and blocks
+/// are an error for the whole file.
+pub fn parse_config(text: &str) -> Result<(CommandList, Vec<(usize, String)>)> {
+    let mut statements = split_statements(text)?.into_iter().peekable();
+    let mut errors = Vec::new();
+    let mut list = Vec::new();
+    loop {
+        let (items, terminator) = build_command_list(&mut statements, &mut errors);
+        list.extend(items);
+        match terminator {
+            Some(stmt) => errors.push((stmt.line, format!("unexpected {}", stmt.text))),
+            None => return Ok((list, errors)),
+        }
+    }
+}
+
+/// A command or `%` directive split out of a command list, with the line it
+/// starts on.
+struct Statement {
+    line: usize,
+    text: String,
+}
+
+/// Split `text` into statements at unquoted `;`, lone `\;` words and
+/// newlines. A `{ ... }` block becomes a single quoted argument holding the
+/// commands inside it, and `#` comments are dropped. Quotes and escapes are
+/// kept, for `shell_words` to handle when each statement is parsed.
+fn split_statements(text: &str) -> Result<Vec<Statement>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut line = 1;
+    let mut start_line = 1;
+    let mut word_start = true;
+    let mut i = 0;
+
+    let mut finish = |current: &mut String, start_line: usize| {
+        let text = current.trim();
+        if !text.is_empty() {
+            statements.push(Statement {
+                line: start_line,
+                text: text.to_string(),
+            });
+        }
+        current.clear();
+    };
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if current.trim().is_empty() {
+            start_line = line;
+        }
+        match ch {
+            '\n' | ';' => {
+                finish(&mut current, start_line);
+                if ch == '\n' {
+                    line += 1;
+                }
+                word_start = true;
+                i += 1;
+            }
+            '\\' if word_start
+                && chars.get(i + 1) == Some(&';')
+                && is_word_end(chars.get(i + 2)) =>
+            {
+                finish(&mut current, start_line);
+                i += 2;
+            }
+            '\\' => {
+                current.extend(chars.get(i..i + 2).unwrap_or(&chars[i..]));
+                if chars.get(i + 1) == Some(&'\n') {
+                    line += 1;
+                }
+                word_start = false;
+                i += 2;
+            }
+            '\'' | '"' => {
+                let end = closing_quote(&chars, i)?;
+                let quoted = &chars[i..=end];
+                line += quoted.iter().filter(|&&c| c == '\n').count();
+                current.extend(quoted);
+                word_start = false;
+                i = end + 1;
+            }
+            '{' if word_start && is_word_end(chars.get(i + 1)) => {
+                let end = closing_brace(&chars, i)?;
+                let inner: String = chars[i + 1..end].iter().collect();
+                line += inner.matches('\n').count();
+                current.push_str(&shell_words::quote(inner.trim()));
+                word_start = false;
+                i = end + 1;
+            }
+            // `#{` after `%if` opens the condition's format; anywhere else
+            // `#` at the start of a word begins a comment.
+            '#' if word_start && !is_condition_directive(&current) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            _ => {
+                current.push(ch);
+                word_start = ch.is_whitespace();
+                i += 1;
+            }
+        }
+    }
+    finish(&mut current, start_line);
+    Ok(statements)
+}
+
+/// Whether a word ends before `next`.
+fn is_word_end(next: Option<&char>) -> bool {
+    match next {
+        Some(c) => c.is_whitespace() || *c == ';',
+        None => true,
+    }
+}
+
+fn is_condition_directive(current: &str) -> bool {
+    let mut words = current.split_whitespace();
+    matches!(words.next(), Some("%if") | Some("%elif"))
+}
+
+/// Find the quote that closes the one at `open`.
+fn closing_quote(chars: &[char], open: usize) -> Result<usize> {
+    let quote = chars[open];
+    let mut i = open + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quote == '"' => i += 2,
+            c if c == quote => return Ok(i),
+            _ => i += 1,
+        }
+    }
+    bail!("missing closing quote: {quote}")
+}
+
+/// Find the `}` that closes the block opened at `open`, skipping quoted text
+/// and nested blocks.
+fn closing_brace(chars: &[char], open: usize) -> Result<usize> {
+    let mut depth = 0;
+    let mut word_start = true;
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                word_start = false;
+                i += 2;
+                continue;
+            }
+            '\'' | '"' => {
+                i = closing_quote(chars, i)?;
+                word_start = false;
+            }
+            '{' if word_start && is_word_end(chars.get(i + 1)) => depth += 1,
+            '}' if word_start && is_word_end(chars.get(i + 1)) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            c => word_start = c.is_whitespace() || c == ';',
+        }
+        i += 1;
+    }
+    bail!("missing }}")
+}
+
+/// Build a list from `statements`, stopping at an `%elif`, `%else` or
+/// `%endif` that belongs to the caller. That directive is returned.
+fn build_command_list(
+    statements: &mut std::iter::Peekable<std::vec::IntoIter<Statement>>,
+    errors: &mut Vec<(usize, String)>,
+) -> (CommandList, Option<Statement>) {
+    let mut list = Vec::new();
+    while let Some(stmt) = statements.next() {
+        if !stmt.text.starts_with('%') {
+            match parse_command(&stmt.text) {
+                Ok(cmd) => list.push(CommandListItem::Command(cmd)),
+                Err(e) => errors.push((stmt.line, e.to_string())),
+            }
+            continue;
+        }
+        let (directive, _) = split_directive(&stmt.text);
+        match directive {
+            "%if" => {
+                if let Some(item) = build_conditional(stmt, statements, errors) {
+                    list.push(item);
+                }
+            }
+            "%elif" | "%else" | "%endif" => return (list, Some(stmt)),
+            other => errors.push((stmt.line, format!("unknown directive: {other}"))),
+        }
+    }
+    (list, None)
+}
+
+/// Build the conditional opened by the `%if` statement `open`.
+fn build_conditional(
+    open: Statement,
+    statements: &mut std::iter::Peekable<std::vec::IntoIter<Statement>>,
+    errors: &mut Vec<(usize, String)>,
+) -> Option<CommandListItem> {
+    let mut branches = Vec::new();
+    let mut otherwise = Vec::new();
+    let mut condition = match directive_condition(&open) {
+        Ok(condition) => condition,
+        Err(e) => {
+            errors.push((open.line, e.to_string()));
+            String::new()
+        }
+    };
+    loop {
+        let (body, terminator) = build_command_list(statements, errors);
+        branches.push((condition, body));
+        let Some(stmt) = terminator else {
+            errors.push((open.line, "missing %endif".to_string()));
+            return None;
+        };
+        match split_directive(&stmt.text).0 {
+            "%elif" => match directive_condition(&stmt) {
+                Ok(next) => condition = next,
+                Err(e) => {
+                    errors.push((stmt.line, e.to_string()));
+                    condition = String::new();
+                }
+            },
+            "%else" => {
+                let (body, terminator) = build_command_list(statements, errors);
+                otherwise = body;
+                match terminator {
+                    Some(stmt) if split_directive(&stmt.text).0 == "%endif" => break,
+                    Some(stmt) => {
+                        errors.push((stmt.line, format!("unexpected {}", stmt.text)));
+                        return None;
+                    }
+                    None => {
+                        errors.push((open.line, "missing %endif".to_string()));
+                        return None;
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+    Some(CommandListItem::Conditional {
+        branches,
+        otherwise,
+    })
+}
+
+/// Split a `%` directive into its name and the rest of the line.
+fn split_directive(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// The format argument of `%if` or `%elif`, unquoted if it was quoted.
+fn directive_condition(stmt: &Statement) -> Result<String> {
+    let (directive, rest) = split_directive(&stmt.text);
+    if rest.is_empty() {
+        bail!("{directive}: missing condition");
+    }
+    if !rest.starts_with(['"', '\'']) {
+        return Ok(rest.to_string());
+    }
+    let mut words = shell_words::split(rest)?;
+    if words.len() != 1 {
+        bail!("{directive}: condition must be a single argument");
+    }
+    Ok(words.remove(0))
+}
+
 /// Helper: consume a flag's required value from the argument iterator.
 ///
 /// Returns an error if the iterator is exhausted (the flag was provided
@@ -385,6 +1071,10 @@ fn parse_send_keys(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
     let mut literal = false;
     let mut hex = false;
+    let mut mouse = false;
+    let mut reset = false;
+    let mut copy_command = false;
+    let mut repeat = None;
     let mut keys = Vec::new();
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
@@ -394,6 +1084,16 @@ fn parse_send_keys(args: &[String]) -> Result<TmuxCliCommand> {
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
             "-l" => literal = true,
             "-H" => hex = true,
+            "-M" => mouse = true,
+            "-R" => reset = true,
+            "-X" => copy_command = true,
+            "-N" => {
+                let val = take_flag_value("-N", &mut iter)?;
+                match val.parse::<u32>() {
+                    Ok(n) if n > 0 => repeat = Some(n),
+                    _ => bail!("send-keys -N: invalid repeat count: {val:?}"),
+                }
+            }
             _ => {
                 // First non-flag argument: this and everything remaining are keys.
                 keys.push(arg.to_string());
@@ -408,6 +1108,10 @@ fn parse_send_keys(args: &[String]) -> Result<TmuxCliCommand> {
         target,
         literal,
         hex,
+        mouse,
+        reset,
+        copy_command,
+        repeat,
         keys,
     })
 }
@@ -417,10 +1121,13 @@ fn parse_capture_pane(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
     let mut escape = false;
     let mut octal_escape = false;
+    let mut join = false;
+    let mut preserve_trailing = false;
     let mut start_line = None;
     let mut end_line = None;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "peCJN");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
@@ -428,19 +1135,15 @@ fn parse_capture_pane(args: &[String]) -> Result<TmuxCliCommand> {
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
             "-e" => escape = true,
             "-C" => octal_escape = true,
+            "-J" => join = true,
+            "-N" => preserve_trailing = true,
             "-S" => {
                 let val = take_flag_value("-S", &mut iter)?;
-                start_line =
-                    Some(val.parse::<i64>().map_err(|_| {
-                        anyhow::anyhow!("capture-pane -S: invalid number: {val:?}")
-                    })?);
+                start_line = Some(parse_capture_line("-S", &val, i64::MIN)?);
             }
             "-E" => {
                 let val = take_flag_value("-E", &mut iter)?;
-                end_line =
-                    Some(val.parse::<i64>().map_err(|_| {
-                        anyhow::anyhow!("capture-pane -E: invalid number: {val:?}")
-                    })?);
+                end_line = Some(parse_capture_line("-E", &val, i64::MAX)?);
             }
             other => bail!("capture-pane: unexpected argument: {other:?}"),
         }
@@ -451,11 +1154,24 @@ fn parse_capture_pane(args: &[String]) -> Result<TmuxCliCommand> {
         target,
         escape,
         octal_escape,
+        join,
+        preserve_trailing,
         start_line,
         end_line,
     })
 }
 
+/// Parse a `capture-pane -S`/`-E` line number. `-` means the start of the
+/// history or the end of the visible pane, given here as `history_end`;
+/// the handler clamps the range to the lines the pane actually has.
+fn parse_capture_line(flag: &str, val: &str, history_end: i64) -> Result<i64> {
+    if val == "-" {
+        return Ok(history_end);
+    }
+    val.parse::<i64>()
+        .map_err(|_| anyhow::anyhow!("capture-pane {flag}: invalid number: {val:?}"))
+}
+
 fn parse_list_panes(args: &[String]) -> Result<TmuxCliCommand> {
     let mut all = false;
     let mut session = false;
@@ -632,34 +1348,26 @@ fn parse_resize_pane(args: &[String]) -> Result<TmuxCliCommand> {
     let mut width = None;
     let mut height = None;
     let mut zoom = false;
+    let mut direction = None;
+    let mut adjustment = None;
+    let mut mouse = false;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "DLMRUZ");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
             "-Z" => zoom = true,
-            "-x" => {
-                let val = take_flag_value("-x", &mut iter)?;
-                // Strip trailing '%' — percentage sizes will be resolved by the handler
-                let val_clean = val.strip_suffix('%').unwrap_or(&val);
-                width = Some(
-                    val_clean
-                        .parse::<u64>()
-                        .map_err(|_| anyhow::anyhow!("resize-pane -x: invalid number: {val:?}"))?,
-                );
+            "-M" => mouse = true,
+            "-x" => width = Some(parse_resize_size("-x", &mut iter)?),
+            "-y" => height = Some(parse_resize_size("-y", &mut iter)?),
+            "-D" | "-U" | "-L" | "-R" => direction = arg.chars().nth(1),
+            other if adjustment.is_none() && !other.starts_with('-') => {
+                adjustment = Some(other.parse::<u64>().map_err(|_| {
+                    anyhow::anyhow!("resize-pane: adjustment is invalid: {other:?}")
+                })?);
             }
-            "-y" => {
-                let val = take_flag_value("-y", &mut iter)?;
-                let val_clean = val.strip_suffix('%').unwrap_or(&val);
-                height = Some(
-                    val_clean
-                        .parse::<u64>()
-                        .map_err(|_| anyhow::anyhow!("resize-pane -y: invalid number: {val:?}"))?,
-                );
-            }
-            // Flags we accept but ignore: -D/-U/-L/-R (relative resize directions)
-            "-D" | "-U" | "-L" | "-R" | "-M" => {}
             other => bail!("resize-pane: unexpected argument: {other:?}"),
         }
     }
@@ -669,15 +1377,32 @@ fn parse_resize_pane(args: &[String]) -> Result<TmuxCliCommand> {
         width,
         height,
         zoom,
+        direction,
+        adjustment,
+        mouse,
     })
 }
 
+/// Take the value of `resize-pane -x`/`-y`: a number of cells, or a
+/// percentage with a `%` suffix, which the handler resolves.
+fn parse_resize_size<'a>(flag: &str, iter: &mut impl Iterator<Item = &'a str>) -> Result<String> {
+    let val = take_flag_value(flag, iter)?;
+    let number = val.strip_suffix('%').unwrap_or(&val);
+    if number.parse::<u64>().is_err() {
+        bail!("resize-pane {flag}: invalid number: {val:?}");
+    }
+    Ok(val)
+}
+
 fn parse_resize_window(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
     let mut width = None;
     let mut height = None;
+    let mut largest = false;
+    let mut smallest = false;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "Aa");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
@@ -696,6 +1421,8 @@ fn parse_resize_window(args: &[String]) -> Result<TmuxCliCommand> {
                         anyhow::anyhow!("resize-window -y: invalid number: {val:?}")
                     })?);
             }
+            "-A" => largest = true,
+            "-a" => smallest = true,
             other => bail!("resize-window: unexpected argument: {other:?}"),
         }
     }
@@ -704,6 +1431,8 @@ fn parse_resize_window(args: &[String]) -> Result<TmuxCliCommand> {
         target,
         width,
         height,
+        largest,
+        smallest,
     })
 }
 
@@ -921,25 +1650,41 @@ fn parse_new_session(args: &[String]) -> Result<TmuxCliCommand> {
 
 fn parse_show_options(args: &[String]) -> Result<TmuxCliCommand> {
     let mut global = false;
+    let mut server = false;
+    let mut window = false;
+    let mut pane = false;
     let mut value_only = false;
     let mut quiet = false;
+    let mut inherited = false;
+    let mut target = None;
     let mut option_name = None;
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         // Parse combined flags like -gvq, -qgv, etc.
-        if arg.starts_with('-') && arg.len() > 1 && arg.chars().skip(1).all(|c| "gvqs".contains(c))
+        if arg.starts_with('-')
+            && arg.len() > 1
+            && arg.chars().skip(1).all(|c| "AgHpqsvw".contains(c))
         {
             for ch in arg.chars().skip(1) {
                 match ch {
+                    'A' => inherited = true,
                     'g' => global = true,
-                    'v' => value_only = true,
+                    'p' => pane = true,
                     'q' => quiet = true,
-                    's' => global = true, // -s (server) is equivalent to -g (global)
-                    _ => {}
+                    // Server options only exist globally.
+                    's' => {
+                        server = true;
+                        global = true;
+                    }
+                    'v' => value_only = true,
+                    'w' => window = true,
+                    _ => {} // -H (include hooks) is accepted but ignored
                 }
             }
+        } else if arg == "-t" {
+            target = Some(take_flag_value("-t", &mut iter)?);
         } else {
             option_name = Some(arg.to_string());
         }
@@ -947,8 +1692,13 @@ fn parse_show_options(args: &[String]) -> Result<TmuxCliCommand> {
 
     Ok(TmuxCliCommand::ShowOptions {
         global,
+        server,
+        window,
+        pane,
         value_only,
         quiet,
+        inherited,
+        target,
         option_name,
     })
 }
@@ -957,6 +1707,7 @@ fn parse_show_window_options(args: &[String]) -> Result<TmuxCliCommand> {
     let mut global = false;
     let mut value_only = false;
     let mut quiet = false;
+    let mut target = None;
     let mut option_name = None;
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
@@ -972,6 +1723,8 @@ fn parse_show_window_options(args: &[String]) -> Result<TmuxCliCommand> {
                     _ => {}
                 }
             }
+        } else if arg == "-t" {
+            target = Some(take_flag_value("-t", &mut iter)?);
         } else {
             option_name = Some(arg.to_string());
         }
@@ -981,6 +1734,7 @@ fn parse_show_window_options(args: &[String]) -> Result<TmuxCliCommand> {
         global,
         value_only,
         quiet,
+        target,
         option_name,
     })
 }
@@ -1020,17 +1774,19 @@ fn parse_detach_client(args: &[String]) -> Result<TmuxCliCommand> {
 
 fn parse_switch_client(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
+    let mut key_table = None;
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-T" => key_table = Some(take_flag_value("-T", &mut iter)?),
             other => bail!("switch-client: unexpected argument: {other:?}"),
         }
     }
 
-    Ok(TmuxCliCommand::SwitchClient { target })
+    Ok(TmuxCliCommand::SwitchClient { target, key_table })
 }
 
 fn parse_list_clients(args: &[String]) -> Result<TmuxCliCommand> {
@@ -1070,15 +1826,18 @@ fn parse_show_buffer(args: &[String]) -> Result<TmuxCliCommand> {
 fn parse_set_buffer(args: &[String]) -> Result<TmuxCliCommand> {
     let mut buffer_name = None;
     let mut append = false;
+    let mut clipboard = false;
     let mut data = None;
+    let args = expand_combined_flags(args, "aw");
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
             "-b" => buffer_name = Some(take_flag_value("-b", &mut iter)?),
             "-a" => append = true,
-            "-w" | "-n" | "-t" => {
-                // Accept but ignore: -w (clipboard sync), -n (rename), -t (target client)
+            "-w" => clipboard = true,
+            "-n" | "-t" => {
+                // Accept but ignore: -n (rename), -t (target client)
                 let _ = take_flag_value(arg, &mut iter).ok();
             }
             // Positional: the data to set. Take the rest as a single string if `--` was used,
@@ -1112,6 +1871,7 @@ fn parse_set_buffer(args: &[String]) -> Result<TmuxCliCommand> {
         buffer_name,
         data,
         append,
+        clipboard,
     })
 }
 
@@ -1176,13 +1936,77 @@ fn parse_paste_buffer(args: &[String]) -> Result<TmuxCliCommand> {
     })
 }
 
+fn parse_load_buffer(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut buffer_name = None;
+    let mut clipboard = false;
+    let mut path = None;
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-b" => buffer_name = Some(take_flag_value("-b", &mut iter)?),
+            "-w" => clipboard = true,
+            "-t" => {
+                // Accept but ignore: -t (target client)
+                let _ = take_flag_value("-t", &mut iter).ok();
+            }
+            // `-` alone means stdin, so it is the path rather than a flag.
+            other if other.starts_with('-') && other != "-" => {
+                bail!("load-buffer: unexpected argument: {other:?}")
+            }
+            other => path = Some(other.to_string()),
+        }
+    }
+    let path = match path {
+        Some(p) => p,
+        None => bail!("load-buffer: path is required"),
+    };
+    Ok(TmuxCliCommand::LoadBuffer {
+        buffer_name,
+        path,
+        clipboard,
+    })
+}
+
+fn parse_save_buffer(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut buffer_name = None;
+    let mut append = false;
+    let mut path = None;
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-b" => buffer_name = Some(take_flag_value("-b", &mut iter)?),
+            "-a" => append = true,
+            // `-` alone means stdout, so it is the path rather than a flag.
+            other if other.starts_with('-') && other != "-" => {
+                bail!("save-buffer: unexpected argument: {other:?}")
+            }
+            other => path = Some(other.to_string()),
+        }
+    }
+    let path = match path {
+        Some(p) => p,
+        None => bail!("save-buffer: path is required"),
+    };
+    Ok(TmuxCliCommand::SaveBuffer {
+        buffer_name,
+        path,
+        append,
+    })
+}
+
 fn parse_move_pane(args: &[String]) -> Result<TmuxCliCommand> {
     let mut src = None;
     let mut dst = None;
     let mut horizontal = false;
     let mut before = false;
+    let mut detach = false;
+    let mut full_size = false;
+    let mut size = None;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "bdfhv");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
@@ -1191,11 +2015,11 @@ fn parse_move_pane(args: &[String]) -> Result<TmuxCliCommand> {
             "-h" => horizontal = true,
             "-v" => {} // vertical is default, no-op
             "-b" => before = true,
-            "-d" | "-f" => {} // accept but ignore: -d (don't focus), -f (full size)
-            "-l" | "-p" => {
-                // Accept but ignore: -l size, -p percentage
-                let _ = take_flag_value(arg, &mut iter).ok();
-            }
+            "-d" => detach = true,
+            "-f" => full_size = true,
+            "-l" => size = Some(take_flag_value("-l", &mut iter)?),
+            // Older tmux spelling of `-l N%`
+            "-p" => size = Some(format!("{}%", take_flag_value("-p", &mut iter)?)),
             other => bail!("move-pane: unexpected argument: {other:?}"),
         }
     }
@@ -1205,42 +2029,196 @@ fn parse_move_pane(args: &[String]) -> Result<TmuxCliCommand> {
         dst,
         horizontal,
         before,
+        detach,
+        full_size,
+        size,
     })
 }
 
-fn parse_move_window(args: &[String]) -> Result<TmuxCliCommand> {
+fn parse_swap_pane(args: &[String]) -> Result<TmuxCliCommand> {
     let mut src = None;
     let mut dst = None;
+    let mut detach = false;
+    let mut up = false;
+    let mut down = false;
+    let mut keep_zoom = false;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "dDUZ");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
             "-s" => src = Some(take_flag_value("-s", &mut iter)?),
             "-t" => dst = Some(take_flag_value("-t", &mut iter)?),
-            "-a" | "-b" | "-d" | "-k" | "-r" => {} // accept but ignore
-            other => bail!("move-window: unexpected argument: {other:?}"),
+            "-d" => detach = true,
+            "-U" => up = true,
+            "-D" => down = true,
+            "-Z" => keep_zoom = true,
+            other => bail!("swap-pane: unexpected argument: {other:?}"),
         }
     }
 
-    Ok(TmuxCliCommand::MoveWindow { src, dst })
+    Ok(TmuxCliCommand::SwapPane {
+        src,
+        dst,
+        detach,
+        up,
+        down,
+        keep_zoom,
+    })
 }
 
-fn parse_copy_mode(args: &[String]) -> Result<TmuxCliCommand> {
-    let mut quit = false;
+fn parse_rotate_window(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
+    let mut down = false;
+    let mut keep_zoom = false;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "DUZ");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
-            "-q" => quit = true,
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
-            "-s" => {
-                let _ = take_flag_value("-s", &mut iter)?;
-            }
-            "-d" | "-e" | "-H" | "-M" | "-S" | "-u" => {} // accept but ignore
-            other => bail!("copy-mode: unexpected argument: {other:?}"),
+            "-D" => down = true,
+            "-U" => down = false,
+            "-Z" => keep_zoom = true,
+            other => bail!("rotate-window: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::RotateWindow {
+        target,
+        down,
+        keep_zoom,
+    })
+}
+
+fn parse_last_pane(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut keep_zoom = false;
+
+    let expanded = expand_combined_flags(args, "deZ");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-Z" => keep_zoom = true,
+            // Flags we accept but ignore: -d/-e (disable/enable input)
+            "-d" | "-e" => {}
+            other => bail!("last-pane: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::LastPane { target, keep_zoom })
+}
+
+/// Parse `last-window`, `next-window` and `previous-window`, which all take
+/// just a target session.
+fn parse_window_step(args: &[String], name: &str) -> Result<TmuxCliCommand> {
+    let mut target = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            // -a (only windows with an alert) is accepted but ignored
+            "-a" if name != "last-window" => {}
+            other => bail!("{name}: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(match name {
+        "last-window" => TmuxCliCommand::LastWindow { target },
+        "next-window" => TmuxCliCommand::NextWindow { target },
+        _ => TmuxCliCommand::PreviousWindow { target },
+    })
+}
+
+/// Parse `respawn-pane` or `respawn-window`, which take the same flags.
+fn parse_respawn(args: &[String], name: &str) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut kill = false;
+    let mut cwd = None;
+    let mut env = Vec::new();
+    let mut shell_command = None;
+
+    let expanded = expand_combined_flags(args, "k");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-k" => kill = true,
+            "-c" => cwd = Some(take_flag_value("-c", &mut iter)?),
+            "-e" => env.push(take_flag_value("-e", &mut iter)?),
+            other if other.starts_with('-') => {
+                bail!("{name}: unexpected argument: {other:?}")
+            }
+            other => {
+                // Trailing positional argument(s) = shell command
+                let mut parts = vec![other.to_string()];
+                for rest in iter.by_ref() {
+                    parts.push(rest.to_string());
+                }
+                shell_command = Some(parts.join(" "));
+            }
+        }
+    }
+
+    Ok(if name == "respawn-pane" {
+        TmuxCliCommand::RespawnPane {
+            target,
+            kill,
+            cwd,
+            env,
+            shell_command,
+        }
+    } else {
+        TmuxCliCommand::RespawnWindow {
+            target,
+            kill,
+            cwd,
+            env,
+            shell_command,
+        }
+    })
+}
+
+fn parse_move_window(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut src = None;
+    let mut dst = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-s" => src = Some(take_flag_value("-s", &mut iter)?),
+            "-t" => dst = Some(take_flag_value("-t", &mut iter)?),
+            "-a" | "-b" | "-d" | "-k" | "-r" => {} // accept but ignore
+            other => bail!("move-window: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::MoveWindow { src, dst })
+}
+
+fn parse_copy_mode(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut quit = false;
+    let mut target = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-q" => quit = true,
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-s" => {
+                let _ = take_flag_value("-s", &mut iter)?;
+            }
+            "-d" | "-e" | "-H" | "-M" | "-S" | "-u" => {} // accept but ignore
+            other => bail!("copy-mode: unexpected argument: {other:?}"),
         }
     }
 
@@ -1253,44 +2231,123 @@ fn parse_set_option(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
     let mut option_name = None;
     let mut value = None;
+    let mut global = false;
+    let mut server = false;
+    let mut window = false;
+    let mut pane = false;
+    let mut append = false;
+    let mut unset = false;
+    let mut unset_panes = false;
+    let mut only_if_unset = false;
+    let mut format = false;
+    let mut quiet = false;
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
-        match arg {
-            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
-            // Scope flags — accept but ignore (we always treat as no-op)
-            "-g" | "-s" | "-w" | "-p" | "-q" | "-o" | "-u" | "-U" | "-a" | "-F" => {}
-            _ => {
-                // First positional = option name, second = value
-                if option_name.is_none() {
-                    option_name = Some(arg.to_string());
-                } else if value.is_none() {
-                    value = Some(arg.to_string());
+        // Flags stop at the option name, so a value may start with `-`.
+        if option_name.is_none() && arg.starts_with('-') && arg.len() > 1 {
+            if arg == "-t" {
+                target = Some(take_flag_value("-t", &mut iter)?);
+                continue;
+            }
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'g' => global = true,
+                    's' => server = true,
+                    'w' => window = true,
+                    'p' => pane = true,
+                    'q' => quiet = true,
+                    'o' => only_if_unset = true,
+                    'u' => unset = true,
+                    'U' => {
+                        unset = true;
+                        unset_panes = true;
+                    }
+                    'a' => append = true,
+                    'F' => format = true,
+                    _ => bail!("set-option: unknown flag: -{ch}"),
                 }
-                // Extra positionals are silently ignored
             }
+        } else if option_name.is_none() {
+            option_name = Some(arg.to_string());
+        } else if value.is_none() {
+            value = Some(arg.to_string());
         }
+        // Extra positionals are silently ignored
     }
 
     Ok(TmuxCliCommand::SetOption {
         target,
         option_name,
         value,
+        global,
+        server,
+        window,
+        pane,
+        append,
+        unset,
+        unset_panes,
+        only_if_unset,
+        format,
+        quiet,
     })
 }
 
+/// `set-window-option` is `set-option -w`.
+fn parse_set_window_option(args: &[String]) -> Result<TmuxCliCommand> {
+    match parse_set_option(args)? {
+        TmuxCliCommand::SetOption {
+            target,
+            option_name,
+            value,
+            global,
+            server,
+            pane,
+            append,
+            unset,
+            unset_panes,
+            only_if_unset,
+            format,
+            quiet,
+            ..
+        } => Ok(TmuxCliCommand::SetOption {
+            target,
+            option_name,
+            value,
+            global,
+            server,
+            window: true,
+            pane,
+            append,
+            unset,
+            unset_panes,
+            only_if_unset,
+            format,
+            quiet,
+        }),
+        other => Ok(other),
+    }
+}
+
 fn parse_select_layout(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
     let mut layout_name = None;
+    let mut spread = false;
+    let mut next = false;
+    let mut previous = false;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "Enop");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
-            // Flags we accept but ignore
-            "-E" | "-n" | "-o" | "-p" => {}
+            "-E" => spread = true,
+            "-n" => next = true,
+            "-p" => previous = true,
+            // Flags we accept but ignore: -o (undo the last layout change)
+            "-o" => {}
             _ => {
                 // Positional argument = layout name
                 if layout_name.is_none() {
@@ -1303,6 +2360,9 @@ fn parse_select_layout(args: &[String]) -> Result<TmuxCliCommand> {
     Ok(TmuxCliCommand::SelectLayout {
         target,
         layout_name,
+        spread,
+        next,
+        previous,
     })
 }
 
@@ -1341,8 +2401,350 @@ fn parse_break_pane(args: &[String]) -> Result<TmuxCliCommand> {
 // Phase 17: missing commands for cleanup & orchestration
 // ---------------------------------------------------------------------------
 
+fn parse_start_server(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut socket_path = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-S" => socket_path = Some(take_flag_value("-S", &mut iter)?),
+            other => bail!("start-server: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::StartServer { socket_path })
+}
+
+fn parse_set_environment(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut global = false;
+    let mut unset = false;
+    let mut remove = false;
+    let mut hidden = false;
+    let mut format = false;
+    let mut name = None;
+    let mut value = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        // Flags stop at the name, so a value may start with `-`.
+        if name.is_none() && arg.starts_with('-') && arg.len() > 1 {
+            if arg == "-t" {
+                target = Some(take_flag_value("-t", &mut iter)?);
+                continue;
+            }
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'F' => format = true,
+                    'g' => global = true,
+                    'h' => hidden = true,
+                    'r' => remove = true,
+                    'u' => unset = true,
+                    _ => bail!("set-environment: unknown flag: -{ch}"),
+                }
+            }
+        } else if name.is_none() {
+            name = Some(arg.to_string());
+        } else if value.is_none() {
+            value = Some(arg.to_string());
+        } else {
+            bail!("set-environment: unexpected argument: {arg:?}");
+        }
+    }
+
+    let Some(name) = name else {
+        bail!("set-environment: missing variable name");
+    };
+    Ok(TmuxCliCommand::SetEnvironment {
+        target,
+        global,
+        unset,
+        remove,
+        hidden,
+        format,
+        name,
+        value,
+    })
+}
+
+fn parse_show_environment(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut global = false;
+    let mut hidden = false;
+    let mut shell = false;
+    let mut name = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        if arg == "-t" {
+            target = Some(take_flag_value("-t", &mut iter)?);
+        } else if arg.starts_with('-') && arg.len() > 1 && name.is_none() {
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'g' => global = true,
+                    'h' => hidden = true,
+                    's' => shell = true,
+                    _ => bail!("show-environment: unknown flag: -{ch}"),
+                }
+            }
+        } else if name.is_none() {
+            name = Some(arg.to_string());
+        } else {
+            bail!("show-environment: unexpected argument: {arg:?}");
+        }
+    }
+
+    Ok(TmuxCliCommand::ShowEnvironment {
+        target,
+        global,
+        hidden,
+        shell,
+        name,
+    })
+}
+
+fn parse_bind_key(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut table = "prefix".to_string();
+    let mut repeat = false;
+    let mut note = None;
+    let mut positional: Vec<String> = Vec::new();
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        // Flags stop at the key; the rest is the command.
+        if positional.is_empty() && arg.starts_with('-') && arg.len() > 1 {
+            match arg {
+                "-T" => table = take_flag_value("-T", &mut iter)?,
+                "-N" => note = Some(take_flag_value("-N", &mut iter)?),
+                _ => {
+                    for ch in arg.chars().skip(1) {
+                        match ch {
+                            'n' => table = "root".to_string(),
+                            'r' => repeat = true,
+                            _ => bail!("bind-key: unknown flag: -{ch}"),
+                        }
+                    }
+                }
+            }
+        } else {
+            positional.push(arg.to_string());
+        }
+    }
+
+    if positional.is_empty() {
+        bail!("bind-key: missing key");
+    }
+    let key = positional.remove(0);
+    // A command given as several words is joined back into one line.
+    let command = match positional.len() {
+        0 => None,
+        1 => positional.pop(),
+        _ => Some(shell_words::join(&positional)),
+    };
+
+    Ok(TmuxCliCommand::BindKey {
+        table,
+        repeat,
+        note,
+        key,
+        command,
+    })
+}
+
+fn parse_unbind_key(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut table = "prefix".to_string();
+    let mut all = false;
+    let mut quiet = false;
+    let mut key = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        if key.is_none() && arg.starts_with('-') && arg.len() > 1 {
+            if arg == "-T" {
+                table = take_flag_value("-T", &mut iter)?;
+                continue;
+            }
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'a' => all = true,
+                    'n' => table = "root".to_string(),
+                    'q' => quiet = true,
+                    _ => bail!("unbind-key: unknown flag: -{ch}"),
+                }
+            }
+        } else if key.is_none() {
+            key = Some(arg.to_string());
+        } else {
+            bail!("unbind-key: unexpected argument: {arg:?}");
+        }
+    }
+
+    match (all, &key) {
+        (false, None) => bail!("unbind-key: missing key"),
+        (true, Some(_)) => bail!("unbind-key: key given with -a"),
+        _ => {}
+    }
+    Ok(TmuxCliCommand::UnbindKey {
+        table,
+        all,
+        quiet,
+        key,
+    })
+}
+
+fn parse_list_keys(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut table = None;
+    let mut notes = false;
+    let mut all = false;
+    let mut prefix = None;
+    let mut key = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-T" => table = Some(take_flag_value("-T", &mut iter)?),
+            "-P" => prefix = Some(take_flag_value("-P", &mut iter)?),
+            _ if key.is_none() && arg.starts_with('-') && arg.len() > 1 => {
+                for ch in arg.chars().skip(1) {
+                    match ch {
+                        'N' => notes = true,
+                        'a' => all = true,
+                        '1' => {} // only the first match — we list them all
+                        _ => bail!("list-keys: unknown flag: -{ch}"),
+                    }
+                }
+            }
+            _ if key.is_none() => key = Some(arg.to_string()),
+            other => bail!("list-keys: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::ListKeys {
+        table,
+        notes,
+        all,
+        prefix,
+        key,
+    })
+}
+
+fn parse_clear_history(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut hyperlinks = false;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-H" => hyperlinks = true,
+            other => bail!("clear-history: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::ClearHistory { target, hyperlinks })
+}
+
+fn parse_choose(args: &[String], name: &str) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut format = None;
+    let mut filter = None;
+    let mut reverse = false;
+    let mut sessions = false;
+    let mut windows = false;
+    let mut template = None;
+
+    let tree = name == "choose-tree";
+    let expanded = expand_combined_flags(args, "GNrswZ");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-F" => format = Some(take_flag_value("-F", &mut iter)?),
+            "-f" => filter = Some(take_flag_value("-f", &mut iter)?),
+            "-r" => reverse = true,
+            "-s" if tree => sessions = true,
+            "-w" if tree => windows = true,
+            // The chooser is a plain list: there is no preview, zoom,
+            // sort order or key format to set.
+            "-N" | "-Z" => {}
+            "-G" if tree => {}
+            "-K" | "-O" => {
+                take_flag_value(arg, &mut iter)?;
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                bail!("{name}: unexpected argument: {other:?}")
+            }
+            other if template.is_none() => template = Some(other.to_string()),
+            other => bail!("{name}: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(match name {
+        "choose-tree" => TmuxCliCommand::ChooseTree {
+            target,
+            format,
+            filter,
+            reverse,
+            sessions,
+            windows,
+            template,
+        },
+        "choose-buffer" => TmuxCliCommand::ChooseBuffer {
+            target,
+            format,
+            filter,
+            reverse,
+            template,
+        },
+        _ => TmuxCliCommand::ChooseClient {
+            target,
+            format,
+            filter,
+            reverse,
+            template,
+        },
+    })
+}
+
+fn parse_display_panes(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut template = None;
+
+    let expanded = expand_combined_flags(args, "bN");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            // The labels stay until a pane is chosen or escape is pressed
+            "-b" | "-N" => {}
+            "-d" => {
+                take_flag_value("-d", &mut iter)?;
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                bail!("display-panes: unexpected argument: {other:?}")
+            }
+            other if template.is_none() => template = Some(other.to_string()),
+            other => bail!("display-panes: unexpected argument: {other:?}"),
+        }
+    }
+
+    Ok(TmuxCliCommand::DisplayPanes { target, template })
+}
+
 fn parse_wait_for(args: &[String]) -> Result<TmuxCliCommand> {
     let mut signal = false;
+    let mut lock = false;
+    let mut unlock = false;
+    let mut timeout = None;
     let mut channel = None;
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
@@ -1350,17 +2752,39 @@ fn parse_wait_for(args: &[String]) -> Result<TmuxCliCommand> {
     while let Some(arg) = iter.next() {
         match arg {
             "-S" => signal = true,
-            "-L" | "-U" => {
-                // Lock/unlock — accept but treat like signal
+            "-L" => lock = true,
+            "-U" => unlock = true,
+            "-T" => {
+                let value = take_flag_value("-T", &mut iter)?;
+                // Also rules out timeouts too long for a `Duration`.
+                if !value
+                    .parse::<f64>()
+                    .is_ok_and(|t| std::time::Duration::try_from_secs_f64(t).is_ok())
+                {
+                    bail!("wait-for: invalid timeout: {value:?}");
+                }
+                timeout = Some(value);
             }
             _ => {
                 channel = Some(arg.to_string());
             }
         }
     }
+    if [signal, lock, unlock].iter().filter(|flag| **flag).count() > 1 {
+        bail!("wait-for: only one of -L, -S and -U may be given");
+    }
 
-    let channel = channel.unwrap_or_default();
-    Ok(TmuxCliCommand::WaitFor { signal, channel })
+    let channel = match channel {
+        Some(channel) if !channel.is_empty() => channel,
+        _ => bail!("wait-for: missing channel"),
+    };
+    Ok(TmuxCliCommand::WaitFor {
+        signal,
+        lock,
+        unlock,
+        timeout,
+        channel,
+    })
 }
 
 fn parse_pipe_pane(args: &[String]) -> Result<TmuxCliCommand> {
@@ -1370,7 +2794,8 @@ fn parse_pipe_pane(args: &[String]) -> Result<TmuxCliCommand> {
     let mut input = false;
     let mut toggle = false;
 
-    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let expanded = expand_combined_flags(args, "IOo");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
@@ -1400,54 +2825,272 @@ fn parse_pipe_pane(args: &[String]) -> Result<TmuxCliCommand> {
 
 fn parse_display_popup(args: &[String]) -> Result<TmuxCliCommand> {
     let mut target = None;
+    let mut close = false;
+    let mut close_on_exit = 0u8;
+    let mut width = None;
+    let mut height = None;
+    let mut x = None;
+    let mut y = None;
+    let mut cwd = None;
+    let mut title = None;
+    let mut env = Vec::new();
+    let mut shell_command = None;
+
+    let expanded = expand_combined_flags(args, "BCEkN");
+    let strs: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-C" => close = true,
+            "-E" => close_on_exit = close_on_exit.saturating_add(1),
+            "-w" => width = Some(take_flag_value("-w", &mut iter)?),
+            "-h" => height = Some(take_flag_value("-h", &mut iter)?),
+            "-x" => x = Some(take_flag_value("-x", &mut iter)?),
+            "-y" => y = Some(take_flag_value("-y", &mut iter)?),
+            "-d" => cwd = Some(take_flag_value("-d", &mut iter)?),
+            "-T" => title = Some(take_flag_value("-T", &mut iter)?),
+            "-e" => env.push(take_flag_value("-e", &mut iter)?),
+            // Border and style flags that take a value — ignored
+            "-b" | "-c" | "-s" | "-S" => {
+                let _ = take_flag_value(arg, &mut iter)?;
+            }
+            // Boolean flags — ignored
+            "-B" | "-k" | "-N" => {}
+            other if other.starts_with('-') => {
+                bail!("display-popup: unexpected argument: {other:?}")
+            }
+            other => {
+                // Trailing positional argument(s) = shell command
+                let mut parts = vec![other.to_string()];
+                for rest in iter.by_ref() {
+                    parts.push(rest.to_string());
+                }
+                shell_command = Some(parts.join(" "));
+            }
+        }
+    }
+
+    Ok(TmuxCliCommand::DisplayPopup {
+        target,
+        close,
+        close_on_exit,
+        width,
+        height,
+        x,
+        y,
+        cwd,
+        title,
+        env,
+        shell_command,
+    })
+}
+
+fn parse_display_menu(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
         match arg {
             "-t" => target = Some(take_flag_value("-t", &mut iter)?),
-            // Flags that take a value (display-popup + display-menu union)
-            "-b" | "-c" | "-d" | "-e" | "-h" | "-w" | "-x" | "-y" | "-s" | "-S" | "-T" | "-H" => {
+            // Flags that take a value
+            "-b" | "-c" | "-C" | "-H" | "-s" | "-S" | "-T" | "-x" | "-y" => {
                 let _ = take_flag_value(arg, &mut iter)?;
             }
             // Boolean flags (no value)
-            "-B" | "-C" | "-E" | "-k" | "-M" | "-N" | "-O" => {}
+            "-M" | "-O" => {}
             _ => {
-                // Remaining args are the popup/menu command — ignore
+                // Remaining args are the menu items — ignore
             }
         }
     }
 
-    Ok(TmuxCliCommand::DisplayPopup { target })
+    Ok(TmuxCliCommand::DisplayMenu { target })
 }
 
 fn parse_run_shell(args: &[String]) -> Result<TmuxCliCommand> {
     let mut background = false;
     let mut target = None;
-    let mut command = None;
-    let mut delay = None;
+    let mut command = None;
+    let mut delay = None;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-b" => background = true,
+            "-C" => {} // tmux command mode — ignore
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            "-d" => {
+                delay = Some(take_flag_value("-d", &mut iter)?);
+            }
+            _ => {
+                command = Some(arg.to_string());
+            }
+        }
+    }
+
+    Ok(TmuxCliCommand::RunShell {
+        background,
+        target,
+        command,
+        delay,
+    })
+}
+
+fn parse_set_hook(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut global = false;
+    let mut append = false;
+    let mut unset = false;
+    let mut run = false;
+    let mut positional: Vec<String> = Vec::new();
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        // Flags stop at the hook name; the rest is the command.
+        if positional.is_empty() && arg.starts_with('-') && arg.len() > 1 {
+            if arg == "-t" {
+                target = Some(take_flag_value("-t", &mut iter)?);
+                continue;
+            }
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'g' => global = true,
+                    'a' => append = true,
+                    'u' => unset = true,
+                    'R' => run = true,
+                    _ => bail!("set-hook: unknown flag: -{ch}"),
+                }
+            }
+        } else {
+            positional.push(arg.to_string());
+        }
+    }
+
+    if positional.is_empty() {
+        bail!("set-hook: missing hook name");
+    }
+    let hook_name = positional.remove(0);
+    // A command given as several words is joined back into one line.
+    let command = match positional.len() {
+        0 => None,
+        1 => positional.pop(),
+        _ => Some(shell_words::join(&positional)),
+    };
+    if command.is_none() && !unset && !run {
+        bail!("set-hook: missing command");
+    }
+
+    Ok(TmuxCliCommand::SetHook {
+        target,
+        global,
+        append,
+        unset,
+        run,
+        hook_name,
+        command,
+    })
+}
+
+fn parse_show_hooks(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut target = None;
+    let mut global = false;
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-g" => global = true,
+            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
+            _ => {} // -w/-p and hook names are accepted but ignored
+        }
+    }
+
+    Ok(TmuxCliCommand::ShowHooks { target, global })
+}
+
+fn parse_if_shell(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut background = false;
+    let mut format = false;
+    let mut target = None;
+    let mut positional: Vec<String> = Vec::new();
+
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut iter = strs.iter().copied();
+    while let Some(arg) = iter.next() {
+        if positional.is_empty() && arg.starts_with('-') && arg.len() > 1 {
+            if arg == "-t" {
+                target = Some(take_flag_value("-t", &mut iter)?);
+                continue;
+            }
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'b' => background = true,
+                    'F' => format = true,
+                    _ => bail!("if-shell: unknown flag: -{ch}"),
+                }
+            }
+        } else {
+            positional.push(arg.to_string());
+        }
+    }
+
+    if positional.len() < 2 || positional.len() > 3 {
+        bail!("if-shell: usage: if-shell [-bF] [-t target-pane] shell-command command [command]");
+    }
+    let mut positional = positional.into_iter();
+    Ok(TmuxCliCommand::IfShell {
+        background,
+        format,
+        target,
+        condition: positional.next().unwrap_or_default(),
+        then_command: positional.next().unwrap_or_default(),
+        else_command: positional.next(),
+    })
+}
+
+fn parse_source_file(args: &[String]) -> Result<TmuxCliCommand> {
+    let mut format = false;
+    let mut parse_only = false;
+    let mut quiet = false;
+    let mut target = None;
+    let mut paths = Vec::new();
 
     let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     let mut iter = strs.iter().copied();
     while let Some(arg) = iter.next() {
-        match arg {
-            "-b" => background = true,
-            "-C" => {} // tmux command mode — ignore
-            "-t" => target = Some(take_flag_value("-t", &mut iter)?),
-            "-d" => {
-                delay = Some(take_flag_value("-d", &mut iter)?);
+        if paths.is_empty() && arg.starts_with('-') && arg.len() > 1 {
+            if arg == "-t" {
+                target = Some(take_flag_value("-t", &mut iter)?);
+                continue;
             }
-            _ => {
-                command = Some(arg.to_string());
+            for ch in arg.chars().skip(1) {
+                match ch {
+                    'F' => format = true,
+                    'n' => parse_only = true,
+                    'q' => quiet = true,
+                    'v' => {} // verbose — ignore
+                    _ => bail!("source-file: unknown flag: -{ch}"),
+                }
             }
+        } else {
+            paths.push(arg.to_string());
         }
     }
 
-    Ok(TmuxCliCommand::RunShell {
-        background,
+    if paths.is_empty() {
+        bail!("source-file: missing path");
+    }
+
+    Ok(TmuxCliCommand::SourceFile {
+        format,
+        parse_only,
+        quiet,
         target,
-        command,
-        delay,
+        paths,
     })
 }
 
@@ -1544,6 +3187,10 @@ mod tests {
                 target: Some("$0:@0.%1".into()),
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["echo hello".into(), "Enter".into()],
             }
         );
@@ -1557,6 +3204,10 @@ mod tests {
                 target: Some("%5".into()),
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["0x68".into(), "0x69".into(), "0xA".into()],
             }
         );
@@ -1570,6 +3221,10 @@ mod tests {
                 target: Some("%5".into()),
                 literal: false,
                 hex: true,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["68".into(), "69".into(), "0A".into()],
             }
         );
@@ -1583,6 +3238,10 @@ mod tests {
                 target: Some("%1".into()),
                 literal: true,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["hello".into()],
             }
         );
@@ -1596,11 +3255,68 @@ mod tests {
                 target: None,
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["Enter".into()],
             }
         );
     }
 
+    #[test]
+    fn send_keys_repeat_reset_and_mouse() {
+        assert_eq!(
+            parse("send-keys -R -N 3 -t %2 Up"),
+            TmuxCliCommand::SendKeys {
+                target: Some("%2".into()),
+                literal: false,
+                hex: false,
+                mouse: false,
+                reset: true,
+                copy_command: false,
+                repeat: Some(3),
+                keys: vec!["Up".into()],
+            }
+        );
+        assert_eq!(
+            parse("send-keys -M -t %2 '<0;4;2M'"),
+            TmuxCliCommand::SendKeys {
+                target: Some("%2".into()),
+                literal: false,
+                hex: false,
+                mouse: true,
+                reset: false,
+                copy_command: false,
+                repeat: None,
+                keys: vec!["<0;4;2M".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn send_keys_copy_command() {
+        assert_eq!(
+            parse("send-keys -X -N 2 -t %1 search-backward foo"),
+            TmuxCliCommand::SendKeys {
+                target: Some("%1".into()),
+                literal: false,
+                hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: true,
+                repeat: Some(2),
+                keys: vec!["search-backward".into(), "foo".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn send_keys_rejects_invalid_repeat() {
+        assert!(parse_command("send-keys -N 0 Up").is_err());
+        assert!(parse_command("send-keys -N many Up").is_err());
+    }
+
     // ---------------------------------------------------------------
     // capture-pane
     // ---------------------------------------------------------------
@@ -1614,6 +3330,8 @@ mod tests {
                 target: Some("%1".into()),
                 escape: false,
                 octal_escape: false,
+                join: false,
+                preserve_trailing: false,
                 start_line: None,
                 end_line: None,
             }
@@ -1629,6 +3347,8 @@ mod tests {
                 target: Some("%1".into()),
                 escape: true,
                 octal_escape: true,
+                join: false,
+                preserve_trailing: false,
                 start_line: Some(-32768),
                 end_line: None,
             }
@@ -1644,12 +3364,66 @@ mod tests {
                 target: None,
                 escape: false,
                 octal_escape: false,
+                join: false,
+                preserve_trailing: false,
                 start_line: Some(0),
                 end_line: Some(100),
             }
         );
     }
 
+    #[test]
+    fn capture_pane_join_and_preserve_trailing() {
+        assert_eq!(
+            parse("capture-pane -p -J -N -t %2"),
+            TmuxCliCommand::CapturePane {
+                print: true,
+                target: Some("%2".into()),
+                escape: false,
+                octal_escape: false,
+                join: true,
+                preserve_trailing: true,
+                start_line: None,
+                end_line: None,
+            }
+        );
+    }
+
+    #[test]
+    fn capture_pane_combined_flags() {
+        assert_eq!(
+            parse("capture-pane -peJ -S -10"),
+            TmuxCliCommand::CapturePane {
+                print: true,
+                target: None,
+                escape: true,
+                octal_escape: false,
+                join: true,
+                preserve_trailing: false,
+                start_line: Some(-10),
+                end_line: None,
+            }
+        );
+    }
+
+    #[test]
+    fn capture_pane_whole_history() {
+        assert_eq!(
+            parse("capture-pane -p -S - -E -"),
+            TmuxCliCommand::CapturePane {
+                print: true,
+                target: None,
+                escape: false,
+                octal_escape: false,
+                join: false,
+                preserve_trailing: false,
+                start_line: Some(i64::MIN),
+                end_line: Some(i64::MAX),
+            }
+        );
+        assert!(parse_command("capture-pane -p -S top").is_err());
+    }
+
     // ---------------------------------------------------------------
     // list-panes
     // ---------------------------------------------------------------
@@ -1833,9 +3607,12 @@ mod tests {
             parse("resize-pane -t %1 -x 80 -y 24"),
             TmuxCliCommand::ResizePane {
                 target: Some("%1".into()),
-                width: Some(80),
-                height: Some(24),
+                width: Some("80".into()),
+                height: Some("24".into()),
                 zoom: false,
+                direction: None,
+                adjustment: None,
+                mouse: false,
             }
         );
     }
@@ -1846,9 +3623,12 @@ mod tests {
             parse("resize-pane -x 120"),
             TmuxCliCommand::ResizePane {
                 target: None,
-                width: Some(120),
+                width: Some("120".into()),
                 height: None,
                 zoom: false,
+                direction: None,
+                adjustment: None,
+                mouse: false,
             }
         );
     }
@@ -1865,6 +3645,8 @@ mod tests {
                 target: Some("@1".into()),
                 width: Some(200),
                 height: Some(50),
+                largest: false,
+                smallest: false,
             }
         );
     }
@@ -2115,6 +3897,10 @@ mod tests {
                 target: None,
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["ls -la".into(), "Enter".into()],
             }
         );
@@ -2133,6 +3919,9 @@ mod tests {
                 width: None,
                 height: None,
                 zoom: true,
+                direction: None,
+                adjustment: None,
+                mouse: false,
             }
         );
     }
@@ -2146,6 +3935,9 @@ mod tests {
                 width: None,
                 height: None,
                 zoom: true,
+                direction: None,
+                adjustment: None,
+                mouse: false,
             }
         );
     }
@@ -2159,6 +3951,9 @@ mod tests {
                 width: None,
                 height: None,
                 zoom: true,
+                direction: None,
+                adjustment: None,
+                mouse: false,
             }
         );
     }
@@ -2363,8 +4158,13 @@ mod tests {
             parse("show-options -gv default-terminal"),
             TmuxCliCommand::ShowOptions {
                 global: true,
+                server: false,
+                window: false,
+                pane: false,
                 value_only: true,
                 quiet: false,
+                inherited: false,
+                target: None,
                 option_name: Some("default-terminal".into()),
             }
         );
@@ -2376,8 +4176,13 @@ mod tests {
             parse("show-options -g"),
             TmuxCliCommand::ShowOptions {
                 global: true,
+                server: false,
+                window: false,
+                pane: false,
                 value_only: false,
                 quiet: false,
+                inherited: false,
+                target: None,
                 option_name: None,
             }
         );
@@ -2389,8 +4194,13 @@ mod tests {
             parse("show -gv escape-time"),
             TmuxCliCommand::ShowOptions {
                 global: true,
+                server: false,
+                window: false,
+                pane: false,
                 value_only: true,
                 quiet: false,
+                inherited: false,
+                target: None,
                 option_name: Some("escape-time".into()),
             }
         );
@@ -2402,8 +4212,13 @@ mod tests {
             parse("show-options -g -v set-clipboard"),
             TmuxCliCommand::ShowOptions {
                 global: true,
+                server: false,
+                window: false,
+                pane: false,
                 value_only: true,
                 quiet: false,
+                inherited: false,
+                target: None,
                 option_name: Some("set-clipboard".into()),
             }
         );
@@ -2415,8 +4230,13 @@ mod tests {
             parse("show-options -gqv nonexistent"),
             TmuxCliCommand::ShowOptions {
                 global: true,
+                server: false,
+                window: false,
+                pane: false,
                 value_only: true,
                 quiet: true,
+                inherited: false,
+                target: None,
                 option_name: Some("nonexistent".into()),
             }
         );
@@ -2424,13 +4244,18 @@ mod tests {
 
     #[test]
     fn show_options_server_flag() {
-        // -s is equivalent to -g (server scope = global)
+        // -s selects server options, which are always global
         assert_eq!(
             parse("show-options -sv default-terminal"),
             TmuxCliCommand::ShowOptions {
                 global: true,
+                server: true,
+                window: false,
+                pane: false,
                 value_only: true,
                 quiet: false,
+                inherited: false,
+                target: None,
                 option_name: Some("default-terminal".into()),
             }
         );
@@ -2440,6 +4265,24 @@ mod tests {
     // show-window-options
     // ---------------------------------------------------------------
 
+    #[test]
+    fn show_options_scope_flags_and_target() {
+        assert_eq!(
+            parse("show-options -Apv -t %3 @color"),
+            TmuxCliCommand::ShowOptions {
+                global: false,
+                server: false,
+                window: false,
+                pane: true,
+                value_only: true,
+                quiet: false,
+                inherited: true,
+                target: Some("%3".into()),
+                option_name: Some("@color".into()),
+            }
+        );
+    }
+
     #[test]
     fn show_window_options_global_value() {
         assert_eq!(
@@ -2448,6 +4291,7 @@ mod tests {
                 global: true,
                 value_only: true,
                 quiet: false,
+                target: None,
                 option_name: Some("aggressive-resize".into()),
             }
         );
@@ -2461,6 +4305,7 @@ mod tests {
                 global: true,
                 value_only: true,
                 quiet: false,
+                target: None,
                 option_name: Some("aggressive-resize".into()),
             }
         );
@@ -2474,6 +4319,7 @@ mod tests {
                 global: false,
                 value_only: false,
                 quiet: false,
+                target: None,
                 option_name: None,
             }
         );
@@ -2487,6 +4333,7 @@ mod tests {
                 global: true,
                 value_only: true,
                 quiet: true,
+                target: None,
                 option_name: Some("nonexistent".into()),
             }
         );
@@ -2561,6 +4408,7 @@ mod tests {
             parse("switch-client -t work"),
             TmuxCliCommand::SwitchClient {
                 target: Some("work".into()),
+                key_table: None,
             }
         );
     }
@@ -2569,7 +4417,10 @@ mod tests {
     fn switch_client_no_args() {
         assert_eq!(
             parse("switch-client"),
-            TmuxCliCommand::SwitchClient { target: None }
+            TmuxCliCommand::SwitchClient {
+                target: None,
+                key_table: None,
+            }
         );
     }
 
@@ -2579,6 +4430,18 @@ mod tests {
             parse("switchc -t $0"),
             TmuxCliCommand::SwitchClient {
                 target: Some("$0".into()),
+                key_table: None,
+            }
+        );
+    }
+
+    #[test]
+    fn switch_client_key_table() {
+        assert_eq!(
+            parse("switch-client -T resize"),
+            TmuxCliCommand::SwitchClient {
+                target: None,
+                key_table: Some("resize".into()),
             }
         );
     }
@@ -2632,6 +4495,81 @@ mod tests {
         );
     }
 
+    // ---------------------------------------------------------------
+    // set-buffer / load-buffer / save-buffer
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn set_buffer_clipboard_flag() {
+        assert_eq!(
+            parse("set-buffer -w -b mybuf hello"),
+            TmuxCliCommand::SetBuffer {
+                buffer_name: Some("mybuf".into()),
+                data: Some("hello".into()),
+                append: false,
+                clipboard: true,
+            }
+        );
+        assert_eq!(
+            parse("setb -aw -b mybuf more"),
+            TmuxCliCommand::SetBuffer {
+                buffer_name: Some("mybuf".into()),
+                data: Some("more".into()),
+                append: true,
+                clipboard: true,
+            }
+        );
+    }
+
+    #[test]
+    fn load_buffer_file() {
+        assert_eq!(
+            parse("load-buffer -w -b notes /tmp/notes.txt"),
+            TmuxCliCommand::LoadBuffer {
+                buffer_name: Some("notes".into()),
+                path: "/tmp/notes.txt".into(),
+                clipboard: true,
+            }
+        );
+    }
+
+    #[test]
+    fn load_buffer_stdin() {
+        assert_eq!(
+            parse("loadb -"),
+            TmuxCliCommand::LoadBuffer {
+                buffer_name: None,
+                path: "-".into(),
+                clipboard: false,
+            }
+        );
+    }
+
+    #[test]
+    fn load_buffer_requires_path() {
+        assert!(parse_command("load-buffer -b notes").is_err());
+    }
+
+    #[test]
+    fn save_buffer_file_and_stdout() {
+        assert_eq!(
+            parse("save-buffer -a -b notes /tmp/out.txt"),
+            TmuxCliCommand::SaveBuffer {
+                buffer_name: Some("notes".into()),
+                path: "/tmp/out.txt".into(),
+                append: true,
+            }
+        );
+        assert_eq!(
+            parse("saveb -"),
+            TmuxCliCommand::SaveBuffer {
+                buffer_name: None,
+                path: "-".into(),
+                append: false,
+            }
+        );
+    }
+
     // ---------------------------------------------------------------
     // move-pane / join-pane
     // ---------------------------------------------------------------
@@ -2645,6 +4583,9 @@ mod tests {
                 dst: Some("%1".into()),
                 horizontal: false,
                 before: false,
+                detach: false,
+                full_size: false,
+                size: None,
             }
         );
     }
@@ -2658,6 +4599,9 @@ mod tests {
                 dst: Some("%1".into()),
                 horizontal: true,
                 before: true,
+                detach: false,
+                full_size: false,
+                size: None,
             }
         );
     }
@@ -2671,6 +4615,9 @@ mod tests {
                 dst: Some("%5".into()),
                 horizontal: false,
                 before: false,
+                detach: false,
+                full_size: false,
+                size: None,
             }
         );
     }
@@ -2684,6 +4631,9 @@ mod tests {
                 dst: Some("%2".into()),
                 horizontal: true,
                 before: false,
+                detach: false,
+                full_size: false,
+                size: None,
             }
         );
     }
@@ -2698,8 +4648,171 @@ mod tests {
                 dst: Some("%1".into()),
                 horizontal: true,
                 before: false,
+                detach: false,
+                full_size: false,
+                size: None,
+            }
+        );
+    }
+
+    #[test]
+    fn join_pane_size_and_flags() {
+        assert_eq!(
+            parse("join-pane -dfh -l 30% -s %3 -t %5"),
+            TmuxCliCommand::MovePane {
+                src: Some("%3".into()),
+                dst: Some("%5".into()),
+                horizontal: true,
+                before: false,
+                detach: true,
+                full_size: true,
+                size: Some("30%".into()),
+            }
+        );
+        match parse("joinp -p 25 -s %1") {
+            TmuxCliCommand::MovePane { size, .. } => assert_eq!(size.as_deref(), Some("25%")),
+            other => panic!("expected MovePane, got {:?}", other),
+        }
+    }
+
+    // ---------------------------------------------------------------
+    // swap-pane / rotate-window / last-pane / window stepping
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn swap_pane_flags() {
+        assert_eq!(
+            parse("swap-pane -s %1 -t %2"),
+            TmuxCliCommand::SwapPane {
+                src: Some("%1".into()),
+                dst: Some("%2".into()),
+                detach: false,
+                up: false,
+                down: false,
+                keep_zoom: false,
+            }
+        );
+        assert_eq!(
+            parse("swapp -dDZ -t %4"),
+            TmuxCliCommand::SwapPane {
+                src: None,
+                dst: Some("%4".into()),
+                detach: true,
+                up: false,
+                down: true,
+                keep_zoom: true,
+            }
+        );
+        assert!(parse_command("swap-pane -x").is_err());
+    }
+
+    #[test]
+    fn rotate_window_direction() {
+        assert_eq!(
+            parse("rotate-window"),
+            TmuxCliCommand::RotateWindow {
+                target: None,
+                down: false,
+                keep_zoom: false,
+            }
+        );
+        assert_eq!(
+            parse("rotatew -D -Z -t @1"),
+            TmuxCliCommand::RotateWindow {
+                target: Some("@1".into()),
+                down: true,
+                keep_zoom: true,
+            }
+        );
+        assert_eq!(
+            parse("rotatew -D -U"),
+            TmuxCliCommand::RotateWindow {
+                target: None,
+                down: false,
+                keep_zoom: false,
+            }
+        );
+    }
+
+    #[test]
+    fn last_pane_flags() {
+        assert_eq!(
+            parse("lastp -Z -t @2"),
+            TmuxCliCommand::LastPane {
+                target: Some("@2".into()),
+                keep_zoom: true,
+            }
+        );
+        assert_eq!(
+            parse("last-pane -d"),
+            TmuxCliCommand::LastPane {
+                target: None,
+                keep_zoom: false,
+            }
+        );
+    }
+
+    #[test]
+    fn window_stepping_commands() {
+        assert_eq!(
+            parse("last-window -t main"),
+            TmuxCliCommand::LastWindow {
+                target: Some("main".into()),
+            }
+        );
+        assert_eq!(
+            parse("next -a"),
+            TmuxCliCommand::NextWindow { target: None }
+        );
+        assert_eq!(
+            parse("previous-window"),
+            TmuxCliCommand::PreviousWindow { target: None }
+        );
+        assert!(parse_command("last-window -a").is_err());
+        assert_eq!(parse("prev").name(), "previous-window");
+    }
+
+    // ---------------------------------------------------------------
+    // respawn-pane / respawn-window
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn respawn_pane_with_command() {
+        assert_eq!(
+            parse("respawn-pane -k -t %3 -c /tmp -e FOO=1 'npm run worker' --verbose"),
+            TmuxCliCommand::RespawnPane {
+                target: Some("%3".into()),
+                kill: true,
+                cwd: Some("/tmp".into()),
+                env: vec!["FOO=1".into()],
+                shell_command: Some("npm run worker --verbose".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn respawn_aliases() {
+        assert_eq!(
+            parse("respawnp"),
+            TmuxCliCommand::RespawnPane {
+                target: None,
+                kill: false,
+                cwd: None,
+                env: vec![],
+                shell_command: None,
+            }
+        );
+        assert_eq!(
+            parse("respawnw -k -t @2"),
+            TmuxCliCommand::RespawnWindow {
+                target: Some("@2".into()),
+                kill: true,
+                cwd: None,
+                env: vec![],
+                shell_command: None,
             }
         );
+        assert!(parse_command("respawn-window -x").is_err());
     }
 
     // ---------------------------------------------------------------
@@ -2933,6 +5046,16 @@ mod tests {
                 target: Some("%5".into()),
                 option_name: Some("pane-border-style".into()),
                 value: Some("fg=blue".into()),
+                global: false,
+                server: false,
+                window: false,
+                pane: true,
+                append: false,
+                unset: false,
+                unset_panes: false,
+                only_if_unset: false,
+                format: false,
+                quiet: false,
             }
         );
     }
@@ -2945,10 +5068,94 @@ mod tests {
                 target: None,
                 option_name: Some("status".into()),
                 value: Some("off".into()),
+                global: true,
+                server: false,
+                window: false,
+                pane: false,
+                append: false,
+                unset: false,
+                unset_panes: false,
+                only_if_unset: false,
+                format: false,
+                quiet: false,
+            }
+        );
+    }
+
+    #[test]
+    fn set_option_combined_flags() {
+        assert_eq!(
+            parse("set -gqoF @theme '#{session_name}'"),
+            TmuxCliCommand::SetOption {
+                target: None,
+                option_name: Some("@theme".into()),
+                value: Some("#{session_name}".into()),
+                global: true,
+                server: false,
+                window: false,
+                pane: false,
+                append: false,
+                unset: false,
+                unset_panes: false,
+                only_if_unset: true,
+                format: true,
+                quiet: true,
             }
         );
     }
 
+    #[test]
+    fn set_option_unset_and_append() {
+        match parse("set-option -U -t @1 remain-on-exit") {
+            TmuxCliCommand::SetOption {
+                unset, unset_panes, ..
+            } => assert!(unset && unset_panes),
+            other => panic!("unexpected: {:?}", other),
+        }
+        match parse("set -a -t main @list ,more") {
+            TmuxCliCommand::SetOption {
+                append,
+                target,
+                value,
+                ..
+            } => {
+                assert!(append);
+                assert_eq!(target.as_deref(), Some("main"));
+                assert_eq!(value.as_deref(), Some(",more"));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_option_value_may_start_with_dash() {
+        match parse("set -g @offset -5") {
+            TmuxCliCommand::SetOption { value, .. } => assert_eq!(value.as_deref(), Some("-5")),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_window_option_alias() {
+        match parse("setw -g mode-keys vi") {
+            TmuxCliCommand::SetOption {
+                window,
+                global,
+                option_name,
+                ..
+            } => {
+                assert!(window && global);
+                assert_eq!(option_name.as_deref(), Some("mode-keys"));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_option_unknown_flag() {
+        assert!(parse_command("set -x status off").is_err());
+    }
+
     #[test]
     fn select_layout_main_vertical() {
         assert_eq!(
@@ -2956,6 +5163,9 @@ mod tests {
             TmuxCliCommand::SelectLayout {
                 target: Some("@0".into()),
                 layout_name: Some("main-vertical".into()),
+                spread: false,
+                next: false,
+                previous: false,
             }
         );
     }
@@ -2967,6 +5177,46 @@ mod tests {
             TmuxCliCommand::SelectLayout {
                 target: None,
                 layout_name: Some("tiled".into()),
+                spread: false,
+                next: false,
+                previous: false,
+            }
+        );
+    }
+
+    #[test]
+    fn select_layout_flags() {
+        assert_eq!(
+            parse("select-layout -E -t %3"),
+            TmuxCliCommand::SelectLayout {
+                target: Some("%3".into()),
+                layout_name: None,
+                spread: true,
+                next: false,
+                previous: false,
+            }
+        );
+        match parse("selectl -n") {
+            TmuxCliCommand::SelectLayout { next, previous, .. } => assert!(next && !previous),
+            other => panic!("unexpected: {:?}", other),
+        }
+        match parse("selectl -p") {
+            TmuxCliCommand::SelectLayout { next, previous, .. } => assert!(!next && previous),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn select_layout_string() {
+        // Layout strings contain commas and brackets but are one argument.
+        assert_eq!(
+            parse("select-layout 'bb62,159x48,0,0{79x48,0,0,0,79x48,80,0,1}'"),
+            TmuxCliCommand::SelectLayout {
+                target: None,
+                layout_name: Some("bb62,159x48,0,0{79x48,0,0,0,79x48,80,0,1}".into()),
+                spread: false,
+                next: false,
+                previous: false,
             }
         );
     }
@@ -2978,6 +5228,9 @@ mod tests {
             TmuxCliCommand::SelectLayout {
                 target: Some("@0".into()),
                 layout_name: Some("even-horizontal".into()),
+                spread: false,
+                next: false,
+                previous: false,
             }
         );
     }
@@ -3013,9 +5266,12 @@ mod tests {
             parse("resize-pane -t %5 -x 30%"),
             TmuxCliCommand::ResizePane {
                 target: Some("%5".into()),
-                width: Some(30),
+                width: Some("30%".into()),
                 height: None,
                 zoom: false,
+                direction: None,
+                adjustment: None,
+                mouse: false,
             }
         );
     }
@@ -3027,10 +5283,65 @@ mod tests {
             TmuxCliCommand::ResizePane {
                 target: Some("%5".into()),
                 width: None,
-                height: Some(50),
+                height: Some("50%".into()),
+                zoom: false,
+                direction: None,
+                adjustment: None,
+                mouse: false,
+            }
+        );
+    }
+
+    #[test]
+    fn resize_pane_directions() {
+        assert_eq!(
+            parse("resize-pane -L -t %2 5"),
+            TmuxCliCommand::ResizePane {
+                target: Some("%2".into()),
+                width: None,
+                height: None,
                 zoom: false,
+                direction: Some('L'),
+                adjustment: Some(5),
+                mouse: false,
+            }
+        );
+        assert_eq!(
+            parse("resizep -ZD"),
+            TmuxCliCommand::ResizePane {
+                target: None,
+                width: None,
+                height: None,
+                zoom: true,
+                direction: Some('D'),
+                adjustment: None,
+                mouse: false,
+            }
+        );
+        assert!(matches!(
+            parse("resize-pane -M"),
+            TmuxCliCommand::ResizePane { mouse: true, .. }
+        ));
+        assert!(parse_command("resize-pane -U x").is_err());
+        assert!(parse_command("resize-pane -U 1 2").is_err());
+    }
+
+    #[test]
+    fn resize_window_to_clients() {
+        assert_eq!(
+            parse("resize-window -A -t @1"),
+            TmuxCliCommand::ResizeWindow {
+                target: Some("@1".into()),
+                width: None,
+                height: None,
+                largest: true,
+                smallest: false,
             }
         );
+        assert!(matches!(
+            parse("resizew -a"),
+            TmuxCliCommand::ResizeWindow { smallest: true, .. }
+        ));
     }
 
     // ---------------------------------------------------------------
@@ -3110,131 +5421,396 @@ mod tests {
     }
 
     #[test]
-    fn alias_selectw_for_select_window() {
+    fn alias_selectw_for_select_window() {
+        assert_eq!(
+            parse("selectw -t @1"),
+            TmuxCliCommand::SelectWindow {
+                target: Some("@1".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn alias_selectp_for_select_pane() {
+        assert_eq!(
+            parse("selectp -t %0"),
+            TmuxCliCommand::SelectPane {
+                target: Some("%0".into()),
+                style: None,
+                title: None,
+            }
+        );
+    }
+
+    #[test]
+    fn alias_killp_for_kill_pane() {
+        assert_eq!(
+            parse("killp -t %1"),
+            TmuxCliCommand::KillPane {
+                target: Some("%1".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn alias_capturep_for_capture_pane() {
+        assert_eq!(
+            parse("capturep -p -t %0"),
+            TmuxCliCommand::CapturePane {
+                print: true,
+                target: Some("%0".into()),
+                escape: false,
+                octal_escape: false,
+                join: false,
+                preserve_trailing: false,
+                start_line: None,
+                end_line: None,
+            }
+        );
+    }
+
+    #[test]
+    fn alias_send_for_send_keys() {
+        assert_eq!(
+            parse("send -t %0 -l hello"),
+            TmuxCliCommand::SendKeys {
+                target: Some("%0".into()),
+                literal: true,
+                hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
+                keys: vec!["hello".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn alias_display_for_display_message() {
+        assert_eq!(
+            parse("display -p '#{pane_id}'"),
+            TmuxCliCommand::DisplayMessage {
+                print: true,
+                verbose: false,
+                format: Some("#{pane_id}".into()),
+                target: None,
+            }
+        );
+    }
+
+    #[test]
+    fn alias_has_for_has_session() {
+        assert_eq!(
+            parse("has -t main"),
+            TmuxCliCommand::HasSession {
+                target: Some("main".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn alias_kills_for_kill_session() {
+        assert_eq!(
+            parse("kills -t old"),
+            TmuxCliCommand::KillSession {
+                target: Some("old".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn alias_resizew_for_resize_window() {
+        assert_eq!(
+            parse("resizew -t @0 -x 120 -y 40"),
+            TmuxCliCommand::ResizeWindow {
+                target: Some("@0".into()),
+                width: Some(120),
+                height: Some(40),
+                largest: false,
+                smallest: false,
+            }
+        );
+    }
+
+    #[test]
+    fn alias_refresh_for_refresh_client() {
+        // "refresh" is alias for "refresh-client"
+        assert_eq!(
+            parse("refresh -C 200x50"),
+            TmuxCliCommand::RefreshClient {
+                size: Some("200x50".into()),
+                flags: None,
+                adjust_pane: None,
+                subscription: None,
+            }
+        );
+    }
+
+    // ---------------------------------------------------------------
+    // Phase 17: parser tests for missing commands
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn phase17_kill_server() {
+        assert_eq!(parse("kill-server"), TmuxCliCommand::KillServer);
+    }
+
+    #[test]
+    fn start_server() {
+        assert_eq!(
+            parse("start-server"),
+            TmuxCliCommand::StartServer { socket_path: None }
+        );
+        assert_eq!(
+            parse("start -S /tmp/tmux-1000/agents"),
+            TmuxCliCommand::StartServer {
+                socket_path: Some("/tmp/tmux-1000/agents".into()),
+            }
+        );
+        assert!(parse_command("start-server -L agents").is_err());
+    }
+
+    #[test]
+    fn set_environment() {
+        assert_eq!(
+            parse("setenv -g API_URL http://localhost:8080"),
+            TmuxCliCommand::SetEnvironment {
+                target: None,
+                global: true,
+                unset: false,
+                remove: false,
+                hidden: false,
+                format: false,
+                name: "API_URL".into(),
+                value: Some("http://localhost:8080".into()),
+            }
+        );
+        assert_eq!(
+            parse("set-environment -t work -hF TOKEN -#{pane_id}"),
+            TmuxCliCommand::SetEnvironment {
+                target: Some("work".into()),
+                global: false,
+                unset: false,
+                remove: false,
+                hidden: true,
+                format: true,
+                name: "TOKEN".into(),
+                value: Some("-#{pane_id}".into()),
+            }
+        );
+        assert!(matches!(
+            parse("set-environment -ru DISPLAY"),
+            TmuxCliCommand::SetEnvironment {
+                unset: true,
+                remove: true,
+                value: None,
+                ..
+            }
+        ));
+        assert!(parse_command("set-environment -g").is_err());
+        assert!(parse_command("set-environment -x NAME").is_err());
+    }
+
+    #[test]
+    fn show_environment() {
+        assert_eq!(
+            parse("showenv -gs"),
+            TmuxCliCommand::ShowEnvironment {
+                target: None,
+                global: true,
+                hidden: false,
+                shell: true,
+                name: None,
+            }
+        );
+        assert_eq!(
+            parse("show-environment -h -t work TOKEN"),
+            TmuxCliCommand::ShowEnvironment {
+                target: Some("work".into()),
+                global: false,
+                hidden: true,
+                shell: false,
+                name: Some("TOKEN".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn bind_key() {
+        assert_eq!(
+            parse("bind-key -T prefix | split-window -h"),
+            TmuxCliCommand::BindKey {
+                table: "prefix".into(),
+                repeat: false,
+                note: None,
+                key: "|".into(),
+                command: Some("split-window -h".into()),
+            }
+        );
+        assert_eq!(
+            parse("bind -nr -N 'Resize left' M-h resize-pane -L 5"),
+            TmuxCliCommand::BindKey {
+                table: "root".into(),
+                repeat: true,
+                note: Some("Resize left".into()),
+                key: "M-h".into(),
+                command: Some("resize-pane -L 5".into()),
+            }
+        );
+        assert_eq!(
+            parse("bind - split-window -v -c '#{pane_current_path}'"),
+            TmuxCliCommand::BindKey {
+                table: "prefix".into(),
+                repeat: false,
+                note: None,
+                key: "-".into(),
+                command: Some("split-window -v -c '#{pane_current_path}'".into()),
+            }
+        );
+        assert!(parse_command("bind-key -T prefix").is_err());
+        assert!(parse_command("bind-key -x a kill-pane").is_err());
+    }
+
+    #[test]
+    fn unbind_key() {
         assert_eq!(
-            parse("selectw -t @1"),
-            TmuxCliCommand::SelectWindow {
-                target: Some("@1".into()),
+            parse("unbind C-b"),
+            TmuxCliCommand::UnbindKey {
+                table: "prefix".into(),
+                all: false,
+                quiet: false,
+                key: Some("C-b".into()),
             }
         );
-    }
-
-    #[test]
-    fn alias_selectp_for_select_pane() {
         assert_eq!(
-            parse("selectp -t %0"),
-            TmuxCliCommand::SelectPane {
-                target: Some("%0".into()),
-                style: None,
-                title: None,
+            parse("unbind-key -aq -T copy-mode"),
+            TmuxCliCommand::UnbindKey {
+                table: "copy-mode".into(),
+                all: true,
+                quiet: true,
+                key: None,
             }
         );
+        assert!(parse_command("unbind-key").is_err());
+        assert!(parse_command("unbind-key -a C-b").is_err());
     }
 
     #[test]
-    fn alias_killp_for_kill_pane() {
+    fn list_keys() {
         assert_eq!(
-            parse("killp -t %1"),
-            TmuxCliCommand::KillPane {
-                target: Some("%1".into()),
+            parse("lsk -T prefix"),
+            TmuxCliCommand::ListKeys {
+                table: Some("prefix".into()),
+                notes: false,
+                all: false,
+                prefix: None,
+                key: None,
             }
         );
-    }
-
-    #[test]
-    fn alias_capturep_for_capture_pane() {
         assert_eq!(
-            parse("capturep -p -t %0"),
-            TmuxCliCommand::CapturePane {
-                print: true,
-                target: Some("%0".into()),
-                escape: false,
-                octal_escape: false,
-                start_line: None,
-                end_line: None,
+            parse("list-keys -Na -P '> ' C-o"),
+            TmuxCliCommand::ListKeys {
+                table: None,
+                notes: true,
+                all: true,
+                prefix: Some("> ".into()),
+                key: Some("C-o".into()),
             }
         );
     }
 
     #[test]
-    fn alias_send_for_send_keys() {
+    fn clear_history() {
         assert_eq!(
-            parse("send -t %0 -l hello"),
-            TmuxCliCommand::SendKeys {
-                target: Some("%0".into()),
-                literal: true,
-                hex: false,
-                keys: vec!["hello".into()],
+            parse("clear-history -H -t %3"),
+            TmuxCliCommand::ClearHistory {
+                target: Some("%3".into()),
+                hyperlinks: true,
             }
         );
-    }
-
-    #[test]
-    fn alias_display_for_display_message() {
         assert_eq!(
-            parse("display -p '#{pane_id}'"),
-            TmuxCliCommand::DisplayMessage {
-                print: true,
-                verbose: false,
-                format: Some("#{pane_id}".into()),
+            parse("clearhist"),
+            TmuxCliCommand::ClearHistory {
                 target: None,
+                hyperlinks: false,
             }
         );
+        assert!(parse_command("clear-history -x").is_err());
     }
 
     #[test]
-    fn alias_has_for_has_session() {
+    fn choose_tree() {
         assert_eq!(
-            parse("has -t main"),
-            TmuxCliCommand::HasSession {
-                target: Some("main".into()),
+            parse("choose-tree -Zw 'swap-pane -t %%'"),
+            TmuxCliCommand::ChooseTree {
+                target: None,
+                format: None,
+                filter: None,
+                reverse: false,
+                sessions: false,
+                windows: true,
+                template: Some("swap-pane -t %%".into()),
             }
         );
-    }
-
-    #[test]
-    fn alias_kills_for_kill_session() {
         assert_eq!(
-            parse("kills -t old"),
-            TmuxCliCommand::KillSession {
-                target: Some("old".into()),
+            parse("choose-tree -t %1 -s -r -O name -F '#{session_name}' -f '#{pane_active}'"),
+            TmuxCliCommand::ChooseTree {
+                target: Some("%1".into()),
+                format: Some("#{session_name}".into()),
+                filter: Some("#{pane_active}".into()),
+                reverse: true,
+                sessions: true,
+                windows: false,
+                template: None,
             }
         );
+        assert!(parse_command("choose-tree a b").is_err());
+        assert!(parse_command("choose-tree -x").is_err());
     }
 
     #[test]
-    fn alias_resizew_for_resize_window() {
+    fn choose_buffer_and_client() {
         assert_eq!(
-            parse("resizew -t @0 -x 120 -y 40"),
-            TmuxCliCommand::ResizeWindow {
-                target: Some("@0".into()),
-                width: Some(120),
-                height: Some(40),
+            parse("choose-buffer -N -F '#{buffer_size}' 'paste-buffer -b %%'"),
+            TmuxCliCommand::ChooseBuffer {
+                target: None,
+                format: Some("#{buffer_size}".into()),
+                filter: None,
+                reverse: false,
+                template: Some("paste-buffer -b %%".into()),
             }
         );
-    }
-
-    #[test]
-    fn alias_refresh_for_refresh_client() {
-        // "refresh" is alias for "refresh-client"
         assert_eq!(
-            parse("refresh -C 200x50"),
-            TmuxCliCommand::RefreshClient {
-                size: Some("200x50".into()),
-                flags: None,
-                adjust_pane: None,
-                subscription: None,
+            parse("choose-client -r -t %2"),
+            TmuxCliCommand::ChooseClient {
+                target: Some("%2".into()),
+                format: None,
+                filter: None,
+                reverse: true,
+                template: None,
             }
         );
+        assert!(parse_command("choose-buffer -s").is_err());
     }
 
-    // ---------------------------------------------------------------
-    // Phase 17: parser tests for missing commands
-    // ---------------------------------------------------------------
-
     #[test]
-    fn phase17_kill_server() {
-        assert_eq!(parse("kill-server"), TmuxCliCommand::KillServer);
+    fn display_panes() {
+        assert_eq!(
+            parse("display-panes -b -d 500 -t %1 'kill-pane -t %%'"),
+            TmuxCliCommand::DisplayPanes {
+                target: Some("%1".into()),
+                template: Some("kill-pane -t %%".into()),
+            }
+        );
+        assert_eq!(
+            parse("displayp"),
+            TmuxCliCommand::DisplayPanes {
+                target: None,
+                template: None,
+            }
+        );
+        assert!(parse_command("display-panes -d").is_err());
     }
 
     #[test]
@@ -3243,6 +5819,9 @@ mod tests {
             parse("wait-for -S mychannel"),
             TmuxCliCommand::WaitFor {
                 signal: true,
+                lock: false,
+                unlock: false,
+                timeout: None,
                 channel: "mychannel".into(),
             }
         );
@@ -3254,17 +5833,59 @@ mod tests {
             parse("wait-for -L lockname"),
             TmuxCliCommand::WaitFor {
                 signal: false,
+                lock: true,
+                unlock: false,
+                timeout: None,
+                channel: "lockname".into(),
+            }
+        );
+        assert_eq!(
+            parse("wait-for -U lockname"),
+            TmuxCliCommand::WaitFor {
+                signal: false,
+                lock: false,
+                unlock: true,
+                timeout: None,
                 channel: "lockname".into(),
             }
         );
     }
 
+    #[test]
+    fn wait_for_timeout() {
+        assert_eq!(
+            parse("wait-for -L -T 2.5 build"),
+            TmuxCliCommand::WaitFor {
+                signal: false,
+                lock: true,
+                unlock: false,
+                timeout: Some("2.5".into()),
+                channel: "build".into(),
+            }
+        );
+        assert!(parse_command("wait-for -T soon build").is_err());
+        assert!(parse_command("wait-for -T -1 build").is_err());
+        assert!(parse_command("wait-for -T 1e300 build").is_err());
+        assert!(parse_command("wait-for -T inf build").is_err());
+        assert!(parse_command("wait-for -L -U build").is_err());
+    }
+
+    #[test]
+    fn wait_for_needs_a_channel() {
+        assert!(parse_command("wait-for").is_err());
+        assert!(parse_command("wait-for -S").is_err());
+        assert!(parse_command("wait-for -S ''").is_err());
+    }
+
     #[test]
     fn phase17_wait_alias() {
         assert_eq!(
             parse("wait -S done"),
             TmuxCliCommand::WaitFor {
                 signal: true,
+                lock: false,
+                unlock: false,
+                timeout: None,
                 channel: "done".into(),
             }
         );
@@ -3340,12 +5961,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pipe_pane_combined_flags() {
+        assert_eq!(
+            parse("pipe-pane -IOo 'cat >> /tmp/log'"),
+            TmuxCliCommand::PipePane {
+                target: None,
+                command: Some("cat >> /tmp/log".into()),
+                output: true,
+                input: true,
+                toggle: true,
+            }
+        );
+    }
+
     #[test]
     fn phase17_display_popup_basic() {
         assert_eq!(
             parse("display-popup -t %0"),
             TmuxCliCommand::DisplayPopup {
                 target: Some("%0".into()),
+                close: false,
+                close_on_exit: 0,
+                width: None,
+                height: None,
+                x: None,
+                y: None,
+                cwd: None,
+                title: None,
+                env: vec![],
+                shell_command: None,
             }
         );
     }
@@ -3354,7 +5999,19 @@ mod tests {
     fn phase17_popup_alias() {
         assert_eq!(
             parse("popup -E ls"),
-            TmuxCliCommand::DisplayPopup { target: None }
+            TmuxCliCommand::DisplayPopup {
+                target: None,
+                close: false,
+                close_on_exit: 1,
+                width: None,
+                height: None,
+                x: None,
+                y: None,
+                cwd: None,
+                title: None,
+                env: vec![],
+                shell_command: Some("ls".into()),
+            }
         );
     }
 
@@ -3364,15 +6021,61 @@ mod tests {
             parse("display-popup -w 80 -h 24 -d /tmp -t %2 echo hello"),
             TmuxCliCommand::DisplayPopup {
                 target: Some("%2".into()),
+                close: false,
+                close_on_exit: 0,
+                width: Some("80".into()),
+                height: Some("24".into()),
+                x: None,
+                y: None,
+                cwd: Some("/tmp".into()),
+                title: None,
+                env: vec![],
+                shell_command: Some("echo hello".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn display_popup_position_title_and_env() {
+        assert_eq!(
+            parse("display-popup -EE -x C -y 10 -w 50% -T files -e FOO=1 -b rounded fzf"),
+            TmuxCliCommand::DisplayPopup {
+                target: None,
+                close: false,
+                close_on_exit: 2,
+                width: Some("50%".into()),
+                height: None,
+                x: Some("C".into()),
+                y: Some("10".into()),
+                cwd: None,
+                title: Some("files".into()),
+                env: vec!["FOO=1".into()],
+                shell_command: Some("fzf".into()),
             }
         );
     }
 
+    #[test]
+    fn display_popup_close() {
+        match parse("display-popup -C -t @1") {
+            TmuxCliCommand::DisplayPopup { target, close, .. } => {
+                assert_eq!(target.as_deref(), Some("@1"));
+                assert!(close);
+            }
+            other => panic!("expected DisplayPopup, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn display_popup_rejects_unknown_flag() {
+        assert!(parse_command("display-popup -Q").is_err());
+    }
+
     #[test]
     fn phase17_display_menu_basic() {
         assert_eq!(
             parse("display-menu -t %1 -T title -x 10 -y 5"),
-            TmuxCliCommand::DisplayPopup {
+            TmuxCliCommand::DisplayMenu {
                 target: Some("%1".into()),
             }
         );
@@ -3382,19 +6085,19 @@ mod tests {
     fn phase17_menu_alias() {
         assert_eq!(
             parse("menu -T test -x 0 -y 0"),
-            TmuxCliCommand::DisplayPopup { target: None }
+            TmuxCliCommand::DisplayMenu { target: None }
         );
     }
 
     #[test]
     fn phase17_display_popup_boolean_b_flag() {
         // -B should be boolean (no value), not consume the next arg
-        assert_eq!(
-            parse("display-popup -B -t %0"),
-            TmuxCliCommand::DisplayPopup {
-                target: Some("%0".into()),
+        match parse("display-popup -B -t %0") {
+            TmuxCliCommand::DisplayPopup { target, .. } => {
+                assert_eq!(target.as_deref(), Some("%0"));
             }
-        );
+            other => panic!("expected DisplayPopup, got {:?}", other),
+        }
     }
 
     #[test]
@@ -3491,6 +6194,10 @@ mod tests {
                 target: Some("%3".into()),
                 literal: true,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["echo $HOME done".into()],
             }
         );
@@ -3505,6 +6212,10 @@ mod tests {
                 target: Some("%5".into()),
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec![
                     "cd /path && env CLAUDECODE=1 claude --agent".into(),
                     "C-m".into(),
@@ -3522,6 +6233,10 @@ mod tests {
                 target: Some("%0".into()),
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["C-c".into()],
             }
         );
@@ -3535,6 +6250,10 @@ mod tests {
                 target: Some("%1".into()),
                 literal: false,
                 hex: false,
+                mouse: false,
+                reset: false,
+                copy_command: false,
+                repeat: None,
                 keys: vec!["Escape".into(), "[A".into()],
             }
         );
@@ -3779,6 +6498,247 @@ mod tests {
         assert!(parse_command("split-window -tP").is_err());
     }
 
+    // ---------------------------------------------------------------
+    // Hooks
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn set_hook_global() {
+        assert_eq!(
+            parse("set-hook -g pane-exited 'run-shell \"echo #{hook_pane}\"'"),
+            TmuxCliCommand::SetHook {
+                target: None,
+                global: true,
+                append: false,
+                unset: false,
+                run: false,
+                hook_name: "pane-exited".into(),
+                command: Some("run-shell \"echo #{hook_pane}\"".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn set_hook_command_words_are_joined() {
+        assert_eq!(
+            parse("set-hook -ag -t work after-split-window display-message 'new pane'"),
+            TmuxCliCommand::SetHook {
+                target: Some("work".into()),
+                global: true,
+                append: true,
+                unset: false,
+                run: false,
+                hook_name: "after-split-window".into(),
+                command: Some("display-message 'new pane'".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn set_hook_unset() {
+        assert_eq!(
+            parse("set-hook -gu client-attached"),
+            TmuxCliCommand::SetHook {
+                target: None,
+                global: true,
+                append: false,
+                unset: true,
+                run: false,
+                hook_name: "client-attached".into(),
+                command: None,
+            }
+        );
+    }
+
+    #[test]
+    fn set_hook_requires_name_and_command() {
+        assert!(parse_command("set-hook -g").is_err());
+        assert!(parse_command("set-hook -g pane-exited").is_err());
+        assert!(parse_command("set-hook -x pane-exited true").is_err());
+    }
+
+    #[test]
+    fn show_hooks() {
+        assert_eq!(
+            parse("show-hooks -g"),
+            TmuxCliCommand::ShowHooks {
+                target: None,
+                global: true,
+            }
+        );
+        assert_eq!(
+            parse("show-hooks -t work"),
+            TmuxCliCommand::ShowHooks {
+                target: Some("work".into()),
+                global: false,
+            }
+        );
+    }
+
+    #[test]
+    fn command_name_is_canonical() {
+        assert_eq!(parse("splitw -h").name(), "split-window");
+        assert_eq!(parse("joinp -s %1 -t %2").name(), "move-pane");
+        assert_eq!(parse("setw -g mode-keys vi").name(), "set-option");
+        assert_eq!(parse("set-hook -gu pane-exited").name(), "set-hook");
+    }
+
+    // ---------------------------------------------------------------
+    // Command lists, if-shell and source-file
+    // ---------------------------------------------------------------
+
+    fn commands(list: &[CommandListItem]) -> Vec<TmuxCliCommand> {
+        list.iter()
+            .map(|item| match item {
+                CommandListItem::Command(cmd) => cmd.clone(),
+                other => panic!("unexpected: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn command_list_separators() {
+        let expected = vec![TmuxCliCommand::ListCommands, TmuxCliCommand::KillServer];
+        for text in [
+            "list-commands ; kill-server",
+            "list-commands \\; kill-server",
+            "list-commands; kill-server",
+            "list-commands\nkill-server\n",
+        ] {
+            let list = parse_command_list(text).unwrap();
+            assert_eq!(commands(&list), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn command_list_quoted_semicolon_is_literal() {
+        let list = parse_command_list("display-message -p 'a;b' ; kill-server").unwrap();
+        let cmds = commands(&list);
+        assert_eq!(cmds.len(), 2);
+        match &cmds[0] {
+            TmuxCliCommand::DisplayMessage { format, .. } => {
+                assert_eq!(format.as_deref(), Some("a;b"));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_list_rejects_bad_command() {
+        assert!(parse_command_list("list-commands ; not-a-command").is_err());
+        assert!(parse_command_list("display-message 'unterminated").is_err());
+    }
+
+    #[test]
+    fn command_list_braces_become_arguments() {
+        let list = parse_command_list("if-shell -F 1 { kill-pane ; kill-window } { kill-server }")
+            .unwrap();
+        match &commands(&list)[0] {
+            TmuxCliCommand::IfShell {
+                then_command,
+                else_command,
+                ..
+            } => {
+                assert_eq!(then_command, "kill-pane ; kill-window");
+                assert_eq!(else_command.as_deref(), Some("kill-server"));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_list_nested_braces() {
+        let list = parse_command_list("if -F 1 { if -F 0 { kill-pane } {kill-window} }").unwrap();
+        match &commands(&list)[0] {
+            TmuxCliCommand::IfShell { then_command, .. } => {
+                assert_eq!(then_command, "if -F 0 { kill-pane } {kill-window}");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(parse_command_list("if -F 1 { kill-pane").is_err());
+    }
+
+    #[test]
+    fn config_conditionals() {
+        let text = "\
+%if '#{pane_dead}'
+kill-pane
+%elif #{==:a,b}
+kill-window
+%else
+kill-server
+%endif
+list-commands
+";
+        let (list, errors) = parse_config(text).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            list,
+            vec![
+                CommandListItem::Conditional {
+                    branches: vec![
+                        (
+                            "#{pane_dead}".to_string(),
+                            vec![CommandListItem::Command(TmuxCliCommand::KillPane {
+                                target: None
+                            })]
+                        ),
+                        (
+                            "#{==:a,b}".to_string(),
+                            vec![CommandListItem::Command(TmuxCliCommand::KillWindow {
+                                target: None
+                            })]
+                        ),
+                    ],
+                    otherwise: vec![CommandListItem::Command(TmuxCliCommand::KillServer)],
+                },
+                CommandListItem::Command(TmuxCliCommand::ListCommands),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_reports_bad_lines() {
+        let text = "# a comment\nlist-commands\nclock-mode\n%endif\n%if 1\nkill-server\n";
+        let (list, errors) = parse_config(text).unwrap();
+        assert_eq!(commands(&list), vec![TmuxCliCommand::ListCommands]);
+        let lines: Vec<usize> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+        assert!(errors[2].1.contains("missing %endif"));
+    }
+
+    #[test]
+    fn if_shell_flags() {
+        assert_eq!(
+            parse("if -bF -t %1 '#{pane_dead}' 'kill-pane' 'send-keys q'"),
+            TmuxCliCommand::IfShell {
+                background: true,
+                format: true,
+                target: Some("%1".into()),
+                condition: "#{pane_dead}".into(),
+                then_command: "kill-pane".into(),
+                else_command: Some("send-keys q".into()),
+            }
+        );
+        assert!(parse_command("if-shell true").is_err());
+        assert!(parse_command("if-shell -x true kill-pane").is_err());
+    }
+
+    #[test]
+    fn source_file_flags() {
+        assert_eq!(
+            parse("source -nq ~/.tmux.conf extra.conf"),
+            TmuxCliCommand::SourceFile {
+                format: false,
+                parse_only: true,
+                quiet: true,
+                target: None,
+                paths: vec!["~/.tmux.conf".into(), "extra.conf".into()],
+            }
+        );
+        assert!(parse_command("source-file").is_err());
+    }
+
     // ---------------------------------------------------------------
     // Shell command positional argument tests
     // ---------------------------------------------------------------
@@ -0,0 +1,1173 @@
+//! Copy mode for `copy-mode` and `send-keys -X`.
+//!
+//! tmux scripts drive copy mode with commands such as `send-keys -X
+//! begin-selection` and `send-keys -X copy-selection`. The GUI's copy
+//! overlay belongs to a window, and there may be no window showing the pane
+//! at all, so copy mode here is kept by the server: each pane in copy mode
+//! has a cursor, a scroll position, a selection and a search, over a
+//! snapshot of the pane's text taken when it entered copy mode. As in tmux,
+//! output that arrives while the pane is in copy mode isn't seen until copy
+//! mode is entered again; taking the snapshot once also spares each command
+//! from copying the whole scrollback.
+//!
+//! Where tmux has a command that the overlay also has, it maps onto the
+//! overlay's [`CopyModeAssignment`], so the two share a vocabulary. The
+//! rest (scrolling by lines, rectangles, copying) are tmux's own. A GUI
+//! window showing the pane opens its overlay as well, and is sent the
+//! [`overlay_actions`] of each command, so that the user sees the cursor
+//! and selection move.
+//!
+//! The copy modes of all servers are kept in one map, returned by
+//! [`copy_modes`]; it is keyed by pane, and a pane belongs to one server.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::LazyLock;
+
+use config::keyassignment::{CopyModeAssignment, SelectionMode};
+use ordered_float::NotNan;
+use parking_lot::{Mutex, MutexGuard};
+use wezterm_term::{SemanticType, StableRowIndex};
+
+use crate::pane::{Pane, PaneId};
+
+/// Copy mode for every pane that is in copy mode.
+static COPY_MODES: LazyLock<Mutex<HashMap<PaneId, PaneCopyMode>>> = LazyLock::new(Default::default);
+
+/// Lock the copy modes of every server's panes, keyed by pane.
+pub fn copy_modes() -> MutexGuard<'static, HashMap<PaneId, PaneCopyMode>> {
+    COPY_MODES.lock()
+}
+
+/// A pane in copy mode.
+#[derive(Debug)]
+pub struct PaneCopyMode {
+    /// The text of the pane when it entered copy mode.
+    pub screen: Screen,
+    pub state: CopyModeState,
+}
+
+impl PaneCopyMode {
+    /// Enter copy mode in `pane`, with the cursor where the pane's cursor
+    /// is.
+    pub fn enter(pane: &dyn Pane) -> Self {
+        let screen = Screen::from_pane(pane);
+        let cursor = pane.get_cursor_position();
+        let state = CopyModeState::new(&screen, cursor.x, cursor.y);
+        Self { screen, state }
+    }
+}
+
+/// The text of a pane, as copy mode sees it.
+#[derive(Debug, Clone, Default)]
+pub struct Screen {
+    /// Stable index of the first row in `rows`.
+    pub first_row: StableRowIndex,
+    /// The scrollback and screen, one string per row, without trailing
+    /// blanks.
+    pub rows: Vec<String>,
+    /// Whether each row continues on the next one.
+    pub wrapped: Vec<bool>,
+    /// Stable index of the top of the screen.
+    pub physical_top: StableRowIndex,
+    pub width: usize,
+    pub height: usize,
+    /// Rows on which a shell prompt starts.
+    pub prompts: Vec<StableRowIndex>,
+}
+
+impl Screen {
+    /// Take a snapshot of the text of a pane.
+    pub fn from_pane(pane: &dyn Pane) -> Self {
+        let dims = pane.get_dimensions();
+        let end = dims.physical_top + dims.viewport_rows as StableRowIndex;
+        let (first_row, lines) = pane.get_lines(dims.scrollback_top..end);
+        let prompts = pane
+            .get_semantic_zones()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|zone| zone.semantic_type == SemanticType::Prompt)
+            .map(|zone| zone.start_y)
+            .collect();
+        Self {
+            first_row,
+            rows: lines
+                .iter()
+                .map(|line| line.as_str().trim_end().to_string())
+                .collect(),
+            wrapped: lines
+                .iter()
+                .map(|line| line.last_cell_was_wrapped())
+                .collect(),
+            physical_top: dims.physical_top,
+            width: dims.cols,
+            height: dims.viewport_rows,
+            prompts,
+        }
+    }
+
+    fn last_row(&self) -> StableRowIndex {
+        self.first_row + (self.rows.len() as StableRowIndex - 1).max(0)
+    }
+
+    fn row(&self, y: StableRowIndex) -> &str {
+        usize::try_from(y - self.first_row)
+            .ok()
+            .and_then(|index| self.rows.get(index))
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+
+    fn row_len(&self, y: StableRowIndex) -> usize {
+        self.row(y).chars().count()
+    }
+
+    fn char_at(&self, (x, y): Pos) -> char {
+        self.row(y).chars().nth(x).unwrap_or(' ')
+    }
+
+    fn is_wrapped(&self, y: StableRowIndex) -> bool {
+        usize::try_from(y - self.first_row)
+            .ok()
+            .and_then(|index| self.wrapped.get(index))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// The columns in `y` from `from` up to and including `to`.
+    fn columns(&self, y: StableRowIndex, from: usize, to: usize) -> String {
+        self.row(y)
+            .chars()
+            .skip(from)
+            .take((to + 1).saturating_sub(from))
+            .collect()
+    }
+
+    /// The next character position, moving on to the next row at the end
+    /// of a row.
+    fn next_pos(&self, (x, y): Pos) -> Option<Pos> {
+        if x + 1 < self.row_len(y) {
+            Some((x + 1, y))
+        } else if y < self.last_row() {
+            Some((0, y + 1))
+        } else {
+            None
+        }
+    }
+
+    /// The previous character position, moving back to the end of the
+    /// previous row at the start of a row.
+    fn prev_pos(&self, (x, y): Pos) -> Option<Pos> {
+        if x > 0 {
+            Some(((x - 1).min(self.row_len(y).saturating_sub(1)), y))
+        } else if y > self.first_row {
+            Some((self.row_len(y - 1).saturating_sub(1), y - 1))
+        } else {
+            None
+        }
+    }
+
+    fn class_at(&self, pos: Pos, big: bool) -> CharClass {
+        let c = self.char_at(pos);
+        if c.is_whitespace() {
+            CharClass::Blank
+        } else if big || c.is_alphanumeric() || c == '_' {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+/// A column and stable row.
+type Pos = (usize, StableRowIndex);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Search {
+    pattern: String,
+    forward: bool,
+    regex: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Jump {
+    target: char,
+    forward: bool,
+    prev_char: bool,
+}
+
+/// What to copy, for the copy commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CopySource {
+    Selection,
+    Line,
+    EndOfLine,
+}
+
+/// A tmux copy mode command.
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    /// One of the copy overlay's actions.
+    Action(CopyModeAssignment),
+    ScrollUp,
+    ScrollDown,
+    HalfPageUp,
+    HalfPageDown,
+    NextSpace,
+    NextSpaceEnd,
+    PreviousSpace,
+    SelectWord,
+    Rectangle(Option<bool>),
+    Search {
+        forward: bool,
+        regex: bool,
+    },
+    SearchAgain,
+    SearchReverse,
+    Copy {
+        source: CopySource,
+        pipe: bool,
+        append: bool,
+        clear: bool,
+        cancel: bool,
+    },
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        use CopyModeAssignment as A;
+
+        let copy = |source, pipe, append, clear, cancel| Command::Copy {
+            source,
+            pipe,
+            append,
+            clear,
+            cancel,
+        };
+        Some(match name {
+            "cursor-up" => Self::Action(A::MoveUp),
+            "cursor-down" => Self::Action(A::MoveDown),
+            "cursor-left" => Self::Action(A::MoveLeft),
+            "cursor-right" => Self::Action(A::MoveRight),
+            "start-of-line" => Self::Action(A::MoveToStartOfLine),
+            "back-to-indentation" => Self::Action(A::MoveToStartOfLineContent),
+            "end-of-line" => Self::Action(A::MoveToEndOfLineContent),
+            "next-word" => Self::Action(A::MoveForwardWord),
+            "next-word-end" => Self::Action(A::MoveForwardWordEnd),
+            "previous-word" => Self::Action(A::MoveBackwardWord),
+            "next-space" => Self::NextSpace,
+            "next-space-end" => Self::NextSpaceEnd,
+            "previous-space" => Self::PreviousSpace,
+            "top-line" => Self::Action(A::MoveToViewportTop),
+            "middle-line" => Self::Action(A::MoveToViewportMiddle),
+            "bottom-line" => Self::Action(A::MoveToViewportBottom),
+            "history-top" => Self::Action(A::MoveToScrollbackTop),
+            "history-bottom" => Self::Action(A::MoveToScrollbackBottom),
+            "page-up" => Self::Action(A::PageUp),
+            "page-down" => Self::Action(A::PageDown),
+            "halfpage-up" => Self::HalfPageUp,
+            "halfpage-down" => Self::HalfPageDown,
+            "scroll-up" => Self::ScrollUp,
+            "scroll-down" => Self::ScrollDown,
+            "previous-prompt" => Self::Action(A::MoveBackwardZoneOfType(SemanticType::Prompt)),
+            "next-prompt" => Self::Action(A::MoveForwardZoneOfType(SemanticType::Prompt)),
+            "begin-selection" => Self::Action(A::SetSelectionMode(Some(SelectionMode::Cell))),
+            "select-line" => Self::Action(A::SetSelectionMode(Some(SelectionMode::Line))),
+            "select-word" => Self::SelectWord,
+            "clear-selection" => Self::Action(A::ClearSelectionMode),
+            "other-end" => Self::Action(A::MoveToSelectionOtherEnd),
+            "rectangle-toggle" => Self::Rectangle(None),
+            "rectangle-on" => Self::Rectangle(Some(true)),
+            "rectangle-off" => Self::Rectangle(Some(false)),
+            "jump-forward" => Self::Action(A::JumpForward { prev_char: false }),
+            "jump-backward" => Self::Action(A::JumpBackward { prev_char: false }),
+            "jump-to-forward" => Self::Action(A::JumpForward { prev_char: true }),
+            "jump-to-backward" => Self::Action(A::JumpBackward { prev_char: true }),
+            "jump-again" => Self::Action(A::JumpAgain),
+            "jump-reverse" => Self::Action(A::JumpReverse),
+            "search-forward" | "search-forward-incremental" => Self::Search {
+                forward: true,
+                regex: true,
+            },
+            "search-backward" | "search-backward-incremental" => Self::Search {
+                forward: false,
+                regex: true,
+            },
+            "search-forward-text" => Self::Search {
+                forward: true,
+                regex: false,
+            },
+            "search-backward-text" => Self::Search {
+                forward: false,
+                regex: false,
+            },
+            "search-again" => Self::SearchAgain,
+            "search-reverse" => Self::SearchReverse,
+            "cancel" => Self::Action(A::Close),
+            "copy-selection" => copy(CopySource::Selection, false, false, true, false),
+            "copy-selection-no-clear" => copy(CopySource::Selection, false, false, false, false),
+            "copy-selection-and-cancel" => copy(CopySource::Selection, false, false, true, true),
+            "copy-pipe" => copy(CopySource::Selection, true, false, true, false),
+            "copy-pipe-no-clear" => copy(CopySource::Selection, true, false, false, false),
+            "copy-pipe-and-cancel" => copy(CopySource::Selection, true, false, true, true),
+            "append-selection" => copy(CopySource::Selection, false, true, true, false),
+            "append-selection-and-cancel" => copy(CopySource::Selection, false, true, true, true),
+            "copy-line" => copy(CopySource::Line, false, false, true, false),
+            "copy-line-and-cancel" => copy(CopySource::Line, false, false, true, true),
+            "copy-end-of-line" => copy(CopySource::EndOfLine, false, false, true, false),
+            "copy-end-of-line-and-cancel" => copy(CopySource::EndOfLine, false, false, true, true),
+            _ => return None,
+        })
+    }
+}
+
+/// What the caller has to do after a copy mode command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing; the state has been updated.
+    Done,
+    /// Copy mode should be left.
+    Cancel,
+    /// Text was copied. It goes into a new paste buffer, or onto the end
+    /// of the most recent one with `append`, and is piped to `pipe`; an
+    /// empty `pipe` means the `copy-command` option.
+    Copy {
+        text: String,
+        append: bool,
+        pipe: Option<String>,
+        cancel: bool,
+    },
+}
+
+/// The copy overlay actions that show, in a GUI window, what the copy mode
+/// command `name` did to a pane's copy mode, from `before` to `after`
+/// (`None` once the pane has left copy mode).
+///
+/// The overlay keeps its own cursor, so it follows the movements it shares
+/// with tmux, repeated `repeat` times, and the half-page ones. Those it has
+/// no match for (scrolling by lines, moving by spaces, jumps, whose target
+/// the overlay reads from the keyboard, and searches) leave it behind; the
+/// selection is copied from the server's state either way.
+pub fn overlay_actions(
+    name: &str,
+    repeat: usize,
+    before: &CopyModeState,
+    after: Option<&CopyModeState>,
+) -> Vec<CopyModeAssignment> {
+    use CopyModeAssignment as A;
+
+    let Some(after) = after else {
+        return vec![A::Close];
+    };
+    let mut actions = vec![];
+    let mut move_by = |action: A| actions.extend(std::iter::repeat_n(action, repeat));
+    match Command::from_name(name) {
+        Some(Command::Action(
+            A::JumpForward { .. }
+            | A::JumpBackward { .. }
+            | A::JumpAgain
+            | A::JumpReverse
+            | A::SetSelectionMode(_)
+            | A::ClearSelectionMode
+            | A::Close,
+        )) => {}
+        Some(Command::Action(action)) => move_by(action),
+        Some(Command::HalfPageUp) => move_by(A::MoveByPage(NotNan::new(-0.5).unwrap())),
+        Some(Command::HalfPageDown) => move_by(A::MoveByPage(NotNan::new(0.5).unwrap())),
+        _ => {}
+    }
+
+    // The overlay starts a selection at its cursor, and setting the mode
+    // it already has clears it again.
+    let restarted = matches!(name, "begin-selection" | "select-line" | "select-word");
+    match (before.start.is_some(), after.start.is_some()) {
+        (true, false) => actions.push(A::ClearSelectionMode),
+        (false, true) => actions.push(A::SetSelectionMode(Some(after.mode))),
+        (true, true) if restarted => {
+            actions.push(A::ClearSelectionMode);
+            actions.push(A::SetSelectionMode(Some(after.mode)));
+        }
+        (true, true) if before.mode != after.mode => {
+            actions.push(A::SetSelectionMode(Some(after.mode)));
+        }
+        _ => {}
+    }
+    actions
+}
+
+/// The copy mode state of one pane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyModeState {
+    /// Cursor column.
+    pub x: usize,
+    /// Cursor row.
+    pub y: StableRowIndex,
+    /// Row at the top of the view.
+    pub top: StableRowIndex,
+    /// Where the selection was started, if there is one.
+    start: Option<Pos>,
+    mode: SelectionMode,
+    search: Option<Search>,
+    jump: Option<Jump>,
+}
+
+impl CopyModeState {
+    /// Enter copy mode with the cursor at `(x, y)` and the view at the
+    /// bottom of the scrollback.
+    pub fn new(screen: &Screen, x: usize, y: StableRowIndex) -> Self {
+        let mut state = Self {
+            x,
+            y,
+            top: screen.physical_top,
+            start: None,
+            mode: SelectionMode::Cell,
+            search: None,
+            jump: None,
+        };
+        state.clamp(screen);
+        state
+    }
+
+    /// How many lines the view is scrolled back from the bottom.
+    pub fn scroll_position(&self, physical_top: StableRowIndex) -> usize {
+        (physical_top - self.top).max(0) as usize
+    }
+
+    pub fn selection_present(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// Run the copy mode command `name`, with its arguments, `repeat`
+    /// times.
+    pub fn run(
+        &mut self,
+        screen: &Screen,
+        name: &str,
+        args: &[String],
+        repeat: usize,
+    ) -> Result<Outcome, String> {
+        let command =
+            Command::from_name(name).ok_or_else(|| format!("unknown command: {}", name))?;
+        self.clamp(screen);
+
+        let outcome = match command {
+            Command::Action(CopyModeAssignment::Close) => Outcome::Cancel,
+            Command::Action(
+                CopyModeAssignment::JumpForward { prev_char }
+                | CopyModeAssignment::JumpBackward { prev_char },
+            ) => {
+                let target = args
+                    .first()
+                    .and_then(|arg| arg.chars().next())
+                    .ok_or_else(|| format!("{}: missing character", name))?;
+                let forward = name.ends_with("forward");
+                self.jump = Some(Jump {
+                    target,
+                    forward,
+                    prev_char,
+                });
+                for _ in 0..repeat {
+                    self.jump(screen, target, forward, prev_char);
+                }
+                Outcome::Done
+            }
+            Command::Action(action) => {
+                for _ in 0..repeat {
+                    self.apply(screen, &action);
+                }
+                Outcome::Done
+            }
+            Command::ScrollUp => {
+                self.scroll(screen, -(repeat as StableRowIndex));
+                Outcome::Done
+            }
+            Command::ScrollDown => {
+                self.scroll(screen, repeat as StableRowIndex);
+                Outcome::Done
+            }
+            Command::HalfPageUp | Command::HalfPageDown => {
+                let half = (screen.height / 2).max(1) as StableRowIndex;
+                let amount = half * repeat as StableRowIndex;
+                let amount = if command == Command::HalfPageUp {
+                    -amount
+                } else {
+                    amount
+                };
+                self.move_by(screen, amount);
+                Outcome::Done
+            }
+            Command::NextSpace => {
+                for _ in 0..repeat {
+                    self.next_word(screen, true);
+                }
+                Outcome::Done
+            }
+            Command::NextSpaceEnd => {
+                for _ in 0..repeat {
+                    self.next_word_end(screen, true);
+                }
+                Outcome::Done
+            }
+            Command::PreviousSpace => {
+                for _ in 0..repeat {
+                    self.previous_word(screen, true);
+                }
+                Outcome::Done
+            }
+            Command::SelectWord => {
+                self.select_word(screen);
+                Outcome::Done
+            }
+            Command::Rectangle(on) => {
+                let on = on.unwrap_or(self.mode != SelectionMode::Block);
+                self.mode = if on {
+                    SelectionMode::Block
+                } else {
+                    SelectionMode::Cell
+                };
+                Outcome::Done
+            }
+            Command::Search { forward, regex } => {
+                let pattern = args
+                    .first()
+                    .filter(|pattern| !pattern.is_empty())
+                    .ok_or_else(|| format!("{}: missing search string", name))?;
+                self.search = Some(Search {
+                    pattern: pattern.clone(),
+                    forward,
+                    regex,
+                });
+                self.search_again(screen, false, repeat)?;
+                Outcome::Done
+            }
+            Command::SearchAgain => {
+                self.search_again(screen, false, repeat)?;
+                Outcome::Done
+            }
+            Command::SearchReverse => {
+                self.search_again(screen, true, repeat)?;
+                Outcome::Done
+            }
+            Command::Copy {
+                source,
+                pipe,
+                append,
+                clear,
+                cancel,
+            } => {
+                let text = match source {
+                    CopySource::Selection => self.selection_text(screen),
+                    CopySource::Line => Some(screen.row(self.y).to_string()),
+                    CopySource::EndOfLine => Some(screen.columns(self.y, self.x, usize::MAX - 1)),
+                };
+                if clear {
+                    self.start = None;
+                }
+                match text {
+                    Some(text) => Outcome::Copy {
+                        text,
+                        append,
+                        pipe: pipe.then(|| args.first().cloned().unwrap_or_default()),
+                        cancel,
+                    },
+                    None if cancel => Outcome::Cancel,
+                    None => Outcome::Done,
+                }
+            }
+        };
+
+        self.show_cursor(screen);
+        Ok(outcome)
+    }
+
+    fn apply(&mut self, screen: &Screen, action: &CopyModeAssignment) {
+        use CopyModeAssignment as A;
+        let view_bottom = (self.top + screen.height as StableRowIndex - 1).min(screen.last_row());
+        match action {
+            A::MoveUp => self.y = (self.y - 1).max(screen.first_row),
+            A::MoveDown => self.y = (self.y + 1).min(screen.last_row()),
+            A::MoveLeft => self.x = self.x.saturating_sub(1),
+            A::MoveRight => self.x = (self.x + 1).min(screen.width.saturating_sub(1)),
+            A::MoveToStartOfLine => self.x = 0,
+            A::MoveToStartOfLineContent => {
+                self.x = screen
+                    .row(self.y)
+                    .chars()
+                    .position(|c| !c.is_whitespace())
+                    .unwrap_or(0);
+            }
+            A::MoveToEndOfLineContent => self.x = screen.row_len(self.y).saturating_sub(1),
+            A::MoveToStartOfNextLine if self.y < screen.last_row() => {
+                self.x = 0;
+                self.y += 1;
+            }
+            A::MoveForwardWord => self.next_word(screen, false),
+            A::MoveForwardWordEnd => self.next_word_end(screen, false),
+            A::MoveBackwardWord => self.previous_word(screen, false),
+            A::MoveToViewportTop => self.y = self.top,
+            A::MoveToViewportMiddle => self.y = (self.top + view_bottom) / 2,
+            A::MoveToViewportBottom => self.y = view_bottom,
+            A::MoveToScrollbackTop => {
+                self.x = 0;
+                self.y = screen.first_row;
+            }
+            A::MoveToScrollbackBottom => {
+                self.y = screen.last_row();
+                self.x = screen.row_len(self.y).saturating_sub(1);
+            }
+            A::PageUp => self.move_by(screen, -(screen.height as StableRowIndex)),
+            A::PageDown => self.move_by(screen, screen.height as StableRowIndex),
+            A::MoveBackwardZoneOfType(_) => {
+                if let Some(&y) = screen.prompts.iter().rev().find(|&&y| y < self.y) {
+                    self.x = 0;
+                    self.y = y;
+                }
+            }
+            A::MoveForwardZoneOfType(_) => {
+                if let Some(&y) = screen.prompts.iter().find(|&&y| y > self.y) {
+                    self.x = 0;
+                    self.y = y;
+                }
+            }
+            A::SetSelectionMode(mode) => {
+                self.start = Some((self.x, self.y));
+                // A character selection stays rectangular if rectangle
+                // mode was turned on first.
+                let mode = mode.unwrap_or(SelectionMode::Cell);
+                if mode != SelectionMode::Cell || self.mode != SelectionMode::Block {
+                    self.mode = mode;
+                }
+            }
+            A::ClearSelectionMode => {
+                self.start = None;
+                self.mode = SelectionMode::Cell;
+            }
+            A::MoveToSelectionOtherEnd => {
+                if let Some(start) = self.start.replace((self.x, self.y)) {
+                    self.x = start.0;
+                    self.y = start.1;
+                }
+            }
+            A::JumpAgain | A::JumpReverse => {
+                if let Some(jump) = self.jump {
+                    let forward = jump.forward != (*action == A::JumpReverse);
+                    self.jump(screen, jump.target, forward, jump.prev_char);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep the cursor and view inside the snapshot, which may have lost
+    /// rows off the top of the scrollback since the last command.
+    fn clamp(&mut self, screen: &Screen) {
+        self.y = self.y.max(screen.first_row).min(screen.last_row());
+        self.x = self.x.min(screen.width.saturating_sub(1));
+        self.top = self.top.max(screen.first_row).min(screen.physical_top);
+    }
+
+    /// Scroll the view so that the cursor is in it.
+    fn show_cursor(&mut self, screen: &Screen) {
+        let height = screen.height.max(1) as StableRowIndex;
+        if self.y < self.top {
+            self.top = self.y;
+        } else if self.y >= self.top + height {
+            self.top = self.y - height + 1;
+        }
+        self.top = self.top.max(screen.first_row).min(screen.physical_top);
+    }
+
+    /// Scroll the view by `amount` lines, keeping the cursor in view.
+    fn scroll(&mut self, screen: &Screen, amount: StableRowIndex) {
+        let height = screen.height.max(1) as StableRowIndex;
+        self.top = (self.top + amount)
+            .max(screen.first_row)
+            .min(screen.physical_top);
+        self.y = self.y.max(self.top).min(self.top + height - 1);
+    }
+
+    /// Move the view and the cursor together by `amount` lines.
+    fn move_by(&mut self, screen: &Screen, amount: StableRowIndex) {
+        let row = self.y - self.top;
+        self.scroll(screen, amount);
+        self.y = (self.top + row).min(screen.last_row());
+    }
+
+    fn next_word(&mut self, screen: &Screen, big: bool) {
+        let mut pos = (self.x, self.y);
+        let class = screen.class_at(pos, big);
+        // Leave the current word...
+        while let Some(next) = screen.next_pos(pos) {
+            let new_row = next.1 != pos.1;
+            pos = next;
+            if new_row || screen.class_at(pos, big) != class {
+                break;
+            }
+        }
+        // ...and the blanks after it.
+        while screen.class_at(pos, big) == CharClass::Blank {
+            match screen.next_pos(pos) {
+                Some(next) => pos = next,
+                None => break,
+            }
+        }
+        (self.x, self.y) = pos;
+    }
+
+    fn next_word_end(&mut self, screen: &Screen, big: bool) {
+        let mut pos = match screen.next_pos((self.x, self.y)) {
+            Some(pos) => pos,
+            None => return,
+        };
+        while screen.class_at(pos, big) == CharClass::Blank {
+            match screen.next_pos(pos) {
+                Some(next) => pos = next,
+                None => break,
+            }
+        }
+        let class = screen.class_at(pos, big);
+        while let Some(next) = screen.next_pos(pos) {
+            if next.1 != pos.1 || screen.class_at(next, big) != class {
+                break;
+            }
+            pos = next;
+        }
+        (self.x, self.y) = pos;
+    }
+
+    fn previous_word(&mut self, screen: &Screen, big: bool) {
+        let mut pos = match screen.prev_pos((self.x, self.y)) {
+            Some(pos) => pos,
+            None => return,
+        };
+        while screen.class_at(pos, big) == CharClass::Blank {
+            match screen.prev_pos(pos) {
+                Some(prev) => pos = prev,
+                None => break,
+            }
+        }
+        let class = screen.class_at(pos, big);
+        while let Some(prev) = screen.prev_pos(pos) {
+            if prev.1 != pos.1 || screen.class_at(prev, big) != class {
+                break;
+            }
+            pos = prev;
+        }
+        (self.x, self.y) = pos;
+    }
+
+    /// Select the word under the cursor, leaving the cursor at its end.
+    fn select_word(&mut self, screen: &Screen) {
+        let class = screen.class_at((self.x, self.y), false);
+        let mut start = (self.x.min(screen.row_len(self.y).saturating_sub(1)), self.y);
+        while let Some(prev) = screen.prev_pos(start) {
+            if prev.1 != start.1 || screen.class_at(prev, false) != class {
+                break;
+            }
+            start = prev;
+        }
+        let mut end = start;
+        while let Some(next) = screen.next_pos(end) {
+            if next.1 != end.1 || screen.class_at(next, false) != class {
+                break;
+            }
+            end = next;
+        }
+        self.start = Some(start);
+        self.mode = SelectionMode::Cell;
+        (self.x, self.y) = end;
+    }
+
+    fn jump(&mut self, screen: &Screen, target: char, forward: bool, prev_char: bool) {
+        let row: Vec<char> = screen.row(self.y).chars().collect();
+        if forward {
+            // Step over the target when already next to it, so that
+            // repeating a jump-to moves on to the next one.
+            let from = self.x + 1 + usize::from(prev_char);
+            if let Some(i) = (from..row.len()).find(|&i| row[i] == target) {
+                self.x = if prev_char { i - 1 } else { i };
+            }
+        } else {
+            let to = self.x.saturating_sub(usize::from(prev_char)).min(row.len());
+            if let Some(i) = (0..to).rev().find(|&i| row[i] == target) {
+                self.x = if prev_char { i + 1 } else { i };
+            }
+        }
+    }
+
+    /// Move to the next match of the last search, in the direction it went
+    /// or, with `reverse`, the other way.
+    fn search_again(
+        &mut self,
+        screen: &Screen,
+        reverse: bool,
+        repeat: usize,
+    ) -> Result<(), String> {
+        let search = match &self.search {
+            Some(search) => search.clone(),
+            None => return Ok(()),
+        };
+        let pattern = if search.regex {
+            search.pattern.clone()
+        } else {
+            fancy_regex::escape(&search.pattern).into_owned()
+        };
+        // As in tmux, a search without capitals ignores case.
+        let pattern = if search.pattern.chars().any(char::is_uppercase) {
+            pattern
+        } else {
+            format!("(?i){}", pattern)
+        };
+        let regex = fancy_regex::Regex::new(&pattern)
+            .map_err(|e| format!("invalid search: {}: {}", search.pattern, e))?;
+
+        let forward = search.forward != reverse;
+        for _ in 0..repeat {
+            match find_match(screen, &regex, (self.x, self.y), forward) {
+                Some(pos) => (self.x, self.y) = pos,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// The selected text, or `None` if nothing is selected.
+    fn selection_text(&self, screen: &Screen) -> Option<String> {
+        let start = self.start?;
+        let end = (self.x, self.y);
+        let (first, last) = if (start.1, start.0) <= (end.1, end.0) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+
+        let mut text = String::new();
+        for y in first.1..=last.1 {
+            let line = match self.mode {
+                SelectionMode::Line => screen.row(y).to_string(),
+                SelectionMode::Block => {
+                    let left = start.0.min(end.0);
+                    let right = start.0.max(end.0);
+                    screen.columns(y, left, right)
+                }
+                _ => {
+                    let from = if y == first.1 { first.0 } else { 0 };
+                    let to = if y == last.1 { last.0 } else { usize::MAX - 1 };
+                    screen.columns(y, from, to)
+                }
+            };
+            text.push_str(line.trim_end());
+            let joined = self.mode == SelectionMode::Cell && screen.is_wrapped(y);
+            if y != last.1 && !joined {
+                text.push('\n');
+            }
+        }
+        Some(text)
+    }
+}
+
+/// Find the next match of `regex` after `from` (or before it, when not
+/// `forward`), wrapping around the ends of the scrollback.
+fn find_match(
+    screen: &Screen,
+    regex: &fancy_regex::Regex,
+    from: Pos,
+    forward: bool,
+) -> Option<Pos> {
+    let matches_in = |y: StableRowIndex| -> Vec<usize> {
+        let row = screen.row(y);
+        regex
+            .find_iter(row)
+            .filter_map(Result::ok)
+            .map(|m| row[..m.start()].chars().count())
+            .collect()
+    };
+
+    let rows = screen.rows.len() as StableRowIndex;
+    for step in 0..=rows {
+        let offset = if forward { step } else { -step };
+        let y = screen.first_row + (from.1 - screen.first_row + offset).rem_euclid(rows.max(1));
+        let matches = matches_in(y);
+        let found = if forward {
+            matches
+                .into_iter()
+                .find(|&x| step == rows || step > 0 || x > from.0)
+        } else {
+            matches
+                .into_iter()
+                .rev()
+                .find(|&x| step == rows || step > 0 || x < from.0)
+        };
+        if let Some(x) = found {
+            return Some((x, y));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(rows: &[&str]) -> Screen {
+        Screen {
+            first_row: 0,
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+            wrapped: vec![false; rows.len()],
+            physical_top: rows.len().saturating_sub(4) as StableRowIndex,
+            width: 20,
+            height: 4,
+            prompts: vec![],
+        }
+    }
+
+    fn run(state: &mut CopyModeState, screen: &Screen, name: &str, args: &[&str]) -> Outcome {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        state.run(screen, name, &args, 1).unwrap()
+    }
+
+    #[test]
+    fn cursor_movement_is_clamped() {
+        let s = screen(&["one", "two"]);
+        let mut state = CopyModeState::new(&s, 0, 1);
+        run(&mut state, &s, "cursor-down", &[]);
+        assert_eq!((state.x, state.y), (0, 1));
+        run(&mut state, &s, "cursor-up", &[]);
+        run(&mut state, &s, "cursor-up", &[]);
+        assert_eq!((state.x, state.y), (0, 0));
+        run(&mut state, &s, "cursor-left", &[]);
+        assert_eq!(state.x, 0);
+        run(&mut state, &s, "end-of-line", &[]);
+        assert_eq!(state.x, 2);
+    }
+
+    #[test]
+    fn word_motions() {
+        let s = screen(&["foo bar.baz", "  qux"]);
+        let mut state = CopyModeState::new(&s, 0, 0);
+        run(&mut state, &s, "next-word", &[]);
+        assert_eq!((state.x, state.y), (4, 0));
+        run(&mut state, &s, "next-word", &[]);
+        assert_eq!((state.x, state.y), (7, 0));
+        run(&mut state, &s, "next-word", &[]);
+        assert_eq!((state.x, state.y), (8, 0));
+        run(&mut state, &s, "next-word", &[]);
+        assert_eq!((state.x, state.y), (2, 1));
+        run(&mut state, &s, "previous-word", &[]);
+        assert_eq!((state.x, state.y), (8, 0));
+
+        let mut state = CopyModeState::new(&s, 0, 0);
+        run(&mut state, &s, "next-space", &[]);
+        run(&mut state, &s, "next-space-end", &[]);
+        assert_eq!((state.x, state.y), (10, 0));
+        run(&mut state, &s, "back-to-indentation", &[]);
+        assert_eq!(state.x, 0);
+    }
+
+    #[test]
+    fn history_and_paging_scroll_the_view() {
+        let rows: Vec<String> = (0..10).map(|i| format!("line {}", i)).collect();
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        let s = screen(&rows);
+        let mut state = CopyModeState::new(&s, 0, 9);
+        assert_eq!(state.scroll_position(s.physical_top), 0);
+
+        run(&mut state, &s, "history-top", &[]);
+        assert_eq!((state.y, state.top), (0, 0));
+        assert_eq!(state.scroll_position(s.physical_top), 6);
+
+        run(&mut state, &s, "page-down", &[]);
+        assert_eq!((state.y, state.top), (4, 4));
+        run(&mut state, &s, "scroll-down", &[]);
+        assert_eq!((state.y, state.top), (5, 5));
+        run(&mut state, &s, "bottom-line", &[]);
+        assert_eq!(state.y, 8);
+        run(&mut state, &s, "history-bottom", &[]);
+        assert_eq!((state.y, state.top), (9, 6));
+        assert_eq!(state.scroll_position(s.physical_top), 0);
+    }
+
+    #[test]
+    fn copy_selection() {
+        let s = screen(&["hello world", "second line"]);
+        let mut state = CopyModeState::new(&s, 6, 0);
+        run(&mut state, &s, "begin-selection", &[]);
+        assert!(state.selection_present());
+        run(&mut state, &s, "cursor-down", &[]);
+        run(&mut state, &s, "cursor-left", &[]);
+        assert_eq!(
+            run(&mut state, &s, "copy-selection", &[]),
+            Outcome::Copy {
+                text: "world\nsecond".to_string(),
+                append: false,
+                pipe: None,
+                cancel: false,
+            }
+        );
+        assert!(!state.selection_present());
+    }
+
+    #[test]
+    fn copy_line_and_rectangle() {
+        let s = screen(&["abcdef", "ghijkl", "mnopqr"]);
+        let mut state = CopyModeState::new(&s, 1, 0);
+        run(&mut state, &s, "rectangle-toggle", &[]);
+        run(&mut state, &s, "begin-selection", &[]);
+        run(&mut state, &s, "cursor-down", &[]);
+        run(&mut state, &s, "cursor-down", &[]);
+        run(&mut state, &s, "cursor-right", &[]);
+        assert_eq!(
+            run(&mut state, &s, "copy-pipe-and-cancel", &["cat"]),
+            Outcome::Copy {
+                text: "bc\nhi\nno".to_string(),
+                append: false,
+                pipe: Some("cat".to_string()),
+                cancel: true,
+            }
+        );
+
+        let mut state = CopyModeState::new(&s, 2, 1);
+        run(&mut state, &s, "select-line", &[]);
+        run(&mut state, &s, "cursor-up", &[]);
+        assert_eq!(
+            run(&mut state, &s, "append-selection", &[]),
+            Outcome::Copy {
+                text: "abcdef\nghijkl".to_string(),
+                append: true,
+                pipe: None,
+                cancel: false,
+            }
+        );
+        assert_eq!(
+            run(&mut state, &s, "copy-end-of-line", &[]),
+            Outcome::Copy {
+                text: "cdef".to_string(),
+                append: false,
+                pipe: None,
+                cancel: false,
+            }
+        );
+    }
+
+    #[test]
+    fn select_word_and_other_end() {
+        let s = screen(&["git commit --amend"]);
+        let mut state = CopyModeState::new(&s, 6, 0);
+        run(&mut state, &s, "select-word", &[]);
+        assert_eq!((state.start, state.x), (Some((4, 0)), 9));
+        run(&mut state, &s, "other-end", &[]);
+        assert_eq!((state.start, state.x), (Some((9, 0)), 4));
+        run(&mut state, &s, "clear-selection", &[]);
+        assert_eq!(run(&mut state, &s, "copy-selection", &[]), Outcome::Done);
+    }
+
+    #[test]
+    fn search_wraps_and_reverses() {
+        let s = screen(&["error: one", "ok", "Error: two", "ok"]);
+        let mut state = CopyModeState::new(&s, 0, 3);
+        run(&mut state, &s, "search-backward", &["error"]);
+        assert_eq!((state.x, state.y), (0, 2));
+        run(&mut state, &s, "search-again", &[]);
+        assert_eq!((state.x, state.y), (0, 0));
+        run(&mut state, &s, "search-again", &[]);
+        assert_eq!((state.x, state.y), (0, 2));
+        run(&mut state, &s, "search-reverse", &[]);
+        assert_eq!((state.x, state.y), (0, 0));
+
+        // Capitals make the search case sensitive, and -text variants
+        // don't treat the pattern as a regular expression.
+        run(&mut state, &s, "search-forward", &["E.*two"]);
+        assert_eq!((state.x, state.y), (0, 2));
+        run(&mut state, &s, "search-forward-text", &["."]);
+        assert_eq!((state.x, state.y), (0, 2));
+        assert!(state
+            .run(&s, "search-forward", &["(".to_string()], 1)
+            .is_err());
+    }
+
+    #[test]
+    fn jumps() {
+        let s = screen(&["a,b,c,d"]);
+        let mut state = CopyModeState::new(&s, 0, 0);
+        run(&mut state, &s, "jump-forward", &[","]);
+        assert_eq!(state.x, 1);
+        run(&mut state, &s, "jump-again", &[]);
+        assert_eq!(state.x, 3);
+        run(&mut state, &s, "jump-reverse", &[]);
+        assert_eq!(state.x, 1);
+        run(&mut state, &s, "jump-to-forward", &["d"]);
+        assert_eq!(state.x, 5);
+        run(&mut state, &s, "jump-to-backward", &["a"]);
+        assert_eq!(state.x, 1);
+    }
+
+    #[test]
+    fn prompts() {
+        let mut s = screen(&["$ ls", "file", "$ pwd", "/tmp", "$"]);
+        s.prompts = vec![0, 2, 4];
+        let mut state = CopyModeState::new(&s, 1, 4);
+        run(&mut state, &s, "previous-prompt", &[]);
+        assert_eq!((state.x, state.y), (0, 2));
+        run(&mut state, &s, "previous-prompt", &[]);
+        assert_eq!(state.y, 0);
+        run(&mut state, &s, "next-prompt", &[]);
+        assert_eq!(state.y, 2);
+    }
+
+    #[test]
+    fn repeat_and_errors() {
+        let s = screen(&["0123456789"]);
+        let mut state = CopyModeState::new(&s, 0, 0);
+        state.run(&s, "cursor-right", &[], 4).unwrap();
+        assert_eq!(state.x, 4);
+        assert_eq!(run(&mut state, &s, "cancel", &[]), Outcome::Cancel);
+        assert!(state.run(&s, "no-such-command", &[], 1).is_err());
+        assert!(state.run(&s, "jump-forward", &[], 1).is_err());
+        assert!(state.run(&s, "search-forward", &[], 1).is_err());
+    }
+
+    #[test]
+    fn the_overlay_follows_what_it_can() {
+        use CopyModeAssignment as A;
+
+        let s = screen(&["one two", "three"]);
+        let mut state = CopyModeState::new(&s, 0, 0);
+        let mut step = |name: &str, repeat: usize| {
+            let before = state.clone();
+            let outcome = state.run(&s, name, &[], repeat).unwrap();
+            let after = (outcome == Outcome::Done).then_some(&state);
+            overlay_actions(name, repeat, &before, after)
+        };
+        assert_eq!(step("cursor-right", 2), vec![A::MoveRight, A::MoveRight]);
+        assert_eq!(
+            step("begin-selection", 1),
+            vec![A::SetSelectionMode(Some(SelectionMode::Cell))]
+        );
+        // Beginning again restarts the selection rather than toggling it.
+        assert_eq!(
+            step("begin-selection", 1),
+            vec![
+                A::ClearSelectionMode,
+                A::SetSelectionMode(Some(SelectionMode::Cell))
+            ]
+        );
+        assert_eq!(
+            step("rectangle-toggle", 1),
+            vec![A::SetSelectionMode(Some(SelectionMode::Block))]
+        );
+        assert_eq!(step("scroll-up", 1), vec![]);
+        assert_eq!(
+            step("halfpage-down", 1),
+            vec![A::MoveByPage(NotNan::new(0.5).unwrap())]
+        );
+        assert_eq!(step("clear-selection", 1), vec![A::ClearSelectionMode]);
+        assert_eq!(step("cancel", 1), vec![A::Close]);
+    }
+}
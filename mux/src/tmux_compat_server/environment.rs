@@ -0,0 +1,236 @@
+//! Session environments (`set-environment` / `show-environment`).
+//!
+//! Like tmux, there is a global environment (`-g`) and one for each session
+//! (WezTerm workspace). A pane spawned in a session gets the global
+//! environment with the session's on top of it, then any `-e` variables of
+//! the spawn command. A variable can also be marked as removed (`-r`), which
+//! takes it out of the spawned program's environment, and hidden (`-h`),
+//! which keeps it out of `show-environment` unless that is given `-h` too.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+
+use parking_lot::MappedMutexGuard;
+
+use super::named_server::PerServer;
+use super::options::OptionScope;
+
+/// A variable in an environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    /// `None` when the variable is marked as removed.
+    pub value: Option<String>,
+    pub hidden: bool,
+}
+
+impl Variable {
+    /// The line `show-environment` prints for the variable `name`: either
+    /// `NAME=value` or `-NAME`, or with `shell`, a command for a POSIX shell
+    /// to evaluate.
+    pub fn show(&self, name: &str, shell: bool) -> String {
+        match (&self.value, shell) {
+            (Some(value), false) => format!("{}={}", name, value),
+            (None, false) => format!("-{}", name),
+            (Some(value), true) => {
+                format!("{}=\"{}\"; export {};", name, shell_escape(value), name)
+            }
+            (None, true) => format!("unset {};", name),
+        }
+    }
+}
+
+/// Escape the characters that are special inside double quotes, as tmux
+/// does for `show-environment -s`.
+fn shell_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '$' | '`' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Environment variables, keyed by scope and then by name. Only
+/// [`OptionScope::GlobalSession`] and [`OptionScope::Session`] are used.
+#[derive(Debug, Default)]
+pub struct EnvironmentStore {
+    environments: HashMap<OptionScope, BTreeMap<String, Variable>>,
+}
+
+impl EnvironmentStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `name` to `value`, or mark it as removed when `value` is `None`.
+    pub fn set(&mut self, scope: &OptionScope, name: &str, value: Option<String>, hidden: bool) {
+        self.environments
+            .entry(scope.clone())
+            .or_default()
+            .insert(name.to_string(), Variable { value, hidden });
+    }
+
+    /// Take `name` out of the environment altogether. Returns `true` if it
+    /// was there.
+    pub fn unset(&mut self, scope: &OptionScope, name: &str) -> bool {
+        self.environments
+            .get_mut(scope)
+            .and_then(|vars| vars.remove(name))
+            .is_some()
+    }
+
+    pub fn get(&self, scope: &OptionScope, name: &str) -> Option<&Variable> {
+        self.environments.get(scope).and_then(|vars| vars.get(name))
+    }
+
+    /// The variables at exactly `scope`, in name order: the hidden ones
+    /// when `hidden` is true, otherwise the rest.
+    pub fn list(&self, scope: &OptionScope, hidden: bool) -> Vec<(&str, &Variable)> {
+        self.environments
+            .get(scope)
+            .into_iter()
+            .flatten()
+            .filter(|(_, var)| var.hidden == hidden)
+            .map(|(name, var)| (name.as_str(), var))
+            .collect()
+    }
+
+    /// The changes to make to the environment of a program spawned in
+    /// `workspace`: a value to set, or `None` to remove the variable.
+    pub fn spawn_environment(&self, workspace: &str) -> Vec<(String, Option<String>)> {
+        let mut merged = BTreeMap::new();
+        for scope in [
+            OptionScope::GlobalSession,
+            OptionScope::Session(workspace.to_string()),
+        ] {
+            for (name, var) in self.environments.get(&scope).into_iter().flatten() {
+                merged.insert(name.clone(), var.value.clone());
+            }
+        }
+        merged.into_iter().collect()
+    }
+
+    /// Forget everything set at `scope`.
+    pub fn remove_scope(&mut self, scope: &OptionScope) {
+        self.environments.remove(scope);
+    }
+
+    /// Move a session's environment when its workspace is renamed.
+    pub fn rename_session(&mut self, old_workspace: &str, new_workspace: &str) {
+        let old = OptionScope::Session(old_workspace.to_string());
+        if let Some(vars) = self.environments.remove(&old) {
+            self.environments
+                .insert(OptionScope::Session(new_workspace.to_string()), vars);
+        }
+    }
+}
+
+/// The environment store of each server.
+pub(super) static ENVIRONMENTS: LazyLock<PerServer<EnvironmentStore>> =
+    LazyLock::new(Default::default);
+
+/// Lock the environment store of `server`, or of the default server for
+/// `None`.
+pub fn environments(server: Option<&str>) -> MappedMutexGuard<'static, EnvironmentStore> {
+    ENVIRONMENTS.lock(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(name: &str) -> OptionScope {
+        OptionScope::Session(name.to_string())
+    }
+
+    #[test]
+    fn session_overrides_global() {
+        let mut store = EnvironmentStore::new();
+        store.set(
+            &OptionScope::GlobalSession,
+            "API_URL",
+            Some("global".into()),
+            false,
+        );
+        store.set(&OptionScope::GlobalSession, "TOKEN", Some("t".into()), true);
+        store.set(&session("work"), "API_URL", Some("work".into()), false);
+        store.set(&session("work"), "TOKEN", None, false);
+
+        assert_eq!(
+            store.spawn_environment("work"),
+            vec![
+                ("API_URL".to_string(), Some("work".to_string())),
+                ("TOKEN".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            store.spawn_environment("other"),
+            vec![
+                ("API_URL".to_string(), Some("global".to_string())),
+                ("TOKEN".to_string(), Some("t".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_variables_are_listed_apart() {
+        let mut store = EnvironmentStore::new();
+        store.set(
+            &OptionScope::GlobalSession,
+            "SHOWN",
+            Some("1".into()),
+            false,
+        );
+        store.set(
+            &OptionScope::GlobalSession,
+            "SECRET",
+            Some("2".into()),
+            true,
+        );
+
+        let names = |hidden| -> Vec<String> {
+            store
+                .list(&OptionScope::GlobalSession, hidden)
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        assert_eq!(names(false), vec!["SHOWN"]);
+        assert_eq!(names(true), vec!["SECRET"]);
+
+        assert!(store.unset(&OptionScope::GlobalSession, "SECRET"));
+        assert!(!store.unset(&OptionScope::GlobalSession, "SECRET"));
+    }
+
+    #[test]
+    fn show_formats() {
+        let set = Variable {
+            value: Some("a \"b\" $c".into()),
+            hidden: false,
+        };
+        let removed = Variable {
+            value: None,
+            hidden: false,
+        };
+        assert_eq!(set.show("V", false), "V=a \"b\" $c");
+        assert_eq!(set.show("V", true), "V=\"a \\\"b\\\" \\$c\"; export V;");
+        assert_eq!(removed.show("V", false), "-V");
+        assert_eq!(removed.show("V", true), "unset V;");
+    }
+
+    #[test]
+    fn rename_moves_the_environment() {
+        let mut store = EnvironmentStore::new();
+        store.set(&session("old"), "X", Some("1".into()), false);
+        store.rename_session("old", "new");
+        assert!(store.get(&session("old"), "X").is_none());
+        assert_eq!(
+            store
+                .get(&session("new"), "X")
+                .and_then(|v| v.value.clone()),
+            Some("1".to_string())
+        );
+    }
+}
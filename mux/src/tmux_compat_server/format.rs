@@ -2,9 +2,32 @@
 //!
 //! Expands tmux-style format strings such as `#{pane_id}`, `#{window_id}`,
 //! and conditional expressions like `#{?pane_active,active,}`.
+//!
+//! Format strings are parsed into a tree of [`FormatNode`]s by
+//! [`parse_format`] and then evaluated against a [`FormatContext`]. The
+//! supported syntax follows the *FORMATS* section of `tmux(1)`:
+//!
+//! - `#{name}` variables and the `#D #F #I #P #S #T #W` short aliases
+//! - `#{?cond,a,b}` conditionals, including `#{?c1,a,c2,b,c}` chains
+//! - `#{==:a,b}`, `#{!=:a,b}`, `#{<:a,b}`, `#{>:a,b}`, `#{<=:a,b}`,
+//!   `#{>=:a,b}`, `#{||:a,b}` and `#{&&:a,b}`
+//! - `#{m:glob,string}`, with `m/r` for regular expressions and `m/i` to
+//!   ignore case
+//! - `#{s/pattern/replacement/:name}` substitution (`i` flag ignores case)
+//! - `#{=N:name}`, `#{=-N:name}` and `#{=/N/marker:name}` truncation
+//! - `#{t:name}`, `#{t/p:name}` and `#{t/f/fmt:name}` time formatting
+//! - `#{l:text}` literals, `#{E:name}` and `#{T:name}` re-expansion
+//! - `#{S:fmt}`, `#{W:fmt,current}` and `#{P:fmt,current}` loops
+//!
+//! Modifiers can be chained with `;`, e.g. `#{=10;s/foo/bar/:window_name}`.
+//! `##`, `#,` and `#}` produce a literal `#`, `,` and `}`.
 
+use std::collections::HashMap;
 use std::fmt::Write;
 
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Local, TimeZone};
+
 /// Holds all the state needed to resolve tmux format variables.
 #[derive(Debug, Clone, Default)]
 pub struct FormatContext {
@@ -16,6 +39,8 @@ pub struct FormatContext {
     pub pane_left: u64,
     pub pane_top: u64,
     pub pane_dead: bool,
+    /// Exit code of the program in a dead pane.
+    pub pane_dead_status: Option<u32>,
     pub window_id: u64,
     pub window_index: u64,
     pub window_name: String,
@@ -34,7 +59,17 @@ pub struct FormatContext {
     pub pane_current_path: String,
     pub pane_pid: u64,
     pub pane_mode: String,
+    pub pane_in_mode: bool,
+    /// Lines scrolled back in copy mode; `None` outside copy mode.
+    pub scroll_position: Option<u64>,
+    pub selection_present: bool,
+    /// Whether the pane has a `pipe-pane` command running.
+    pub pane_pipe: bool,
     pub window_flags: String,
+    // Window alerts (see `alerts.rs`), also shown in window_flags.
+    pub window_activity_flag: bool,
+    pub window_bell_flag: bool,
+    pub window_silence_flag: bool,
     pub window_panes: u64,
     pub session_windows: u64,
     pub session_attached: u64,
@@ -45,6 +80,26 @@ pub struct FormatContext {
     pub buffer_name: String,
     pub buffer_size: u64,
     pub buffer_sample: String,
+    /// User options (`@name`) visible to this pane, for `#{@name}`.
+    pub user_options: HashMap<String, String>,
+    // Hook variables, set while the commands of a hook run. IDs are in tmux
+    // form (`%1`, `@2`, `$0`) and empty when the hook has no such target.
+    pub hook: String,
+    pub hook_pane: String,
+    pub hook_window: String,
+    pub hook_window_name: String,
+    pub hook_session: String,
+    pub hook_session_name: String,
+    pub hook_client: String,
+    /// What a `choose-tree` line is being formatted for: `session`, `window`
+    /// or `pane`, making `#{session_format}`, `#{window_format}` or
+    /// `#{pane_format}` true. Empty everywhere else.
+    pub format_type: &'static str,
+    // Loop items for `#{S:}`, `#{W:}` and `#{P:}`. These are only filled in
+    // by the handlers when the format being expanded contains a loop.
+    pub loop_sessions: Vec<FormatContext>,
+    pub loop_windows: Vec<FormatContext>,
+    pub loop_panes: Vec<FormatContext>,
 }
 
 impl FormatContext {
@@ -57,6 +112,107 @@ impl FormatContext {
     }
 }
 
+/// Maximum nesting depth for `#{E:}`/`#{T:}` re-expansion and loops, matching
+/// tmux's `FORMAT_LOOP_LIMIT`.
+const FORMAT_LOOP_LIMIT: usize = 100;
+
+// ---------------------------------------------------------------------------
+// AST
+// ---------------------------------------------------------------------------
+
+/// One node of a parsed format string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatNode {
+    /// Literal text copied to the output unchanged.
+    Text(String),
+    /// A variable lookup: `#{pane_id}` or a short alias such as `#D`.
+    Variable(String),
+    /// `#{?cond,a,b}`. Each branch pairs a condition with the output used
+    /// when it is true; `otherwise` is used when no condition matched.
+    Conditional {
+        branches: Vec<(Vec<FormatNode>, Vec<FormatNode>)>,
+        otherwise: Vec<FormatNode>,
+    },
+    /// A comparison or boolean operator such as `#{==:a,b}`. Evaluates to
+    /// `1` or `0`.
+    Compare {
+        op: CompareOp,
+        left: Vec<FormatNode>,
+        right: Vec<FormatNode>,
+    },
+    /// `#{m:pattern,string}`. Evaluates to `1` or `0`.
+    Match {
+        pattern: Vec<FormatNode>,
+        subject: Vec<FormatNode>,
+        regex: bool,
+        ignore_case: bool,
+    },
+    /// `#{S:}`, `#{W:}` or `#{P:}`: expand `each` once per item, using
+    /// `current` (when given) for the current session, window or pane.
+    Loop {
+        kind: LoopKind,
+        each: Vec<FormatNode>,
+        current: Option<Vec<FormatNode>>,
+    },
+    /// A value post-processed by one or more modifiers, e.g. `#{=5:pane_title}`.
+    Modified {
+        value: Vec<FormatNode>,
+        modifiers: Vec<Modifier>,
+    },
+}
+
+/// Operators that take two arguments and produce `1` or `0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Or,
+    And,
+}
+
+/// What a `#{S:}`, `#{W:}` or `#{P:}` loop iterates over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+    Sessions,
+    Windows,
+    Panes,
+}
+
+/// Modifiers that transform the value of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Modifier {
+    /// `=N` keeps the first N characters, `=-N` the last N. The marker is
+    /// added on the truncated side when anything was removed.
+    Truncate { length: i64, marker: String },
+    /// `t:` interprets the value as a Unix timestamp.
+    Time(TimeFormat),
+    /// `s/pattern/replacement/flags`: regex substitution of every match.
+    Substitute {
+        pattern: String,
+        replacement: String,
+        ignore_case: bool,
+    },
+    /// `E:` expands the value again as a format.
+    Expand,
+    /// `T:` is like `E:` but also expands strftime(3) sequences.
+    ExpandTime,
+}
+
+/// Output style for the `t:` modifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// `t:` — the same layout as ctime(3), e.g. `Thu Oct 16 09:30:00 2026`.
+    Default,
+    /// `t/p:` — a short form that depends on how long ago the time was.
+    Pretty,
+    /// `t/f/fmt:` — a custom strftime(3) format.
+    Custom(String),
+}
+
 /// Map a single-character tmux short-form alias to the equivalent long-form
 /// variable name. Returns `None` if the character is not a recognized alias.
 ///
@@ -74,22 +230,17 @@ fn short_alias_to_variable(ch: u8) -> Option<&'static str> {
     }
 }
 
-/// Expand a tmux format string, substituting `#{variable}` placeholders,
-/// single-character `#X` short-form aliases, and evaluating
-/// `#{?condition,true_value,false_value}` conditionals using the provided
-/// context.
-///
-/// Short-form aliases (from tmux `format_table[]`):
-///   `#D` → `#{pane_id}`, `#F` → `#{window_flags}`,
-///   `#I` → `#{window_index}`, `#P` → `#{pane_index}`,
-///   `#S` → `#{session_name}`, `#T` → `#{pane_title}`,
-///   `#W` → `#{window_name}`
-///
-/// `##` expands to a literal `#`.
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// Parse a tmux format string into a list of nodes.
 ///
-/// Unknown variables expand to the empty string.
-pub fn expand_format(fmt: &str, ctx: &FormatContext) -> String {
-    let mut output = String::with_capacity(fmt.len());
+/// Parsing never fails: malformed input (such as an unclosed `#{`) is kept as
+/// literal text, the same way tmux prints it.
+pub fn parse_format(fmt: &str) -> Vec<FormatNode> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
     let bytes = fmt.as_bytes();
     let len = bytes.len();
     let mut i = 0;
@@ -98,46 +249,60 @@ pub fn expand_format(fmt: &str, ctx: &FormatContext) -> String {
         if i + 1 < len && bytes[i] == b'#' {
             let next = bytes[i + 1];
             if next == b'{' {
-                // Long-form expression: #{variable} or #{?cond,t,f}
+                // Long-form expression: #{variable}, #{?cond,t,f}, #{mod:...}
                 let start = i + 2;
                 if let Some(end) = find_matching_brace(bytes, start) {
-                    let expr = &fmt[start..end];
-                    expand_expr(expr, ctx, &mut output);
+                    flush_text(&mut text, &mut nodes);
+                    nodes.push(parse_expr(&fmt[start..end]));
                     i = end + 1;
                 } else {
                     // No matching `}` found — emit the `#{` literally and move on.
-                    output.push_str("#{");
+                    text.push_str("#{");
                     i += 2;
                 }
-            } else if next == b'#' {
-                // `##` → literal `#`
-                output.push('#');
+            } else if next == b'#' || next == b',' || next == b'}' {
+                // `##`, `#,` and `#}` escape the second character.
+                text.push(next as char);
                 i += 2;
             } else if let Some(var_name) = short_alias_to_variable(next) {
                 // Short-form alias: #D, #F, #I, #P, #S, #T, #W
-                resolve_variable(var_name, ctx, &mut output);
+                flush_text(&mut text, &mut nodes);
+                nodes.push(FormatNode::Variable(var_name.to_string()));
                 i += 2;
             } else {
                 // Unrecognized `#X` — emit literally.
-                output.push('#');
+                text.push('#');
                 i += 1;
             }
         } else {
-            output.push(fmt[i..].chars().next().unwrap());
-            i += fmt[i..].chars().next().unwrap().len_utf8();
+            let ch = fmt[i..].chars().next().unwrap();
+            text.push(ch);
+            i += ch.len_utf8();
         }
     }
 
-    output
+    flush_text(&mut text, &mut nodes);
+    nodes
+}
+
+/// Move any pending literal text into `nodes`.
+fn flush_text(text: &mut String, nodes: &mut Vec<FormatNode>) {
+    if !text.is_empty() {
+        nodes.push(FormatNode::Text(std::mem::take(text)));
+    }
 }
 
 /// Find the index of the `}` that closes the brace opened at `start`,
-/// respecting nested brace pairs.
+/// respecting nested brace pairs and `#`-escaped characters.
 fn find_matching_brace(bytes: &[u8], start: usize) -> Option<usize> {
     let mut depth: usize = 1;
     let mut i = start;
     while i < bytes.len() {
         match bytes[i] {
+            b'#' if is_escape(bytes, i) => {
+                i += 2;
+                continue;
+            }
             b'{' => depth += 1,
             b'}' => {
                 depth -= 1;
@@ -152,78 +317,712 @@ fn find_matching_brace(bytes: &[u8], start: usize) -> Option<usize> {
     None
 }
 
-/// Expand a single expression (the content between `#{` and `}`).
-///
-/// This handles both plain variable names like `pane_id` and conditional
-/// expressions like `?pane_active,active,inactive`.
-fn expand_expr(expr: &str, ctx: &FormatContext, output: &mut String) {
+/// Return `true` if the `#` at `i` escapes the following character.
+fn is_escape(bytes: &[u8], i: usize) -> bool {
+    matches!(bytes.get(i + 1), Some(b'#') | Some(b',') | Some(b'}'))
+}
+
+/// Split an argument list on top-level commas (those not nested inside
+/// `#{}` expressions and not escaped as `#,`).
+fn split_arguments(s: &str) -> Vec<&str> {
+    let bytes = s.as_bytes();
+    let mut parts = Vec::new();
+    let mut depth: usize = 0;
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' if is_escape(bytes, i) => {
+                i += 2;
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    // Push the final segment.
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Split `s` at its first top-level comma. The second half is empty when
+/// there is no comma.
+fn split_first_argument(s: &str) -> (&str, &str) {
+    let first = split_arguments(s)[0];
+    let rest = s.get(first.len() + 1..).unwrap_or("");
+    (first, rest)
+}
+
+/// Parse the argument of a value modifier or conditional. A bare name is a
+/// variable lookup; anything containing `#` is treated as a nested format.
+fn parse_value(arg: &str) -> Vec<FormatNode> {
+    if arg.contains('#') {
+        parse_format(arg)
+    } else {
+        vec![FormatNode::Variable(arg.to_string())]
+    }
+}
+
+/// Parse a single expression (the content between `#{` and `}`).
+fn parse_expr(expr: &str) -> FormatNode {
     if let Some(rest) = expr.strip_prefix('?') {
-        expand_conditional(rest, ctx, output);
+        return parse_conditional(rest);
+    }
+
+    let Some((parsed, args)) = parse_modifiers(expr) else {
+        return FormatNode::Variable(expr.to_string());
+    };
+
+    let mut modifiers = Vec::new();
+    let mut shape = None;
+    for modifier in parsed {
+        match modifier {
+            ParsedModifier::Value(m) => modifiers.push(m),
+            ParsedModifier::Shape(s) => {
+                if shape.is_none() {
+                    shape = Some(s);
+                }
+            }
+        }
+    }
+
+    let node = match shape {
+        None => {
+            return FormatNode::Modified {
+                value: parse_value(args),
+                modifiers,
+            }
+        }
+        Some(Shape::Literal) => FormatNode::Text(args.to_string()),
+        Some(Shape::Compare(op)) => {
+            let (left, right) = split_first_argument(args);
+            FormatNode::Compare {
+                op,
+                left: parse_format(left),
+                right: parse_format(right),
+            }
+        }
+        Some(Shape::Match { regex, ignore_case }) => {
+            let (pattern, subject) = split_first_argument(args);
+            FormatNode::Match {
+                pattern: parse_format(pattern),
+                subject: parse_format(subject),
+                regex,
+                ignore_case,
+            }
+        }
+        Some(Shape::Loop(kind)) => {
+            let parts = split_arguments(args);
+            let (each, current) = split_first_argument(args);
+            FormatNode::Loop {
+                kind,
+                each: parse_format(each),
+                current: (parts.len() > 1).then(|| parse_format(current)),
+            }
+        }
+    };
+
+    if modifiers.is_empty() {
+        node
     } else {
-        resolve_variable(expr, ctx, output);
+        FormatNode::Modified {
+            value: vec![node],
+            modifiers,
+        }
     }
 }
 
-/// Expand a conditional expression of the form `condition,true_str,false_str`.
+/// Parse the body of `#{?cond,a,b}` (without the leading `?`).
 ///
-/// The condition is resolved as a variable. If the resolved value is non-empty
-/// and not `"0"`, the true branch is used; otherwise the false branch.
+/// Arguments pair up as condition/value; a trailing unpaired argument is the
+/// fallback. Fewer than two arguments is malformed and expands to nothing.
+fn parse_conditional(rest: &str) -> FormatNode {
+    let parts = split_arguments(rest);
+    let mut branches = Vec::new();
+    let mut otherwise = Vec::new();
+
+    if parts.len() >= 2 {
+        for chunk in parts.chunks(2) {
+            match chunk {
+                [cond, value] => branches.push((parse_value(cond), parse_format(value))),
+                [last] => otherwise = parse_format(last),
+                _ => {}
+            }
+        }
+    }
+
+    FormatNode::Conditional {
+        branches,
+        otherwise,
+    }
+}
+
+/// Modifiers that decide the structure of an expression rather than
+/// post-processing its value.
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    Literal,
+    Compare(CompareOp),
+    Match { regex: bool, ignore_case: bool },
+    Loop(LoopKind),
+}
+
+/// A single entry of a `mod;mod:` prefix.
+#[derive(Debug)]
+enum ParsedModifier {
+    Shape(Shape),
+    Value(Modifier),
+}
+
+/// Parse the `mod;mod;...:` prefix of an expression.
 ///
-/// The commas that separate the three parts are found at the top level only
-/// (i.e., commas inside nested `#{}` expressions are not treated as
-/// separators).
-fn expand_conditional(rest: &str, ctx: &FormatContext, output: &mut String) {
-    // Split into exactly three parts: condition, true_str, false_str.
-    // We need to split on top-level commas only (not inside braces).
-    let parts = split_conditional_parts(rest);
-
-    let (condition, true_str, false_str) = match parts.len() {
-        3 => (&parts[0], &parts[1], &parts[2]),
-        2 => (&parts[0], &parts[1], &"".to_string()),
-        _ => {
-            // Malformed conditional — emit nothing.
-            return;
+/// Returns the modifiers and the argument text following the `:`, or `None`
+/// if `expr` does not start with a modifier list (it is then a plain
+/// variable name such as `session_name`).
+fn parse_modifiers(expr: &str) -> Option<(Vec<ParsedModifier>, &str)> {
+    let mut modifiers = Vec::new();
+    let mut rest = expr;
+    loop {
+        let (modifier, after) = parse_modifier(rest)?;
+        modifiers.push(modifier);
+        if let Some(args) = after.strip_prefix(':') {
+            return Some((modifiers, args));
+        }
+        rest = after.strip_prefix(';')?;
+    }
+}
+
+/// Parse one modifier from the start of `s`, returning it with the
+/// remaining text (which must then start with `:` or `;`).
+fn parse_modifier(s: &str) -> Option<(ParsedModifier, &str)> {
+    const OPERATORS: &[(&str, CompareOp)] = &[
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("||", CompareOp::Or),
+        ("&&", CompareOp::And),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+    for (token, op) in OPERATORS {
+        if let Some(after) = s.strip_prefix(token) {
+            return Some((ParsedModifier::Shape(Shape::Compare(*op)), after));
+        }
+    }
+
+    let mut chars = s.chars();
+    let first = chars.next()?;
+    let after = chars.as_str();
+    let parsed = match first {
+        'l' => ParsedModifier::Shape(Shape::Literal),
+        'S' => ParsedModifier::Shape(Shape::Loop(LoopKind::Sessions)),
+        'W' => ParsedModifier::Shape(Shape::Loop(LoopKind::Windows)),
+        'P' => ParsedModifier::Shape(Shape::Loop(LoopKind::Panes)),
+        'E' => ParsedModifier::Value(Modifier::Expand),
+        'T' => ParsedModifier::Value(Modifier::ExpandTime),
+        'm' => {
+            let (flags, after) = match after.strip_prefix('/') {
+                Some(flags) => split_at_modifier_end(flags),
+                None => ("", after),
+            };
+            let shape = Shape::Match {
+                regex: flags.contains('r'),
+                ignore_case: flags.contains('i'),
+            };
+            return Some((ParsedModifier::Shape(shape), after));
         }
+        't' => {
+            let Some(spec) = after.strip_prefix('/') else {
+                return Some((
+                    ParsedModifier::Value(Modifier::Time(TimeFormat::Default)),
+                    after,
+                ));
+            };
+            if let Some(custom) = spec.strip_prefix("f/") {
+                // `#:` lets a custom time format contain a colon.
+                let (fmt, after) = split_at_modifier_end(custom);
+                let style = TimeFormat::Custom(fmt.replace("#:", ":"));
+                return Some((ParsedModifier::Value(Modifier::Time(style)), after));
+            }
+            let after = spec.strip_prefix('p')?;
+            return Some((
+                ParsedModifier::Value(Modifier::Time(TimeFormat::Pretty)),
+                after,
+            ));
+        }
+        '=' => {
+            if let Some(spec) = after.strip_prefix('/') {
+                let (length, rest) = spec.split_once('/')?;
+                let length = length.parse::<i64>().ok()?;
+                let (marker, after) = split_at_modifier_end(rest);
+                let modifier = Modifier::Truncate {
+                    length,
+                    marker: marker.to_string(),
+                };
+                return Some((ParsedModifier::Value(modifier), after));
+            }
+            let end = after
+                .find(|c: char| c != '-' && !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            let length = after[..end].parse::<i64>().ok()?;
+            let modifier = Modifier::Truncate {
+                length,
+                marker: String::new(),
+            };
+            return Some((ParsedModifier::Value(modifier), &after[end..]));
+        }
+        's' => {
+            // Any non-word character may be used as the separator.
+            let sep = after.chars().next()?;
+            if sep.is_alphanumeric() || sep == '_' {
+                return None;
+            }
+            let body = &after[sep.len_utf8()..];
+            let (pattern, body) = body.split_once(sep)?;
+            let (replacement, body) = body.split_once(sep)?;
+            let (flags, after) = split_at_modifier_end(body);
+            let modifier = Modifier::Substitute {
+                pattern: pattern.to_string(),
+                replacement: replacement.to_string(),
+                ignore_case: flags.contains('i'),
+            };
+            return Some((ParsedModifier::Value(modifier), after));
+        }
+        _ => return None,
     };
+    Some((parsed, after))
+}
+
+/// Split `s` before the first `:` or `;` that is not escaped with `#`.
+fn split_at_modifier_end(s: &str) -> (&str, &str) {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' if matches!(bytes.get(i + 1), Some(b':') | Some(b';')) => i += 2,
+            b':' | b';' => return (&s[..i], &s[i..]),
+            _ => i += 1,
+        }
+    }
+    (s, "")
+}
 
-    // Resolve the condition variable.
-    let mut cond_value = String::new();
-    resolve_variable(condition, ctx, &mut cond_value);
+/// Return `true` if the parsed format contains a `#{S:}`, `#{W:}` or
+/// `#{P:}` loop, or re-expands a value that might.
+pub fn format_uses_loops(fmt: &str) -> bool {
+    nodes_use_loops(&parse_format(fmt))
+}
 
-    let is_true = !cond_value.is_empty() && cond_value != "0";
+fn nodes_use_loops(nodes: &[FormatNode]) -> bool {
+    nodes.iter().any(|node| match node {
+        FormatNode::Text(_) | FormatNode::Variable(_) => false,
+        FormatNode::Loop { .. } => true,
+        FormatNode::Conditional {
+            branches,
+            otherwise,
+        } => {
+            branches
+                .iter()
+                .any(|(cond, value)| nodes_use_loops(cond) || nodes_use_loops(value))
+                || nodes_use_loops(otherwise)
+        }
+        FormatNode::Compare { left, right, .. } => nodes_use_loops(left) || nodes_use_loops(right),
+        FormatNode::Match {
+            pattern, subject, ..
+        } => nodes_use_loops(pattern) || nodes_use_loops(subject),
+        FormatNode::Modified { value, modifiers } => {
+            nodes_use_loops(value)
+                || modifiers
+                    .iter()
+                    .any(|m| matches!(m, Modifier::Expand | Modifier::ExpandTime))
+        }
+    })
+}
 
-    let branch = if is_true { true_str } else { false_str };
+// ---------------------------------------------------------------------------
+// Evaluation
+// ---------------------------------------------------------------------------
 
-    // The branch itself may contain `#{}` expressions, so expand it.
-    output.push_str(&expand_format(branch, ctx));
+/// Expand a tmux format string, substituting `#{variable}` placeholders,
+/// single-character `#X` short-form aliases, and evaluating conditionals,
+/// comparisons, modifiers and loops using the provided context.
+///
+/// Short-form aliases (from tmux `format_table[]`):
+///   `#D` → `#{pane_id}`, `#F` → `#{window_flags}`,
+///   `#I` → `#{window_index}`, `#P` → `#{pane_index}`,
+///   `#S` → `#{session_name}`, `#T` → `#{pane_title}`,
+///   `#W` → `#{window_name}`
+///
+/// `##` expands to a literal `#`.
+///
+/// Unknown variables expand to the empty string.
+pub fn expand_format(fmt: &str, ctx: &FormatContext) -> String {
+    let mut output = String::with_capacity(fmt.len());
+    eval_nodes(&parse_format(fmt), ctx, 0, &mut output);
+    output
 }
 
-/// Split the conditional body on top-level commas (those not nested inside
-/// `#{}` expressions). Returns a `Vec` of the parts as `String`s.
-fn split_conditional_parts(s: &str) -> Vec<String> {
-    let bytes = s.as_bytes();
-    let mut parts = Vec::new();
-    let mut depth: usize = 0;
-    let mut start = 0;
+fn eval_nodes(nodes: &[FormatNode], ctx: &FormatContext, depth: usize, out: &mut String) {
+    for node in nodes {
+        eval_node(node, ctx, depth, out);
+    }
+}
 
-    for (i, &b) in bytes.iter().enumerate() {
-        match b {
-            b'{' => depth += 1,
-            b'}' => {
-                if depth > 0 {
-                    depth -= 1;
-                }
+fn eval_to_string(nodes: &[FormatNode], ctx: &FormatContext, depth: usize) -> String {
+    let mut out = String::new();
+    eval_nodes(nodes, ctx, depth, &mut out);
+    out
+}
+
+fn eval_node(node: &FormatNode, ctx: &FormatContext, depth: usize, out: &mut String) {
+    match node {
+        FormatNode::Text(text) => out.push_str(text),
+        FormatNode::Variable(name) => resolve_variable(name, ctx, out),
+        FormatNode::Conditional {
+            branches,
+            otherwise,
+        } => {
+            let chosen = branches
+                .iter()
+                .find(|(cond, _)| is_true(&eval_to_string(cond, ctx, depth)))
+                .map(|(_, value)| value)
+                .unwrap_or(otherwise);
+            eval_nodes(chosen, ctx, depth, out);
+        }
+        FormatNode::Compare { op, left, right } => {
+            let left = eval_to_string(left, ctx, depth);
+            let right = eval_to_string(right, ctx, depth);
+            let result = match op {
+                CompareOp::Eq => left == right,
+                CompareOp::Ne => left != right,
+                CompareOp::Lt => left < right,
+                CompareOp::Gt => left > right,
+                CompareOp::Le => left <= right,
+                CompareOp::Ge => left >= right,
+                CompareOp::Or => is_true(&left) || is_true(&right),
+                CompareOp::And => is_true(&left) && is_true(&right),
+            };
+            out.push(if result { '1' } else { '0' });
+        }
+        FormatNode::Match {
+            pattern,
+            subject,
+            regex,
+            ignore_case,
+        } => {
+            let pattern = eval_to_string(pattern, ctx, depth);
+            let subject = eval_to_string(subject, ctx, depth);
+            let matched = if *regex {
+                regex_matches(&pattern, &subject, *ignore_case)
+            } else {
+                glob_matches(&pattern, &subject, *ignore_case)
+            };
+            out.push(if matched { '1' } else { '0' });
+        }
+        FormatNode::Loop {
+            kind,
+            each,
+            current,
+        } => {
+            if depth >= FORMAT_LOOP_LIMIT {
+                return;
             }
-            b',' if depth == 0 => {
-                parts.push(s[start..i].to_string());
-                start = i + 1;
+            let items = match kind {
+                LoopKind::Sessions => &ctx.loop_sessions,
+                LoopKind::Windows => &ctx.loop_windows,
+                LoopKind::Panes => &ctx.loop_panes,
+            };
+            for item in items {
+                let is_current = match kind {
+                    LoopKind::Sessions => item.session_id == ctx.session_id,
+                    LoopKind::Windows => item.window_id == ctx.window_id,
+                    LoopKind::Panes => item.pane_active,
+                };
+                let body = match current {
+                    Some(current) if is_current => current,
+                    _ => each,
+                };
+                eval_nodes(body, item, depth + 1, out);
+            }
+        }
+        FormatNode::Modified { value, modifiers } => {
+            let value = eval_to_string(value, ctx, depth);
+            out.push_str(&apply_modifiers(value, modifiers, ctx, depth));
+        }
+    }
+}
+
+/// tmux's notion of truth: non-empty and not `"0"`.
+pub fn is_true(value: &str) -> bool {
+    !value.is_empty() && value != "0"
+}
+
+/// Apply value modifiers in the order tmux does, regardless of the order
+/// they were written in: re-expansion, time formatting, substitutions and
+/// finally truncation.
+fn apply_modifiers(
+    mut value: String,
+    modifiers: &[Modifier],
+    ctx: &FormatContext,
+    depth: usize,
+) -> String {
+    for modifier in modifiers {
+        match modifier {
+            Modifier::Expand => value = expand_nested(&value, ctx, depth),
+            Modifier::ExpandTime => {
+                value = strftime(&expand_nested(&value, ctx, depth), &Local::now())
             }
             _ => {}
         }
     }
-    // Push the final segment.
-    parts.push(s[start..].to_string());
-    parts
+    for modifier in modifiers {
+        if let Modifier::Time(style) = modifier {
+            value = format_time(&value, style);
+        }
+    }
+    for modifier in modifiers {
+        if let Modifier::Substitute {
+            pattern,
+            replacement,
+            ignore_case,
+        } = modifier
+        {
+            value = substitute(&value, pattern, replacement, *ignore_case);
+        }
+    }
+    for modifier in modifiers {
+        if let Modifier::Truncate { length, marker } = modifier {
+            value = truncate(&value, *length, marker);
+        }
+    }
+    value
+}
+
+/// Expand `value` as a format one level deeper, for `E:` and `T:`.
+fn expand_nested(value: &str, ctx: &FormatContext, depth: usize) -> String {
+    if depth >= FORMAT_LOOP_LIMIT {
+        return String::new();
+    }
+    let mut out = String::new();
+    eval_nodes(&parse_format(value), ctx, depth + 1, &mut out);
+    out
+}
+
+/// Format a Unix timestamp for the `t:` modifier. Non-numeric and zero
+/// values expand to nothing.
+fn format_time(value: &str, style: &TimeFormat) -> String {
+    let time = match value.trim().parse::<i64>() {
+        Ok(secs) if secs > 0 => Local.timestamp_opt(secs, 0).single(),
+        _ => None,
+    };
+    let Some(time) = time else {
+        return String::new();
+    };
+    match style {
+        TimeFormat::Default => strftime("%a %b %e %H:%M:%S %Y", &time),
+        TimeFormat::Pretty => pretty_time(&time, &Local::now()),
+        TimeFormat::Custom(fmt) => strftime(fmt, &time),
+    }
+}
+
+/// The `t/p:` layout: the time of day for the last 24 hours, then the day,
+/// then day and month, then month and year.
+fn pretty_time<Tz: TimeZone>(time: &DateTime<Tz>, now: &DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let age = now.timestamp() - time.timestamp();
+    let same_year = time.year() == now.year();
+    let fmt = if age < 24 * 3600 {
+        "%H:%M"
+    } else if (same_year && time.month() == now.month()) || age < 28 * 24 * 3600 {
+        "%a%d"
+    } else if (same_year && time.month() < now.month())
+        || (time.year() + 1 == now.year() && time.month() > now.month())
+    {
+        "%d%b"
+    } else {
+        "%b%y"
+    };
+    strftime(fmt, time)
+}
+
+/// Format `time` with a strftime(3) string. Invalid specifiers leave the
+/// format unexpanded rather than failing.
+fn strftime<Tz: TimeZone>(fmt: &str, time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let items: Vec<Item> = StrftimeItems::new(fmt).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return fmt.to_string();
+    }
+    time.format_with_items(items.into_iter()).to_string()
+}
+
+/// Build a regex, prefixing `(?i)` when matching should ignore case.
+fn build_regex(pattern: &str, ignore_case: bool) -> Option<fancy_regex::Regex> {
+    let pattern = if ignore_case {
+        format!("(?i){pattern}")
+    } else {
+        pattern.to_string()
+    };
+    fancy_regex::Regex::new(&pattern).ok()
+}
+
+/// The `s/pattern/replacement/` modifier. An invalid pattern leaves the value
+/// unchanged.
+fn substitute(value: &str, pattern: &str, replacement: &str, ignore_case: bool) -> String {
+    let Some(re) = build_regex(pattern, ignore_case) else {
+        return value.to_string();
+    };
+    let replacement = regex_replacement(replacement);
+    match re.try_replacen(value, 0, replacement.as_str()) {
+        Ok(result) => result.into_owned(),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// Convert a POSIX-style replacement (`\1` back-references) into the `${1}`
+/// syntax understood by the regex engine, escaping literal `$`.
+fn regex_replacement(replacement: &str) -> String {
+    let mut out = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '$' => out.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => {
+                    let _ = write!(out, "${{{d}}}");
+                }
+                Some('$') => out.push_str("$$"),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// The `=N` modifier: keep the first `length` characters, or the last
+/// `-length` characters when negative, marking the cut with `marker`.
+fn truncate(value: &str, length: i64, marker: &str) -> String {
+    let count = value.chars().count();
+    let limit = length.unsigned_abs() as usize;
+    if count <= limit {
+        return value.to_string();
+    }
+    if length >= 0 {
+        let mut out: String = value.chars().take(limit).collect();
+        out.push_str(marker);
+        out
+    } else {
+        let mut out = marker.to_string();
+        out.extend(value.chars().skip(count - limit));
+        out
+    }
+}
+
+/// Regular expression match for `m/r:`.
+fn regex_matches(pattern: &str, subject: &str, ignore_case: bool) -> bool {
+    build_regex(pattern, ignore_case)
+        .and_then(|re| re.is_match(subject).ok())
+        .unwrap_or(false)
+}
+
+/// fnmatch(3)-style match for `m:`, supporting `*`, `?`, `[...]` classes
+/// and `\` escapes.
+fn glob_matches(pattern: &str, subject: &str, ignore_case: bool) -> bool {
+    let (pattern, subject) = if ignore_case {
+        (pattern.to_lowercase(), subject.to_lowercase())
+    } else {
+        (pattern.to_string(), subject.to_string())
+    };
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = subject.chars().collect();
+
+    let (mut pi, mut si) = (0, 0);
+    // Position of the most recent `*` and the subject index it matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    while si < s.len() {
+        if p.get(pi) == Some(&'*') {
+            star = Some((pi, si));
+            pi += 1;
+            continue;
+        }
+        if let Some(next) = glob_step(&p, pi, s[si]) {
+            pi = next;
+            si += 1;
+            continue;
+        }
+        match star {
+            Some((star_pi, star_si)) => {
+                pi = star_pi + 1;
+                si = star_si + 1;
+                star = Some((star_pi, star_si + 1));
+            }
+            None => return false,
+        }
+    }
+    while p.get(pi) == Some(&'*') {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+/// Try to match the single pattern element at `pi` against `ch`, returning
+/// the index of the next pattern element on success.
+fn glob_step(p: &[char], pi: usize, ch: char) -> Option<usize> {
+    match *p.get(pi)? {
+        '?' => Some(pi + 1),
+        '[' => match glob_bracket(p, pi, ch) {
+            Some((matched, next)) => matched.then_some(next),
+            // No closing `]`: the `[` is an ordinary character.
+            None => (ch == '[').then_some(pi + 1),
+        },
+        '\\' if pi + 1 < p.len() => (p[pi + 1] == ch).then_some(pi + 2),
+        c => (c == ch).then_some(pi + 1),
+    }
+}
+
+/// Match `ch` against the bracket expression starting at `p[start] == '['`.
+/// Returns whether it matched and the index after the closing `]`, or `None`
+/// if the bracket is never closed.
+fn glob_bracket(p: &[char], start: usize, ch: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(p.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < p.len() {
+        if p[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            if p[i] <= ch && ch <= p[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if p[i] == ch {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+    None
 }
 
 /// Resolve a single variable name and write the result into `output`.
@@ -261,6 +1060,11 @@ fn resolve_variable(name: &str, ctx: &FormatContext, output: &mut String) {
         "pane_dead" => {
             output.push(if ctx.pane_dead { '1' } else { '0' });
         }
+        "pane_dead_status" => {
+            if let Some(status) = ctx.pane_dead_status {
+                let _ = write!(output, "{}", status);
+            }
+        }
         "window_index" => {
             let _ = write!(output, "{}", ctx.window_index);
         }
@@ -307,9 +1111,32 @@ fn resolve_variable(name: &str, ctx: &FormatContext, output: &mut String) {
         "pane_mode" => {
             output.push_str(&ctx.pane_mode);
         }
+        "pane_in_mode" => {
+            output.push(if ctx.pane_in_mode { '1' } else { '0' });
+        }
+        "scroll_position" => {
+            if let Some(position) = ctx.scroll_position {
+                let _ = write!(output, "{}", position);
+            }
+        }
+        "selection_present" => {
+            output.push(if ctx.selection_present { '1' } else { '0' });
+        }
+        "pane_pipe" => {
+            output.push(if ctx.pane_pipe { '1' } else { '0' });
+        }
         "window_flags" => {
             output.push_str(&ctx.window_flags);
         }
+        "window_activity_flag" => {
+            output.push(if ctx.window_activity_flag { '1' } else { '0' });
+        }
+        "window_bell_flag" => {
+            output.push(if ctx.window_bell_flag { '1' } else { '0' });
+        }
+        "window_silence_flag" => {
+            output.push(if ctx.window_silence_flag { '1' } else { '0' });
+        }
         "window_panes" => {
             let _ = write!(output, "{}", ctx.window_panes);
         }
@@ -341,6 +1168,23 @@ fn resolve_variable(name: &str, ctx: &FormatContext, output: &mut String) {
         "buffer_sample" => {
             output.push_str(&ctx.buffer_sample);
         }
+        "hook" => output.push_str(&ctx.hook),
+        "hook_pane" => output.push_str(&ctx.hook_pane),
+        "hook_window" => output.push_str(&ctx.hook_window),
+        "hook_window_name" => output.push_str(&ctx.hook_window_name),
+        "hook_session" => output.push_str(&ctx.hook_session),
+        "hook_session_name" => output.push_str(&ctx.hook_session_name),
+        "hook_client" => output.push_str(&ctx.hook_client),
+        "session_format" | "window_format" | "pane_format" => {
+            let is_type = !ctx.format_type.is_empty()
+                && name.strip_suffix("_format") == Some(ctx.format_type);
+            output.push(if is_type { '1' } else { '0' });
+        }
+        _ if name.starts_with('@') => {
+            if let Some(value) = ctx.user_options.get(name) {
+                output.push_str(value);
+            }
+        }
         _ => {
             // Unknown variable — expand to empty string.
         }
@@ -557,6 +1401,7 @@ mod tests {
             pane_left: 0,
             pane_top: 0,
             pane_dead: false,
+            pane_dead_status: None,
             window_id: 1,
             window_index: 0,
             window_name: "bash".to_string(),
@@ -574,7 +1419,14 @@ mod tests {
             pane_current_path: "/home/user/project".to_string(),
             pane_pid: 12345,
             pane_mode: String::new(),
+            pane_in_mode: false,
+            scroll_position: None,
+            selection_present: false,
+            pane_pipe: false,
             window_flags: "*".to_string(),
+            window_activity_flag: false,
+            window_bell_flag: false,
+            window_silence_flag: false,
             window_panes: 2,
             session_windows: 3,
             session_attached: 1,
@@ -584,6 +1436,18 @@ mod tests {
             buffer_name: String::new(),
             buffer_size: 0,
             buffer_sample: String::new(),
+            user_options: HashMap::new(),
+            hook: String::new(),
+            hook_pane: String::new(),
+            hook_window: String::new(),
+            hook_window_name: String::new(),
+            hook_session: String::new(),
+            hook_session_name: String::new(),
+            hook_client: String::new(),
+            format_type: "",
+            loop_sessions: Vec::new(),
+            loop_windows: Vec::new(),
+            loop_panes: Vec::new(),
         }
     }
 
@@ -751,6 +1615,20 @@ mod tests {
         assert_eq!(expand_format("#{pane_dead}", &ctx), "1");
     }
 
+    #[test]
+    fn pane_dead_status() {
+        assert_eq!(expand_format("#{pane_dead_status}", &test_ctx()), "");
+        let ctx = FormatContext {
+            pane_dead: true,
+            pane_dead_status: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            expand_format("#{?pane_dead,exited #{pane_dead_status},}", &ctx),
+            "exited 3"
+        );
+    }
+
     #[test]
     fn literal_hash_not_followed_by_brace() {
         let ctx = test_ctx();
@@ -885,7 +1763,7 @@ mod tests {
     #[test]
     fn phase10_pane_mode_empty() {
         let ctx = test_ctx();
-        // No mode infrastructure — always empty
+        // Empty outside copy mode
         assert_eq!(expand_format("#{pane_mode}", &ctx), "");
     }
 
@@ -895,6 +1773,32 @@ mod tests {
         assert_eq!(expand_format("#{window_flags}", &ctx), "*");
     }
 
+    #[test]
+    fn pane_pipe() {
+        assert_eq!(expand_format("#{pane_pipe}", &test_ctx()), "0");
+        let ctx = FormatContext {
+            pane_pipe: true,
+            ..test_ctx()
+        };
+        assert_eq!(expand_format("#{?pane_pipe,logging,}", &ctx), "logging");
+    }
+
+    #[test]
+    fn window_alert_flags() {
+        let ctx = FormatContext {
+            window_bell_flag: true,
+            ..test_ctx()
+        };
+        assert_eq!(
+            expand_format(
+                "#{window_activity_flag}#{window_bell_flag}#{window_silence_flag}",
+                &ctx
+            ),
+            "010"
+        );
+        assert_eq!(expand_format("#{?window_bell_flag,ding,}", &ctx), "ding");
+    }
+
     #[test]
     fn phase10_window_panes() {
         let ctx = test_ctx();
@@ -913,6 +1817,29 @@ mod tests {
         assert_eq!(expand_format("#{session_attached}", &ctx), "1");
     }
 
+    #[test]
+    fn copy_mode_variables() {
+        let mut ctx = test_ctx();
+        assert_eq!(
+            expand_format(
+                "#{pane_in_mode}:#{scroll_position}:#{selection_present}",
+                &ctx
+            ),
+            "0::0"
+        );
+        ctx.pane_mode = "copy-mode".to_string();
+        ctx.pane_in_mode = true;
+        ctx.scroll_position = Some(12);
+        ctx.selection_present = true;
+        assert_eq!(
+            expand_format(
+                "#{pane_mode} #{?pane_in_mode,#{scroll_position},-} #{selection_present}",
+                &ctx
+            ),
+            "copy-mode 12 1"
+        );
+    }
+
     #[test]
     fn phase10_pane_mode_conditional() {
         // pane_mode is empty → falsy in conditional
@@ -1182,4 +2109,450 @@ mod tests {
             expand_format("#{window_name}", &ctx)
         );
     }
+
+    // --- Format language: comparisons, modifiers and loops ---
+    //
+    // Most of these mirror the examples in the FORMATS section of tmux(1).
+
+    #[test]
+    fn parse_plain_variable() {
+        assert_eq!(
+            parse_format("#{pane_id}"),
+            vec![FormatNode::Variable("pane_id".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse_text_and_alias() {
+        assert_eq!(
+            parse_format("pane #D"),
+            vec![
+                FormatNode::Text("pane ".to_string()),
+                FormatNode::Variable("pane_id".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_variable_named_like_modifier() {
+        // `session_name` must not be mistaken for an `s` substitution.
+        assert_eq!(
+            parse_format("#{session_name}"),
+            vec![FormatNode::Variable("session_name".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse_comparison() {
+        assert_eq!(
+            parse_format("#{==:#{pane_index},0}"),
+            vec![FormatNode::Compare {
+                op: CompareOp::Eq,
+                left: vec![FormatNode::Variable("pane_index".to_string())],
+                right: vec![FormatNode::Text("0".to_string())],
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_chained_modifiers() {
+        assert_eq!(
+            parse_format("#{=3;s/a/b/:window_name}"),
+            vec![FormatNode::Modified {
+                value: vec![FormatNode::Variable("window_name".to_string())],
+                modifiers: vec![
+                    Modifier::Truncate {
+                        length: 3,
+                        marker: String::new(),
+                    },
+                    Modifier::Substitute {
+                        pattern: "a".to_string(),
+                        replacement: "b".to_string(),
+                        ignore_case: false,
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn compare_equal() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{==:#{session_name},main}", &ctx), "1");
+        assert_eq!(expand_format("#{==:#{session_name},other}", &ctx), "0");
+    }
+
+    #[test]
+    fn compare_not_equal() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{!=:#{window_name},bash}", &ctx), "0");
+        assert_eq!(expand_format("#{!=:#{window_name},zsh}", &ctx), "1");
+    }
+
+    #[test]
+    fn compare_ordering() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{<:a,b}", &ctx), "1");
+        assert_eq!(expand_format("#{>:a,b}", &ctx), "0");
+        assert_eq!(expand_format("#{<=:b,b}", &ctx), "1");
+        assert_eq!(expand_format("#{>=:a,b}", &ctx), "0");
+    }
+
+    #[test]
+    fn boolean_or_and() {
+        // tmux(1): #{||:#{pane_in_mode},#{alternate_on}}
+        let ctx = test_ctx();
+        assert_eq!(
+            expand_format("#{||:#{pane_active},#{pane_dead}}", &ctx),
+            "1"
+        );
+        assert_eq!(
+            expand_format("#{&&:#{pane_active},#{pane_dead}}", &ctx),
+            "0"
+        );
+        assert_eq!(expand_format("#{||:0,}", &ctx), "0");
+    }
+
+    #[test]
+    fn conditional_with_comparison() {
+        // tmux(1): #{?#{==:#{host},myhost},yes,no}
+        let ctx = test_ctx();
+        assert_eq!(
+            expand_format("#{?#{==:#{session_name},main},yes,no}", &ctx),
+            "yes"
+        );
+        assert_eq!(
+            expand_format("#{?#{==:#{session_name},dev},yes,no}", &ctx),
+            "no"
+        );
+    }
+
+    #[test]
+    fn conditional_chain() {
+        let ctx = test_ctx();
+        assert_eq!(
+            expand_format("#{?pane_dead,dead,pane_active,active,other}", &ctx),
+            "active"
+        );
+        assert_eq!(
+            expand_format("#{?pane_dead,dead,window_panes,many}", &ctx),
+            "many"
+        );
+    }
+
+    #[test]
+    fn conditional_escaped_comma() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{?pane_active,a#,b,c}", &ctx), "a,b");
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let ctx = test_ctx();
+        assert_eq!(
+            expand_format("#{?pane_dead,dead,#{?pane_active,[#{pane_id}],idle}}", &ctx),
+            "[%5]"
+        );
+    }
+
+    #[test]
+    fn match_glob() {
+        // tmux(1): #{m:*foo*,#{host}}
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{m:*proj*,#{pane_title}}", &ctx), "1");
+        assert_eq!(expand_format("#{m:*foo*,#{pane_title}}", &ctx), "0");
+        assert_eq!(expand_format("#{m:v?m,#{pane_current_command}}", &ctx), "1");
+        assert_eq!(
+            expand_format("#{m:[uv]im,#{pane_current_command}}", &ctx),
+            "1"
+        );
+        assert_eq!(
+            expand_format("#{m:[!v]im,#{pane_current_command}}", &ctx),
+            "0"
+        );
+    }
+
+    #[test]
+    fn match_regex_ignore_case() {
+        // tmux(1): #{m/ri:^A,MYVAR}
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{m/ri:^M,#{session_name}}", &ctx), "1");
+        assert_eq!(expand_format("#{m/r:^M,#{session_name}}", &ctx), "0");
+        assert_eq!(expand_format("#{m/i:MAIN,#{session_name}}", &ctx), "1");
+    }
+
+    #[test]
+    fn glob_matching_rules() {
+        assert!(glob_matches("*", "", false));
+        assert!(glob_matches("a*b*c", "aXXbYYc", false));
+        assert!(!glob_matches("a*b", "aXXc", false));
+        assert!(glob_matches("[a-c]x", "bx", false));
+        assert!(glob_matches("\\*", "*", false));
+        assert!(!glob_matches("\\*", "x", false));
+        assert!(glob_matches("[x", "[x", false));
+    }
+
+    #[test]
+    fn truncate_from_start_and_end() {
+        // tmux(1): #{=5:pane_title} and #{=-5:pane_title}
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{=5:pane_title}", &ctx), "~/pro");
+        assert_eq!(expand_format("#{=-5:pane_title}", &ctx), "oject");
+        assert_eq!(expand_format("#{=50:pane_title}", &ctx), "~/project");
+    }
+
+    #[test]
+    fn truncate_with_marker() {
+        // tmux(1): #{=/5/...:pane_title}
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{=/5/...:pane_title}", &ctx), "~/pro...");
+        assert_eq!(expand_format("#{=/-5/...:pane_title}", &ctx), "...oject");
+        assert_eq!(expand_format("#{=/20/...:pane_title}", &ctx), "~/project");
+    }
+
+    #[test]
+    fn substitute_simple() {
+        // tmux(1): #{s/foo/bar/:window_name}
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{s/ba/z/:window_name}", &ctx), "zsh");
+    }
+
+    #[test]
+    fn substitute_backreference_ignore_case() {
+        // tmux(1): s/a(.)/\1x/i: changes abABab into bxBxbx
+        let ctx = FormatContext {
+            window_name: "abABab".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            expand_format("#{s/a(.)/\\1x/i:window_name}", &ctx),
+            "bxBxbx"
+        );
+    }
+
+    #[test]
+    fn substitute_literal_dollar_in_replacement() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{s/bash/$0/:window_name}", &ctx), "$0");
+    }
+
+    #[test]
+    fn substitute_then_truncate() {
+        let ctx = test_ctx();
+        assert_eq!(
+            expand_format("#{=4;s/project/code/:pane_current_path}", &ctx),
+            "/hom"
+        );
+        assert_eq!(
+            expand_format("#{=-4;s/project/code/:pane_current_path}", &ctx),
+            "code"
+        );
+    }
+
+    #[test]
+    fn literal_modifier() {
+        // tmux(1): #{l:#{?pane_in_mode,yes,no}}
+        let ctx = test_ctx();
+        assert_eq!(
+            expand_format("#{l:#{?pane_in_mode,yes,no}}", &ctx),
+            "#{?pane_in_mode,yes,no}"
+        );
+    }
+
+    #[test]
+    fn expand_modifier() {
+        let ctx = FormatContext {
+            pane_id: 5,
+            window_name: "#{pane_id}".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(expand_format("#{window_name}", &ctx), "#{pane_id}");
+        assert_eq!(expand_format("#{E:window_name}", &ctx), "%5");
+    }
+
+    #[test]
+    fn expand_time_modifier() {
+        let ctx = FormatContext {
+            session_name: "main".to_string(),
+            window_name: "#{session_name}%%".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(expand_format("#{T:window_name}", &ctx), "main%");
+    }
+
+    #[test]
+    fn expand_is_depth_limited() {
+        let ctx = FormatContext {
+            window_name: "#{E:window_name}".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(expand_format("#{E:window_name}", &ctx), "");
+    }
+
+    #[test]
+    fn time_modifier() {
+        let ctx = test_ctx();
+        let expected = Local
+            .timestamp_opt(1_700_000_000, 0)
+            .single()
+            .unwrap()
+            .format("%a %b %e %H:%M:%S %Y")
+            .to_string();
+        assert_eq!(expand_format("#{t;l:1700000000}", &ctx), expected);
+        assert_eq!(expand_format("#{t:pane_mode}", &ctx), "");
+    }
+
+    #[test]
+    fn time_modifier_custom_format() {
+        // tmux(1): #{t/f/%%H#:%%M:window_activity}
+        let ctx = test_ctx();
+        let expected = Local
+            .timestamp_opt(1_700_000_000, 0)
+            .single()
+            .unwrap()
+            .format("%H:%M")
+            .to_string();
+        assert_eq!(expand_format("#{t/f/%H#:%M;l:1700000000}", &ctx), expected);
+    }
+
+    #[test]
+    fn pretty_time_ranges() {
+        use chrono::Utc;
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let at = |y, m, d, h| Utc.with_ymd_and_hms(y, m, d, h, 30, 0).unwrap();
+        assert_eq!(pretty_time(&at(2026, 10, 16, 9), &now), "09:30");
+        assert_eq!(pretty_time(&at(2026, 10, 3, 9), &now), "Sat03");
+        assert_eq!(pretty_time(&at(2026, 2, 3, 9), &now), "03Feb");
+        assert_eq!(pretty_time(&at(2025, 11, 3, 9), &now), "03Nov");
+        assert_eq!(pretty_time(&at(2024, 2, 3, 9), &now), "Feb24");
+    }
+
+    #[test]
+    fn strftime_invalid_specifier_is_left_alone() {
+        use chrono::Utc;
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        assert_eq!(strftime("%Y-%m", &now), "2026-10");
+        assert_eq!(strftime("100%", &now), "100%");
+    }
+
+    /// A session with two windows; the second window has two panes.
+    fn loop_ctx() -> FormatContext {
+        let pane = |id, active| FormatContext {
+            pane_id: id,
+            pane_active: active,
+            ..Default::default()
+        };
+        let window = |id, name: &str, panes: Vec<FormatContext>| FormatContext {
+            window_id: id,
+            window_name: name.to_string(),
+            loop_panes: panes,
+            ..Default::default()
+        };
+        let windows = vec![
+            window(1, "edit", vec![pane(1, true)]),
+            window(2, "build", vec![pane(2, false), pane(3, true)]),
+        ];
+        let session = |id, name: &str| FormatContext {
+            session_id: id,
+            session_name: name.to_string(),
+            ..Default::default()
+        };
+        FormatContext {
+            session_id: 0,
+            session_name: "main".to_string(),
+            window_id: 2,
+            pane_id: 3,
+            loop_panes: windows[1].loop_panes.clone(),
+            loop_windows: windows,
+            loop_sessions: vec![session(0, "main"), session(1, "scratch")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn window_loop_with_current_format() {
+        // tmux(1): #{W:#{E:window-status-format} ,#{E:window-status-current-format} }
+        let ctx = loop_ctx();
+        assert_eq!(
+            expand_format("#{W:#{window_name} ,[#{window_name}] }", &ctx),
+            "edit [build] "
+        );
+        assert_eq!(expand_format("#{W:#{window_id}}", &ctx), "@1@2");
+    }
+
+    #[test]
+    fn pane_loop() {
+        let ctx = loop_ctx();
+        assert_eq!(
+            expand_format("#{P:#{pane_id} ,*#{pane_id} }", &ctx),
+            "%2 *%3 "
+        );
+    }
+
+    #[test]
+    fn session_loop() {
+        let ctx = loop_ctx();
+        assert_eq!(
+            expand_format("#{S:#{session_name}#{?#{==:#{session_id},$0},*,} }", &ctx),
+            "main* scratch "
+        );
+    }
+
+    #[test]
+    fn nested_window_pane_loop() {
+        let ctx = loop_ctx();
+        assert_eq!(
+            expand_format("#{W:#{window_name}(#{P:#{pane_id}}) }", &ctx),
+            "edit(%1) build(%2%3) "
+        );
+    }
+
+    #[test]
+    fn loop_without_items_is_empty() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{W:#{window_name}}", &ctx), "");
+    }
+
+    #[test]
+    fn format_uses_loops_detection() {
+        assert!(format_uses_loops("#{W:#{window_name}}"));
+        assert!(format_uses_loops("#{?pane_active,#{P:x},}"));
+        assert!(format_uses_loops("#{E:status-left}"));
+        assert!(!format_uses_loops("#{pane_id} #{=5:pane_title}"));
+    }
+
+    #[test]
+    fn hook_variables() {
+        let ctx = FormatContext {
+            hook: "pane-exited".to_string(),
+            hook_pane: "%3".to_string(),
+            hook_session_name: "work".to_string(),
+            ..FormatContext::default()
+        };
+        assert_eq!(
+            expand_format("#{hook} #{hook_pane} #{hook_session_name}", &ctx),
+            "pane-exited %3 work"
+        );
+        assert_eq!(expand_format("#{?hook_window,yes,no}", &ctx), "no");
+    }
+
+    #[test]
+    fn user_option_lookup() {
+        let mut ctx = test_ctx();
+        ctx.user_options
+            .insert("@theme".to_string(), "dark".to_string());
+        assert_eq!(expand_format("#{@theme}", &ctx), "dark");
+        assert_eq!(expand_format("#{@missing}", &ctx), "");
+        assert_eq!(
+            expand_format("#{?#{==:#{@theme},dark},night,day}", &ctx),
+            "night"
+        );
+    }
+
+    #[test]
+    fn escaped_brace_and_hash() {
+        let ctx = test_ctx();
+        assert_eq!(expand_format("#{?pane_active,#},}", &ctx), "}");
+        assert_eq!(expand_format("a#,b##", &ctx), "a,b#");
+    }
 }
@@ -4,69 +4,62 @@
 //! so that each command performs real operations and returns response content.
 
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, LazyLock};
 
+use futures::future::LocalBoxFuture;
 use parking_lot::Mutex as ParkMutex;
 
-use config::keyassignment::SpawnTabDomain;
-use wezterm_term::TerminalSize;
+use config::keyassignment::{
+    CopyModeAssignment, KeyAssignment, PaneDirection, ScrollbackEraseMode, SpawnTabDomain,
+};
+use config::{ExitBehavior, MAX_SCROLLBACK_LINES};
+use termwiz::escape::csi::KittyKeyboardFlags;
+use termwiz::input::KeyboardEncoding;
+use termwiz::surface::{Line, SEQ_ZERO};
+use wezterm_term::{
+    ClipboardSelection, KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    TerminalSize,
+};
 
 use portable_pty::CommandBuilder;
 
 use crate::domain::SplitSource;
-use crate::pane::{CachePolicy, Pane, PaneId};
-use crate::tab::{SplitDirection, SplitRequest, SplitSize, Tab};
+use crate::localpane::LocalPane;
+use crate::pane::{CachePolicy, Pane, PaneId, RemainOnExit};
+use crate::tab::{SplitDirection, SplitRequest, SplitSize, Tab, TabPopup};
 use crate::window::WindowId;
-use crate::Mux;
-
-use super::command_parser::TmuxCliCommand;
-use super::format::{expand_format, tmux_style_to_ansi, FormatContext};
+use crate::{Mux, MuxNotification};
+
+use super::alerts::window_alert_flags;
+use super::choose::{
+    self, buffer_choices, client_choices, tree_choices, ChooseOptions, TreeDepth, BUFFER_TEMPLATE,
+    CLIENT_TEMPLATE, PANES_TEMPLATE, TREE_TEMPLATE,
+};
+use super::command_parser::{parse_command_list, parse_config, CommandListItem, TmuxCliCommand};
+use super::copy_mode::{copy_modes, overlay_actions, CopyModeState, Outcome, PaneCopyMode};
+use super::environment::environments;
+use super::format::{expand_format, format_uses_loops, is_true, tmux_style_to_ansi, FormatContext};
+use super::hooks::{fire_after_hook, fire_hook, hooks, is_valid_hook, note_pane_killed, HookEvent};
 use super::id_map::IdMap;
-use super::paste_buffer::{buffer_sample, PasteBufferStore};
-use super::response::session_changed_notification;
-use super::target::{parse_target, PaneRef, SessionRef, TmuxTarget, WindowRef};
-
-// ---------------------------------------------------------------------------
-// Wait channel infrastructure for `wait-for` command
-// ---------------------------------------------------------------------------
-
-/// State for a single wait channel.
-struct WaitChannel {
-    /// Whether a signal has been sent but no one has consumed it yet.
-    woken: bool,
-    /// Senders waiting to be notified. When signaled, we send `()` to all.
-    waiters: Vec<async_channel::Sender<()>>,
-}
-
-/// Global wait channel store, keyed by channel name.
-static WAIT_CHANNELS: LazyLock<ParkMutex<HashMap<String, WaitChannel>>> =
-    LazyLock::new(Default::default);
-
-// ---------------------------------------------------------------------------
-// Pipe-pane infrastructure
-// ---------------------------------------------------------------------------
-
-/// Handle to a running pipe-pane child process and its shutdown channel.
-struct PipePaneHandle {
-    child: std::process::Child,
-    shutdown_tx: async_channel::Sender<()>,
-}
-
-/// Global pipe-pane state, keyed by WezTerm pane ID.
-static PIPE_PANE_STATE: LazyLock<ParkMutex<HashMap<PaneId, PipePaneHandle>>> =
-    LazyLock::new(Default::default);
-
-/// Close (and clean up) any active pipe-pane for the given WezTerm pane.
-pub fn close_pipe_pane(pane_id: PaneId) {
-    let mut state = PIPE_PANE_STATE.lock();
-    if let Some(mut handle) = state.remove(&pane_id) {
-        // Signal shutdown to the feeder threads
-        let _ = handle.shutdown_tx.try_send(());
-        // Kill the child process
-        let _ = handle.child.kill();
-        let _ = handle.child.wait();
-    }
-}
+use super::key_bindings::{
+    format_bindings, format_notes, key_bindings, normalize_key, prefix_keys,
+};
+use super::layout::{parse_layout_string, spread_out, tree_from_layout, LayoutNode};
+use super::named_server;
+use super::options::{
+    default_value, is_user_option, option_table, options, quote_option_value, OptionScope,
+    OptionTable,
+};
+use super::paste_buffer::{buffer_sample, paste_buffers, DEFAULT_BUFFER_LIMIT};
+use super::pipe_pane::{close_pipe_pane, close_pipe_panes, is_piped, open_pipe_pane};
+use super::popup::{popup_rect, Rect};
+use super::response::{layout_change_notification, session_changed_notification};
+use super::split_drag;
+use super::target::{
+    parse_session_target, parse_target, PaneRef, SessionRef, TmuxTarget, WindowRef,
+};
+use super::wait_for;
 
 // ---------------------------------------------------------------------------
 // Global CC pane tracking (shared across one-shot connections)
@@ -178,6 +171,7 @@ pub fn cleanup_cc_spawned_panes(workspace: &str) {
     );
     if let Some(mux) = Mux::try_get() {
         for pane_id in panes {
+            note_pane_killed(pane_id);
             mux.remove_pane(pane_id);
         }
     }
@@ -186,7 +180,8 @@ pub fn cleanup_cc_spawned_panes(workspace: &str) {
 /// Reap dead CC-spawned panes during an active session.
 ///
 /// Checks all tracked CC-spawned panes; any that are dead (process exited)
-/// or already removed from the mux are cleaned up.
+/// or already removed from the mux are cleaned up. Panes kept open by
+/// `remain-on-exit` are left in place.
 pub fn reap_dead_cc_panes(ctx: &mut HandlerContext) {
     let mux = match Mux::try_get() {
         Some(m) => m,
@@ -273,8 +268,6 @@ pub struct HandlerContext {
     pub client_name: String,
     /// Listen address for `#{socket_path}` format variable.
     pub socket_path: String,
-    /// In-process paste buffer store for clipboard/buffer commands.
-    pub paste_buffers: PasteBufferStore,
     /// Pause-after age in milliseconds. When set, `%extended-output` is sent
     /// instead of `%output`, and panes are paused when buffered output exceeds
     /// this age. `None` means pause mode is disabled.
@@ -297,6 +290,22 @@ pub struct HandlerContext {
     /// These are preferred over the terminal's own title (which the shell can
     /// override via OSC 2) when expanding `#{pane_title}`.
     pub pane_titles: HashMap<PaneId, String>,
+    /// Whether `after-*` hooks fire for commands run in this context. Hooks
+    /// run their commands with this off, so a hook never triggers a hook.
+    pub fire_after_hooks: bool,
+    /// How many `source-file` commands are running, to stop a file that
+    /// sources itself.
+    pub source_depth: usize,
+    /// Socket path of the named server (`tmux -L`/`-S`) this client is
+    /// attached to, or `None` for the default server.
+    pub server: Option<String>,
+    /// Closed when the client's connection closes, so that commands that
+    /// block, like `wait-for`, can give up.
+    pub client_gone: Option<async_channel::Receiver<()>>,
+    /// Whether the commands run for the GUI, such as key bindings, rather
+    /// than for a connected client. `switch-client` then switches the
+    /// workspace and pane shown in the GUI.
+    pub for_gui: bool,
 
 }
 
@@ -315,7 +324,6 @@ impl HandlerContext {
             suppress_window_changed: 0,
             client_name: String::new(),
             socket_path: String::new(),
-            paste_buffers: PasteBufferStore::new(),
             pause_age_ms: None,
             wait_exit: false,
             paused_panes: HashMap::new(),
@@ -323,6 +331,11 @@ impl HandlerContext {
             subscriptions: Vec::new(),
             pane_border_formats: HashMap::new(),
             pane_titles: HashMap::new(),
+            fire_after_hooks: true,
+            source_depth: 0,
+            server: None,
+            client_gone: None,
+            for_gui: false,
         }
     }
 
@@ -331,8 +344,11 @@ impl HandlerContext {
     /// Loads previously persisted pane/window/session ID mappings so that
     /// reconnecting CC clients see the same tmux IDs as before.
     /// Stale mappings (referencing panes/tabs that no longer exist) are pruned.
-    pub fn with_persistent_ids(workspace: String) -> Self {
-        let mut id_map = IdMap::load(&workspace);
+    pub fn with_persistent_ids(workspace: String, server: Option<String>) -> Self {
+        let mut ctx = Self::new(workspace);
+        ctx.server = server;
+        let workspace = ctx.workspace.clone();
+        let mut id_map = IdMap::load(&ctx.id_map_key());
 
         // Prune mappings that reference dead panes/tabs.
         if let Some(mux) = Mux::try_get() {
@@ -350,14 +366,63 @@ impl HandlerContext {
             id_map.prune_stale(&live_pane_ids, &live_tab_ids);
         }
 
-        let mut ctx = Self::new(workspace);
         ctx.id_map = id_map;
         ctx
     }
 
     /// Persist the current ID mappings to disk.
     pub fn save_id_map(&self) {
-        self.id_map.save(&self.workspace);
+        self.id_map.save(&self.id_map_key());
+    }
+
+    /// The name the ID mappings are saved under.
+    pub(super) fn id_map_key(&self) -> String {
+        id_map_key(&self.workspace, self.server.as_deref())
+    }
+
+    /// The sessions (workspaces) of this client's server.
+    pub fn workspaces(&self, mux: &Mux) -> Vec<String> {
+        named_server::sessions(self.server.as_deref(), mux.iter_workspaces())
+    }
+
+    /// The panes in the windows of this client's server. Popup panes aren't
+    /// in a window, so they are left out.
+    fn server_panes(&self, mux: &Mux) -> Vec<Arc<dyn Pane>> {
+        self.workspaces(mux)
+            .iter()
+            .flat_map(|workspace| mux.iter_windows_in_workspace(workspace))
+            .filter_map(|window_id| mux.get_window(window_id))
+            .flat_map(|window| window.iter().cloned().collect::<Vec<_>>())
+            .flat_map(|tab| tab.iter_panes_ignoring_zoom())
+            .map(|pp| pp.pane)
+            .collect()
+    }
+
+    /// The environment changes for a pane spawned in `workspace` with
+    /// `-e env`: the global and session environments, then `env`. A value of
+    /// `None` removes the variable. Panes of a named server point
+    /// `WEZTERM_TMUX_CC` at it, so that tmux run in them talks to the same
+    /// server, as it would with real tmux.
+    fn spawn_env(&self, workspace: &str, env: &[String]) -> Vec<(String, Option<String>)> {
+        let mut vars = environments(self.server.as_deref()).spawn_environment(workspace);
+        vars.extend(
+            env.iter()
+                .filter_map(|kv| kv.split_once('='))
+                .map(|(k, v)| (k.to_string(), Some(v.to_string()))),
+        );
+        if let Some(server) = &self.server {
+            vars.push(("WEZTERM_TMUX_CC".to_string(), Some(server.clone())));
+        }
+        vars
+    }
+}
+
+/// The name the ID mappings of `workspace` are saved under. tmux IDs belong
+/// to a server, so each named server keeps its own.
+pub(super) fn id_map_key(workspace: &str, server: Option<&str>) -> String {
+    match server {
+        Some(server) => format!("{}@{}", workspace, server),
+        None => workspace.to_string(),
     }
 }
 
@@ -365,69 +430,231 @@ impl HandlerContext {
 // Key resolution helpers
 // ---------------------------------------------------------------------------
 
-/// Resolve a single named key to its byte sequence.
-pub fn resolve_named_key(name: &str) -> Option<Vec<u8>> {
-    match name {
-        "Enter" | "CR" => Some(b"\r".to_vec()),
-        "Space" => Some(b" ".to_vec()),
-        "Tab" | "BTab" => Some(b"\t".to_vec()),
-        "Escape" => Some(b"\x1b".to_vec()),
-        "BSpace" => Some(b"\x7f".to_vec()),
-        "Up" => Some(b"\x1b[A".to_vec()),
-        "Down" => Some(b"\x1b[B".to_vec()),
-        "Right" => Some(b"\x1b[C".to_vec()),
-        "Left" => Some(b"\x1b[D".to_vec()),
-        "Home" => Some(b"\x1b[H".to_vec()),
-        "End" => Some(b"\x1b[F".to_vec()),
-        "Insert" => Some(b"\x1b[2~".to_vec()),
-        "Delete" | "DC" => Some(b"\x1b[3~".to_vec()),
-        "PageUp" | "PgUp" | "PPage" => Some(b"\x1b[5~".to_vec()),
-        "PageDown" | "PgDn" | "NPage" => Some(b"\x1b[6~".to_vec()),
-        "F1" => Some(b"\x1bOP".to_vec()),
-        "F2" => Some(b"\x1bOQ".to_vec()),
-        "F3" => Some(b"\x1bOR".to_vec()),
-        "F4" => Some(b"\x1bOS".to_vec()),
-        "F5" => Some(b"\x1b[15~".to_vec()),
-        "F6" => Some(b"\x1b[17~".to_vec()),
-        "F7" => Some(b"\x1b[18~".to_vec()),
-        "F8" => Some(b"\x1b[19~".to_vec()),
-        "F9" => Some(b"\x1b[20~".to_vec()),
-        "F10" => Some(b"\x1b[21~".to_vec()),
-        "F11" => Some(b"\x1b[23~".to_vec()),
-        "F12" => Some(b"\x1b[24~".to_vec()),
-        _ => {
-            // C-a through C-z: control characters
-            if name.starts_with("C-") && name.len() == 3 {
-                let ch = name.as_bytes()[2];
-                if ch.is_ascii_lowercase() {
-                    return Some(vec![ch - b'a' + 1]);
-                }
-            }
-            None
+/// A key from send-keys, ready to be delivered to a pane.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendKey {
+    /// A key press, encoded by the pane's terminal according to its modes.
+    Key(KeyCode, KeyModifiers),
+    /// Bytes written to the pane as they are.
+    Bytes(Vec<u8>),
+}
+
+impl SendKey {
+    /// Whether this key submits a line, as `Enter` and `C-m` do.
+    fn is_enter(&self) -> bool {
+        match self {
+            Self::Key(KeyCode::Enter, mods) => mods.is_empty(),
+            Self::Key(KeyCode::Char('m'), mods) => *mods == KeyModifiers::CTRL,
+            Self::Bytes(bytes) => bytes.ends_with(b"\r"),
+            Self::Key(..) => false,
+        }
+    }
+}
+
+/// Resolve a tmux key name to a key code and modifiers.
+///
+/// A name is an optional `^` (control) or any number of `C-`, `M-` and
+/// `S-` prefixes, followed by either a single character or one of tmux's
+/// key names. Key names are matched without regard to case.
+pub fn resolve_named_key(name: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut mods = KeyModifiers::NONE;
+    let mut rest = name;
+    if rest.len() > 1 {
+        if let Some(stripped) = rest.strip_prefix('^') {
+            mods |= KeyModifiers::CTRL;
+            rest = stripped;
+        }
+    }
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        mods |= match rest.as_bytes()[0] {
+            b'C' | b'c' => KeyModifiers::CTRL,
+            b'M' | b'm' => KeyModifiers::ALT,
+            b'S' | b's' => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = &rest[2..];
+    }
+
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if mods.is_empty() {
+            // A lone character is only a key when it has modifiers;
+            // otherwise it is just text.
+            return None;
         }
+        // As in tmux, C-A is the same key as C-a.
+        let c = if mods.contains(KeyModifiers::CTRL) {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        };
+        return Some((KeyCode::Char(c), mods));
     }
+
+    let lower = rest.to_ascii_lowercase();
+    let key = match lower.as_str() {
+        "enter" | "cr" => KeyCode::Enter,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "btab" => {
+            mods |= KeyModifiers::SHIFT;
+            KeyCode::Tab
+        }
+        "escape" => KeyCode::Escape,
+        "bspace" => KeyCode::Backspace,
+        "up" => KeyCode::UpArrow,
+        "down" => KeyCode::DownArrow,
+        "right" => KeyCode::RightArrow,
+        "left" => KeyCode::LeftArrow,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "insert" | "ic" => KeyCode::Insert,
+        "delete" | "dc" => KeyCode::Delete,
+        "pageup" | "pgup" | "ppage" => KeyCode::PageUp,
+        "pagedown" | "pgdn" | "npage" => KeyCode::PageDown,
+        // The key encoder has no application keypad mode, so keypad keys
+        // send what they do with the keypad in numeric mode.
+        "kpenter" => KeyCode::Enter,
+        kp if kp.len() == 3 && kp.starts_with("kp") => match kp.as_bytes()[2] {
+            c @ (b'0'..=b'9' | b'/' | b'*' | b'-' | b'+' | b'.') => KeyCode::Char(c as char),
+            _ => return None,
+        },
+        f => match f
+            .strip_prefix('f')
+            .filter(|n| n.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|n| n.parse::<u8>().ok())
+        {
+            Some(n) if (1..=24).contains(&n) => KeyCode::Function(n),
+            _ => return None,
+        },
+    };
+    Some((key, mods))
+}
+
+/// Encode a key for a pane whose program has enabled the kitty keyboard
+/// protocol.
+///
+/// The terminal's own encoder only produces the xterm forms; the GUI does
+/// the kitty encoding from its key events, so do the same here. Returns
+/// `None` for keys that have no equivalent key event.
+fn encode_kitty_key(key: KeyCode, mods: KeyModifiers, flags: KittyKeyboardFlags) -> Option<String> {
+    use wezterm_input_types::{KeyCode as WK, KeyEvent, KeyboardLedStatus};
+
+    let key = match key {
+        KeyCode::Char(c) if mods.contains(KeyModifiers::SHIFT) => WK::Char(c.to_ascii_uppercase()),
+        KeyCode::Char(c) => WK::Char(c),
+        KeyCode::Enter => WK::Char('\r'),
+        KeyCode::Tab => WK::Char('\t'),
+        KeyCode::Escape => WK::Char('\x1b'),
+        KeyCode::Backspace => WK::Char('\x08'),
+        KeyCode::Delete => WK::Char('\x7f'),
+        KeyCode::UpArrow => WK::UpArrow,
+        KeyCode::DownArrow => WK::DownArrow,
+        KeyCode::LeftArrow => WK::LeftArrow,
+        KeyCode::RightArrow => WK::RightArrow,
+        KeyCode::Home => WK::Home,
+        KeyCode::End => WK::End,
+        KeyCode::Insert => WK::Insert,
+        KeyCode::PageUp => WK::PageUp,
+        KeyCode::PageDown => WK::PageDown,
+        KeyCode::Function(n) => WK::Function(n),
+        _ => return None,
+    };
+    let event = KeyEvent {
+        key,
+        modifiers: mods,
+        leds: KeyboardLedStatus::empty(),
+        repeat_count: 1,
+        key_is_down: true,
+        raw: None,
+        #[cfg(windows)]
+        win32_uni_char: None,
+    };
+    Some(event.encode_kitty(flags))
+}
+
+/// Parse an SGR mouse report such as `\x1b[<0;10;5M` for `send-keys -M`.
+///
+/// The leading `\x1b[` may be left out. Coordinates are 1-based, as they
+/// are on the wire. A final `M` is a press or motion and `m` a release.
+pub fn parse_mouse_report(report: &str) -> Result<MouseEvent, String> {
+    let invalid = || format!("invalid mouse event: {}", report);
+    let body = report.strip_prefix("\x1b[").unwrap_or(report);
+    let body = body.strip_prefix('<').ok_or_else(invalid)?;
+    let (body, release) = match (body.strip_suffix('M'), body.strip_suffix('m')) {
+        (Some(body), _) => (body, false),
+        (_, Some(body)) => (body, true),
+        _ => return Err(invalid()),
+    };
+    let fields = body
+        .split(';')
+        .map(|field| field.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    let (code, x, y) = match fields[..] {
+        [code, x, y] if x > 0 && y > 0 => (code, x, y),
+        _ => return Err(invalid()),
+    };
+
+    let mut modifiers = KeyModifiers::NONE;
+    if code & 4 != 0 {
+        modifiers |= KeyModifiers::SHIFT;
+    }
+    if code & 8 != 0 {
+        modifiers |= KeyModifiers::ALT;
+    }
+    if code & 16 != 0 {
+        modifiers |= KeyModifiers::CTRL;
+    }
+    let button = match code & !(4 | 8 | 16 | 32) {
+        0 => MouseButton::Left,
+        1 => MouseButton::Middle,
+        2 => MouseButton::Right,
+        3 => MouseButton::None,
+        64 => MouseButton::WheelUp(1),
+        65 => MouseButton::WheelDown(1),
+        66 => MouseButton::WheelLeft(1),
+        67 => MouseButton::WheelRight(1),
+        _ => return Err(invalid()),
+    };
+    let kind = if code & 32 != 0 {
+        MouseEventKind::Move
+    } else if release {
+        MouseEventKind::Release
+    } else {
+        MouseEventKind::Press
+    };
+
+    Ok(MouseEvent {
+        kind,
+        x: x - 1,
+        y: y as i64 - 1,
+        x_pixel_offset: 0,
+        y_pixel_offset: 0,
+        button,
+        modifiers,
+    })
 }
 
-/// Resolve a single key argument from send-keys to bytes.
+/// Resolve a single key argument from send-keys.
 ///
 /// If `hex` is true, the key is a hex-encoded byte value (e.g. "0x1b" or "1b").
 /// If `literal` is true, the key is sent as literal UTF-8 text.
 /// Otherwise, try named key resolution first, then fall back to literal.
-pub fn resolve_key(key: &str, literal: bool, hex: bool) -> Result<Vec<u8>, String> {
+pub fn resolve_key(key: &str, literal: bool, hex: bool) -> Result<SendKey, String> {
     if hex {
         let hex_str = key.strip_prefix("0x").unwrap_or(key);
         let byte =
             u8::from_str_radix(hex_str, 16).map_err(|_| format!("invalid hex key: {}", key))?;
-        return Ok(vec![byte]);
+        return Ok(SendKey::Bytes(vec![byte]));
     }
     if literal {
-        return Ok(key.as_bytes().to_vec());
+        return Ok(SendKey::Bytes(key.as_bytes().to_vec()));
     }
     // Try named key, fall back to literal
-    if let Some(bytes) = resolve_named_key(key) {
-        Ok(bytes)
-    } else {
-        Ok(key.as_bytes().to_vec())
+    match resolve_named_key(key) {
+        Some((code, mods)) => Ok(SendKey::Key(code, mods)),
+        None => Ok(SendKey::Bytes(key.as_bytes().to_vec())),
     }
 }
 
@@ -463,7 +690,8 @@ pub fn parse_split_size(size: Option<&str>) -> Result<SplitSize, String> {
 // ---------------------------------------------------------------------------
 
 impl HandlerContext {
-    /// Resolve a tmux target string to WezTerm IDs.
+    /// Resolve a tmux target string to WezTerm IDs. Failures are reported
+    /// with tmux's own messages, such as `can't find pane: %9`.
     pub fn resolve_target(&self, target: &Option<String>) -> Result<ResolvedTarget, String> {
         let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
 
@@ -484,7 +712,7 @@ impl HandlerContext {
                 Some(ws.to_string())
             }
             Some(SessionRef::Name(name)) => {
-                let workspaces = mux.iter_workspaces();
+                let workspaces = self.workspaces(&mux);
                 if workspaces.contains(name) {
                     Some(name.clone())
                 } else {
@@ -508,7 +736,7 @@ impl HandlerContext {
                 // Find the mux window containing this tab
                 let wid = mux
                     .window_containing_tab(tab_id)
-                    .ok_or_else(|| format!("can't find window containing tab {}", tab_id))?;
+                    .ok_or_else(|| format!("can't find window: @{}", id))?;
                 resolved.tab_id = Some(tab_id);
                 Some(wid)
             }
@@ -516,11 +744,11 @@ impl HandlerContext {
                 let wid = window_ids
                     .get(*idx as usize)
                     .copied()
-                    .ok_or_else(|| format!("can't find window: index {}", idx))?;
+                    .ok_or_else(|| format!("can't find window: {}", idx))?;
                 // Get the active tab in that window
                 let window = mux
                     .get_window(wid)
-                    .ok_or_else(|| format!("can't find window: {}", wid))?;
+                    .ok_or_else(|| format!("can't find window: {}", idx))?;
                 resolved.tab_id = window.get_active().map(|t| t.tab_id());
                 Some(wid)
             }
@@ -584,16 +812,13 @@ impl HandlerContext {
             }
             Some(PaneRef::Index(idx)) => {
                 if let Some(tab_id) = resolved.tab_id {
-                    let tab = mux
+                    let pp = mux
                         .get_tab(tab_id)
-                        .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
-                    let panes = tab.iter_panes();
-                    let pp = panes
-                        .get(*idx as usize)
-                        .ok_or_else(|| format!("can't find pane: index {}", idx))?;
+                        .and_then(|tab| tab.iter_panes().into_iter().nth(*idx as usize))
+                        .ok_or_else(|| format!("can't find pane: {}", idx))?;
                     Some(pp.pane.pane_id())
                 } else {
-                    return Err("no window resolved for pane index lookup".to_string());
+                    return Err(format!("can't find pane: {}", idx));
                 }
             }
             None => {
@@ -603,7 +828,7 @@ impl HandlerContext {
                 } else if let Some(tab_id) = resolved.tab_id {
                     let tab = mux
                         .get_tab(tab_id)
-                        .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
+                        .ok_or_else(|| "no current target".to_string())?;
                     tab.get_active_pane().map(|p| p.pane_id())
                 } else {
                     None
@@ -624,7 +849,7 @@ pub fn build_format_context(
     ctx: &mut HandlerContext,
     pp: &crate::tab::PositionedPane,
     tab: &Arc<Tab>,
-    _window_id: WindowId,
+    window_id: WindowId,
     window_index: usize,
     workspace: &str,
 ) -> FormatContext {
@@ -665,9 +890,23 @@ pub fn build_format_context(
         .map(|info| info.pid as u64)
         .unwrap_or(0);
 
-    // Phase 10: window flags — tmux uses *=current, -=last, Z=zoomed
+    // Phase 10: window flags — tmux uses *=current, -=last, #=activity,
+    // !=bell, ~=silence, Z=zoomed
     let mut flags = String::new();
-    // window_active is set by caller, but we can detect zoom here
+    // window_active is set by caller, but we can detect alerts and zoom here
+    let is_current = Mux::try_get()
+        .and_then(|mux| mux.get_active_tab_for_window(window_id))
+        .is_some_and(|active| active.tab_id() == tab.tab_id());
+    let alert = window_alert_flags(ctx.server.as_deref(), tab.tab_id(), is_current);
+    if alert.activity {
+        flags.push('#');
+    }
+    if alert.bell {
+        flags.push('!');
+    }
+    if alert.silence {
+        flags.push('~');
+    }
     if tab.get_zoomed_pane().is_some() {
         flags.push('Z');
     }
@@ -680,6 +919,10 @@ pub fn build_format_context(
         .map(|mux| mux.iter_windows_in_workspace(workspace).len() as u64)
         .unwrap_or(0);
 
+    let copy_mode = copy_modes()
+        .get(&wez_pane_id)
+        .map(|mode| mode.state.clone());
+
     FormatContext {
         pane_id: tmux_pane_id,
         pane_index: pp.index as u64,
@@ -688,7 +931,8 @@ pub fn build_format_context(
         pane_active: pp.is_active,
         pane_left: pp.left as u64,
         pane_top: pp.top as u64,
-        pane_dead: pp.pane.is_dead(),
+        pane_dead: pp.pane.is_dead() || pp.pane.exit_code().is_some(),
+        pane_dead_status: pp.pane.exit_code(),
         window_id: tmux_window_id,
         window_index: window_index as u64,
         window_name: tab.get_title(),
@@ -699,14 +943,29 @@ pub fn build_format_context(
         session_name: workspace.to_string(),
         cursor_x: cursor.x as u64,
         cursor_y: cursor.y as u64,
-        history_limit: dims.scrollback_rows as u64,
+        history_limit: history_limit(&*pp.pane, workspace) as u64,
         history_size: dims.physical_top.saturating_sub(dims.scrollback_top) as u64,
         pane_title,
         pane_current_command,
         pane_current_path,
         pane_pid,
-        pane_mode: String::new(), // No pane mode infrastructure yet
+        pane_mode: if copy_mode.is_some() {
+            "copy-mode".to_string()
+        } else {
+            String::new()
+        },
+        pane_in_mode: copy_mode.is_some(),
+        scroll_position: copy_mode
+            .as_ref()
+            .map(|state| state.scroll_position(dims.physical_top) as u64),
+        selection_present: copy_mode
+            .as_ref()
+            .is_some_and(CopyModeState::selection_present),
+        pane_pipe: is_piped(wez_pane_id),
         window_flags: flags,
+        window_activity_flag: alert.activity,
+        window_bell_flag: alert.bell,
+        window_silence_flag: alert.silence,
         window_panes,
         session_windows,
         session_attached: 1, // Single-client CC
@@ -716,7 +975,141 @@ pub fn build_format_context(
         buffer_name: String::new(),
         buffer_size: 0,
         buffer_sample: String::new(),
+        user_options: options(ctx.server.as_deref()).format_user_options(
+            Some(wez_pane_id),
+            Some(tab.tab_id()),
+            workspace,
+        ),
+        hook: String::new(),
+        hook_pane: String::new(),
+        hook_window: String::new(),
+        hook_window_name: String::new(),
+        hook_session: String::new(),
+        hook_session_name: String::new(),
+        hook_client: String::new(),
+        format_type: "",
+        loop_sessions: Vec::new(),
+        loop_windows: Vec::new(),
+        loop_panes: Vec::new(),
+    }
+}
+
+/// Build the `FormatContext` of a pane in whichever workspace it lives, or
+/// `None` if the pane no longer exists.
+pub fn format_context_for_pane(ctx: &mut HandlerContext, pane_id: PaneId) -> Option<FormatContext> {
+    let mux = Mux::try_get()?;
+    let (_domain, window_id, tab_id) = mux.resolve_pane_id(pane_id)?;
+    let workspace = mux.get_window(window_id)?.get_workspace().to_string();
+    let tab = mux.get_tab(tab_id)?;
+    let pp = tab
+        .iter_panes()
+        .into_iter()
+        .find(|p| p.pane.pane_id() == pane_id)?;
+    let window_index = mux
+        .iter_windows_in_workspace(&workspace)
+        .iter()
+        .position(|&w| w == window_id)
+        .unwrap_or(0);
+    Some(build_format_context(
+        ctx,
+        &pp,
+        &tab,
+        window_id,
+        window_index,
+        &workspace,
+    ))
+}
+
+/// Build one `FormatContext` per window in `workspace`, each carrying the
+/// contexts of its panes in `loop_panes`. The window context itself is that
+/// of the window's active pane.
+fn window_loop_contexts(
+    ctx: &mut HandlerContext,
+    mux: &Arc<Mux>,
+    workspace: &str,
+) -> Vec<FormatContext> {
+    let mut windows = Vec::new();
+    let window_ids = mux.iter_windows_in_workspace(workspace);
+    for (window_index, &wid) in window_ids.iter().enumerate() {
+        let tabs: Vec<Arc<Tab>> = {
+            match mux.get_window(wid) {
+                Some(win) => win.iter().map(Arc::clone).collect(),
+                None => continue,
+            }
+        };
+        let active_tab_id = mux.get_active_tab_for_window(wid).map(|t| t.tab_id());
+
+        for tab in &tabs {
+            let is_active_tab = active_tab_id == Some(tab.tab_id());
+            let mut panes = Vec::new();
+            for pp in tab.iter_panes() {
+                let mut pctx = build_format_context(ctx, &pp, tab, wid, window_index, workspace);
+                pctx.set_window_active(is_active_tab);
+                panes.push(pctx);
+            }
+            let mut window = match panes.iter().find(|p| p.pane_active).or(panes.first()) {
+                Some(active) => active.clone(),
+                None => continue,
+            };
+            window.loop_panes = panes;
+            windows.push(window);
+        }
+    }
+    windows
+}
+
+/// Fill in the loop items of `fctx` so that `#{S:}`, `#{W:}` and `#{P:}`
+/// in `fmt` have something to iterate over. Walking every session is not
+/// free, so this does nothing unless `fmt` actually contains a loop.
+fn populate_format_loops(ctx: &mut HandlerContext, fmt: &str, fctx: &mut FormatContext) {
+    if !format_uses_loops(fmt) {
+        return;
+    }
+    let mux = match Mux::try_get() {
+        Some(m) => m,
+        None => return,
+    };
+
+    let sessions = session_loop_contexts(ctx, &mux);
+    let windows = match sessions
+        .iter()
+        .find(|s| s.session_name == fctx.session_name)
+    {
+        Some(session) => session.loop_windows.clone(),
+        None => window_loop_contexts(ctx, &mux, &fctx.session_name),
+    };
+    fctx.loop_panes = windows
+        .iter()
+        .find(|w| w.window_id == fctx.window_id)
+        .map(|w| w.loop_panes.clone())
+        .unwrap_or_default();
+    fctx.loop_sessions = sessions;
+    fctx.loop_windows = windows;
+}
+
+/// Build one `FormatContext` per session, each carrying its windows in
+/// `loop_windows` as built by [`window_loop_contexts`]. The session context
+/// itself is that of the session's active window.
+fn session_loop_contexts(ctx: &mut HandlerContext, mux: &Arc<Mux>) -> Vec<FormatContext> {
+    let mut sessions = Vec::new();
+    for workspace in ctx.workspaces(mux) {
+        let session_windows = window_loop_contexts(ctx, mux, &workspace);
+        let mut session = match session_windows
+            .iter()
+            .find(|w| w.window_active)
+            .or(session_windows.first())
+        {
+            Some(active) => active.clone(),
+            None => FormatContext {
+                session_id: ctx.id_map.get_or_create_tmux_session_id(&workspace),
+                session_name: workspace.clone(),
+                ..FormatContext::default()
+            },
+        };
+        session.loop_windows = session_windows;
+        sessions.push(session);
     }
+    sessions
 }
 
 // ---------------------------------------------------------------------------
@@ -752,35 +1145,82 @@ pub async fn dispatch_command(
         TmuxCliCommand::CapturePane {
             print: _,
             target,
-            escape: _,
-            octal_escape: _,
+            escape,
+            octal_escape,
+            join,
+            preserve_trailing,
+            start_line,
+            end_line,
+        } => handle_capture_pane(
+            ctx,
+            &target,
+            CaptureOptions {
+                escape,
+                octal_escape,
+                join,
+                preserve_trailing,
+            },
             start_line,
             end_line,
-        } => handle_capture_pane(ctx, &target, start_line, end_line),
+        ),
         TmuxCliCommand::SendKeys {
             target,
             literal,
             hex,
+            mouse,
+            reset,
+            copy_command,
+            repeat,
             keys,
-        } => handle_send_keys(ctx, &target, literal, hex, &keys),
+        } => handle_send_keys(
+            ctx,
+            &target,
+            literal,
+            hex,
+            mouse,
+            reset,
+            copy_command,
+            repeat,
+            &keys,
+        ),
         TmuxCliCommand::SelectPane {
             target,
             style,
             title,
         } => handle_select_pane(ctx, &target, title.as_deref(), style.as_deref()),
         TmuxCliCommand::SelectWindow { target } => handle_select_window(ctx, &target),
-        TmuxCliCommand::KillPane { target } => handle_kill_pane(ctx, &target),
+        TmuxCliCommand::KillPane { target } => handle_kill_pane(ctx, &target).await,
         TmuxCliCommand::ResizePane {
             target,
             width,
             height,
             zoom,
-        } => handle_resize_pane(ctx, &target, width, height, zoom),
+            direction,
+            adjustment,
+            mouse,
+        } => handle_resize_pane(
+            ctx,
+            &target,
+            width.as_deref(),
+            height.as_deref(),
+            zoom,
+            mouse,
+            direction.map(|d| (d, adjustment.unwrap_or(1) as usize)),
+        ),
         TmuxCliCommand::ResizeWindow {
             target,
             width,
             height,
-        } => handle_resize_window(ctx, &target, width, height),
+            largest,
+            smallest,
+        } => {
+            let fit = match (largest, smallest) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+            handle_resize_window(ctx, &target, width, height, fit)
+        }
         TmuxCliCommand::RefreshClient {
             size,
             flags,
@@ -834,8 +1274,8 @@ pub async fn dispatch_command(
             )
             .await
         }
-        TmuxCliCommand::KillWindow { target } => handle_kill_window(ctx, &target),
-        TmuxCliCommand::KillSession { target } => handle_kill_session(ctx, &target),
+        TmuxCliCommand::KillWindow { target } => handle_kill_window(ctx, &target).await,
+        TmuxCliCommand::KillSession { target } => handle_kill_session(ctx, &target).await,
         TmuxCliCommand::RenameWindow { target, name } => handle_rename_window(ctx, &target, &name),
         TmuxCliCommand::RenameSession { target, name } => {
             handle_rename_session(ctx, &target, &name)
@@ -862,19 +1302,52 @@ pub async fn dispatch_command(
         }
         TmuxCliCommand::ShowOptions {
             global,
+            server,
+            window,
+            pane,
             value_only,
             quiet,
+            inherited,
+            target,
             option_name,
-        } => handle_show_options(global, value_only, quiet, option_name.as_deref()),
+        } => handle_show_options(
+            ctx,
+            &target,
+            OptionScopeFlags {
+                global,
+                server,
+                window,
+                pane,
+            },
+            value_only,
+            quiet,
+            inherited,
+            option_name.as_deref(),
+        ),
         TmuxCliCommand::ShowWindowOptions {
             global,
             value_only,
             quiet,
+            target,
             option_name,
-        } => handle_show_window_options(global, value_only, quiet, option_name.as_deref()),
+        } => handle_show_options(
+            ctx,
+            &target,
+            OptionScopeFlags {
+                global,
+                window: true,
+                ..Default::default()
+            },
+            value_only,
+            quiet,
+            false,
+            option_name.as_deref(),
+        ),
         TmuxCliCommand::AttachSession { target } => handle_attach_session(ctx, &target),
         TmuxCliCommand::DetachClient => handle_detach_client(ctx),
-        TmuxCliCommand::SwitchClient { target } => handle_attach_session(ctx, &target),
+        TmuxCliCommand::SwitchClient { target, key_table } => {
+            handle_switch_client(ctx, &target, key_table.as_deref())
+        }
         TmuxCliCommand::ListClients { format, target: _ } => {
             handle_list_clients(ctx, format.as_deref())
         }
@@ -886,7 +1359,14 @@ pub async fn dispatch_command(
             buffer_name,
             data,
             append,
-        } => handle_set_buffer(ctx, buffer_name.as_deref(), data.as_deref(), append),
+            clipboard,
+        } => handle_set_buffer(
+            ctx,
+            buffer_name.as_deref(),
+            data.as_deref(),
+            append,
+            clipboard,
+        ),
         TmuxCliCommand::DeleteBuffer { buffer_name } => {
             handle_delete_buffer(ctx, buffer_name.as_deref())
         }
@@ -897,41 +1377,183 @@ pub async fn dispatch_command(
             delete_after,
             bracketed: _,
         } => handle_paste_buffer(ctx, buffer_name.as_deref(), &target, delete_after),
+        TmuxCliCommand::LoadBuffer {
+            buffer_name,
+            path,
+            clipboard,
+        } => handle_load_buffer(ctx, buffer_name.as_deref(), &path, clipboard),
+        TmuxCliCommand::SaveBuffer {
+            buffer_name,
+            path,
+            append,
+        } => handle_save_buffer(ctx, buffer_name.as_deref(), &path, append),
         TmuxCliCommand::MovePane {
             src,
             dst,
             horizontal,
             before,
-        } => handle_move_pane(ctx, &src, &dst, horizontal, before).await,
+            detach,
+            full_size,
+            size,
+        } => {
+            let flags = MovePaneFlags {
+                horizontal,
+                before,
+                detach,
+                full_size,
+            };
+            handle_move_pane(ctx, &src, &dst, flags, size.as_deref()).await
+        }
         TmuxCliCommand::MoveWindow { src, dst } => handle_move_window(ctx, &src, &dst),
-        TmuxCliCommand::CopyMode { quit, target: _ } => handle_copy_mode(quit),
+        TmuxCliCommand::SwapPane {
+            src,
+            dst,
+            detach,
+            up,
+            down,
+            keep_zoom,
+        } => handle_swap_pane(ctx, &src, &dst, detach, up, down, keep_zoom),
+        TmuxCliCommand::RotateWindow {
+            target,
+            down,
+            keep_zoom,
+        } => handle_rotate_window(ctx, &target, down, keep_zoom),
+        TmuxCliCommand::LastPane { target, keep_zoom } => handle_last_pane(ctx, &target, keep_zoom),
+        TmuxCliCommand::LastWindow { target } => handle_window_step(ctx, &target, WindowStep::Last),
+        TmuxCliCommand::NextWindow { target } => handle_window_step(ctx, &target, WindowStep::Next),
+        TmuxCliCommand::PreviousWindow { target } => {
+            handle_window_step(ctx, &target, WindowStep::Previous)
+        }
+        TmuxCliCommand::RespawnPane {
+            target,
+            kill,
+            cwd,
+            env,
+            shell_command,
+        } => {
+            handle_respawn_pane(
+                ctx,
+                &target,
+                kill,
+                cwd.as_deref(),
+                &env,
+                shell_command.as_deref(),
+            )
+            .await
+        }
+        TmuxCliCommand::RespawnWindow {
+            target,
+            kill,
+            cwd,
+            env,
+            shell_command,
+        } => {
+            handle_respawn_window(
+                ctx,
+                &target,
+                kill,
+                cwd.as_deref(),
+                &env,
+                shell_command.as_deref(),
+            )
+            .await
+        }
+        TmuxCliCommand::CopyMode { quit, target } => handle_copy_mode(ctx, quit, &target),
         // Phase 13: Claude Code agent teams compatibility
         TmuxCliCommand::SetOption {
             target,
             option_name,
             value,
-        } => handle_set_option(ctx, &target, option_name.as_deref(), value.as_deref()),
+            global,
+            server,
+            window,
+            pane,
+            append,
+            unset,
+            unset_panes,
+            only_if_unset,
+            format,
+            quiet,
+        } => handle_set_option(
+            ctx,
+            &target,
+            OptionScopeFlags {
+                global,
+                server,
+                window,
+                pane,
+            },
+            SetOptionFlags {
+                append,
+                unset,
+                unset_panes,
+                only_if_unset,
+                format,
+                quiet,
+            },
+            option_name.as_deref(),
+            value.as_deref(),
+        ),
         TmuxCliCommand::SelectLayout {
             target,
             layout_name,
-        } => handle_select_layout(ctx, &target, layout_name.as_deref()),
+            spread,
+            next,
+            previous,
+        } => handle_select_layout(ctx, &target, layout_name.as_deref(), spread, next, previous),
         TmuxCliCommand::BreakPane {
             detach,
             source,
             target,
         } => handle_break_pane(ctx, detach, &source, &target).await,
         // Phase 17: missing commands for cleanup & orchestration
-        TmuxCliCommand::KillServer => handle_kill_server(ctx),
-        TmuxCliCommand::WaitFor { signal, channel } => handle_wait_for(signal, &channel).await,
+        TmuxCliCommand::KillServer => handle_kill_server(ctx).await,
+        TmuxCliCommand::StartServer { socket_path } => handle_start_server(socket_path.as_deref()),
+        TmuxCliCommand::WaitFor {
+            signal,
+            lock,
+            unlock,
+            timeout,
+            channel,
+        } => handle_wait_for(ctx, signal, lock, unlock, timeout.as_deref(), &channel).await,
         TmuxCliCommand::PipePane {
             target,
             command,
             output,
             input,
             toggle,
-        } => handle_pipe_pane(ctx, &target, command.as_deref(), output, input, toggle),
-        TmuxCliCommand::DisplayPopup { target: _ } => {
-            // No-op: GUI popups don't apply to CC mode
+        } => handle_pipe_pane(ctx, &target, command.as_deref(), output, input, toggle).await,
+        TmuxCliCommand::DisplayPopup {
+            target,
+            close,
+            close_on_exit,
+            width,
+            height,
+            x,
+            y,
+            cwd,
+            title,
+            env,
+            shell_command,
+        } => {
+            handle_display_popup(
+                ctx,
+                &target,
+                close,
+                close_on_exit,
+                width.as_deref(),
+                height.as_deref(),
+                x.as_deref(),
+                y.as_deref(),
+                cwd.as_deref(),
+                title.as_deref(),
+                &env,
+                shell_command.as_deref(),
+            )
+            .await
+        }
+        TmuxCliCommand::DisplayMenu { target: _ } => {
+            // No-op: menus aren't supported
             Ok(String::new())
         }
         TmuxCliCommand::RunShell {
@@ -951,61 +1573,336 @@ pub async fn dispatch_command(
         }
         // Phase 19: diagnostic & debugging
         TmuxCliCommand::ServerInfo => Ok(handle_server_info(ctx)),
-    }
-}
-
-// ---------------------------------------------------------------------------
-// Stateless handlers
-// ---------------------------------------------------------------------------
-
-/// Returns a sorted list of all supported commands.
-pub fn handle_list_commands() -> String {
-    let mut commands = vec![
-        "attach-session",
-        "break-pane",
-        "capture-pane",
-        "copy-mode",
-        "delete-buffer",
-        "detach-client",
-        "display-menu",
-        "display-message",
-        "display-popup",
-        "has-session",
-        "kill-pane",
-        "kill-server",
-        "kill-session",
-        "kill-window",
-        "join-pane",
-        "list-buffers",
-        "list-clients",
-        "list-commands",
-        "list-panes",
-        "list-sessions",
-        "list-windows",
-        "move-pane",
-        "move-window",
-        "new-session",
-        "new-window",
-        "paste-buffer",
+        // Hooks
+        TmuxCliCommand::SetHook {
+            target,
+            global,
+            append,
+            unset,
+            run,
+            hook_name,
+            command,
+        } => {
+            if run {
+                handle_run_hook(ctx, &target, &hook_name)
+            } else {
+                handle_set_hook(ctx, &target, global, append, unset, &hook_name, command)
+            }
+        }
+        TmuxCliCommand::ShowHooks { target, global } => handle_show_hooks(ctx, &target, global),
+        TmuxCliCommand::IfShell {
+            background,
+            format,
+            target,
+            condition,
+            then_command,
+            else_command,
+        } => {
+            handle_if_shell(
+                ctx,
+                background,
+                format,
+                &target,
+                &condition,
+                &then_command,
+                else_command.as_deref(),
+            )
+            .await
+        }
+        TmuxCliCommand::SourceFile {
+            format,
+            parse_only,
+            quiet,
+            target,
+            paths,
+        } => handle_source_file(ctx, format, parse_only, quiet, &target, &paths).await,
+        TmuxCliCommand::SetEnvironment {
+            target,
+            global,
+            unset,
+            remove,
+            hidden,
+            format,
+            name,
+            value,
+        } => handle_set_environment(
+            ctx,
+            &target,
+            global,
+            unset,
+            remove,
+            hidden,
+            format,
+            &name,
+            value.as_deref(),
+        ),
+        TmuxCliCommand::ShowEnvironment {
+            target,
+            global,
+            hidden,
+            shell,
+            name,
+        } => handle_show_environment(ctx, &target, global, hidden, shell, name.as_deref()),
+        TmuxCliCommand::BindKey {
+            table,
+            repeat,
+            note,
+            key,
+            command,
+        } => handle_bind_key(
+            ctx,
+            &table,
+            repeat,
+            note.as_deref(),
+            &key,
+            command.as_deref(),
+        ),
+        TmuxCliCommand::UnbindKey {
+            table,
+            all,
+            quiet,
+            key,
+        } => handle_unbind_key(ctx, &table, all, quiet, key.as_deref()),
+        TmuxCliCommand::ListKeys {
+            table,
+            notes,
+            all,
+            prefix,
+            key,
+        } => handle_list_keys(
+            ctx,
+            table.as_deref(),
+            notes,
+            all,
+            prefix.as_deref(),
+            key.as_deref(),
+        ),
+        TmuxCliCommand::ClearHistory { target, hyperlinks } => {
+            handle_clear_history(ctx, &target, hyperlinks)
+        }
+        TmuxCliCommand::ChooseTree {
+            target,
+            format,
+            filter,
+            reverse,
+            sessions,
+            windows,
+            template,
+        } => {
+            let depth = if sessions {
+                TreeDepth::Sessions
+            } else if windows {
+                TreeDepth::Windows
+            } else {
+                TreeDepth::Panes
+            };
+            let opts = ChooseOptions {
+                format: format.as_deref(),
+                filter: filter.as_deref(),
+                reverse,
+            };
+            handle_choose(
+                ctx,
+                Chooser::Tree(depth),
+                &target,
+                opts,
+                template.as_deref(),
+            )
+        }
+        TmuxCliCommand::ChooseBuffer {
+            target,
+            format,
+            filter,
+            reverse,
+            template,
+        } => {
+            let opts = ChooseOptions {
+                format: format.as_deref(),
+                filter: filter.as_deref(),
+                reverse,
+            };
+            handle_choose(ctx, Chooser::Buffer, &target, opts, template.as_deref())
+        }
+        TmuxCliCommand::ChooseClient {
+            target,
+            format,
+            filter,
+            reverse,
+            template,
+        } => {
+            let opts = ChooseOptions {
+                format: format.as_deref(),
+                filter: filter.as_deref(),
+                reverse,
+            };
+            handle_choose(ctx, Chooser::Client, &target, opts, template.as_deref())
+        }
+        TmuxCliCommand::DisplayPanes { target, template } => {
+            handle_display_panes(ctx, &target, template.as_deref())
+        }
+    }
+}
+
+/// Run a command list, joining the output of its commands into a single
+/// response. Like tmux, the rest of the list is skipped once a command fails.
+/// `after-*` hooks fire for each command that succeeds, unless the context
+/// is running a hook.
+pub fn dispatch_command_list<'a>(
+    ctx: &'a mut HandlerContext,
+    list: &'a [CommandListItem],
+) -> LocalBoxFuture<'a, Result<String, String>> {
+    Box::pin(async move {
+        let mut output = Vec::new();
+        match run_command_list_into(ctx, list, &mut output).await {
+            Ok(()) => Ok(output.join("\n")),
+            Err(e) => {
+                output.push(e);
+                Err(output.join("\n"))
+            }
+        }
+    })
+}
+
+/// Run a command list like [`dispatch_command_list`], but return the output
+/// of the commands that ran and the error that stopped the list separately.
+pub async fn run_command_list(
+    ctx: &mut HandlerContext,
+    list: &[CommandListItem],
+) -> (String, Option<String>) {
+    let mut output = Vec::new();
+    let error = run_command_list_into(ctx, list, &mut output).await.err();
+    (output.join("\n"), error)
+}
+
+/// Run a command list, collecting the output of each command into `output`.
+fn run_command_list_into<'a>(
+    ctx: &'a mut HandlerContext,
+    list: &'a [CommandListItem],
+    output: &'a mut Vec<String>,
+) -> LocalBoxFuture<'a, Result<(), String>> {
+    Box::pin(async move {
+        for item in list {
+            match item {
+                CommandListItem::Command(cmd) => {
+                    let name = cmd.name();
+                    let text = dispatch_command(ctx, cmd.clone()).await?;
+                    if ctx.fire_after_hooks {
+                        fire_after_hook(ctx, name);
+                    }
+                    if !text.is_empty() {
+                        output.push(text);
+                    }
+                }
+                CommandListItem::Conditional {
+                    branches,
+                    otherwise,
+                } => {
+                    let mut chosen = otherwise;
+                    for (condition, body) in branches {
+                        if is_true(&expand_for_target(ctx, condition, &None)?) {
+                            chosen = body;
+                            break;
+                        }
+                    }
+                    run_command_list_into(ctx, chosen, output).await?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Expand `fmt` for `target`, or the current pane. Without a pane to
+/// describe, only the variables that need no pane are filled in.
+fn expand_for_target(
+    ctx: &mut HandlerContext,
+    fmt: &str,
+    target: &Option<String>,
+) -> Result<String, String> {
+    if target.is_some() {
+        ctx.resolve_target(target)?;
+    }
+    Ok(handle_display_message(ctx, false, Some(fmt), target)
+        .unwrap_or_else(|_| expand_format(fmt, &FormatContext::default())))
+}
+
+// ---------------------------------------------------------------------------
+// Stateless handlers
+// ---------------------------------------------------------------------------
+
+/// Returns a sorted list of all supported commands.
+pub fn handle_list_commands() -> String {
+    let mut commands = vec![
+        "attach-session",
+        "bind-key",
+        "break-pane",
+        "capture-pane",
+        "choose-buffer",
+        "choose-client",
+        "choose-tree",
+        "clear-history",
+        "copy-mode",
+        "delete-buffer",
+        "detach-client",
+        "display-menu",
+        "display-message",
+        "display-panes",
+        "display-popup",
+        "has-session",
+        "if-shell",
+        "kill-pane",
+        "kill-server",
+        "kill-session",
+        "kill-window",
+        "last-pane",
+        "last-window",
+        "join-pane",
+        "list-buffers",
+        "list-clients",
+        "list-commands",
+        "list-keys",
+        "list-panes",
+        "list-sessions",
+        "list-windows",
+        "load-buffer",
+        "move-pane",
+        "move-window",
+        "new-session",
+        "new-window",
+        "next-window",
+        "paste-buffer",
         "pipe-pane",
+        "previous-window",
         "refresh-client",
         "rename-session",
         "rename-window",
         "resize-pane",
         "resize-window",
+        "respawn-pane",
+        "respawn-window",
+        "rotate-window",
         "run-shell",
+        "save-buffer",
         "server-info",
         "select-layout",
         "select-pane",
         "select-window",
         "send-keys",
         "set-buffer",
+        "set-environment",
+        "set-hook",
         "set-option",
         "show-buffer",
+        "show-environment",
+        "show-hooks",
         "show-options",
         "show-window-options",
+        "source-file",
         "split-window",
+        "start-server",
         "switch-client",
+        "swap-pane",
+        "unbind-key",
         "wait-for",
     ];
     commands.sort();
@@ -1021,7 +1918,7 @@ pub fn handle_server_info(ctx: &HandlerContext) -> String {
     lines.push(format!("pid: {}", std::process::id()));
 
     if let Some(mux) = Mux::try_get() {
-        let workspaces = mux.iter_workspaces();
+        let workspaces = ctx.workspaces(&mux);
         lines.push(format!("sessions: {}", workspaces.len()));
 
         let mut total_windows = 0usize;
@@ -1055,25 +1952,82 @@ pub fn handle_server_info(ctx: &HandlerContext) -> String {
     lines.join("\n")
 }
 
-/// Handle `copy-mode [-q]`.
+/// Handle `copy-mode [-q] [-t target]`.
 ///
-/// With `-q`: exits copy mode (no-op for WezTerm — used defensively by iTerm2
-/// to ensure tmux isn't stuck in copy mode after config errors).
-/// Without `-q`: would enter copy mode — accepted silently as a no-op since
-/// WezTerm manages its own copy overlay independently.
-pub fn handle_copy_mode(_quit: bool) -> Result<String, String> {
-    // No-op: WezTerm's copy overlay is independent of tmux CC protocol.
-    // iTerm2 sends `copy-mode -q` on connect as a defensive measure.
+/// Puts the pane into the server's copy mode, which `send-keys -X` then
+/// drives, and opens the copy overlay of a GUI window showing the pane to
+/// follow it.
+/// With `-q`: leaves copy mode. iTerm2 sends this defensively on connect, so
+/// it succeeds even if the pane isn't in copy mode or can't be found.
+pub fn handle_copy_mode(
+    ctx: &mut HandlerContext,
+    quit: bool,
+    target: &Option<String>,
+) -> Result<String, String> {
+    if quit {
+        if let Ok(ResolvedTarget {
+            pane_id: Some(pane_id),
+            ..
+        }) = ctx.resolve_target(target)
+        {
+            let left = copy_modes().remove(&pane_id).is_some();
+            if left {
+                show_copy_mode_in_gui(
+                    pane_id,
+                    vec![KeyAssignment::CopyMode(CopyModeAssignment::Close)],
+                );
+            }
+        }
+        return Ok(String::new());
+    }
+
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+    let pane_id = ctx
+        .resolve_target(target)?
+        .pane_id
+        .ok_or_else(|| "can't find pane".to_string())?;
+    let pane = mux
+        .get_pane(pane_id)
+        .ok_or_else(|| format!("can't find pane: {}", pane_id))?;
+
+    let entered = {
+        let mut modes = copy_modes();
+        let entered = !modes.contains_key(&pane_id);
+        modes
+            .entry(pane_id)
+            .or_insert_with(|| PaneCopyMode::enter(&*pane));
+        entered
+    };
+    if entered {
+        show_copy_mode_in_gui(pane_id, vec![KeyAssignment::ActivateCopyMode]);
+    }
     Ok(String::new())
 }
 
+/// Have a GUI window showing `pane_id` perform `assignments` on its copy
+/// overlay, so that it follows the server's copy mode.
+fn show_copy_mode_in_gui(pane_id: PaneId, assignments: Vec<KeyAssignment>) {
+    let Some(mux) = Mux::try_get() else {
+        return;
+    };
+    for assignment in assignments {
+        mux.notify(MuxNotification::PerformAssignment {
+            pane_id,
+            assignment: Box::new(assignment),
+        });
+    }
+}
+
 /// Handle `pipe-pane [-I] [-O] [-o] [-t target] [command]`.
 ///
-/// Spawns a shell command and pipes pane output to its stdin (`-O`, default)
-/// and/or child stdout to the pane's input (`-I`). With no command, closes
-/// any existing pipe. With `-o` (toggle), does nothing if a pipe already exists.
-fn handle_pipe_pane(
-    ctx: &HandlerContext,
+/// Runs a shell command that is sent the pane's output (`-O`, the default)
+/// and/or whose output is typed into the pane (`-I`). As in tmux, any
+/// existing pipe is closed first, and with no command that is all; with
+/// `-o` no new pipe is opened if there was one, so the same command toggles
+/// the pipe. The command is expanded as a format, so a log file can be
+/// named after `#{pane_id}`.
+async fn handle_pipe_pane(
+    ctx: &mut HandlerContext,
     target: &Option<String>,
     command: Option<&str>,
     output: bool,
@@ -1085,209 +2039,210 @@ fn handle_pipe_pane(
         .pane_id
         .ok_or_else(|| "no pane resolved".to_string())?;
 
-    // No command → close existing pipe
-    let cmd_str = match command {
+    let had_pipe = close_pipe_pane(pane_id).await;
+    let command = match command {
         Some(c) if !c.is_empty() => c,
-        _ => {
-            close_pipe_pane(pane_id);
-            return Ok(String::new());
-        }
+        _ => return Ok(String::new()),
     };
-
-    // Toggle mode: if pipe already exists, do nothing
-    if toggle {
-        let state = PIPE_PANE_STATE.lock();
-        if state.contains_key(&pane_id) {
-            return Ok(String::new());
-        }
-    }
-
-    // Close any existing pipe before starting a new one
-    close_pipe_pane(pane_id);
-
-    // Determine shell and flag for the platform
-    #[cfg(windows)]
-    let (shell, shell_flag) = ("cmd.exe", "/C");
-    #[cfg(not(windows))]
-    let (shell, shell_flag) = ("sh", "-c");
-
-    let mut child = std::process::Command::new(shell)
-        .arg(shell_flag)
-        .arg(cmd_str)
-        .stdin(if output {
-            std::process::Stdio::piped()
-        } else {
-            std::process::Stdio::null()
-        })
-        .stdout(if input {
-            std::process::Stdio::piped()
-        } else {
-            std::process::Stdio::null()
-        })
-        .stderr(std::process::Stdio::null())
-        .spawn()
-        .map_err(|e| format!("pipe-pane: failed to spawn: {}", e))?;
-
-    let (shutdown_tx, shutdown_rx) = async_channel::bounded::<()>(1);
-
-    // Output mode: tap pane output → child stdin
-    if output {
-        if let Some(child_stdin) = child.stdin.take() {
-            let tap_rx = crate::register_output_tap(pane_id);
-            let shutdown_rx_clone = shutdown_rx.clone();
-            std::thread::Builder::new()
-                .name(format!("pipe-pane-out-{}", pane_id))
-                .spawn(move || {
-                    use std::io::Write;
-                    let mut stdin = child_stdin;
-                    loop {
-                        // Check shutdown
-                        if shutdown_rx_clone.try_recv().is_ok() {
-                            break;
-                        }
-                        match tap_rx.recv_timeout(std::time::Duration::from_millis(100)) {
-                            Ok((data, _ts)) => {
-                                if stdin.write_all(&data).is_err() {
-                                    break;
-                                }
-                                let _ = stdin.flush();
-                            }
-                            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
-                            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
-                        }
-                    }
-                })
-                .ok();
-        }
-    }
-
-    // Input mode: child stdout → pane input
-    if input {
-        if let Some(child_stdout) = child.stdout.take() {
-            let shutdown_rx_clone = shutdown_rx.clone();
-            std::thread::Builder::new()
-                .name(format!("pipe-pane-in-{}", pane_id))
-                .spawn(move || {
-                    use std::io::Read;
-                    let mut stdout = child_stdout;
-                    let mut buf = [0u8; 4096];
-                    loop {
-                        if shutdown_rx_clone.try_recv().is_ok() {
-                            break;
-                        }
-                        match stdout.read(&mut buf) {
-                            Ok(0) => break,
-                            Ok(n) => {
-                                if let Some(mux) = Mux::try_get() {
-                                    if let Some(pane) = mux.get_pane(pane_id) {
-                                        let mut writer = pane.writer();
-                                        if writer.write_all(&buf[..n]).is_err() {
-                                            break;
-                                        }
-                                    } else {
-                                        break;
-                                    }
-                                } else {
-                                    break;
-                                }
-                            }
-                            Err(_) => break,
-                        }
-                    }
-                })
-                .ok();
-        }
+    if toggle && had_pipe {
+        return Ok(String::new());
     }
 
-    // Store the handle
-    let handle = PipePaneHandle { child, shutdown_tx };
-    PIPE_PANE_STATE.lock().insert(pane_id, handle);
-
+    let command = match format_context_for_pane(ctx, pane_id) {
+        Some(fctx) => expand_format(command, &fctx),
+        None => command.to_string(),
+    };
+    open_pipe_pane(pane_id, &command, output, input)?;
     Ok(String::new())
 }
 
 /// Handle `kill-server`.
 ///
-/// Kills all sessions (workspaces) by removing their windows and panes,
-/// then requests detach with a "server killed" reason.
-fn handle_kill_server(ctx: &mut HandlerContext) -> Result<String, String> {
+/// Kills the server's sessions (workspaces) by removing their windows and
+/// panes, then requests detach with a "server killed" reason. The sessions
+/// of other servers are left alone, and a named server also stops listening.
+async fn handle_kill_server(ctx: &mut HandlerContext) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
 
-    let workspaces = mux.iter_workspaces();
+    let workspaces = ctx.workspaces(&mux);
+    let mut window_ids = vec![];
+    let mut pane_ids = vec![];
     for workspace in &workspaces {
-        let window_ids = mux.iter_windows_in_workspace(workspace);
-        for &wid in &window_ids {
+        let workspace_windows = mux.iter_windows_in_workspace(workspace);
+        for &wid in &workspace_windows {
             let tabs: Vec<Arc<Tab>> = match mux.get_window(wid) {
                 Some(win) => win.iter().map(Arc::clone).collect(),
                 None => continue,
             };
             for tab in &tabs {
-                for pp in tab.iter_panes() {
-                    close_pipe_pane(pp.pane.pane_id());
+                for pp in tab.iter_panes_ignoring_zoom() {
+                    note_pane_killed(pp.pane.pane_id());
                     ctx.id_map.remove_pane(pp.pane.pane_id());
+                    pane_ids.push(pp.pane.pane_id());
                 }
                 ctx.id_map.remove_window(tab.tab_id());
             }
         }
-        for wid in window_ids {
-            mux.kill_window(wid);
-        }
+        window_ids.extend(workspace_windows);
         ctx.id_map.remove_session(workspace);
     }
+    // Hand the pipes everything their panes printed before they go.
+    close_pipe_panes(pane_ids).await;
+    for wid in window_ids {
+        mux.kill_window(wid);
+    }
+    if let Some(server) = &ctx.server {
+        super::server::stop_named_server(server);
+    }
 
     ctx.detach_requested = true;
     ctx.detach_reason = Some("server killed".to_string());
     Ok(String::new())
 }
 
-/// Handle `wait-for [-S] <channel>`.
+/// Handle `start-server [-S socket-path]`.
+///
+/// The default server is running as long as WezTerm is. With `-S`, starts
+/// the named server on that socket; see [`named_server`].
+fn handle_start_server(socket_path: Option<&str>) -> Result<String, String> {
+    if let Some(path) = socket_path {
+        super::server::start_named_server(std::path::Path::new(path))
+            .map_err(|e| format!("{:#}", e))?;
+    }
+    Ok(String::new())
+}
+
+/// Handle `wait-for [-L|-S|-U] [-T timeout] <channel>`.
 ///
-/// Implements a channel-based wait/signal synchronization mechanism:
 /// - `wait-for <channel>`: blocks until another client signals the channel
 /// - `wait-for -S <channel>`: signals the channel, waking all waiters
-async fn handle_wait_for(signal: bool, channel: &str) -> Result<String, String> {
+/// - `wait-for -L <channel>`: takes the channel's lock, blocking while
+///   another client holds it
+/// - `wait-for -U <channel>`: releases the lock
+///
+/// See [`wait_for`] for how waiting clients give up.
+async fn handle_wait_for(
+    ctx: &HandlerContext,
+    signal: bool,
+    lock: bool,
+    unlock: bool,
+    timeout: Option<&str>,
+    channel: &str,
+) -> Result<String, String> {
+    if channel.is_empty() {
+        return Err("missing channel".to_string());
+    }
+    let timeout = match timeout {
+        Some(t) => Some(
+            t.parse::<f64>()
+                .ok()
+                .and_then(|t| std::time::Duration::try_from_secs_f64(t).ok())
+                .ok_or_else(|| "invalid timeout".to_string())?,
+        ),
+        None => None,
+    };
+    let client_gone = ctx.client_gone.clone();
+    let server = ctx.server.as_deref();
     if signal {
-        // Signal mode: wake all waiters on this channel
-        let mut channels = WAIT_CHANNELS.lock();
-        let entry = channels
-            .entry(channel.to_string())
-            .or_insert_with(|| WaitChannel {
-                woken: false,
-                waiters: Vec::new(),
-            });
-        if entry.waiters.is_empty() {
-            // No one waiting — set woken flag so next waiter returns immediately
-            entry.woken = true;
-        } else {
-            // Wake all waiters
-            for sender in entry.waiters.drain(..) {
-                let _ = sender.try_send(());
-            }
-        }
-        Ok(String::new())
+        wait_for::signal(server, channel);
+    } else if unlock {
+        wait_for::unlock(server, channel)?;
+    } else if lock {
+        wait_for::lock(server, channel, client_gone, timeout).await?;
     } else {
-        // Wait mode: block until signaled
-        let receiver = {
-            let mut channels = WAIT_CHANNELS.lock();
-            let entry = channels
-                .entry(channel.to_string())
-                .or_insert_with(|| WaitChannel {
-                    woken: false,
-                    waiters: Vec::new(),
-                });
-            if entry.woken {
-                // Already signaled — consume the signal and return
-                entry.woken = false;
-                return Ok(String::new());
-            }
-            let (sender, receiver) = async_channel::bounded(1);
-            entry.waiters.push(sender);
-            receiver
-        };
-        // Wait for signal (lock is released)
-        let _ = receiver.recv().await;
-        Ok(String::new())
+        wait_for::wait(server, channel, client_gone, timeout).await?;
+    }
+    Ok(String::new())
+}
+
+/// Handle `display-popup`: run a command in a bordered pane that floats
+/// over the target window and takes key focus until it is closed. `-E`
+/// closes it when the command exits; without it the popup stays open
+/// showing the exit status. `-C` closes the popup instead.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_display_popup(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    close: bool,
+    close_on_exit: u8,
+    width: Option<&str>,
+    height: Option<&str>,
+    x: Option<&str>,
+    y: Option<&str>,
+    cwd: Option<&str>,
+    title: Option<&str>,
+    env: &[String],
+    shell_command: Option<&str>,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let resolved = ctx.resolve_target(target)?;
+    let tab_id = resolved
+        .tab_id
+        .ok_or_else(|| "no window resolved for display-popup".to_string())?;
+    let tab = mux
+        .get_tab(tab_id)
+        .ok_or_else(|| format!("can't find tab {}", tab_id))?;
+
+    if close {
+        mux.close_popup(tab_id);
+        return Ok(String::new());
     }
+
+    let pos = tab
+        .iter_panes()
+        .into_iter()
+        .find(|p| Some(p.pane.pane_id()) == resolved.pane_id)
+        .ok_or_else(|| "can't find pane".to_string())?;
+    let tab_size = tab.get_size();
+    let pane_rect = Rect {
+        left: pos.left,
+        top: pos.top,
+        width: pos.width,
+        height: pos.height,
+    };
+    let rect = popup_rect(tab_size.cols, tab_size.rows, pane_rect, width, height, x, y)
+        .map_err(|e| e.to_string())?;
+
+    let domain = mux
+        .get_domain(pos.pane.domain_id())
+        .ok_or_else(|| format!("can't find domain for pane {}", pos.pane.pane_id()))?;
+    let command_dir = match cwd {
+        Some(dir) => Some(dir.to_string()),
+        None => pos
+            .pane
+            .get_current_working_dir(CachePolicy::AllowStale)
+            .map(|url| url.path().to_string()),
+    };
+    let size = TabPopup::content_size(rect.width, rect.height, &tab_size);
+    let workspace = resolved.workspace.unwrap_or_else(|| ctx.workspace.clone());
+    let command = build_spawn_command(shell_command, None, &ctx.spawn_env(&workspace, env));
+    let pane = domain
+        .spawn_pane(size, command, command_dir)
+        .await
+        .map_err(|e| format!("display-popup failed: {:#}", e))?;
+    // The popup, not the pane, decides when to close
+    pane.set_remain_on_exit(RemainOnExit::On);
+
+    let exit_behavior = match close_on_exit {
+        0 => ExitBehavior::Hold,
+        1 => ExitBehavior::Close,
+        _ => ExitBehavior::CloseOnCleanExit,
+    };
+    mux.open_popup(
+        tab_id,
+        TabPopup {
+            pane,
+            left: rect.left,
+            top: rect.top,
+            width: rect.width,
+            height: rect.height,
+            title: title.unwrap_or_default().to_string(),
+            exit_behavior,
+        },
+    )
+    .map_err(|e| format!("display-popup failed: {:#}", e))?;
+    Ok(String::new())
 }
 
 /// Execute a shell command and return its stdout (helper).
@@ -1388,11 +2343,11 @@ async fn handle_run_shell(
 /// Check whether a session (workspace) exists.
 pub fn handle_has_session(ctx: &HandlerContext, target: &Option<String>) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
-    let workspaces = mux.iter_workspaces();
+    let workspaces = ctx.workspaces(&mux);
 
     let workspace_to_check = match target {
         Some(t) => {
-            let parsed = parse_target(t).map_err(|e| e.to_string())?;
+            let parsed = parse_session_target(t).map_err(|e| e.to_string())?;
             match parsed.session {
                 Some(SessionRef::Name(name)) => name,
                 Some(SessionRef::Id(id)) => {
@@ -1438,7 +2393,7 @@ pub fn handle_list_panes(
 
     if all {
         // All panes across all workspaces
-        for workspace in mux.iter_workspaces() {
+        for workspace in ctx.workspaces(&mux) {
             collect_panes_in_workspace(ctx, &mux, &workspace, fmt, &mut lines)?;
         }
     } else if session {
@@ -1471,6 +2426,7 @@ pub fn handle_list_panes(
             for pp in tab.iter_panes() {
                 let mut fctx = build_format_context(ctx, &pp, &tab, wid, window_index, &workspace);
                 fctx.set_window_active(is_active_tab);
+                populate_format_loops(ctx, fmt, &mut fctx);
                 lines.push(expand_format(fmt, &fctx));
             }
         }
@@ -1502,6 +2458,7 @@ fn collect_panes_in_workspace(
             for pp in tab.iter_panes() {
                 let mut fctx = build_format_context(ctx, &pp, tab, wid, window_index, workspace);
                 fctx.set_window_active(is_active_tab);
+                populate_format_loops(ctx, fmt, &mut fctx);
                 lines.push(expand_format(fmt, &fctx));
             }
         }
@@ -1524,7 +2481,7 @@ pub fn handle_list_windows(
     let mut lines = Vec::new();
 
     let workspaces: Vec<String> = if all {
-        mux.iter_workspaces()
+        ctx.workspaces(&mux)
     } else {
         let resolved = ctx.resolve_target(target)?;
         let ws = resolved.workspace.unwrap_or_else(|| ctx.workspace.clone());
@@ -1549,6 +2506,7 @@ pub fn handle_list_windows(
                 if let Some(pp) = panes.first() {
                     let mut fctx = build_format_context(ctx, pp, tab, wid, window_index, workspace);
                     fctx.set_window_active(is_active_tab);
+                    populate_format_loops(ctx, fmt, &mut fctx);
                     lines.push(expand_format(fmt, &fctx));
                 } else {
                     // Tab with no panes — build minimal context
@@ -1590,13 +2548,16 @@ pub fn handle_list_sessions(
     let fmt = format.unwrap_or(default_format);
 
     let mut lines = Vec::new();
-    for workspace in mux.iter_workspaces() {
+    for workspace in ctx.workspaces(&mux) {
         let tmux_sid = ctx.id_map.get_or_create_tmux_session_id(&workspace);
-        let fctx = FormatContext {
+        let mut fctx = FormatContext {
             session_id: tmux_sid,
             session_name: workspace.to_string(),
+            user_options: options(ctx.server.as_deref())
+                .format_user_options(None, None, &workspace),
             ..FormatContext::default()
         };
+        populate_format_loops(ctx, fmt, &mut fctx);
         lines.push(expand_format(fmt, &fctx));
     }
 
@@ -1634,7 +2595,8 @@ pub fn handle_display_message(
                 let wids = mux.iter_windows_in_workspace(&workspace);
                 wids.iter().position(|&w| w == wid).unwrap_or(0)
             };
-            let fctx = build_format_context(ctx, pp, &tab, wid, window_index, &workspace);
+            let mut fctx = build_format_context(ctx, pp, &tab, wid, window_index, &workspace);
+            populate_format_loops(ctx, fmt, &mut fctx);
             let expanded = expand_format(fmt, &fctx);
             if verbose {
                 return Ok(format_verbose_output(fmt, &fctx, &expanded));
@@ -1717,6 +2679,7 @@ fn format_verbose_output(fmt: &str, ctx: &FormatContext, expanded: &str) -> Stri
 pub fn handle_capture_pane(
     ctx: &mut HandlerContext,
     target: &Option<String>,
+    opts: CaptureOptions,
     start_line: Option<i64>,
     end_line: Option<i64>,
 ) -> Result<String, String> {
@@ -1731,19 +2694,77 @@ pub fn handle_capture_pane(
         .get_pane(pane_id)
         .ok_or_else(|| format!("can't find pane: {}", pane_id))?;
 
-    let dims = pane.get_dimensions();
-    let viewport_rows = dims.viewport_rows as isize;
-    let physical_top = dims.physical_top;
+    capture_pane_contents(&*pane, opts, start_line, end_line)
+}
 
-    // Resolve start/end lines relative to the visible area.
-    // In tmux, line 0 is the first visible line, negative values are scrollback.
-    let start = match start_line {
-        Some(s) => physical_top + s as isize,
-        None => physical_top,
+/// Discard the scrollback of a pane, as a mux client's
+/// `EraseScrollbackRequest` does. The visible lines are kept, and with
+/// `hyperlinks` (`-H`) lose their hyperlinks.
+fn handle_clear_history(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    hyperlinks: bool,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let resolved = ctx.resolve_target(target)?;
+    let pane_id = resolved
+        .pane_id
+        .ok_or_else(|| "can't find pane".to_string())?;
+    let pane = mux
+        .get_pane(pane_id)
+        .ok_or_else(|| format!("can't find pane: {}", pane_id))?;
+
+    pane.erase_scrollback(ScrollbackEraseMode::ScrollbackOnly);
+    if hyperlinks {
+        pane.erase_hyperlinks();
+    }
+    Ok(String::new())
+}
+
+/// Output flags for `capture-pane`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureOptions {
+    /// `-e`: include SGR escape sequences for text and background attributes.
+    pub escape: bool,
+    /// `-C`: escape non-printable characters as octal `\ooo`.
+    pub octal_escape: bool,
+    /// `-J`: join soft-wrapped lines and preserve trailing spaces.
+    pub join: bool,
+    /// `-N`: preserve trailing spaces at the end of each line.
+    pub preserve_trailing: bool,
+}
+
+/// Render the requested range of `pane` the way `capture-pane -p` does.
+///
+/// Line 0 is the first visible line and negative values reach into the
+/// scrollback, matching tmux's `-S`/`-E` semantics. Like tmux, the range is
+/// clamped to the history and visible lines the pane has.
+pub fn capture_pane_contents(
+    pane: &dyn Pane,
+    opts: CaptureOptions,
+    start_line: Option<i64>,
+    end_line: Option<i64>,
+) -> Result<String, String> {
+    let dims = pane.get_dimensions();
+    let viewport_rows = dims.viewport_rows as isize;
+    let physical_top = dims.physical_top;
+
+    // Resolve start/end lines relative to the visible area.
+    // In tmux, line 0 is the first visible line, negative values are scrollback.
+    let bottom = physical_top + viewport_rows;
+    let start = match start_line {
+        Some(s) => (physical_top as i64)
+            .saturating_add(s)
+            .clamp(dims.scrollback_top as i64, bottom as i64) as isize,
+        None => physical_top,
     };
     let end = match end_line {
-        Some(e) => physical_top + e as isize + 1,
-        None => physical_top + viewport_rows,
+        Some(e) => (physical_top as i64)
+            .saturating_add(e)
+            .saturating_add(1)
+            .clamp(dims.scrollback_top as i64, bottom as i64) as isize,
+        None => bottom,
     };
 
     if start >= end {
@@ -1751,26 +2772,110 @@ pub fn handle_capture_pane(
     }
 
     let (_first_row, lines) = pane.get_lines(start..end);
+
+    // With -J, soft-wrapped rows are folded into the row they continue.
+    // The final segment of the capture range keeps whatever wrap state it
+    // had; tmux does the same and simply omits the trailing newline.
+    let mut rows: Vec<(Line, bool)> = Vec::with_capacity(lines.len());
+    for line in lines {
+        let wrapped = opts.join && line.last_cell_was_wrapped();
+        match rows.last_mut() {
+            Some((prev, prev_wrapped)) if *prev_wrapped => {
+                prev.append_line(line, SEQ_ZERO);
+                *prev_wrapped = wrapped;
+            }
+            _ => rows.push((line, wrapped)),
+        }
+    }
+
+    // tmux trims trailing blanks unless either -J or -N was given.
+    let trim = !opts.join && !opts.preserve_trailing;
+
     let mut output = String::new();
-    for line in &lines {
-        let text = line.as_str();
-        output.push_str(text.trim_end());
-        output.push('\n');
+    if opts.escape {
+        let (lines, wrapped): (Vec<Line>, Vec<bool>) = rows.into_iter().unzip();
+        let rendered =
+            termwiz_funcs::lines_to_escapes(lines).map_err(|e| format!("capture-pane: {e:#}"))?;
+        // lines_to_escapes terminates each row with CRLF and resets the
+        // attributes at the very end; reassemble using tmux's bare LF.
+        let mut segments = rendered.split("\r\n");
+        for is_wrapped in wrapped {
+            let segment = segments.next().unwrap_or("");
+            push_capture_row(&mut output, segment, opts.octal_escape);
+            if !is_wrapped {
+                output.push('\n');
+            }
+        }
+        // Keep the final attribute reset on the last row rather than
+        // after the trailing newline.
+        let tail: String = segments.collect();
+        let had_newline = output.ends_with('\n');
+        if had_newline {
+            output.pop();
+        }
+        push_capture_row(&mut output, &tail, opts.octal_escape);
+        if had_newline {
+            output.push('\n');
+        }
+    } else {
+        for (line, is_wrapped) in &rows {
+            let text = line.as_str();
+            let text: &str = if trim { text.trim_end() } else { &text };
+            push_capture_row(&mut output, text, opts.octal_escape);
+            if !is_wrapped {
+                output.push('\n');
+            }
+        }
     }
 
     Ok(output)
 }
 
+/// Append one captured row, applying `-C` octal escaping when requested.
+///
+/// tmux escapes control characters (including the ESC that introduces SGR
+/// sequences) as `\ooo` and doubles literal backslashes.
+fn push_capture_row(out: &mut String, text: &str, octal_escape: bool) {
+    if !octal_escape {
+        out.push_str(text);
+        return;
+    }
+    for ch in text.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 || c == '\x7f' => {
+                out.push_str(&format!("\\{:03o}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Write handlers
 // ---------------------------------------------------------------------------
 
 /// Send keys to a pane.
+///
+/// Named keys go through the pane's key encoder, so they are sent in
+/// whatever form the terminal's current modes call for. With `repeat`, the
+/// keys are sent that many times over, one key at a time. With `mouse`,
+/// each key is an SGR mouse report, delivered as a mouse event: tmux's
+/// `send-keys -M` forwards the mouse event that triggered a key binding,
+/// which a command sent by a client doesn't have, so the events are given
+/// as arguments instead; they also drag the border that `resize-pane -M`
+/// picked up. With `copy_command`, the keys are a copy mode command and its
+/// arguments.
+#[allow(clippy::too_many_arguments)]
 pub fn handle_send_keys(
     ctx: &mut HandlerContext,
     target: &Option<String>,
     literal: bool,
     hex: bool,
+    mouse: bool,
+    reset: bool,
+    copy_command: bool,
+    repeat: Option<u32>,
     keys: &[String],
 ) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
@@ -1784,33 +2889,225 @@ pub fn handle_send_keys(
         .get_pane(pane_id)
         .ok_or_else(|| format!("can't find pane: {}", pane_id))?;
 
-    let mut all_bytes = Vec::new();
+    if copy_command {
+        return run_copy_command(ctx, &pane, repeat, keys);
+    }
+
+    if reset {
+        use termwiz::escape::{Action, Esc, EscCode};
+        pane.perform_actions(vec![Action::Esc(Esc::Code(EscCode::FullReset))]);
+    }
+
+    if mouse {
+        let tab = mux
+            .resolve_pane_id(pane_id)
+            .and_then(|(_, _, tab_id)| mux.get_tab(tab_id));
+        let origin = tab.as_ref().and_then(|tab| {
+            let panes = tab.iter_panes();
+            let pos = panes.iter().find(|p| p.pane.pane_id() == pane_id)?;
+            Some((pos.left, pos.top))
+        });
+        for key in keys {
+            let event = parse_mouse_report(key)?;
+            if let (Some(tab), Some((left, top))) = (&tab, origin) {
+                if split_drag::mouse_event(tab, left, top, &event) {
+                    continue;
+                }
+            }
+            pane.mouse_event(event)
+                .map_err(|e| format!("failed to send mouse event: {}", e))?;
+        }
+        return Ok(String::new());
+    }
+
+    let mut once = Vec::new();
     for key in keys {
-        let bytes = resolve_key(key, literal, hex)?;
-        all_bytes.extend_from_slice(&bytes);
+        once.push(resolve_key(key, literal, hex)?);
     }
+    // The keys are sent `repeat` times; only the last time may get "; exit".
+    let mut last = once.clone();
 
     // If this pane was created programmatically (via -P -F), append "; exit"
     // before the trailing Enter so the shell closes after the command finishes.
-    // Check ends_with first (cheap, no side effects) before consuming the
+    // Check the last key first (cheap, no side effects) before consuming the
     // one-shot auto_exit entry from global state.
-    if all_bytes.ends_with(b"\r") {
+    if last.last().is_some_and(SendKey::is_enter) {
         if let Some(tid) = ctx.id_map.tmux_pane_id(pane_id) {
             if cc_global_check_auto_exit(&ctx.workspace, tid) {
-                let enter_pos = all_bytes.len() - 1;
                 let exit_bytes = b"; exit";
-                all_bytes.splice(enter_pos..enter_pos, exit_bytes.iter().copied());
+                match last.last_mut() {
+                    Some(SendKey::Bytes(bytes)) => {
+                        let enter_pos = bytes.len() - 1;
+                        bytes.splice(enter_pos..enter_pos, exit_bytes.iter().copied());
+                    }
+                    _ => {
+                        let enter_pos = last.len() - 1;
+                        last.insert(enter_pos, SendKey::Bytes(exit_bytes.to_vec()));
+                    }
+                }
+            }
+        }
+    }
+
+    if !once.is_empty() {
+        for _ in 1..repeat.unwrap_or(1) {
+            for key in &once {
+                send_key(&pane, key)?;
+            }
+        }
+    }
+    for key in &last {
+        send_key(&pane, key)?;
+    }
+
+    Ok(String::new())
+}
+
+/// Deliver one key from `send-keys` to `pane`.
+fn send_key(pane: &Arc<dyn Pane>, key: &SendKey) -> Result<(), String> {
+    match key {
+        SendKey::Key(code, mods) => {
+            let kitty = match pane.get_keyboard_encoding() {
+                KeyboardEncoding::Kitty(flags) => encode_kitty_key(*code, *mods, flags),
+                _ => None,
+            };
+            match kitty {
+                Some(encoded) => pane
+                    .writer()
+                    .write_all(encoded.as_bytes())
+                    .map_err(|e| format!("failed to write to pane: {}", e)),
+                None => pane
+                    .key_down(*code, *mods)
+                    .map_err(|e| format!("failed to send key to pane: {}", e)),
             }
         }
+        SendKey::Bytes(bytes) => pane
+            .writer()
+            .write_all(bytes)
+            .map_err(|e| format!("failed to write to pane: {}", e)),
     }
+}
+
+/// Run a copy mode command for `send-keys -X`.
+///
+/// Copied text goes into a paste buffer and, unless `set-clipboard` is off,
+/// the system clipboard. The `copy-pipe` commands also pipe it to their
+/// command, or to `copy-command` if they weren't given one.
+fn run_copy_command(
+    ctx: &mut HandlerContext,
+    pane: &Arc<dyn Pane>,
+    repeat: Option<u32>,
+    keys: &[String],
+) -> Result<String, String> {
+    let (command, args) = keys
+        .split_first()
+        .ok_or_else(|| "no command specified".to_string())?;
+    let pane_id = pane.pane_id();
+
+    let repeat = repeat.unwrap_or(1) as usize;
+    let (outcome, gui_actions) = {
+        let mut modes = copy_modes();
+        let mode = modes
+            .get_mut(&pane_id)
+            .ok_or_else(|| "not in a mode".to_string())?;
+        let before = mode.state.clone();
+        let outcome = mode.state.run(&mode.screen, command, args, repeat)?;
+        if matches!(
+            outcome,
+            Outcome::Cancel | Outcome::Copy { cancel: true, .. }
+        ) {
+            modes.remove(&pane_id);
+        }
+        let gui_actions = overlay_actions(
+            command,
+            repeat,
+            &before,
+            modes.get(&pane_id).map(|mode| &mode.state),
+        );
+        (outcome, gui_actions)
+    };
+    show_copy_mode_in_gui(
+        pane_id,
+        gui_actions
+            .into_iter()
+            .map(KeyAssignment::CopyMode)
+            .collect(),
+    );
+
+    let (text, append, pipe) = match outcome {
+        Outcome::Copy {
+            text, append, pipe, ..
+        } => (text, append, pipe),
+        Outcome::Done | Outcome::Cancel => return Ok(String::new()),
+    };
+
+    // append-selection adds to the most recent buffer, if there is one.
+    let (name, content) = {
+        let mut store = paste_buffers(ctx.server.as_deref());
+        let recent = store.most_recent().map(|buf| buf.name.clone());
+        match recent {
+            Some(name) if append => {
+                store.append(&name, &text)?;
+                let content = store.get(&name).map(|b| b.data.clone()).unwrap_or_default();
+                (name, content)
+            }
+            _ => (store.set(None, text.clone()), text.clone()),
+        }
+    };
+    ctx.pending_notifications
+        .push(super::response::paste_buffer_changed_notification(&name));
 
-    pane.writer()
-        .write_all(&all_bytes)
-        .map_err(|e| format!("failed to write to pane: {}", e))?;
+    let server = OptionScope::Server.chain(None);
+    if options(ctx.server.as_deref()).lookup(&server, "set-clipboard") != Some("off") {
+        set_system_clipboard(ctx, content);
+    }
 
+    let pipe_command = match pipe {
+        Some(command) if !command.is_empty() => Some(command),
+        Some(_) => options(ctx.server.as_deref())
+            .lookup(&server, "copy-command")
+            .filter(|command| !command.is_empty())
+            .map(str::to_string),
+        None => None,
+    };
+    if let Some(pipe_command) = pipe_command {
+        pipe_to_command(&pipe_command, text)?;
+    }
     Ok(String::new())
 }
 
+/// Start `command` in the background with `text` as its input.
+fn pipe_to_command(command: &str, text: String) -> Result<(), String> {
+    #[cfg(windows)]
+    let (shell, shell_flag) = ("cmd.exe", "/C");
+    #[cfg(not(windows))]
+    let (shell, shell_flag) = ("sh", "-c");
+
+    let mut child = std::process::Command::new(shell)
+        .arg(shell_flag)
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("copy-pipe: failed to spawn: {}", e))?;
+
+    // Feed and reap the child off the server's thread, as it may be slow to
+    // read its input or to exit.
+    let stdin = child.stdin.take();
+    std::thread::Builder::new()
+        .name("copy-pipe".to_string())
+        .spawn(move || {
+            if let Some(mut stdin) = stdin {
+                use std::io::Write;
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        })
+        .map_err(|e| format!("copy-pipe: {}", e))?;
+    Ok(())
+}
+
 /// Parse a tmux style string like `"bg=red,fg=#00ff00"` into (fg, bg) color tuples.
 ///
 /// Supports:
@@ -2021,6 +3318,17 @@ pub fn handle_select_window(
         .window_id
         .ok_or_else(|| "no window resolved".to_string())?;
 
+    activate_tab_in_window(ctx, &mux, wid, tab_id)
+}
+
+/// Make `tab_id` the active tab of mux window `wid` and the client's
+/// current window, as `select-window` does.
+fn activate_tab_in_window(
+    ctx: &mut HandlerContext,
+    mux: &Mux,
+    wid: WindowId,
+    tab_id: crate::tab::TabId,
+) -> Result<String, String> {
     // Suppress the resulting WindowInvalidated → %session-window-changed
     // notification to prevent a feedback loop (like iTerm2's approach).
     ctx.suppress_window_changed += 1;
@@ -2050,7 +3358,7 @@ pub fn handle_select_window(
 }
 
 /// Kill (remove) a pane.
-pub fn handle_kill_pane(
+pub async fn handle_kill_pane(
     ctx: &mut HandlerContext,
     target: &Option<String>,
 ) -> Result<String, String> {
@@ -2067,66 +3375,321 @@ pub fn handle_kill_pane(
         cc_global_remove_auto_exit(&ctx.workspace, tid);
     }
     ctx.id_map.remove_pane(pane_id);
+    // Hand the pipe everything the pane printed before it goes.
+    close_pipe_pane(pane_id).await;
+    note_pane_killed(pane_id);
     mux.remove_pane(pane_id);
 
     Ok(String::new())
 }
 
+/// Whether the program in `pane` is still running, rather than having
+/// exited with the pane kept open.
+fn pane_is_running(pane: &dyn Pane) -> bool {
+    !pane.is_dead() && pane.exit_code().is_none()
+}
+
+/// Handle `respawn-pane`: start a new program in a pane whose program has
+/// exited, or with `-k` in place of the running one. The pane keeps its
+/// id, position and scrollback.
+pub async fn handle_respawn_pane(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    kill: bool,
+    cwd: Option<&str>,
+    env: &[String],
+    shell_command: Option<&str>,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let resolved = ctx.resolve_target(target)?;
+    let pane_id = resolved
+        .pane_id
+        .ok_or_else(|| "can't find pane".to_string())?;
+    let pane = mux
+        .get_pane(pane_id)
+        .ok_or_else(|| format!("can't find pane {}", pane_id))?;
+
+    if !kill && pane_is_running(&*pane) {
+        let tmux_pane_id = ctx.id_map.get_or_create_tmux_pane_id(pane_id);
+        return Err(format!("pane %{} still active", tmux_pane_id));
+    }
+
+    let workspace = resolved.workspace.unwrap_or_else(|| ctx.workspace.clone());
+    respawn(ctx, &pane, &workspace, cwd, env, shell_command).await?;
+    Ok(String::new())
+}
+
+/// Handle `respawn-window`: like tmux, the other panes of the window are
+/// closed and a new program is started in the first one.
+pub async fn handle_respawn_window(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    kill: bool,
+    cwd: Option<&str>,
+    env: &[String],
+    shell_command: Option<&str>,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let resolved = ctx.resolve_target(target)?;
+    let tab_id = resolved
+        .tab_id
+        .ok_or_else(|| "no window resolved for respawn-window".to_string())?;
+    let tab = mux
+        .get_tab(tab_id)
+        .ok_or_else(|| format!("can't find tab {}", tab_id))?;
+
+    let panes: Vec<Arc<dyn Pane>> = tab
+        .iter_panes_ignoring_zoom()
+        .into_iter()
+        .map(|p| p.pane)
+        .collect();
+    if !kill && panes.iter().any(|pane| pane_is_running(&**pane)) {
+        let tmux_window_id = ctx.id_map.get_or_create_tmux_window_id(tab_id);
+        return Err(format!("window @{} still active", tmux_window_id));
+    }
+    let (first, rest) = panes
+        .split_first()
+        .ok_or_else(|| "window has no panes".to_string())?;
+
+    for pane in rest {
+        let pane_id = pane.pane_id();
+        cc_global_remove_spawned(&ctx.workspace, pane_id);
+        if let Some(tid) = ctx.id_map.tmux_pane_id(pane_id) {
+            cc_global_remove_auto_exit(&ctx.workspace, tid);
+        }
+        ctx.id_map.remove_pane(pane_id);
+        note_pane_killed(pane_id);
+        mux.remove_pane(pane_id);
+    }
+
+    let workspace = resolved.workspace.unwrap_or_else(|| ctx.workspace.clone());
+    respawn(ctx, first, &workspace, cwd, env, shell_command).await?;
+    if !rest.is_empty() {
+        queue_layout_change(ctx, &tab);
+    }
+    Ok(String::new())
+}
+
+/// Start a new program in `pane`, which belongs to `workspace`, through the
+/// domain it belongs to.
+async fn respawn(
+    ctx: &HandlerContext,
+    pane: &Arc<dyn Pane>,
+    workspace: &str,
+    cwd: Option<&str>,
+    env: &[String],
+    shell_command: Option<&str>,
+) -> Result<(), String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+    let domain = mux
+        .get_domain(pane.domain_id())
+        .ok_or_else(|| format!("can't find domain for pane {}", pane.pane_id()))?;
+
+    // The directory is passed separately so that, without a command, the
+    // pane's own command is run again in it, in the current environment.
+    let env = ctx.spawn_env(workspace, env);
+    let own_command = pane
+        .downcast_ref::<LocalPane>()
+        .and_then(|local| local.spawn_command());
+    let command = match (shell_command, own_command) {
+        (None, Some(mut builder)) if !env.is_empty() => {
+            apply_spawn_env(&mut builder, &env);
+            Some(builder)
+        }
+        _ => build_spawn_command(shell_command, None, &env),
+    };
+    domain
+        .respawn_pane(pane, command, cwd.map(str::to_string))
+        .await
+        .map_err(|e| format!("respawn failed: {:#}", e))?;
+    Ok(())
+}
+
+/// Tell `pane` whether to stay open when its program exits, from the
+/// `remain-on-exit` option that applies to it.
+fn sync_remain_on_exit(pane: &dyn Pane) {
+    let located = Mux::try_get().and_then(|mux| {
+        let (_, window_id, tab_id) = mux.resolve_pane_id(pane.pane_id())?;
+        let workspace = mux.get_window(window_id)?.get_workspace().to_string();
+        Some((tab_id, named_server::owner(&workspace)))
+    });
+    let (tab_id, server) = located.unzip();
+    let chain = OptionScope::Pane(pane.pane_id()).chain(tab_id);
+    let remain = match options(server.flatten().as_deref()).lookup(&chain, "remain-on-exit") {
+        Some("on" | "yes" | "1") => RemainOnExit::On,
+        Some("failed") => RemainOnExit::Failed,
+        _ => RemainOnExit::Off,
+    };
+    pane.set_remain_on_exit(remain);
+}
+
+/// The `history-limit` set for `workspace`, or `None` if it was never set
+/// and panes keep the configured `scrollback_lines`.
+fn session_history_limit(workspace: &str) -> Option<usize> {
+    let chain = OptionScope::Session(workspace.to_string()).chain(None);
+    options(named_server::owner(workspace).as_deref())
+        .lookup(&chain, "history-limit")
+        .and_then(|value| value.parse().ok())
+}
+
+/// The number of lines of scrollback `pane` keeps.
+fn history_limit(pane: &dyn Pane, workspace: &str) -> usize {
+    pane.scrollback_limit()
+        .or_else(|| session_history_limit(workspace))
+        .unwrap_or_else(|| {
+            default_value("history-limit")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0)
+        })
+}
+
+/// Apply the `history-limit` of the session `pane` is in to its scrollback.
+fn sync_history_limit(pane: &dyn Pane) {
+    let workspace = Mux::try_get().and_then(|mux| {
+        let (_, window_id, _) = mux.resolve_pane_id(pane.pane_id())?;
+        Some(mux.get_window(window_id)?.get_workspace().to_string())
+    });
+    if let Some(workspace) = workspace {
+        pane.set_scrollback_limit(session_history_limit(&workspace));
+    }
+}
+
 /// Resize a pane, or toggle zoom if `-Z` was specified.
+///
+/// As in tmux, the pane is resized by moving its borders: `-x`/`-y` (cells,
+/// or a percentage of the window) move the split next to it so that it gets
+/// that size, and `adjust` moves the split in the direction `L`, `R`, `U` or
+/// `D` by that many cells. The window is unzoomed first. With `mouse`, the
+/// border under the last `send-keys -M` event is dragged by those that
+/// follow, until the button is released.
 pub fn handle_resize_pane(
     ctx: &mut HandlerContext,
     target: &Option<String>,
-    width: Option<u64>,
-    height: Option<u64>,
+    width: Option<&str>,
+    height: Option<&str>,
     zoom: bool,
+    mouse: bool,
+    adjust: Option<(char, usize)>,
 ) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
 
+    let resolved = ctx.resolve_target(target)?;
+    let tab_id = resolved
+        .tab_id
+        .ok_or_else(|| "no window resolved for resize".to_string())?;
+    let tab = mux
+        .get_tab(tab_id)
+        .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
+
     if zoom {
-        let resolved = ctx.resolve_target(target)?;
-        let tab_id = resolved
-            .tab_id
-            .ok_or_else(|| "no window resolved for zoom".to_string())?;
-        let tab = mux
-            .get_tab(tab_id)
-            .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
         tab.toggle_zoom();
         return Ok(String::new());
     }
+    if mouse {
+        split_drag::start_drag(&tab);
+        return Ok(String::new());
+    }
 
-    let resolved = ctx.resolve_target(target)?;
     let pane_id = resolved
         .pane_id
         .ok_or_else(|| "can't find pane".to_string())?;
-
-    let pane = mux
-        .get_pane(pane_id)
+    tab.set_zoomed(false);
+    let index = tab
+        .iter_panes()
+        .iter()
+        .find(|p| p.pane.pane_id() == pane_id)
+        .map(|p| p.index)
         .ok_or_else(|| format!("can't find pane: {}", pane_id))?;
 
-    let dims = pane.get_dimensions();
-    let new_cols = width.map(|w| w as usize).unwrap_or(dims.cols);
-    let new_rows = height.map(|h| h as usize).unwrap_or(dims.viewport_rows);
+    let tab_size = tab.get_size();
+    if let Some(width) = width {
+        let cols = resize_cells(width, tab_size.cols, "width")?;
+        tab.resize_pane_at(index, SplitDirection::Horizontal, cols);
+    }
+    if let Some(height) = height {
+        let rows = resize_cells(height, tab_size.rows, "height")?;
+        tab.resize_pane_at(index, SplitDirection::Vertical, rows);
+    }
+    if let Some((direction, amount)) = adjust {
+        let direction = match direction {
+            'L' => PaneDirection::Left,
+            'R' => PaneDirection::Right,
+            'U' => PaneDirection::Up,
+            _ => PaneDirection::Down,
+        };
+        tab.adjust_pane_size_at(index, direction, amount);
+    }
 
-    let size = TerminalSize {
-        cols: new_cols,
-        rows: new_rows,
-        pixel_width: 0,
-        pixel_height: 0,
-        dpi: dims.dpi,
-    };
+    Ok(String::new())
+}
 
-    pane.resize(size)
-        .map_err(|e| format!("resize-pane failed: {}", e))?;
+/// Convert a `resize-pane -x`/`-y` value, in cells or as a percentage of
+/// `total`, to cells.
+fn resize_cells(value: &str, total: usize, what: &str) -> Result<usize, String> {
+    match parse_split_size(Some(value)) {
+        Ok(SplitSize::Cells(cells)) => Ok(cells),
+        Ok(SplitSize::Percent(percent)) => Ok((total * percent as usize / 100).max(1)),
+        Err(_) => Err(format!("{} is invalid: {}", what, value)),
+    }
+}
 
-    Ok(String::new())
+/// The sizes of the GUI windows showing the session of `tab_id`, which are
+/// what tmux's clients are to `resize-window -A` and `-a`. The tabs of a GUI
+/// window all have its size, so each window is measured by its active tab,
+/// or by another of its tabs when that is `tab_id`, whose size is the one
+/// being changed.
+fn gui_window_sizes(mux: &Mux, tab_id: crate::tab::TabId) -> Vec<TerminalSize> {
+    let workspace = match mux
+        .window_containing_tab(tab_id)
+        .and_then(|window_id| mux.get_window(window_id))
+    {
+        Some(window) => window.get_workspace().to_string(),
+        None => return vec![],
+    };
+    mux.iter_windows_in_workspace(&workspace)
+        .into_iter()
+        .filter_map(|window_id| {
+            let window = mux.get_window(window_id)?;
+            let tab = window
+                .get_active()
+                .filter(|tab| tab.tab_id() != tab_id)
+                .or_else(|| window.iter().find(|tab| tab.tab_id() != tab_id))?;
+            Some(tab.get_size())
+        })
+        .collect()
+}
+
+/// `current` with the columns and rows of the largest (`-A`) or smallest
+/// (`-a`) of `sizes`, each on its own, as tmux sizes a window for several
+/// clients. `current` is kept when there are no `sizes`.
+fn fit_size(current: TerminalSize, sizes: &[TerminalSize], largest: bool) -> TerminalSize {
+    let cols = sizes.iter().map(|s| s.cols);
+    let rows = sizes.iter().map(|s| s.rows);
+    let (cols, rows) = if largest {
+        (cols.max(), rows.max())
+    } else {
+        (cols.min(), rows.min())
+    };
+    TerminalSize {
+        cols: cols.unwrap_or(current.cols),
+        rows: rows.unwrap_or(current.rows),
+        ..current
+    }
 }
 
 /// Resize a window (all panes in tab).
+///
+/// With `fit`, the window takes the size of the largest (`Some(true)`, `-A`)
+/// or smallest (`Some(false)`, `-a`) of the GUI windows showing its session;
+/// see [`gui_window_sizes`]. `-x`/`-y` still override either dimension.
 pub fn handle_resize_window(
     ctx: &mut HandlerContext,
     target: &Option<String>,
     width: Option<u64>,
     height: Option<u64>,
+    fit: Option<bool>,
 ) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
 
@@ -2139,7 +3702,10 @@ pub fn handle_resize_window(
         .get_tab(tab_id)
         .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
 
-    let current_size = tab.get_size();
+    let mut current_size = tab.get_size();
+    if let Some(largest) = fit {
+        current_size = fit_size(current_size, &gui_window_sizes(&mux, tab_id), largest);
+    }
     let new_cols = width.map(|w| w as usize).unwrap_or(current_size.cols);
     let new_rows = height.map(|h| h as usize).unwrap_or(current_size.rows);
 
@@ -2405,8 +3971,7 @@ pub fn check_subscriptions(ctx: &mut HandlerContext) -> Vec<String> {
                     if let Some(pane) = mux.get_pane(real_pane_id) {
                         let (window_id_str, window_index_str) =
                             find_window_for_pane(&mux, &window_ids, &ctx.id_map, real_pane_id);
-                        let fctx =
-                            build_pane_format_context_minimal(&ctx.id_map, &pane, &ctx.workspace);
+                        let fctx = build_pane_format_context_minimal(ctx, &pane, &ctx.workspace);
                         let value = expand_format(&ctx.subscriptions[sub_idx].format, &fctx);
                         let key = format!("%{}", pane_tmux_id);
                         let changed = ctx.subscriptions[sub_idx]
@@ -2506,7 +4071,7 @@ pub fn check_subscriptions(ctx: &mut HandlerContext) -> Vec<String> {
                                 let tmux_wid =
                                     ctx.id_map.get_or_create_tmux_window_id(tab.tab_id());
                                 let fctx = build_pane_format_context_minimal(
-                                    &ctx.id_map,
+                                    ctx,
                                     &pp.pane,
                                     &ctx.workspace,
                                 );
@@ -2581,10 +4146,11 @@ pub fn check_subscriptions(ctx: &mut HandlerContext) -> Vec<String> {
 
 /// Build a minimal FormatContext from a pane reference (for subscriptions).
 fn build_pane_format_context_minimal(
-    id_map: &IdMap,
+    ctx: &HandlerContext,
     pane: &Arc<dyn Pane>,
     workspace: &str,
 ) -> FormatContext {
+    let id_map = &ctx.id_map;
     let tmux_pane_id = id_map.tmux_pane_id(pane.pane_id()).unwrap_or(0);
     let dims = pane.get_dimensions();
     let cursor = pane.get_cursor_position();
@@ -2609,6 +4175,11 @@ fn build_pane_format_context_minimal(
         pane_current_path,
         session_id: id_map.tmux_session_id(workspace).unwrap_or(0),
         session_name: workspace.to_string(),
+        user_options: options(ctx.server.as_deref()).format_user_options(
+            Some(pane.pane_id()),
+            None,
+            workspace,
+        ),
         ..FormatContext::default()
     }
 }
@@ -2675,10 +4246,40 @@ fn build_shell_command(shell_command: &str) -> CommandBuilder {
     builder
 }
 
-/// Split a window pane.
-///
-/// tmux `-h` = horizontal split (side by side) = WezTerm `SplitDirection::Horizontal`
-/// default (no flag or `-v`) = vertical split (stacked) = WezTerm `SplitDirection::Vertical`
+/// The command to spawn for `-c`, `-e` and a shell command, or `None` to
+/// spawn the default program.
+fn build_spawn_command(
+    shell_command: Option<&str>,
+    cwd: Option<&str>,
+    env: &[(String, Option<String>)],
+) -> Option<CommandBuilder> {
+    let mut builder = match shell_command {
+        Some(cmd) => build_shell_command(cmd),
+        None if !env.is_empty() || cwd.is_some() => CommandBuilder::new_default_prog(),
+        None => return None,
+    };
+    apply_spawn_env(&mut builder, env);
+    if let Some(dir) = cwd {
+        builder.cwd(dir);
+    }
+    Some(builder)
+}
+
+/// Make the environment changes from [`HandlerContext::spawn_env`].
+fn apply_spawn_env(builder: &mut CommandBuilder, env: &[(String, Option<String>)]) {
+    for (name, value) in env {
+        match value {
+            Some(value) => builder.env(name, value),
+            None => builder.env_remove(name),
+        }
+    }
+}
+
+/// Split a window pane.
+///
+/// tmux `-h` = horizontal split (side by side) = WezTerm `SplitDirection::Horizontal`
+/// default (no flag or `-v`) = vertical split (stacked) = WezTerm `SplitDirection::Vertical`
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_split_window(
     ctx: &mut HandlerContext,
     horizontal: bool,
@@ -2697,6 +4298,7 @@ pub async fn handle_split_window(
         .ok_or_else(|| "can't find pane for split".to_string())?;
 
     let split_size = parse_split_size(size)?;
+    let workspace = resolved.workspace.unwrap_or_else(|| ctx.workspace.clone());
 
     let direction = if horizontal {
         SplitDirection::Horizontal
@@ -2711,31 +4313,7 @@ pub async fn handle_split_window(
         size: split_size,
     };
 
-    let command = if let Some(cmd) = shell_command {
-        let mut builder = build_shell_command(cmd);
-        for kv in env {
-            if let Some((k, v)) = kv.split_once('=') {
-                builder.env(k, v);
-            }
-        }
-        if let Some(dir) = cwd {
-            builder.cwd(dir);
-        }
-        Some(builder)
-    } else if !env.is_empty() || cwd.is_some() {
-        let mut builder = CommandBuilder::new_default_prog();
-        for kv in env {
-            if let Some((k, v)) = kv.split_once('=') {
-                builder.env(k, v);
-            }
-        }
-        if let Some(dir) = cwd {
-            builder.cwd(dir);
-        }
-        Some(builder)
-    } else {
-        None
-    };
+    let command = build_spawn_command(shell_command, cwd, &ctx.spawn_env(&workspace, env));
 
     let source = SplitSource::Spawn {
         command,
@@ -2753,6 +4331,8 @@ pub async fn handle_split_window(
 
     // Track CC-spawned panes for disconnect cleanup and dead-pane reaping
     cc_global_insert_spawned(&ctx.workspace, new_pane.pane_id());
+    sync_remain_on_exit(&*new_pane);
+    sync_history_limit(&*new_pane);
 
     // If -P was specified, mark as auto-exit and return format-expanded info.
     // Skip auto-exit when a shell_command was given — the pane will close
@@ -2786,31 +4366,7 @@ pub async fn handle_new_window(
 
     let current_pane_id = resolved.pane_id;
 
-    let command = if let Some(cmd) = shell_command {
-        let mut builder = build_shell_command(cmd);
-        for kv in env {
-            if let Some((k, v)) = kv.split_once('=') {
-                builder.env(k, v);
-            }
-        }
-        if let Some(dir) = cwd {
-            builder.cwd(dir);
-        }
-        Some(builder)
-    } else if !env.is_empty() || cwd.is_some() {
-        let mut builder = CommandBuilder::new_default_prog();
-        for kv in env {
-            if let Some((k, v)) = kv.split_once('=') {
-                builder.env(k, v);
-            }
-        }
-        if let Some(dir) = cwd {
-            builder.cwd(dir);
-        }
-        Some(builder)
-    } else {
-        None
-    };
+    let command = build_spawn_command(shell_command, cwd, &ctx.spawn_env(&workspace, env));
 
     let (tab, pane, _wid) = mux
         .spawn_tab_or_window(
@@ -2838,6 +4394,8 @@ pub async fn handle_new_window(
 
     // Track CC-spawned panes for disconnect cleanup and dead-pane reaping
     cc_global_insert_spawned(&ctx.workspace, pane.pane_id());
+    sync_remain_on_exit(&*pane);
+    sync_history_limit(&*pane);
 
     // If -P was specified, mark as auto-exit and return format-expanded info.
     // Skip auto-exit when a shell_command was given — the pane will close
@@ -2857,7 +4415,7 @@ pub async fn handle_new_window(
 // ---------------------------------------------------------------------------
 
 /// Kill (remove) a window (tab).
-pub fn handle_kill_window(
+pub async fn handle_kill_window(
     ctx: &mut HandlerContext,
     target: &Option<String>,
 ) -> Result<String, String> {
@@ -2868,11 +4426,18 @@ pub fn handle_kill_window(
         .tab_id
         .ok_or_else(|| "no window resolved".to_string())?;
 
-    // Clean up pane mappings for all panes in this tab
+    // Clean up pane mappings and pipes for all panes in this tab
     if let Some(tab) = mux.get_tab(tab_id) {
-        for pp in tab.iter_panes() {
-            ctx.id_map.remove_pane(pp.pane.pane_id());
+        let pane_ids: Vec<PaneId> = tab
+            .iter_panes_ignoring_zoom()
+            .iter()
+            .map(|pp| pp.pane.pane_id())
+            .collect();
+        for &pane_id in &pane_ids {
+            ctx.id_map.remove_pane(pane_id);
+            note_pane_killed(pane_id);
         }
+        close_pipe_panes(pane_ids).await;
     }
 
     // Clean up window mapping
@@ -2885,7 +4450,7 @@ pub fn handle_kill_window(
 }
 
 /// Kill (remove) a session (all windows in a workspace).
-pub fn handle_kill_session(
+pub async fn handle_kill_session(
     ctx: &mut HandlerContext,
     target: &Option<String>,
 ) -> Result<String, String> {
@@ -2897,7 +4462,9 @@ pub fn handle_kill_session(
     // Collect all mux windows in the workspace
     let window_ids = mux.iter_windows_in_workspace(&workspace);
 
-    // Clean up id_map entries for all tabs and panes in those windows
+    // Clean up id_map entries and pipes for all tabs and panes in those
+    // windows
+    let mut pane_ids = vec![];
     for &wid in &window_ids {
         let tabs: Vec<Arc<Tab>> = {
             match mux.get_window(wid) {
@@ -2906,12 +4473,15 @@ pub fn handle_kill_session(
             }
         };
         for tab in &tabs {
-            for pp in tab.iter_panes() {
+            for pp in tab.iter_panes_ignoring_zoom() {
                 ctx.id_map.remove_pane(pp.pane.pane_id());
+                note_pane_killed(pp.pane.pane_id());
+                pane_ids.push(pp.pane.pane_id());
             }
             ctx.id_map.remove_window(tab.tab_id());
         }
     }
+    close_pipe_panes(pane_ids).await;
 
     // Kill all mux windows in the workspace
     for wid in window_ids {
@@ -2970,6 +4540,7 @@ pub fn handle_rename_session(
 
     mux.rename_workspace(&old_workspace, name);
     ctx.id_map.rename_session(&old_workspace, name);
+    named_server::rename_session(&old_workspace, name);
 
     // Update context workspace if it was the one renamed
     if ctx.workspace == old_workspace {
@@ -2987,8 +4558,8 @@ pub async fn handle_move_pane(
     ctx: &mut HandlerContext,
     src: &Option<String>,
     dst: &Option<String>,
-    horizontal: bool,
-    before: bool,
+    flags: MovePaneFlags,
+    size: Option<&str>,
 ) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
 
@@ -3008,7 +4579,7 @@ pub async fn handle_move_pane(
         return Err("source and target panes must be different".to_string());
     }
 
-    let direction = if horizontal {
+    let direction = if flags.horizontal {
         SplitDirection::Horizontal
     } else {
         SplitDirection::Vertical
@@ -3016,11 +4587,22 @@ pub async fn handle_move_pane(
 
     let request = SplitRequest {
         direction,
-        target_is_second: !before,
-        top_level: false,
-        size: SplitSize::Percent(50),
+        target_is_second: !flags.before,
+        top_level: flags.full_size,
+        size: parse_split_size(size)?,
     };
 
+    let (_, _, src_tab_id) = mux
+        .resolve_pane_id(src_real_pane_id)
+        .ok_or_else(|| format!("can't find window for pane {}", src_real_pane_id))?;
+    let (_, _, dst_tab_id) = mux
+        .resolve_pane_id(dst_real_pane_id)
+        .ok_or_else(|| format!("can't find window for pane {}", dst_real_pane_id))?;
+    let dst_tab = mux
+        .get_tab(dst_tab_id)
+        .ok_or_else(|| format!("can't find window for tab {}", dst_tab_id))?;
+    let prior_active = dst_tab.get_active_pane();
+
     let source = SplitSource::MovePane(src_real_pane_id);
 
     mux.split_pane(
@@ -3032,9 +4614,264 @@ pub async fn handle_move_pane(
     .await
     .map_err(|e| format!("move-pane failed: {}", e))?;
 
+    if flags.detach {
+        if let Some(pane) = prior_active {
+            dst_tab.set_active_pane(&pane);
+        }
+    } else {
+        mux.focus_pane_and_containing_tab(src_real_pane_id)
+            .map_err(|e| format!("move-pane failed: {}", e))?;
+        ctx.active_pane_id = ctx.id_map.tmux_pane_id(src_real_pane_id);
+    }
+
+    // The source window only changes layout if it still has panes left.
+    if src_tab_id != dst_tab_id {
+        if let Some(src_tab) = mux.get_tab(src_tab_id) {
+            queue_layout_change(ctx, &src_tab);
+        }
+    }
+    queue_layout_change(ctx, &dst_tab);
+
+    Ok(String::new())
+}
+
+/// Flags for `move-pane` / `join-pane`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MovePaneFlags {
+    /// `-h`: place the pane beside the target rather than below it.
+    pub horizontal: bool,
+    /// `-b`: place the pane before (left of or above) the target.
+    pub before: bool,
+    /// `-d`: keep the destination window's active pane.
+    pub detach: bool,
+    /// `-f`: span the full width or height of the window.
+    pub full_size: bool,
+}
+
+/// Queue a `%layout-change` for `tab` after panes were rearranged.
+fn queue_layout_change(ctx: &mut HandlerContext, tab: &Arc<Tab>) {
+    let tmux_wid = ctx.id_map.get_or_create_tmux_window_id(tab.tab_id());
+    let layout = super::server::build_layout_for_tab(ctx, tab);
+    ctx.pending_notifications
+        .push(layout_change_notification(tmux_wid, &layout));
+}
+
+/// After panes in `tab` moved around, point the client's current pane at
+/// the tab's active pane, unless the current pane is in another tab.
+fn follow_active_pane(ctx: &mut HandlerContext, tab: &Tab) {
+    let current = ctx
+        .active_pane_id
+        .and_then(|id| ctx.id_map.wezterm_pane_id(id));
+    if let Some(pane_id) = current {
+        if !tab.contains_pane(pane_id) {
+            return;
+        }
+    }
+    if let Some(pane) = tab.get_active_pane() {
+        ctx.active_pane_id = ctx.id_map.tmux_pane_id(pane.pane_id());
+    }
+}
+
+/// Swap two panes of the same window.
+///
+/// tmux: `swap-pane [-dDUZ] [-s src-pane] [-t dst-pane]`. With `-U` or `-D`
+/// the target is swapped with the pane before or after it instead of the
+/// source. The target pane stays active in its new position unless `-d` is
+/// given, in which case the active position is kept.
+pub fn handle_swap_pane(
+    ctx: &mut HandlerContext,
+    src: &Option<String>,
+    dst: &Option<String>,
+    detach: bool,
+    up: bool,
+    down: bool,
+    keep_zoom: bool,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let dst_pane_id = ctx
+        .resolve_target(dst)?
+        .pane_id
+        .ok_or_else(|| "no target pane resolved for swap-pane".to_string())?;
+    let (_, _, tab_id) = mux
+        .resolve_pane_id(dst_pane_id)
+        .ok_or_else(|| format!("can't find window for pane {}", dst_pane_id))?;
+    let tab = mux
+        .get_tab(tab_id)
+        .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
+
+    let panes = tab.iter_panes_ignoring_zoom();
+    let index_of = |pane_id: PaneId| {
+        panes
+            .iter()
+            .find(|pp| pp.pane.pane_id() == pane_id)
+            .map(|pp| pp.index)
+    };
+    let dst_idx =
+        index_of(dst_pane_id).ok_or_else(|| format!("can't find pane: {}", dst_pane_id))?;
+    let src_idx = if up {
+        (dst_idx + panes.len() - 1) % panes.len()
+    } else if down {
+        (dst_idx + 1) % panes.len()
+    } else {
+        let src_pane_id = ctx
+            .resolve_target(src)?
+            .pane_id
+            .ok_or_else(|| "no source pane resolved for swap-pane".to_string())?;
+        // Tabs can only swap their own panes, so unlike tmux the two panes
+        // must be in the same window.
+        index_of(src_pane_id)
+            .ok_or_else(|| "swap-pane: source and target must be in the same window".to_string())?
+    };
+    if src_idx == dst_idx {
+        return Ok(String::new());
+    }
+
+    let was_zoomed = tab.set_zoomed(false);
+    let active_idx = tab.get_active_idx();
+    // Swapping with focus kept moves the target pane, still active, into
+    // the source's position.
+    tab.set_active_idx(dst_idx);
+    tab.swap_active_with_index(src_idx, true);
+    if detach {
+        tab.set_active_idx(active_idx);
+    }
+    if keep_zoom && was_zoomed {
+        tab.set_zoomed(true);
+    }
+
+    follow_active_pane(ctx, &tab);
+    queue_layout_change(ctx, &tab);
+    Ok(String::new())
+}
+
+/// Rotate the panes of a window.
+///
+/// tmux: `rotate-window [-DUZ] [-t target-window]`. `-U` (the default)
+/// moves each pane to the position before it, `-D` to the one after it.
+/// The active pane moves with the rotation and stays active.
+pub fn handle_rotate_window(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    down: bool,
+    keep_zoom: bool,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let tab_id = ctx
+        .resolve_target(target)?
+        .tab_id
+        .ok_or_else(|| "no window resolved".to_string())?;
+    let tab = mux
+        .get_tab(tab_id)
+        .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
+
+    let was_zoomed = tab.set_zoomed(false);
+    let active = tab.get_active_pane();
+    if down {
+        tab.rotate_clockwise();
+    } else {
+        tab.rotate_counter_clockwise();
+        // Unlike rotate_clockwise, this doesn't tell the GUI about the new
+        // pane sizes.
+        mux.notify(MuxNotification::TabResized(tab_id));
+    }
+    // The rotation keeps the active position, so follow the pane to where
+    // it went.
+    if let Some(pane) = active {
+        tab.set_active_pane(&pane);
+    }
+    if keep_zoom && was_zoomed {
+        tab.set_zoomed(true);
+    }
+
+    follow_active_pane(ctx, &tab);
+    queue_layout_change(ctx, &tab);
+    Ok(String::new())
+}
+
+/// Select the previously active pane of a window.
+///
+/// tmux: `last-pane [-deZ] [-t target-window]`.
+pub fn handle_last_pane(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    keep_zoom: bool,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let tab_id = ctx
+        .resolve_target(target)?
+        .tab_id
+        .ok_or_else(|| "no window resolved".to_string())?;
+    let tab = mux
+        .get_tab(tab_id)
+        .ok_or_else(|| format!("can't find window for tab {}", tab_id))?;
+    let idx = tab
+        .get_last_active_idx()
+        .ok_or_else(|| "no last pane".to_string())?;
+
+    let was_zoomed = tab.set_zoomed(false);
+    tab.set_active_idx(idx);
+    if keep_zoom && was_zoomed {
+        tab.set_zoomed(true);
+    }
+
+    if let Some(pane) = tab.get_active_pane() {
+        ctx.active_pane_id = ctx.id_map.tmux_pane_id(pane.pane_id());
+    }
+    if was_zoomed {
+        queue_layout_change(ctx, &tab);
+    }
     Ok(String::new())
 }
 
+/// Which window `last-window`, `next-window` and `previous-window` select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowStep {
+    Last,
+    Next,
+    Previous,
+}
+
+/// Select the last, next or previous window (tab) of a session, wrapping
+/// around at either end.
+pub fn handle_window_step(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    step: WindowStep,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+
+    let wid = ctx
+        .resolve_target(target)?
+        .window_id
+        .ok_or_else(|| "no window resolved".to_string())?;
+    let tab_id = {
+        let window = mux
+            .get_window(wid)
+            .ok_or_else(|| format!("can't find window: {}", wid))?;
+        let count = window.len();
+        let active = window.get_active_idx();
+        let idx = match step {
+            WindowStep::Last => window
+                .get_last_active_idx()
+                .filter(|&idx| idx != active)
+                .ok_or_else(|| "no last window".to_string())?,
+            WindowStep::Next if count > 1 => (active + 1) % count,
+            WindowStep::Previous if count > 1 => (active + count - 1) % count,
+            WindowStep::Next => return Err("no next window".to_string()),
+            WindowStep::Previous => return Err("no previous window".to_string()),
+        };
+        window
+            .get_by_idx(idx)
+            .map(|tab| tab.tab_id())
+            .ok_or_else(|| format!("can't find window: index {}", idx))?
+    };
+
+    activate_tab_in_window(ctx, &mux, wid, tab_id)
+}
+
 /// Move a window (tab) from one session to another.
 ///
 /// tmux: `move-window -s <src> -t <dst>`
@@ -3116,6 +4953,19 @@ pub fn handle_move_window(
     Ok(String::new())
 }
 
+/// The name of a session made by `new-session` without `-s`. Sessions are
+/// named "default" on the default server. A named server numbers them like
+/// tmux, so that they don't clash with the default server's sessions.
+pub(super) fn new_session_name(server: Option<&str>, existing: &[String]) -> String {
+    match server {
+        None => "default".to_string(),
+        Some(_) => (0..)
+            .map(|n: usize| n.to_string())
+            .find(|name| !existing.contains(name))
+            .unwrap_or_default(),
+    }
+}
+
 /// Create a new session (workspace with a new window).
 pub async fn handle_new_session(
     ctx: &mut HandlerContext,
@@ -3128,38 +4978,29 @@ pub async fn handle_new_session(
 ) -> Result<String, String> {
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
 
-    let workspace = name.unwrap_or("default").to_string();
+    let existing = mux.iter_workspaces();
+    let workspace = match name {
+        Some(name) => name.to_string(),
+        None => new_session_name(ctx.server.as_deref(), &existing),
+    };
 
     // Check if workspace already exists
-    if mux.iter_workspaces().contains(&workspace) {
+    if existing.contains(&workspace) {
         return Err(format!("duplicate session: {}", workspace));
     }
 
-    let command = if let Some(cmd) = shell_command {
-        let mut builder = build_shell_command(cmd);
-        for kv in env {
-            if let Some((k, v)) = kv.split_once('=') {
-                builder.env(k, v);
-            }
-        }
-        if let Some(dir) = cwd {
-            builder.cwd(dir);
-        }
-        Some(builder)
-    } else if !env.is_empty() || cwd.is_some() {
-        let mut builder = CommandBuilder::new_default_prog();
-        for kv in env {
-            if let Some((k, v)) = kv.split_once('=') {
-                builder.env(k, v);
-            }
-        }
-        if let Some(dir) = cwd {
-            builder.cwd(dir);
+    // Like tmux, `-e` sets up the environment of the new session, rather
+    // than only that of its first pane. Anything left over from an earlier
+    // session of the same name goes.
+    {
+        let scope = OptionScope::Session(workspace.clone());
+        let mut environments = environments(ctx.server.as_deref());
+        environments.remove_scope(&scope);
+        for (name, value) in env.iter().filter_map(|kv| kv.split_once('=')) {
+            environments.set(&scope, name, Some(value.to_string()), false);
         }
-        Some(builder)
-    } else {
-        None
-    };
+    }
+    let command = build_spawn_command(shell_command, cwd, &ctx.spawn_env(&workspace, &[]));
 
     let (tab, pane, _wid) = mux
         .spawn_tab_or_window(
@@ -3187,10 +5028,13 @@ pub async fn handle_new_session(
     ctx.active_session_id = Some(tmux_session_id);
     ctx.active_window_id = Some(tmux_window_id);
     ctx.active_pane_id = Some(tmux_pane_id);
+    named_server::add_session(ctx.server.as_deref(), &workspace);
     ctx.workspace = workspace;
 
     // Track CC-spawned panes for disconnect cleanup and dead-pane reaping
     cc_global_insert_spawned(&ctx.workspace, pane.pane_id());
+    sync_remain_on_exit(&*pane);
+    sync_history_limit(&*pane);
 
     // If -P was specified, mark as auto-exit and return format-expanded info.
     // Skip auto-exit when a shell_command was given — the pane will close
@@ -3212,9 +5056,10 @@ fn handle_select_layout(
     ctx: &mut HandlerContext,
     target: &Option<String>,
     layout_name: Option<&str>,
+    spread: bool,
+    next: bool,
+    previous: bool,
 ) -> Result<String, String> {
-    let layout = layout_name.ok_or_else(|| "select-layout: no layout name given".to_string())?;
-
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
     let resolved = ctx.resolve_target(target)?;
     let tab_id = resolved
@@ -3224,10 +5069,170 @@ fn handle_select_layout(
         .get_tab(tab_id)
         .ok_or_else(|| format!("can't find tab {}", tab_id))?;
 
-    tab.apply_layout(layout)?;
+    if spread {
+        let pane_id = resolved
+            .pane_id
+            .ok_or_else(|| "no pane resolved for select-layout".to_string())?;
+        let tmux_pane_id = ctx.id_map.get_or_create_tmux_pane_id(pane_id);
+        let mut node = super::server::build_layout_node_for_tab(ctx, &tab);
+        if spread_out(&mut node, tmux_pane_id) {
+            apply_layout_node(ctx, &tab, &node)?;
+            queue_layout_change(ctx, &tab);
+        }
+        return Ok(String::new());
+    }
+
+    let last = LAST_LAYOUT_PRESET.lock().get(&tab_id).copied();
+    let preset = if next {
+        Some(last.map_or(0, |idx| (idx + 1) % LAYOUT_PRESETS.len()))
+    } else if previous {
+        Some(last.map_or(LAYOUT_PRESETS.len() - 1, |idx| {
+            (idx + LAYOUT_PRESETS.len() - 1) % LAYOUT_PRESETS.len()
+        }))
+    } else {
+        match layout_name {
+            // No name reapplies the last preset, if there was one.
+            None => match last {
+                Some(idx) => Some(idx),
+                None => return Ok(String::new()),
+            },
+            Some(name) => lookup_layout_preset(name),
+        }
+    };
+
+    match preset {
+        Some(idx) => {
+            tab.apply_layout(LAYOUT_PRESETS[idx])?;
+            LAST_LAYOUT_PRESET.lock().insert(tab_id, idx);
+        }
+        None => {
+            // Not a preset, so it must be a full layout string.
+            let layout = layout_name.unwrap_or_default();
+            let node = parse_layout_string(layout).map_err(|e| format!("{}: {}", e, layout))?;
+            apply_layout_node(ctx, &tab, &node)?;
+        }
+    }
+    queue_layout_change(ctx, &tab);
     Ok(String::new())
 }
 
+/// The preset layouts in the order `select-layout -n`/`-p` cycle through.
+const LAYOUT_PRESETS: [&str; 5] = [
+    "even-horizontal",
+    "even-vertical",
+    "main-horizontal",
+    "main-vertical",
+    "tiled",
+];
+
+/// The index in [`LAYOUT_PRESETS`] last applied to each tab, until the tab
+/// goes away.
+static LAST_LAYOUT_PRESET: LazyLock<ParkMutex<HashMap<crate::tab::TabId, usize>>> =
+    LazyLock::new(Default::default);
+
+/// Forget the last preset of the tabs not in `live`.
+fn forget_layout_presets(live: &[crate::tab::TabId]) {
+    LAST_LAYOUT_PRESET
+        .lock()
+        .retain(|tab_id, _| live.contains(tab_id));
+}
+
+/// Forget the last preset of the tabs that have been closed. The mux can't
+/// be looked up from inside a subscriber, so this happens later on the main
+/// thread, once for a burst of removals.
+pub fn forget_closed_layout_presets_later() {
+    static PENDING: AtomicBool = AtomicBool::new(false);
+    if LAST_LAYOUT_PRESET.lock().is_empty() || PENDING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    promise::spawn::spawn_into_main_thread(async {
+        PENDING.store(false, Ordering::SeqCst);
+        let Some(mux) = Mux::try_get() else {
+            return;
+        };
+        let live: Vec<crate::tab::TabId> = mux
+            .iter_windows()
+            .into_iter()
+            .filter_map(|window_id| mux.get_window(window_id))
+            .flat_map(|window| window.iter().map(|tab| tab.tab_id()).collect::<Vec<_>>())
+            .collect();
+        forget_layout_presets(&live);
+    })
+    .detach();
+}
+
+/// Find a preset layout by name or unique prefix, as tmux does.
+fn lookup_layout_preset(name: &str) -> Option<usize> {
+    if let Some(idx) = LAYOUT_PRESETS.iter().position(|p| *p == name) {
+        return Some(idx);
+    }
+    let mut matches = LAYOUT_PRESETS
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.starts_with(name))
+        .map(|(idx, _)| idx);
+    match (matches.next(), matches.next()) {
+        (Some(idx), None) if !name.is_empty() => Some(idx),
+        _ => None,
+    }
+}
+
+/// Rearrange the panes of `tab` to match a parsed layout. Leaves are
+/// matched to panes by tmux pane id when every id names a distinct pane
+/// of the tab, and in order otherwise.
+fn apply_layout_node(
+    ctx: &mut HandlerContext,
+    tab: &Arc<Tab>,
+    node: &LayoutNode,
+) -> Result<(), String> {
+    let panes: Vec<Arc<dyn Pane>> = tab
+        .iter_panes_ignoring_zoom()
+        .into_iter()
+        .map(|p| p.pane)
+        .collect();
+    let leaf_ids = node.pane_ids();
+    if panes.len() != leaf_ids.len() {
+        return Err(format!(
+            "have {} panes but need {}",
+            panes.len(),
+            leaf_ids.len()
+        ));
+    }
+
+    let mut by_id: Vec<Arc<dyn Pane>> = vec![];
+    for tmux_id in &leaf_ids {
+        let found = ctx.id_map.wezterm_pane_id(*tmux_id).and_then(|wez_id| {
+            panes
+                .iter()
+                .find(|p| p.pane_id() == wez_id)
+                .filter(|p| !by_id.iter().any(|b| b.pane_id() == p.pane_id()))
+                .cloned()
+        });
+        match found {
+            Some(pane) => by_id.push(pane),
+            None => break,
+        }
+    }
+    let mut ordered = if by_id.len() == panes.len() {
+        by_id
+    } else {
+        panes
+    };
+    ordered.reverse();
+
+    let size = tab.get_size();
+    let cell = TerminalSize {
+        cols: 1,
+        rows: 1,
+        pixel_width: size.pixel_width / size.cols.max(1),
+        pixel_height: size.pixel_height / size.rows.max(1),
+        dpi: size.dpi,
+    };
+    let tree = tree_from_layout(node, &cell, &mut |_| ordered.pop())
+        .ok_or_else(|| "invalid layout".to_string())?;
+    tab.set_split_tree(tree)
+}
+
 /// Helper: find the tab and mux window ID containing a given pane.
 fn find_tab_and_window_for_pane(
     mux: &Arc<Mux>,
@@ -3304,7 +5309,8 @@ fn refresh_pane_header(ctx: &mut HandlerContext, wez_pane_id: PaneId) {
                 let wids = mux.iter_windows_in_workspace(&workspace);
                 wids.iter().position(|&w| w == window_id).unwrap_or(0)
             };
-            let fctx = build_format_context(ctx, pp, &tab, window_id, window_index, &workspace);
+            let mut fctx = build_format_context(ctx, pp, &tab, window_id, window_index, &workspace);
+            populate_format_loops(ctx, &template, &mut fctx);
             let expanded = expand_format(&template, &fctx);
             let styled = tmux_style_to_ansi(&expanded);
             log::info!(
@@ -3320,15 +5326,198 @@ fn refresh_pane_header(ctx: &mut HandlerContext, wez_pane_id: PaneId) {
     }
 }
 
-/// Handle `set-option` — apply supported options, log and ignore the rest.
+/// Scope flags shared by `set-option` and `show-options`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OptionScopeFlags {
+    /// `-g`: act on the global options of the table.
+    pub global: bool,
+    /// `-s`: server options.
+    pub server: bool,
+    /// `-w`: window options.
+    pub window: bool,
+    /// `-p`: pane options.
+    pub pane: bool,
+}
+
+/// Modifier flags of `set-option`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetOptionFlags {
+    /// `-a`: append to the current value.
+    pub append: bool,
+    /// `-u`: unset the option.
+    pub unset: bool,
+    /// `-U`: unset the option, including on every pane of the window.
+    pub unset_panes: bool,
+    /// `-o`: only set the option if it has no value yet.
+    pub only_if_unset: bool,
+    /// `-F`: expand the value as a format first.
+    pub format: bool,
+    /// `-q`: don't complain about unknown or already set options.
+    pub quiet: bool,
+}
+
+/// The level of the option tree a command acts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionLevel {
+    Server,
+    Session,
+    Window,
+    Pane,
+}
+
+/// Pick the level for `name`. Built-in options live in a fixed table
+/// (window options may also be set on a pane with `-p`); user options and
+/// listings without a name go by the flags, defaulting to the session.
+fn option_level(name: Option<&str>, flags: OptionScopeFlags) -> OptionLevel {
+    match name.and_then(option_table) {
+        Some(OptionTable::Server) => OptionLevel::Server,
+        Some(OptionTable::Session) => OptionLevel::Session,
+        Some(OptionTable::Window) if flags.pane => OptionLevel::Pane,
+        Some(OptionTable::Window) => OptionLevel::Window,
+        None if flags.server => OptionLevel::Server,
+        None if flags.pane => OptionLevel::Pane,
+        None if flags.window => OptionLevel::Window,
+        None => OptionLevel::Session,
+    }
+}
+
+/// Resolve the scope a command writes to and the chain it reads through.
+/// Only local scopes need a target; a session without `-t` is the client's
+/// own workspace, so global and session lookups work without a mux.
+fn resolve_option_scope(
+    ctx: &HandlerContext,
+    level: OptionLevel,
+    global: bool,
+    target: &Option<String>,
+) -> Result<(OptionScope, Vec<OptionScope>), String> {
+    let scope = match (level, global) {
+        (OptionLevel::Server, _) => OptionScope::Server,
+        (OptionLevel::Session, true) => OptionScope::GlobalSession,
+        (OptionLevel::Window | OptionLevel::Pane, true) => OptionScope::GlobalWindow,
+        (OptionLevel::Session, false) if target.is_none() => {
+            OptionScope::Session(ctx.workspace.clone())
+        }
+        (OptionLevel::Session, false) => {
+            let resolved = ctx.resolve_target(target)?;
+            OptionScope::Session(resolved.workspace.unwrap_or_else(|| ctx.workspace.clone()))
+        }
+        (OptionLevel::Window, false) => {
+            let resolved = ctx.resolve_target(target)?;
+            let tab_id = resolved
+                .tab_id
+                .ok_or_else(|| "no current window".to_string())?;
+            OptionScope::Window(tab_id)
+        }
+        (OptionLevel::Pane, false) => {
+            let resolved = ctx.resolve_target(target)?;
+            let pane_id = resolved
+                .pane_id
+                .ok_or_else(|| "no current pane".to_string())?;
+            let chain = OptionScope::Pane(pane_id).chain(resolved.tab_id);
+            return Ok((OptionScope::Pane(pane_id), chain));
+        }
+    };
+    let chain = scope.chain(None);
+    Ok((scope, chain))
+}
+
+/// Handle `set-option` / `set-window-option` — update the option store and
+/// apply the options WezTerm acts on (pane headers, buffer limit).
 fn handle_set_option(
     ctx: &mut HandlerContext,
     target: &Option<String>,
+    scope_flags: OptionScopeFlags,
+    flags: SetOptionFlags,
     option_name: Option<&str>,
     value: Option<&str>,
 ) -> Result<String, String> {
     let name = option_name.unwrap_or("");
-    let val = value.unwrap_or("");
+    if option_table(name).is_none() && !is_user_option(name) {
+        // Options tmux has that we don't model: log and return success
+        // (soft no-op) so scripts written for tmux keep working.
+        log::debug!("set-option: {}={:?} (no-op)", name, value);
+        return Ok(String::new());
+    }
+
+    let level = option_level(Some(name), scope_flags);
+    let (scope, chain) = resolve_option_scope(ctx, level, scope_flags.global, target)?;
+
+    let new_value = if flags.unset || flags.unset_panes {
+        None
+    } else {
+        let current = options(ctx.server.as_deref())
+            .lookup(&chain, name)
+            .map(str::to_string);
+        if flags.only_if_unset
+            && options(ctx.server.as_deref())
+                .get_local(&scope, name)
+                .is_some()
+        {
+            if flags.quiet {
+                return Ok(String::new());
+            }
+            return Err(format!("already set: {}", name));
+        }
+        let mut val = match value {
+            Some(v) if flags.format => handle_display_message(ctx, false, Some(v), target)?,
+            Some(v) => v.to_string(),
+            // A flag option without a value is toggled.
+            None => match current.as_deref() {
+                Some("on") => "off".to_string(),
+                Some("off") => "on".to_string(),
+                _ => String::new(),
+            },
+        };
+        if flags.append {
+            val = format!("{}{}", current.unwrap_or_default(), val);
+        }
+        if name == "buffer-limit" {
+            let limit: usize = val
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("value is invalid: {}", val))?;
+            paste_buffers(ctx.server.as_deref()).set_limit(limit);
+        }
+        if matches!(name, "history-limit" | "monitor-silence") {
+            match val.parse::<usize>() {
+                Err(_) => return Err(format!("value is invalid: {}", val)),
+                // The scrollback is sized from it, so keep to the limit
+                // the scrollback_lines config has.
+                Ok(n) if name == "history-limit" && n > MAX_SCROLLBACK_LINES => {
+                    return Err(format!("value is too large: {}", val));
+                }
+                Ok(_) => {}
+            }
+        }
+        Some(val)
+    };
+
+    match &new_value {
+        Some(val) => options(ctx.server.as_deref()).set(&scope, name, val.clone()),
+        None => {
+            let mut store = options(ctx.server.as_deref());
+            store.unset(&scope, name);
+            if flags.unset_panes {
+                if let OptionScope::Window(tab_id) = &scope {
+                    if let Some(tab) = Mux::try_get().and_then(|mux| mux.get_tab(*tab_id)) {
+                        for pp in tab.iter_panes() {
+                            store.unset(&OptionScope::Pane(pp.pane.pane_id()), name);
+                        }
+                    }
+                }
+            }
+            if name == "buffer-limit" {
+                let limit = store
+                    .lookup(&chain, name)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_BUFFER_LIMIT);
+                paste_buffers(ctx.server.as_deref()).set_limit(limit);
+            }
+        }
+    }
+    let val = new_value.as_deref().unwrap_or("");
+    log::debug!("set-option: {:?} {}={:?}", scope, name, new_value);
 
     match name {
         "pane-border-format" => {
@@ -3374,18 +5563,443 @@ fn handle_set_option(
                 }
             }
         }
-        _ => {
-            // All other options: log and return success (soft no-op)
-            log::debug!("set-option: {}={} (no-op)", name, val);
+        "history-limit" => {
+            // Unlike tmux, which only applies it to new panes, trim or grow
+            // the scrollback of the panes already in the affected sessions.
+            if let Some(mux) = Mux::try_get() {
+                for pane in ctx.server_panes(&mux) {
+                    sync_history_limit(&*pane);
+                }
+            }
+        }
+        "remain-on-exit" => {
+            // The option may have been set for a pane, a window or all of
+            // them, so bring every pane of the server up to date.
+            if let Some(mux) = Mux::try_get() {
+                for pane in ctx.server_panes(&mux) {
+                    sync_remain_on_exit(&*pane);
+                }
+            }
         }
+        _ => {}
     }
 
     Ok(String::new())
 }
 
-/// Handle `break-pane` — move a pane to its own new tab.
-async fn handle_break_pane(
-    ctx: &mut HandlerContext,
+/// The scope for `set-hook` / `show-hooks` and `set-environment` /
+/// `show-environment`: the global scope with `-g`, otherwise the target
+/// session (the client's own by default).
+fn session_scope(
+    ctx: &HandlerContext,
+    target: &Option<String>,
+    global: bool,
+) -> Result<OptionScope, String> {
+    if global {
+        return Ok(OptionScope::GlobalSession);
+    }
+    let workspace = match target {
+        Some(_) => ctx
+            .resolve_target(target)?
+            .workspace
+            .unwrap_or_else(|| ctx.workspace.clone()),
+        None => ctx.workspace.clone(),
+    };
+    Ok(OptionScope::Session(workspace))
+}
+
+/// Handle `set-hook` — set, append to or unset a hook.
+fn handle_set_hook(
+    ctx: &HandlerContext,
+    target: &Option<String>,
+    global: bool,
+    append: bool,
+    unset: bool,
+    hook_name: &str,
+    command: Option<String>,
+) -> Result<String, String> {
+    if !is_valid_hook(hook_name) {
+        return Err(format!("invalid option: {}", hook_name));
+    }
+    let scope = session_scope(ctx, target, global)?;
+    match command {
+        Some(command) if !unset => {
+            hooks(ctx.server.as_deref()).set(&scope, hook_name, command, append)
+        }
+        _ => {
+            hooks(ctx.server.as_deref()).unset(&scope, hook_name);
+        }
+    }
+    Ok(String::new())
+}
+
+/// Handle `set-hook -R` — run a hook now, as if its event had happened on
+/// the target pane.
+fn handle_run_hook(
+    ctx: &HandlerContext,
+    target: &Option<String>,
+    hook_name: &str,
+) -> Result<String, String> {
+    if !is_valid_hook(hook_name) {
+        return Err(format!("invalid option: {}", hook_name));
+    }
+    let resolved = ctx.resolve_target(target)?;
+    fire_hook(HookEvent {
+        pane_id: resolved.pane_id,
+        tab_id: resolved.tab_id,
+        workspace: resolved.workspace,
+        client: Some(ctx.client_name.clone()),
+        ..HookEvent::new(hook_name)
+    });
+    Ok(String::new())
+}
+
+/// Handle `show-hooks` — list the hooks set in the global or session scope.
+fn handle_show_hooks(
+    ctx: &HandlerContext,
+    target: &Option<String>,
+    global: bool,
+) -> Result<String, String> {
+    let scope = session_scope(ctx, target, global)?;
+    Ok(hooks(ctx.server.as_deref()).list(&scope).join("\n"))
+}
+
+// ---------------------------------------------------------------------------
+// Session environments: set-environment / show-environment
+// ---------------------------------------------------------------------------
+
+/// Handle `set-environment` — set, unset, or mark as removed a variable in
+/// the global or a session environment.
+#[allow(clippy::too_many_arguments)]
+fn handle_set_environment(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    global: bool,
+    unset: bool,
+    remove: bool,
+    hidden: bool,
+    format: bool,
+    name: &str,
+    value: Option<&str>,
+) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("empty variable name".to_string());
+    }
+    if name.contains('=') {
+        return Err("variable name contains =".to_string());
+    }
+    let scope = session_scope(ctx, target, global)?;
+
+    if unset || remove {
+        if value.is_some() {
+            let flag = if unset { "-u" } else { "-r" };
+            return Err(format!("can't specify a value with {}", flag));
+        }
+        if unset {
+            environments(ctx.server.as_deref()).unset(&scope, name);
+        } else {
+            environments(ctx.server.as_deref()).set(&scope, name, None, hidden);
+        }
+        return Ok(String::new());
+    }
+
+    let value = match value {
+        Some(v) if format => expand_for_target(ctx, v, target)?,
+        Some(v) => v.to_string(),
+        None => return Err("no value specified".to_string()),
+    };
+    environments(ctx.server.as_deref()).set(&scope, name, Some(value), hidden);
+    Ok(String::new())
+}
+
+/// Handle `show-environment` — list the global or a session environment, or
+/// one variable of it. With `-s` the output can be evaluated by a shell.
+fn handle_show_environment(
+    ctx: &HandlerContext,
+    target: &Option<String>,
+    global: bool,
+    hidden: bool,
+    shell: bool,
+    name: Option<&str>,
+) -> Result<String, String> {
+    let scope = session_scope(ctx, target, global)?;
+    let environments = environments(ctx.server.as_deref());
+    match name {
+        Some(name) => match environments.get(&scope, name) {
+            Some(var) if var.hidden == hidden => Ok(var.show(name, shell)),
+            Some(_) => Ok(String::new()),
+            None => Err(format!("unknown variable: {}", name)),
+        },
+        None => Ok(environments
+            .list(&scope, hidden)
+            .into_iter()
+            .map(|(name, var)| var.show(name, shell))
+            .collect::<Vec<_>>()
+            .join("\n")),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Key bindings: bind-key / unbind-key / list-keys
+// ---------------------------------------------------------------------------
+
+/// Handle `bind-key` — bind a key in a key table to a command list. The
+/// command is checked now and parsed again each time the key is pressed.
+fn handle_bind_key(
+    ctx: &HandlerContext,
+    table: &str,
+    repeat: bool,
+    note: Option<&str>,
+    key: &str,
+    command: Option<&str>,
+) -> Result<String, String> {
+    let key = normalize_key(key)?;
+    if let Some(command) = command {
+        parse_command_list(command).map_err(|e| e.to_string())?;
+    }
+    key_bindings(ctx.server.as_deref()).bind(
+        table,
+        &key,
+        command.map(str::to_string),
+        repeat,
+        note.map(str::to_string),
+    );
+    Ok(String::new())
+}
+
+/// Handle `unbind-key` — remove one binding, or with `-a` every binding in
+/// the table. `-q` hides the errors.
+fn handle_unbind_key(
+    ctx: &HandlerContext,
+    table: &str,
+    all: bool,
+    quiet: bool,
+    key: Option<&str>,
+) -> Result<String, String> {
+    let mut store = key_bindings(ctx.server.as_deref());
+    let result = match key {
+        Some(key) if !all => normalize_key(key).and_then(|key| store.unbind(table, &key)),
+        _ => store.unbind_all(table),
+    };
+    match result {
+        Err(_) if quiet => Ok(String::new()),
+        result => result.map(|()| String::new()),
+    }
+}
+
+/// Handle `list-keys` — print the bindings as `bind-key` commands, or with
+/// `-N` the keys and their notes.
+fn handle_list_keys(
+    ctx: &HandlerContext,
+    table: Option<&str>,
+    notes: bool,
+    all: bool,
+    prefix: Option<&str>,
+    key: Option<&str>,
+) -> Result<String, String> {
+    let key = key.map(normalize_key).transpose()?;
+    let prefix = match prefix {
+        Some(prefix) => prefix.to_string(),
+        None => prefix_keys(&ctx.workspace)
+            .first()
+            .map(|key| format!("{} ", key))
+            .unwrap_or_default(),
+    };
+
+    let store = key_bindings(ctx.server.as_deref());
+    if let Some(table) = table {
+        if !store.has_table(table) {
+            return Err(format!("table {} doesn't exist", table));
+        }
+    }
+    let mut bindings = store.bindings(table);
+    if notes && table.is_none() {
+        // Like tmux, notes are listed for the keys a user presses directly.
+        bindings.retain(|(table, _, _)| matches!(*table, "prefix" | "root"));
+    }
+    if let Some(key) = &key {
+        bindings.retain(|(_, k, _)| k == key);
+        if bindings.is_empty() {
+            return Err(format!("unknown key: {}", key));
+        }
+    }
+
+    let lines = if notes {
+        format_notes(&bindings, &prefix, all)
+    } else {
+        format_bindings(&bindings)
+    };
+    Ok(lines.join("\n"))
+}
+
+// ---------------------------------------------------------------------------
+// Command lists: if-shell / source-file
+// ---------------------------------------------------------------------------
+
+/// How deeply `source-file` may nest, as in tmux.
+const SOURCE_FILE_DEPTH_LIMIT: usize = 50;
+
+/// Handle `if-shell [-bF] [-t target] condition command [command]`.
+///
+/// The condition is expanded as a format first. With `-F` its value picks
+/// the command list to run; otherwise it is run with the shell and a zero exit
+/// status picks the first list. With `-b` the shell runs in the background
+/// and the chosen list runs once it exits, outside of this client.
+async fn handle_if_shell(
+    ctx: &mut HandlerContext,
+    background: bool,
+    format: bool,
+    target: &Option<String>,
+    condition: &str,
+    then_command: &str,
+    else_command: Option<&str>,
+) -> Result<String, String> {
+    let condition = expand_for_target(ctx, condition, target)?;
+    let then_command = then_command.to_string();
+    let else_command = else_command.map(str::to_string);
+    let choose = move |ok: bool| if ok { Some(then_command) } else { else_command };
+
+    if background && !format {
+        let workspace = ctx.workspace.clone();
+        let server = ctx.server.clone();
+        std::thread::spawn(move || {
+            let Some(command) = choose(shell_command_succeeds(&condition)) else {
+                return;
+            };
+            promise::spawn::spawn_into_main_thread(async move {
+                promise::spawn::spawn(run_detached_command_list(workspace, server, command))
+                    .detach();
+            })
+            .detach();
+        });
+        return Ok(String::new());
+    }
+
+    let ok = if format {
+        is_true(&condition)
+    } else {
+        shell_command_succeeds(&condition)
+    };
+    match choose(ok) {
+        Some(command) => {
+            let list = parse_command_list(&command).map_err(|e| e.to_string())?;
+            dispatch_command_list(ctx, &list).await
+        }
+        None => Ok(String::new()),
+    }
+}
+
+/// Run `cmd` with the shell, as `run_shell_exec` does, and report whether it
+/// exited successfully.
+fn shell_command_succeeds(cmd: &str) -> bool {
+    let shell = if cfg!(windows) { "cmd" } else { "sh" };
+    let flag = if cfg!(windows) { "/C" } else { "-c" };
+    let status = std::process::Command::new(shell)
+        .arg(flag)
+        .arg(cmd)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    matches!(status, Ok(status) if status.success())
+}
+
+/// Run a command list outside of any client, for `if-shell -b`.
+async fn run_detached_command_list(workspace: String, server: Option<String>, command: String) {
+    let mut ctx = HandlerContext::with_persistent_ids(workspace, server);
+    let result = match parse_command_list(&command) {
+        Ok(list) => dispatch_command_list(&mut ctx, &list).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = result {
+        log::warn!("if-shell -b: {:?} failed: {}", command, e);
+    }
+    ctx.save_id_map();
+}
+
+/// Handle `source-file [-Fnq] [-t target] path ...`.
+///
+/// Each file is parsed as a tmux config file and run in this context, so
+/// commands such as `set-option` and `set-hook` fill the server's stores.
+/// A line that fails to parse or run does not stop the rest of the file; the
+/// errors are reported together at the end. `-n` only checks the syntax and
+/// `-q` skips files that do not exist.
+async fn handle_source_file(
+    ctx: &mut HandlerContext,
+    format: bool,
+    parse_only: bool,
+    quiet: bool,
+    target: &Option<String>,
+    paths: &[String],
+) -> Result<String, String> {
+    if ctx.source_depth >= SOURCE_FILE_DEPTH_LIMIT {
+        return Err("too many nested files".to_string());
+    }
+
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        let path = if format {
+            expand_for_target(ctx, path, target)?
+        } else {
+            path.clone()
+        };
+        let path = expand_home_dir(&path);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if quiet && e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                errors.push(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
+        let list = match parse_config(&text) {
+            Ok((list, parse_errors)) => {
+                for (line, e) in parse_errors {
+                    errors.push(format!("{}:{}: {}", path.display(), line, e));
+                }
+                list
+            }
+            Err(e) => {
+                errors.push(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
+        if parse_only {
+            continue;
+        }
+
+        ctx.source_depth += 1;
+        for item in &list {
+            match dispatch_command_list(ctx, std::slice::from_ref(item)).await {
+                Ok(text) if text.is_empty() => {}
+                Ok(text) => output.push(text),
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        ctx.source_depth -= 1;
+    }
+
+    if errors.is_empty() {
+        Ok(output.join("\n"))
+    } else {
+        output.extend(errors);
+        Err(output.join("\n"))
+    }
+}
+
+/// Expand a leading `~` in a path to the home directory.
+fn expand_home_dir(path: &str) -> std::path::PathBuf {
+    match path.strip_prefix('~') {
+        Some("") => config::HOME_DIR.clone(),
+        Some(rest) if rest.starts_with('/') => config::HOME_DIR.join(&rest[1..]),
+        _ => std::path::PathBuf::from(path),
+    }
+}
+
+/// Handle `break-pane` — move a pane to its own new tab.
+async fn handle_break_pane(
+    ctx: &mut HandlerContext,
     _detach: bool,
     source: &Option<String>,
     target: &Option<String>,
@@ -3435,137 +6049,72 @@ async fn handle_break_pane(
     Ok(String::new())
 }
 
-/// Return hardcoded tmux server options.
+/// Handle `show-options` / `show-window-options` from the option store.
 ///
 /// iTerm2 queries: `show -gv default-terminal`, `show -gv escape-time`,
-/// `show -gv set-clipboard`.
+/// `show -gv set-clipboard` and `showw -gv aggressive-resize`. Like tmux,
+/// only values set at the requested scope are shown unless `-A` asks for
+/// inherited ones too; those are marked with `*`.
 pub fn handle_show_options(
-    global: bool,
+    ctx: &HandlerContext,
+    target: &Option<String>,
+    scope_flags: OptionScopeFlags,
     value_only: bool,
     quiet: bool,
+    inherited: bool,
     option_name: Option<&str>,
 ) -> Result<String, String> {
-    // Known global server/session options with sensible defaults.
-    // Phase 15: expanded with options Claude Code and common tools query.
-    let options: &[(&str, &str)] = &[
-        ("base-index", "0"),
-        ("default-shell", "/bin/sh"),
-        ("default-terminal", "screen-256color"),
-        ("escape-time", "500"),
-        ("focus-events", "on"),
-        ("mouse", "off"),
-        ("pane-base-index", "0"),
-        ("renumber-windows", "off"),
-        ("set-clipboard", "on"),
-        ("set-titles", "off"),
-        ("status", "off"),
-        ("allow-rename", "on"),
-    ];
-
-    if global {
-        match option_name {
-            Some(name) => {
-                if let Some((_, value)) = options.iter().find(|(k, _)| *k == name) {
-                    if value_only {
-                        Ok(value.to_string())
-                    } else {
-                        Ok(format!("{} {}", name, value))
-                    }
-                } else if quiet {
-                    // -q: suppress errors for unknown options
-                    Ok(String::new())
-                } else {
-                    Err(format!("unknown option: {}", name))
-                }
-            }
-            None => {
-                // Return all known options
-                let lines: Vec<String> = options
-                    .iter()
-                    .map(|(k, v)| {
-                        if value_only {
-                            v.to_string()
-                        } else {
-                            format!("{} {}", k, v)
-                        }
-                    })
-                    .collect();
-                Ok(lines.join("\n"))
-            }
-        }
-    } else {
-        // Non-global options: we don't track per-session options.
-        // With -q, return empty success instead of error.
-        match option_name {
-            Some(name) => {
-                if quiet {
-                    Ok(String::new())
-                } else {
-                    Err(format!("unknown option: {}", name))
-                }
-            }
-            None => Ok(String::new()),
+    if let Some(name) = option_name {
+        if option_table(name).is_none() && !is_user_option(name) {
+            // -q: suppress errors for unknown options
+            return if quiet {
+                Ok(String::new())
+            } else {
+                Err(format!("unknown option: {}", name))
+            };
         }
     }
-}
 
-/// Return hardcoded tmux window options.
-///
-/// iTerm2 queries: `showw -gv aggressive-resize`.
-/// Phase 15: expanded with pane-base-index and other common options.
-pub fn handle_show_window_options(
-    global: bool,
-    value_only: bool,
-    quiet: bool,
-    option_name: Option<&str>,
-) -> Result<String, String> {
-    let options: &[(&str, &str)] = &[
-        ("aggressive-resize", "off"),
-        ("allow-rename", "on"),
-        ("mode-keys", "emacs"),
-        ("pane-base-index", "0"),
-        ("remain-on-exit", "off"),
-    ];
+    let level = option_level(option_name, scope_flags);
+    let (scope, chain) = resolve_option_scope(ctx, level, scope_flags.global, target)?;
+    let chain = if inherited { &chain[..] } else { &chain[..1] };
 
-    if global {
-        match option_name {
-            Some(name) => {
-                if let Some((_, value)) = options.iter().find(|(k, _)| *k == name) {
-                    if value_only {
-                        Ok(value.to_string())
-                    } else {
-                        Ok(format!("{} {}", name, value))
-                    }
-                } else if quiet {
-                    Ok(String::new())
-                } else {
-                    Err(format!("unknown option: {}", name))
-                }
-            }
-            None => {
-                let lines: Vec<String> = options
-                    .iter()
-                    .map(|(k, v)| {
-                        if value_only {
-                            v.to_string()
-                        } else {
-                            format!("{} {}", k, v)
-                        }
-                    })
-                    .collect();
-                Ok(lines.join("\n"))
-            }
+    let store = options(ctx.server.as_deref());
+    let show = |name: &str, value: &str, parent: bool| {
+        if value_only {
+            value.to_string()
+        } else {
+            let marker = if parent { "*" } else { "" };
+            format!("{}{} {}", name, marker, quote_option_value(value))
         }
-    } else {
-        match option_name {
-            Some(name) => {
-                if quiet {
-                    Ok(String::new())
-                } else {
-                    Err(format!("unknown option: {}", name))
-                }
+    };
+
+    match option_name {
+        Some(name) => {
+            let found = chain
+                .iter()
+                .enumerate()
+                .find_map(|(depth, scope)| store.get_local(scope, name).map(|v| (v, depth > 0)));
+            match found {
+                Some((value, parent)) => Ok(show(name, value, parent)),
+                None if is_user_option(name) && !quiet => Err(format!("invalid option: {}", name)),
+                None => Ok(String::new()),
             }
-            None => Ok(String::new()),
+        }
+        None => {
+            let table = match level {
+                OptionLevel::Server => OptionTable::Server,
+                OptionLevel::Session => OptionTable::Session,
+                OptionLevel::Window | OptionLevel::Pane => OptionTable::Window,
+            };
+            log::debug!("show-options: {:?} via {:?}", scope, chain);
+            let lines: Vec<String> = store
+                .merged(chain)
+                .into_iter()
+                .filter(|(name, _, _)| is_user_option(name) || option_table(name) == Some(table))
+                .map(|(name, value, parent)| show(name, value, parent))
+                .collect();
+            Ok(lines.join("\n"))
         }
     }
 }
@@ -3574,6 +6123,50 @@ pub fn handle_show_window_options(
 // Phase 8 handlers — session/client management
 // ---------------------------------------------------------------------------
 
+/// Handle `switch-client` — switch to another session (`-t`) and/or look
+/// the next key pressed in the GUI up in another key table (`-T`).
+fn handle_switch_client(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    key_table: Option<&str>,
+) -> Result<String, String> {
+    if let Some(table) = key_table {
+        let mut store = key_bindings(ctx.server.as_deref());
+        if !store.has_table(table) {
+            return Err(format!("table {} doesn't exist", table));
+        }
+        store.switch_table(table);
+        if target.is_none() {
+            return Ok(String::new());
+        }
+    }
+    handle_attach_session(ctx, target)?;
+    if ctx.for_gui {
+        show_target_in_gui(ctx, target)?;
+    }
+    Ok(String::new())
+}
+
+/// Bring the pane `target` resolves to into view in the GUI: switch to its
+/// workspace and focus its tab and the pane itself.
+fn show_target_in_gui(ctx: &mut HandlerContext, target: &Option<String>) -> Result<(), String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+    let pane_id = ctx
+        .resolve_target(target)?
+        .pane_id
+        .ok_or_else(|| "can't find pane".to_string())?;
+    let workspace = mux
+        .resolve_pane_id(pane_id)
+        .and_then(|(_, window_id, _)| Some(mux.get_window(window_id)?.get_workspace().to_string()));
+    if let Some(workspace) = workspace {
+        if mux.active_workspace() != workspace {
+            mux.set_active_workspace(&workspace);
+        }
+    }
+    mux.focus_pane_and_containing_tab(pane_id)
+        .map_err(|e| format!("{:#}", e))
+}
+
 /// Attach to (switch to) a different session (workspace).
 ///
 /// Resolves the target workspace, updates context, re-registers windows/panes
@@ -3588,10 +6181,10 @@ pub fn handle_attach_session(
     // Resolve target workspace
     let new_workspace = match target {
         Some(t) => {
-            let parsed = parse_target(t).map_err(|e| e.to_string())?;
+            let parsed = parse_session_target(t).map_err(|e| e.to_string())?;
             match parsed.session {
                 Some(SessionRef::Name(name)) => {
-                    if mux.iter_workspaces().contains(&name) {
+                    if ctx.workspaces(&mux).contains(&name) {
                         name
                     } else {
                         return Err(format!("can't find session: {}", name));
@@ -3692,50 +6285,134 @@ pub fn handle_list_clients(
 // ---------------------------------------------------------------------------
 
 /// `show-buffer [-b buffer-name]` — return buffer content as raw text.
-fn handle_show_buffer(
-    ctx: &mut HandlerContext,
+fn handle_show_buffer(ctx: &HandlerContext, buffer_name: Option<&str>) -> Result<String, String> {
+    buffer_data(ctx, buffer_name).map(|(_, data)| data)
+}
+
+/// Look up a buffer by name, or the most recent buffer when `buffer_name` is
+/// `None`. Returns the buffer's name and a copy of its content.
+fn buffer_data(
+    ctx: &HandlerContext,
     buffer_name: Option<&str>,
-) -> Result<String, String> {
+) -> Result<(String, String), String> {
+    let store = paste_buffers(ctx.server.as_deref());
     let buf = match buffer_name {
-        Some(name) => ctx
-            .paste_buffers
+        Some(name) => store
             .get(name)
             .ok_or_else(|| format!("unknown buffer: {}", name))?,
-        None => ctx
-            .paste_buffers
+        None => store
             .most_recent()
             .ok_or_else(|| "no buffers".to_string())?,
     };
-    Ok(buf.data.clone())
+    Ok((buf.name.clone(), buf.data.clone()))
 }
 
-/// `set-buffer [-a] [-b buffer-name] [data]` — create/update a buffer.
+/// `set-buffer [-aw] [-b buffer-name] [data]` — create/update a buffer.
 fn handle_set_buffer(
     ctx: &mut HandlerContext,
     buffer_name: Option<&str>,
     data: Option<&str>,
     append: bool,
+    clipboard: bool,
 ) -> Result<String, String> {
     if append {
         let name = buffer_name.ok_or_else(|| "set-buffer -a requires -b".to_string())?;
         let content = data.unwrap_or("");
-        ctx.paste_buffers
-            .append(name, content)
-            .map_err(|e| e.to_string())?;
+        let updated = {
+            let mut store = paste_buffers(ctx.server.as_deref());
+            store.append(name, content).map_err(|e| e.to_string())?;
+            store.get(name).map(|b| b.data.clone()).unwrap_or_default()
+        };
         ctx.pending_notifications
             .push(super::response::paste_buffer_changed_notification(name));
+        if clipboard {
+            set_system_clipboard(ctx, updated);
+        }
         return Ok(String::new());
     }
 
     let content = data
         .ok_or_else(|| "no data specified".to_string())?
         .to_string();
-    let name = ctx.paste_buffers.set(buffer_name, content);
+    let name = paste_buffers(ctx.server.as_deref()).set(buffer_name, content.clone());
     ctx.pending_notifications
         .push(super::response::paste_buffer_changed_notification(&name));
+    if clipboard {
+        set_system_clipboard(ctx, content);
+    }
     Ok(String::new())
 }
 
+/// `load-buffer [-w] [-b buffer-name] path` — fill a buffer from a file.
+///
+/// The path is read by the server process. The `tmux` shim rewrites relative
+/// paths and `-` (stdin) into absolute file paths before sending the command,
+/// so `-` only reaches us from clients that have no way to supply stdin.
+fn handle_load_buffer(
+    ctx: &mut HandlerContext,
+    buffer_name: Option<&str>,
+    path: &str,
+    clipboard: bool,
+) -> Result<String, String> {
+    if path == "-" {
+        return Err("load-buffer: stdin is not available to this client".to_string());
+    }
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let content = String::from_utf8_lossy(&bytes).into_owned();
+
+    let name = paste_buffers(ctx.server.as_deref()).set(buffer_name, content.clone());
+    ctx.pending_notifications
+        .push(super::response::paste_buffer_changed_notification(&name));
+    if clipboard {
+        set_system_clipboard(ctx, content);
+    }
+    Ok(String::new())
+}
+
+/// `save-buffer [-a] [-b buffer-name] path` — write a buffer to a file, or
+/// return it as command output when `path` is `-` (stdout).
+fn handle_save_buffer(
+    ctx: &HandlerContext,
+    buffer_name: Option<&str>,
+    path: &str,
+    append: bool,
+) -> Result<String, String> {
+    let (_, data) = buffer_data(ctx, buffer_name)?;
+    if path == "-" {
+        return Ok(data);
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    std::io::Write::write_all(&mut file, data.as_bytes())
+        .map_err(|e| format!("{}: {}", path, e))?;
+    Ok(String::new())
+}
+
+/// Push `data` to the system clipboard (`-w`). This goes through the same
+/// `AssignClipboard` notification that OSC 52 uses, so the GUI sets the
+/// clipboard and every CC session reports `%paste-buffer-changed`.
+fn set_system_clipboard(ctx: &HandlerContext, data: String) {
+    let mux = match Mux::try_get() {
+        Some(m) => m,
+        None => return,
+    };
+    let pane_id = ctx
+        .active_pane_id
+        .and_then(|tmux_id| ctx.id_map.wezterm_pane_id(tmux_id))
+        .unwrap_or(0);
+    mux.notify(MuxNotification::AssignClipboard {
+        pane_id,
+        selection: ClipboardSelection::Clipboard,
+        clipboard: Some(data),
+    });
+}
+
 /// `delete-buffer [-b buffer-name]` — remove a buffer.
 fn handle_delete_buffer(
     ctx: &mut HandlerContext,
@@ -3743,7 +6420,7 @@ fn handle_delete_buffer(
 ) -> Result<String, String> {
     match buffer_name {
         Some(name) => {
-            if ctx.paste_buffers.delete(name) {
+            if paste_buffers(ctx.server.as_deref()).delete(name) {
                 ctx.pending_notifications
                     .push(super::response::paste_buffer_deleted_notification(name));
                 Ok(String::new())
@@ -3751,7 +6428,7 @@ fn handle_delete_buffer(
                 Err(format!("unknown buffer: {}", name))
             }
         }
-        None => match ctx.paste_buffers.delete_most_recent() {
+        None => match paste_buffers(ctx.server.as_deref()).delete_most_recent() {
             Some(name) => {
                 ctx.pending_notifications
                     .push(super::response::paste_buffer_deleted_notification(&name));
@@ -3767,30 +6444,30 @@ fn handle_list_buffers(ctx: &mut HandlerContext, format: Option<&str>) -> Result
     let default_fmt = "#{buffer_name}: #{buffer_size} bytes: \"#{buffer_sample}\"";
     let fmt = format.unwrap_or(default_fmt);
 
-    let bufs: Vec<_> = ctx
-        .paste_buffers
-        .list()
+    let lines: Vec<String> = buffer_contexts(ctx)
         .iter()
-        .map(|b| (b.name.clone(), b.data.clone()))
+        .map(|fctx| expand_format(fmt, fctx))
         .collect();
+    Ok(lines.join("\n"))
+}
 
-    let mut lines = Vec::new();
-    for (name, data) in &bufs {
-        // Build a minimal FormatContext with buffer fields populated.
-        let fctx = FormatContext {
-            buffer_name: name.clone(),
-            buffer_size: data.len() as u64,
-            buffer_sample: buffer_sample(data),
+/// Build one `FormatContext` per paste buffer, most recent first.
+fn buffer_contexts(ctx: &HandlerContext) -> Vec<FormatContext> {
+    paste_buffers(ctx.server.as_deref())
+        .list()
+        .iter()
+        .map(|b| FormatContext {
+            buffer_name: b.name.clone(),
+            buffer_size: b.data.len() as u64,
+            buffer_sample: buffer_sample(&b.data),
             session_name: ctx.workspace.clone(),
             session_attached: 1,
             client_name: ctx.client_name.clone(),
             socket_path: ctx.socket_path.clone(),
             server_pid: std::process::id() as u64,
             ..FormatContext::default()
-        };
-        lines.push(expand_format(fmt, &fctx));
-    }
-    Ok(lines.join("\n"))
+        })
+        .collect()
 }
 
 /// `paste-buffer [-d] [-p] [-b buffer-name] [-t target-pane]` — send buffer
@@ -3801,18 +6478,7 @@ fn handle_paste_buffer(
     target: &Option<String>,
     delete_after: bool,
 ) -> Result<String, String> {
-    let buf = match buffer_name {
-        Some(name) => ctx
-            .paste_buffers
-            .get(name)
-            .ok_or_else(|| format!("unknown buffer: {}", name))?,
-        None => ctx
-            .paste_buffers
-            .most_recent()
-            .ok_or_else(|| "no buffers".to_string())?,
-    };
-    let data = buf.data.clone();
-    let buf_name = buf.name.clone();
+    let (buf_name, data) = buffer_data(ctx, buffer_name)?;
 
     // Resolve target pane.
     let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
@@ -3836,7 +6502,7 @@ fn handle_paste_buffer(
         .map_err(|e| format!("paste failed: {}", e))?;
 
     if delete_after {
-        ctx.paste_buffers.delete(&buf_name);
+        paste_buffers(ctx.server.as_deref()).delete(&buf_name);
         ctx.pending_notifications
             .push(super::response::paste_buffer_deleted_notification(
                 &buf_name,
@@ -3846,6 +6512,97 @@ fn handle_paste_buffer(
     Ok(String::new())
 }
 
+// ---------------------------------------------------------------------------
+// Choosers and display-panes
+// ---------------------------------------------------------------------------
+
+/// Which list a chooser shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chooser {
+    Tree(TreeDepth),
+    Buffer,
+    Client,
+}
+
+/// `choose-tree`, `choose-buffer` and `choose-client`: ask the GUI window
+/// holding the target pane to show the list, and to run `template` for the
+/// item the user picks.
+fn handle_choose(
+    ctx: &mut HandlerContext,
+    chooser: Chooser,
+    target: &Option<String>,
+    opts: ChooseOptions,
+    template: Option<&str>,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+    let pane_id = ctx
+        .resolve_target(target)?
+        .pane_id
+        .ok_or_else(|| "can't find pane".to_string())?;
+
+    let (title, choices, default_template) = match chooser {
+        Chooser::Tree(depth) => {
+            let sessions = session_loop_contexts(ctx, &mux);
+            (
+                "Choose",
+                tree_choices(&sessions, depth, opts),
+                TREE_TEMPLATE,
+            )
+        }
+        Chooser::Buffer => (
+            "Choose a buffer",
+            buffer_choices(&buffer_contexts(ctx), opts),
+            BUFFER_TEMPLATE,
+        ),
+        Chooser::Client => {
+            let client = FormatContext {
+                client_name: ctx.client_name.clone(),
+                session_name: ctx.workspace.clone(),
+                session_attached: 1,
+                ..FormatContext::default()
+            };
+            (
+                "Choose a client",
+                client_choices(&[client], opts),
+                CLIENT_TEMPLATE,
+            )
+        }
+    };
+    if choices.is_empty() {
+        return Ok(String::new());
+    }
+
+    mux.notify(MuxNotification::PerformAssignment {
+        pane_id,
+        assignment: Box::new(choose::selector(
+            title,
+            choices,
+            template.unwrap_or(default_template),
+        )),
+    });
+    Ok(String::new())
+}
+
+/// `display-panes`: label the panes of the target's window in the GUI and
+/// run `template` for the one the user picks.
+fn handle_display_panes(
+    ctx: &mut HandlerContext,
+    target: &Option<String>,
+    template: Option<&str>,
+) -> Result<String, String> {
+    let mux = Mux::try_get().ok_or_else(|| "mux not available".to_string())?;
+    let pane_id = ctx
+        .resolve_target(target)?
+        .pane_id
+        .ok_or_else(|| "can't find pane".to_string())?;
+
+    mux.notify(MuxNotification::PerformAssignment {
+        pane_id,
+        assignment: Box::new(choose::pane_labels(template.unwrap_or(PANES_TEMPLATE))),
+    });
+    Ok(String::new())
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -3856,102 +6613,245 @@ mod tests {
 
     // --- resolve_named_key tests ---
 
+    /// Encode a named key as a terminal in its default modes would.
+    fn named_key_bytes(name: &str) -> Option<Vec<u8>> {
+        encode_named_key(name, false, None)
+    }
+
+    fn encode_named_key(
+        name: &str,
+        application_cursor_keys: bool,
+        modify_other_keys: Option<i64>,
+    ) -> Option<Vec<u8>> {
+        let (key, mods) = resolve_named_key(name)?;
+        let modes = termwiz::input::KeyCodeEncodeModes {
+            encoding: termwiz::input::KeyboardEncoding::Xterm,
+            application_cursor_keys,
+            newline_mode: false,
+            modify_other_keys,
+        };
+        Some(key.encode(mods, modes, true).unwrap().into_bytes())
+    }
+
     #[test]
     fn named_key_enter() {
-        assert_eq!(resolve_named_key("Enter"), Some(b"\r".to_vec()));
-        assert_eq!(resolve_named_key("CR"), Some(b"\r".to_vec()));
+        assert_eq!(named_key_bytes("Enter"), Some(b"\r".to_vec()));
+        assert_eq!(named_key_bytes("CR"), Some(b"\r".to_vec()));
+        assert_eq!(named_key_bytes("C-m"), Some(b"\r".to_vec()));
     }
 
     #[test]
     fn named_key_space() {
-        assert_eq!(resolve_named_key("Space"), Some(b" ".to_vec()));
+        assert_eq!(named_key_bytes("Space"), Some(b" ".to_vec()));
     }
 
     #[test]
     fn named_key_tab() {
-        assert_eq!(resolve_named_key("Tab"), Some(b"\t".to_vec()));
+        assert_eq!(named_key_bytes("Tab"), Some(b"\t".to_vec()));
+        assert_eq!(named_key_bytes("BTab"), Some(b"\x1b[Z".to_vec()));
     }
 
     #[test]
     fn named_key_escape() {
-        assert_eq!(resolve_named_key("Escape"), Some(b"\x1b".to_vec()));
+        assert_eq!(named_key_bytes("Escape"), Some(b"\x1b".to_vec()));
     }
 
     #[test]
     fn named_key_bspace() {
-        assert_eq!(resolve_named_key("BSpace"), Some(b"\x7f".to_vec()));
+        assert_eq!(named_key_bytes("BSpace"), Some(b"\x7f".to_vec()));
     }
 
     #[test]
     fn named_key_arrows() {
-        assert_eq!(resolve_named_key("Up"), Some(b"\x1b[A".to_vec()));
-        assert_eq!(resolve_named_key("Down"), Some(b"\x1b[B".to_vec()));
-        assert_eq!(resolve_named_key("Right"), Some(b"\x1b[C".to_vec()));
-        assert_eq!(resolve_named_key("Left"), Some(b"\x1b[D".to_vec()));
+        assert_eq!(named_key_bytes("Up"), Some(b"\x1b[A".to_vec()));
+        assert_eq!(named_key_bytes("Down"), Some(b"\x1b[B".to_vec()));
+        assert_eq!(named_key_bytes("Right"), Some(b"\x1b[C".to_vec()));
+        assert_eq!(named_key_bytes("Left"), Some(b"\x1b[D".to_vec()));
+    }
+
+    #[test]
+    fn named_key_arrows_follow_application_cursor_mode() {
+        assert_eq!(encode_named_key("Up", true, None), Some(b"\x1bOA".to_vec()));
+        assert_eq!(
+            encode_named_key("Left", true, None),
+            Some(b"\x1bOD".to_vec())
+        );
+        // Modified arrows are the same in either mode.
+        assert_eq!(
+            encode_named_key("C-Up", true, None),
+            Some(b"\x1b[1;5A".to_vec())
+        );
+    }
+
+    #[test]
+    fn named_key_follows_modify_other_keys() {
+        assert_eq!(encode_named_key("C-a", false, None), Some(vec![1]));
+        assert_eq!(
+            encode_named_key("C-a", false, Some(2)),
+            Some(b"\x1b[27;5;97~".to_vec())
+        );
+    }
+
+    #[test]
+    fn named_key_kitty_encoding() {
+        let kitty = |name: &str| {
+            let (key, mods) = resolve_named_key(name)?;
+            encode_kitty_key(key, mods, KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES)
+        };
+        assert_eq!(kitty("C-a"), Some("\x1b[97;5u".to_string()));
+        assert_eq!(kitty("M-x"), Some("\x1b[120;3u".to_string()));
+        assert_eq!(kitty("C-Up"), Some("\x1b[1;5A".to_string()));
+        assert_eq!(kitty("Enter"), Some("\r".to_string()));
+        assert_eq!(kitty("KP7"), Some("7".to_string()));
     }
 
     #[test]
     fn named_key_home_end() {
-        assert_eq!(resolve_named_key("Home"), Some(b"\x1b[H".to_vec()));
-        assert_eq!(resolve_named_key("End"), Some(b"\x1b[F".to_vec()));
+        assert_eq!(named_key_bytes("Home"), Some(b"\x1b[H".to_vec()));
+        assert_eq!(named_key_bytes("End"), Some(b"\x1b[F".to_vec()));
     }
 
     #[test]
     fn named_key_function_keys() {
-        assert_eq!(resolve_named_key("F1"), Some(b"\x1bOP".to_vec()));
-        assert_eq!(resolve_named_key("F2"), Some(b"\x1bOQ".to_vec()));
-        assert_eq!(resolve_named_key("F3"), Some(b"\x1bOR".to_vec()));
-        assert_eq!(resolve_named_key("F4"), Some(b"\x1bOS".to_vec()));
-        assert_eq!(resolve_named_key("F5"), Some(b"\x1b[15~".to_vec()));
-        assert_eq!(resolve_named_key("F6"), Some(b"\x1b[17~".to_vec()));
-        assert_eq!(resolve_named_key("F7"), Some(b"\x1b[18~".to_vec()));
-        assert_eq!(resolve_named_key("F8"), Some(b"\x1b[19~".to_vec()));
-        assert_eq!(resolve_named_key("F9"), Some(b"\x1b[20~".to_vec()));
-        assert_eq!(resolve_named_key("F10"), Some(b"\x1b[21~".to_vec()));
-        assert_eq!(resolve_named_key("F11"), Some(b"\x1b[23~".to_vec()));
-        assert_eq!(resolve_named_key("F12"), Some(b"\x1b[24~".to_vec()));
+        assert_eq!(named_key_bytes("F1"), Some(b"\x1bOP".to_vec()));
+        assert_eq!(named_key_bytes("F2"), Some(b"\x1bOQ".to_vec()));
+        assert_eq!(named_key_bytes("F3"), Some(b"\x1bOR".to_vec()));
+        assert_eq!(named_key_bytes("F4"), Some(b"\x1bOS".to_vec()));
+        assert_eq!(named_key_bytes("F5"), Some(b"\x1b[15~".to_vec()));
+        assert_eq!(named_key_bytes("F6"), Some(b"\x1b[17~".to_vec()));
+        assert_eq!(named_key_bytes("F7"), Some(b"\x1b[18~".to_vec()));
+        assert_eq!(named_key_bytes("F8"), Some(b"\x1b[19~".to_vec()));
+        assert_eq!(named_key_bytes("F9"), Some(b"\x1b[20~".to_vec()));
+        assert_eq!(named_key_bytes("F10"), Some(b"\x1b[21~".to_vec()));
+        assert_eq!(named_key_bytes("F11"), Some(b"\x1b[23~".to_vec()));
+        assert_eq!(named_key_bytes("F12"), Some(b"\x1b[24~".to_vec()));
+        assert_eq!(named_key_bytes("F13"), Some(b"\x1b[25~".to_vec()));
+        assert_eq!(named_key_bytes("F24"), Some(b"\x1b[45~".to_vec()));
+        assert_eq!(named_key_bytes("S-F5"), Some(b"\x1b[15;2~".to_vec()));
+        assert_eq!(resolve_named_key("F25"), None);
+        assert_eq!(resolve_named_key("F0"), None);
     }
 
     #[test]
     fn named_key_page_up_down() {
-        assert_eq!(resolve_named_key("PageUp"), Some(b"\x1b[5~".to_vec()));
-        assert_eq!(resolve_named_key("PgUp"), Some(b"\x1b[5~".to_vec()));
-        assert_eq!(resolve_named_key("PPage"), Some(b"\x1b[5~".to_vec()));
-        assert_eq!(resolve_named_key("PageDown"), Some(b"\x1b[6~".to_vec()));
-        assert_eq!(resolve_named_key("PgDn"), Some(b"\x1b[6~".to_vec()));
-        assert_eq!(resolve_named_key("NPage"), Some(b"\x1b[6~".to_vec()));
+        assert_eq!(named_key_bytes("PageUp"), Some(b"\x1b[5~".to_vec()));
+        assert_eq!(named_key_bytes("PgUp"), Some(b"\x1b[5~".to_vec()));
+        assert_eq!(named_key_bytes("PPage"), Some(b"\x1b[5~".to_vec()));
+        assert_eq!(named_key_bytes("PageDown"), Some(b"\x1b[6~".to_vec()));
+        assert_eq!(named_key_bytes("PgDn"), Some(b"\x1b[6~".to_vec()));
+        assert_eq!(named_key_bytes("NPage"), Some(b"\x1b[6~".to_vec()));
     }
 
     #[test]
     fn named_key_insert_delete() {
-        assert_eq!(resolve_named_key("Insert"), Some(b"\x1b[2~".to_vec()));
-        assert_eq!(resolve_named_key("Delete"), Some(b"\x1b[3~".to_vec()));
-        assert_eq!(resolve_named_key("DC"), Some(b"\x1b[3~".to_vec()));
+        assert_eq!(named_key_bytes("Insert"), Some(b"\x1b[2~".to_vec()));
+        assert_eq!(named_key_bytes("IC"), Some(b"\x1b[2~".to_vec()));
+        assert_eq!(named_key_bytes("Delete"), Some(b"\x1b[3~".to_vec()));
+        assert_eq!(named_key_bytes("DC"), Some(b"\x1b[3~".to_vec()));
     }
 
     #[test]
     fn named_key_ctrl_a_through_z() {
-        assert_eq!(resolve_named_key("C-a"), Some(vec![1]));
-        assert_eq!(resolve_named_key("C-c"), Some(vec![3]));
-        assert_eq!(resolve_named_key("C-z"), Some(vec![26]));
+        assert_eq!(named_key_bytes("C-a"), Some(vec![1]));
+        assert_eq!(named_key_bytes("C-c"), Some(vec![3]));
+        assert_eq!(named_key_bytes("C-z"), Some(vec![26]));
+        assert_eq!(named_key_bytes("C-Z"), Some(vec![26]));
+        assert_eq!(named_key_bytes("^c"), Some(vec![3]));
+    }
+
+    #[test]
+    fn named_key_modifier_combinations() {
+        assert_eq!(
+            resolve_named_key("M-x"),
+            Some((KeyCode::Char('x'), KeyModifiers::ALT))
+        );
+        assert_eq!(
+            resolve_named_key("C-S-Left"),
+            Some((KeyCode::LeftArrow, KeyModifiers::CTRL | KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            resolve_named_key("c-m-a"),
+            Some((KeyCode::Char('a'), KeyModifiers::CTRL | KeyModifiers::ALT))
+        );
+        assert_eq!(named_key_bytes("M-x"), Some(b"\x1bx".to_vec()));
+        assert_eq!(named_key_bytes("M-Enter"), Some(b"\x1b\r".to_vec()));
+        assert_eq!(named_key_bytes("C-S-Left"), Some(b"\x1b[1;6D".to_vec()));
+    }
+
+    #[test]
+    fn named_key_names_ignore_case() {
+        assert_eq!(resolve_named_key("enter"), resolve_named_key("Enter"));
+        assert_eq!(resolve_named_key("PAGEUP"), resolve_named_key("PageUp"));
+    }
+
+    #[test]
+    fn named_key_keypad() {
+        assert_eq!(named_key_bytes("KP7"), Some(b"7".to_vec()));
+        assert_eq!(named_key_bytes("KP*"), Some(b"*".to_vec()));
+        assert_eq!(named_key_bytes("KP."), Some(b".".to_vec()));
+        assert_eq!(named_key_bytes("KPEnter"), Some(b"\r".to_vec()));
+        assert_eq!(resolve_named_key("KPx"), None);
     }
 
     #[test]
     fn named_key_unknown() {
         assert_eq!(resolve_named_key("FooBar"), None);
         assert_eq!(resolve_named_key(""), None);
+        assert_eq!(resolve_named_key("x"), None);
+        assert_eq!(resolve_named_key("X-a"), None);
+    }
+
+    // --- parse_mouse_report tests ---
+
+    #[test]
+    fn mouse_report_press_and_release() {
+        let press = parse_mouse_report("\x1b[<0;10;5M").unwrap();
+        assert_eq!(press.kind, MouseEventKind::Press);
+        assert_eq!(press.button, MouseButton::Left);
+        assert_eq!((press.x, press.y), (9, 4));
+        assert_eq!(press.modifiers, KeyModifiers::NONE);
+
+        let release = parse_mouse_report("<2;1;1m").unwrap();
+        assert_eq!(release.kind, MouseEventKind::Release);
+        assert_eq!(release.button, MouseButton::Right);
+        assert_eq!((release.x, release.y), (0, 0));
+    }
+
+    #[test]
+    fn mouse_report_wheel_motion_and_modifiers() {
+        let wheel = parse_mouse_report("<65;3;3M").unwrap();
+        assert_eq!(wheel.button, MouseButton::WheelDown(1));
+        let drag = parse_mouse_report("<48;3;3M").unwrap();
+        assert_eq!(drag.kind, MouseEventKind::Move);
+        assert_eq!(drag.button, MouseButton::Left);
+        assert_eq!(drag.modifiers, KeyModifiers::CTRL);
+    }
+
+    #[test]
+    fn mouse_report_invalid() {
+        assert!(parse_mouse_report("0;1;1M").is_err());
+        assert!(parse_mouse_report("<0;1;1").is_err());
+        assert!(parse_mouse_report("<0;0;1M").is_err());
+        assert!(parse_mouse_report("<0;1M").is_err());
+        assert!(parse_mouse_report("<200;1;1M").is_err());
     }
 
     // --- resolve_key tests ---
 
     #[test]
     fn resolve_key_hex_with_prefix() {
-        assert_eq!(resolve_key("0x1b", false, true), Ok(vec![0x1b]));
+        assert_eq!(
+            resolve_key("0x1b", false, true),
+            Ok(SendKey::Bytes(vec![0x1b]))
+        );
     }
 
     #[test]
     fn resolve_key_hex_without_prefix() {
-        assert_eq!(resolve_key("0d", false, true), Ok(vec![0x0d]));
+        assert_eq!(
+            resolve_key("0d", false, true),
+            Ok(SendKey::Bytes(vec![0x0d]))
+        );
     }
 
     #[test]
@@ -3961,17 +6861,35 @@ mod tests {
 
     #[test]
     fn resolve_key_literal() {
-        assert_eq!(resolve_key("Enter", true, false), Ok(b"Enter".to_vec()));
+        assert_eq!(
+            resolve_key("Enter", true, false),
+            Ok(SendKey::Bytes(b"Enter".to_vec()))
+        );
     }
 
     #[test]
     fn resolve_key_named_fallback() {
-        assert_eq!(resolve_key("Enter", false, false), Ok(b"\r".to_vec()));
+        assert_eq!(
+            resolve_key("Enter", false, false),
+            Ok(SendKey::Key(KeyCode::Enter, KeyModifiers::NONE))
+        );
     }
 
     #[test]
     fn resolve_key_plain_text_fallback() {
-        assert_eq!(resolve_key("hello", false, false), Ok(b"hello".to_vec()));
+        assert_eq!(
+            resolve_key("hello", false, false),
+            Ok(SendKey::Bytes(b"hello".to_vec()))
+        );
+    }
+
+    #[test]
+    fn send_key_is_enter() {
+        assert!(SendKey::Key(KeyCode::Enter, KeyModifiers::NONE).is_enter());
+        assert!(SendKey::Key(KeyCode::Char('m'), KeyModifiers::CTRL).is_enter());
+        assert!(SendKey::Bytes(b"ls\r".to_vec()).is_enter());
+        assert!(!SendKey::Key(KeyCode::Enter, KeyModifiers::ALT).is_enter());
+        assert!(!SendKey::Bytes(b"ls".to_vec()).is_enter());
     }
 
     // --- parse_split_size tests ---
@@ -4002,6 +6920,40 @@ mod tests {
         assert!(parse_split_size(Some("0%")).is_err());
     }
 
+    #[test]
+    fn resize_sizes_in_cells_or_percent() {
+        assert_eq!(resize_cells("30", 80, "width"), Ok(30));
+        assert_eq!(resize_cells("30%", 80, "width"), Ok(24));
+        assert_eq!(resize_cells("1%", 24, "height"), Ok(1));
+        assert_eq!(
+            resize_cells("x", 24, "height"),
+            Err("height is invalid: x".to_string())
+        );
+    }
+
+    #[test]
+    fn resize_window_fits_the_gui_windows() {
+        let size = |cols, rows| TerminalSize {
+            cols,
+            rows,
+            ..TerminalSize::default()
+        };
+        let windows = [size(120, 30), size(80, 50)];
+        assert_eq!(fit_size(size(10, 10), &windows, true), size(120, 50));
+        assert_eq!(fit_size(size(10, 10), &windows, false), size(80, 30));
+        assert_eq!(fit_size(size(10, 10), &[], true), size(10, 10));
+    }
+
+    #[test]
+    fn closed_tabs_forget_their_layout_preset() {
+        LAST_LAYOUT_PRESET.lock().insert(900_001, 1);
+        LAST_LAYOUT_PRESET.lock().insert(900_002, 3);
+        forget_layout_presets(&[900_002]);
+        let presets = LAST_LAYOUT_PRESET.lock();
+        assert_eq!(presets.get(&900_001), None);
+        assert_eq!(presets.get(&900_002), Some(&3));
+    }
+
     #[test]
     fn split_size_over_100_percent() {
         assert!(parse_split_size(Some("101%")).is_err());
@@ -4023,15 +6975,19 @@ mod tests {
     fn list_commands_contains_all() {
         let output = handle_list_commands();
         let commands: Vec<&str> = output.lines().collect();
-        assert_eq!(commands.len(), 46);
+        assert_eq!(commands.len(), 71);
         assert!(commands.contains(&"attach-session"));
+        assert!(commands.contains(&"bind-key"));
         assert!(commands.contains(&"break-pane"));
         assert!(commands.contains(&"capture-pane"));
+        assert!(commands.contains(&"choose-tree"));
+        assert!(commands.contains(&"clear-history"));
         assert!(commands.contains(&"copy-mode"));
         assert!(commands.contains(&"delete-buffer"));
         assert!(commands.contains(&"detach-client"));
         assert!(commands.contains(&"display-menu"));
         assert!(commands.contains(&"display-message"));
+        assert!(commands.contains(&"display-panes"));
         assert!(commands.contains(&"has-session"));
         assert!(commands.contains(&"join-pane"));
         assert!(commands.contains(&"kill-pane"));
@@ -4040,6 +6996,7 @@ mod tests {
         assert!(commands.contains(&"list-buffers"));
         assert!(commands.contains(&"list-clients"));
         assert!(commands.contains(&"list-commands"));
+        assert!(commands.contains(&"list-keys"));
         assert!(commands.contains(&"list-panes"));
         assert!(commands.contains(&"list-sessions"));
         assert!(commands.contains(&"list-windows"));
@@ -4058,18 +7015,31 @@ mod tests {
         assert!(commands.contains(&"select-window"));
         assert!(commands.contains(&"send-keys"));
         assert!(commands.contains(&"set-buffer"));
+        assert!(commands.contains(&"set-environment"));
         assert!(commands.contains(&"set-option"));
         assert!(commands.contains(&"show-buffer"));
+        assert!(commands.contains(&"show-environment"));
         assert!(commands.contains(&"show-options"));
         assert!(commands.contains(&"show-window-options"));
         assert!(commands.contains(&"split-window"));
+        assert!(commands.contains(&"start-server"));
         assert!(commands.contains(&"switch-client"));
+        assert!(commands.contains(&"unbind-key"));
         // Phase 17 commands
         assert!(commands.contains(&"display-popup"));
         assert!(commands.contains(&"kill-server"));
         assert!(commands.contains(&"pipe-pane"));
         assert!(commands.contains(&"run-shell"));
         assert!(commands.contains(&"wait-for"));
+        // Rearranging panes and windows
+        assert!(commands.contains(&"last-pane"));
+        assert!(commands.contains(&"last-window"));
+        assert!(commands.contains(&"next-window"));
+        assert!(commands.contains(&"previous-window"));
+        assert!(commands.contains(&"rotate-window"));
+        assert!(commands.contains(&"swap-pane"));
+        assert!(commands.contains(&"respawn-pane"));
+        assert!(commands.contains(&"respawn-window"));
     }
 
     #[test]
@@ -4081,49 +7051,112 @@ mod tests {
         assert_eq!(commands, sorted);
     }
 
+    #[test]
+    fn spawn_command_only_when_needed() {
+        assert!(build_spawn_command(None, None, &[]).is_none());
+
+        let env = [
+            ("FOO".to_string(), Some("bar".to_string())),
+            ("HOME".to_string(), None),
+        ];
+        let cmd = build_spawn_command(None, Some("/tmp"), &env).unwrap();
+        assert_eq!(cmd.get_cwd().map(|d| d.to_str().unwrap()), Some("/tmp"));
+        assert_eq!(cmd.get_env("FOO").and_then(|v| v.to_str()), Some("bar"));
+        assert!(cmd.get_env("HOME").is_none());
+        assert!(cmd.is_default_prog());
+
+        let cmd = build_spawn_command(Some("make test"), None, &[]).unwrap();
+        assert!(!cmd.is_default_prog());
+        assert_eq!(
+            cmd.get_argv().last().and_then(|a| a.to_str()),
+            Some("make test")
+        );
+    }
+
     // --- show-options tests ---
 
+    fn show(
+        scope: OptionScopeFlags,
+        value_only: bool,
+        quiet: bool,
+        name: Option<&str>,
+    ) -> Result<String, String> {
+        let ctx = HandlerContext::new("default".to_string());
+        handle_show_options(&ctx, &None, scope, value_only, quiet, false, name)
+    }
+
+    const GLOBAL: OptionScopeFlags = OptionScopeFlags {
+        global: true,
+        server: false,
+        window: false,
+        pane: false,
+    };
+    const SERVER: OptionScopeFlags = OptionScopeFlags {
+        global: true,
+        server: true,
+        window: false,
+        pane: false,
+    };
+    const GLOBAL_WINDOW: OptionScopeFlags = OptionScopeFlags {
+        global: true,
+        server: false,
+        window: true,
+        pane: false,
+    };
+
+    fn set_global(
+        flags: SetOptionFlags,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<String, String> {
+        let mut ctx = HandlerContext::new("default".to_string());
+        handle_set_option(&mut ctx, &None, GLOBAL, flags, Some(name), value)
+    }
+
     #[test]
     fn show_options_global_value_default_terminal() {
-        let result = handle_show_options(true, true, false, Some("default-terminal"));
+        let result = show(GLOBAL, true, false, Some("default-terminal"));
         assert_eq!(result, Ok("screen-256color".to_string()));
     }
 
     #[test]
     fn show_options_global_value_escape_time() {
-        let result = handle_show_options(true, true, false, Some("escape-time"));
+        let result = show(GLOBAL, true, false, Some("escape-time"));
         assert_eq!(result, Ok("500".to_string()));
     }
 
     #[test]
     fn show_options_global_value_set_clipboard() {
-        let result = handle_show_options(true, true, false, Some("set-clipboard"));
+        let result = show(GLOBAL, true, false, Some("set-clipboard"));
         assert_eq!(result, Ok("on".to_string()));
     }
 
     #[test]
     fn show_options_global_key_value_format() {
-        let result = handle_show_options(true, false, false, Some("default-terminal"));
+        let result = show(GLOBAL, false, false, Some("default-terminal"));
         assert_eq!(result, Ok("default-terminal screen-256color".to_string()));
     }
 
     #[test]
     fn show_options_global_all() {
-        let result = handle_show_options(true, false, false, None).unwrap();
+        let result = show(GLOBAL, false, false, None).unwrap();
         assert!(result.contains("default-terminal screen-256color"));
+        // Server options are listed with -s, not with the session options.
+        assert!(!result.contains("escape-time"));
+        let result = show(SERVER, false, false, None).unwrap();
         assert!(result.contains("escape-time 500"));
         assert!(result.contains("set-clipboard on"));
     }
 
     #[test]
     fn show_options_unknown_option_is_error() {
-        let result = handle_show_options(true, true, false, Some("nonexistent"));
+        let result = show(GLOBAL, true, false, Some("nonexistent"));
         assert!(result.is_err());
     }
 
     #[test]
     fn show_options_non_global_unknown_is_error() {
-        let result = handle_show_options(false, false, false, Some("anything"));
+        let result = show(OptionScopeFlags::default(), false, false, Some("anything"));
         assert!(result.is_err());
     }
 
@@ -4131,49 +7164,51 @@ mod tests {
 
     #[test]
     fn show_options_base_index() {
-        let result = handle_show_options(true, true, false, Some("base-index"));
+        let result = show(GLOBAL, true, false, Some("base-index"));
         assert_eq!(result, Ok("0".to_string()));
     }
 
     #[test]
     fn show_options_pane_base_index() {
-        let result = handle_show_options(true, true, false, Some("pane-base-index"));
+        let result = show(GLOBAL, true, false, Some("pane-base-index"));
         assert_eq!(result, Ok("0".to_string()));
     }
 
     #[test]
     fn show_options_focus_events() {
-        let result = handle_show_options(true, true, false, Some("focus-events"));
+        let result = show(GLOBAL, true, false, Some("focus-events"));
         assert_eq!(result, Ok("on".to_string()));
     }
 
     #[test]
     fn show_options_mouse() {
-        let result = handle_show_options(true, true, false, Some("mouse"));
+        let result = show(GLOBAL, true, false, Some("mouse"));
         assert_eq!(result, Ok("off".to_string()));
     }
 
     #[test]
     fn show_options_quiet_unknown_is_ok() {
         // -q flag suppresses errors for unknown options
-        let result = handle_show_options(true, true, true, Some("nonexistent"));
+        let result = show(GLOBAL, true, true, Some("nonexistent"));
         assert_eq!(result, Ok(String::new()));
     }
 
     #[test]
     fn show_options_quiet_non_global_is_ok() {
-        let result = handle_show_options(false, false, true, Some("anything"));
+        let result = show(OptionScopeFlags::default(), false, true, Some("anything"));
         assert_eq!(result, Ok(String::new()));
     }
 
     #[test]
     fn show_options_global_all_has_new_options() {
-        let result = handle_show_options(true, false, false, None).unwrap();
+        let result = show(GLOBAL, false, false, None).unwrap();
         assert!(result.contains("base-index 0"));
-        assert!(result.contains("pane-base-index 0"));
-        assert!(result.contains("focus-events on"));
         assert!(result.contains("mouse off"));
         assert!(result.contains("status off"));
+        let result = show(SERVER, false, false, None).unwrap();
+        assert!(result.contains("focus-events on"));
+        let result = show(GLOBAL_WINDOW, false, false, None).unwrap();
+        assert!(result.contains("pane-base-index 0"));
         assert!(result.contains("allow-rename on"));
     }
 
@@ -4181,46 +7216,552 @@ mod tests {
 
     #[test]
     fn show_window_options_aggressive_resize() {
-        let result = handle_show_window_options(true, true, false, Some("aggressive-resize"));
+        let result = show(GLOBAL_WINDOW, true, false, Some("aggressive-resize"));
         assert_eq!(result, Ok("off".to_string()));
     }
 
     #[test]
     fn show_window_options_mode_keys() {
-        let result = handle_show_window_options(true, true, false, Some("mode-keys"));
+        let result = show(GLOBAL_WINDOW, true, false, Some("mode-keys"));
         assert_eq!(result, Ok("emacs".to_string()));
     }
 
     #[test]
     fn show_window_options_key_value_format() {
-        let result = handle_show_window_options(true, false, false, Some("aggressive-resize"));
+        let result = show(GLOBAL_WINDOW, false, false, Some("aggressive-resize"));
         assert_eq!(result, Ok("aggressive-resize off".to_string()));
     }
 
     #[test]
     fn show_window_options_unknown_is_error() {
-        let result = handle_show_window_options(true, true, false, Some("nonexistent"));
+        let result = show(GLOBAL_WINDOW, true, false, Some("nonexistent"));
         assert!(result.is_err());
     }
 
     #[test]
     fn show_window_options_pane_base_index() {
-        let result = handle_show_window_options(true, true, false, Some("pane-base-index"));
+        let result = show(GLOBAL_WINDOW, true, false, Some("pane-base-index"));
         assert_eq!(result, Ok("0".to_string()));
     }
 
     #[test]
     fn show_window_options_remain_on_exit() {
-        let result = handle_show_window_options(true, true, false, Some("remain-on-exit"));
+        let result = show(GLOBAL_WINDOW, true, false, Some("remain-on-exit"));
         assert_eq!(result, Ok("off".to_string()));
     }
 
     #[test]
     fn show_window_options_quiet_unknown_is_ok() {
-        let result = handle_show_window_options(true, true, true, Some("nonexistent"));
+        let result = show(GLOBAL_WINDOW, true, true, Some("nonexistent"));
+        assert_eq!(result, Ok(String::new()));
+    }
+
+    // --- set-option tests ---
+
+    #[test]
+    fn set_option_user_option_round_trip() {
+        let flags = SetOptionFlags::default();
+        set_global(flags, "@test-round-trip", Some("hello")).unwrap();
+        let result = show(GLOBAL, true, false, Some("@test-round-trip"));
+        assert_eq!(result, Ok("hello".to_string()));
+        let result = show(GLOBAL, false, false, None).unwrap();
+        assert!(result.contains("@test-round-trip hello"));
+    }
+
+    #[test]
+    fn set_option_value_is_quoted_in_listing() {
+        let flags = SetOptionFlags::default();
+        set_global(flags, "@test-quoted", Some("two words")).unwrap();
+        let result = show(GLOBAL, false, false, Some("@test-quoted"));
+        assert_eq!(result, Ok("@test-quoted \"two words\"".to_string()));
+    }
+
+    #[test]
+    fn set_option_append() {
+        set_global(SetOptionFlags::default(), "@test-append", Some("foo")).unwrap();
+        let append = SetOptionFlags {
+            append: true,
+            ..Default::default()
+        };
+        set_global(append, "@test-append", Some("bar")).unwrap();
+        let result = show(GLOBAL, true, false, Some("@test-append"));
+        assert_eq!(result, Ok("foobar".to_string()));
+    }
+
+    #[test]
+    fn set_option_only_if_unset() {
+        let only = SetOptionFlags {
+            only_if_unset: true,
+            ..Default::default()
+        };
+        set_global(only, "@test-only", Some("first")).unwrap();
+        let result = set_global(only, "@test-only", Some("second"));
+        assert_eq!(result, Err("already set: @test-only".to_string()));
+        let quiet = SetOptionFlags {
+            quiet: true,
+            ..only
+        };
+        assert_eq!(
+            set_global(quiet, "@test-only", Some("third")),
+            Ok(String::new())
+        );
+        let result = show(GLOBAL, true, false, Some("@test-only"));
+        assert_eq!(result, Ok("first".to_string()));
+    }
+
+    #[test]
+    fn set_option_unset_user_option() {
+        set_global(SetOptionFlags::default(), "@test-unset", Some("x")).unwrap();
+        let unset = SetOptionFlags {
+            unset: true,
+            ..Default::default()
+        };
+        set_global(unset, "@test-unset", None).unwrap();
+        let result = show(GLOBAL, true, false, Some("@test-unset"));
+        assert_eq!(result, Err("invalid option: @test-unset".to_string()));
+        let result = show(GLOBAL, true, true, Some("@test-unset"));
+        assert_eq!(result, Ok(String::new()));
+    }
+
+    #[test]
+    fn set_option_toggles_flag_without_value() {
+        set_global(SetOptionFlags::default(), "@test-toggle", Some("on")).unwrap();
+        set_global(SetOptionFlags::default(), "@test-toggle", None).unwrap();
+        let result = show(GLOBAL, true, false, Some("@test-toggle"));
+        assert_eq!(result, Ok("off".to_string()));
+    }
+
+    #[test]
+    fn set_option_unknown_option_is_soft_noop() {
+        let result = set_global(SetOptionFlags::default(), "not-a-real-option", Some("1"));
+        assert_eq!(result, Ok(String::new()));
+    }
+
+    #[test]
+    fn show_options_session_inherits_with_a_flag() {
+        set_global(SetOptionFlags::default(), "@test-inherit", Some("global")).unwrap();
+        let ctx = HandlerContext::new("inherit-test".to_string());
+        let session = OptionScopeFlags::default();
+        // Without -A only values set on the session itself are shown.
+        let name = Some("@test-inherit");
+        let result = handle_show_options(&ctx, &None, session, true, true, false, name);
         assert_eq!(result, Ok(String::new()));
+        let result = handle_show_options(&ctx, &None, session, true, false, true, name);
+        assert_eq!(result, Ok("global".to_string()));
+        let result = handle_show_options(&ctx, &None, session, false, false, true, None).unwrap();
+        assert!(result.contains("@test-inherit* global"));
+    }
+
+    // --- set-hook / show-hooks tests ---
+
+    #[test]
+    fn set_hook_and_show_hooks() {
+        let ctx = HandlerContext::new("default".to_string());
+        let name = "after-test-show-hooks";
+        let command = Some("display-message hi".to_string());
+        handle_set_hook(&ctx, &None, true, false, false, name, command).unwrap();
+        let command = Some("display-message again".to_string());
+        handle_set_hook(&ctx, &None, true, true, false, name, command).unwrap();
+        let output = handle_show_hooks(&ctx, &None, true).unwrap();
+        assert!(output.contains("after-test-show-hooks[0] display-message hi"));
+        assert!(output.contains("after-test-show-hooks[1] display-message again"));
+
+        handle_set_hook(&ctx, &None, true, false, true, name, None).unwrap();
+        let output = handle_show_hooks(&ctx, &None, true).unwrap();
+        assert!(!output.contains("after-test-show-hooks"));
+    }
+
+    #[test]
+    fn set_hook_session_scope_uses_client_workspace() {
+        let ctx = HandlerContext::new("hook-session-test".to_string());
+        let command = Some("display-message hi".to_string());
+        handle_set_hook(&ctx, &None, false, false, false, "pane-exited", command).unwrap();
+        let output = handle_show_hooks(&ctx, &None, false).unwrap();
+        assert_eq!(output, "pane-exited[0] display-message hi");
+        let commands = hooks(None).commands("hook-session-test", "pane-exited");
+        assert_eq!(commands, vec!["display-message hi"]);
+    }
+
+    #[test]
+    fn set_hook_invalid_name_is_error() {
+        let ctx = HandlerContext::new("default".to_string());
+        let command = Some("display-message hi".to_string());
+        let result = handle_set_hook(&ctx, &None, true, false, false, "not-a-hook", command);
+        assert_eq!(result, Err("invalid option: not-a-hook".to_string()));
+    }
+
+    // --- set-environment / show-environment tests ---
+
+    #[test]
+    fn set_and_show_environment() {
+        let mut ctx = HandlerContext::new("env-show-test".to_string());
+        run_list(
+            &mut ctx,
+            "setenv API_URL 'http://x/$y' ; setenv -h TOKEN secret ; setenv -r DISPLAY",
+        )
+        .unwrap();
+
+        let output = run_list(&mut ctx, "show-environment").unwrap();
+        assert_eq!(output, "API_URL=http://x/$y\n-DISPLAY");
+        let output = run_list(&mut ctx, "show-environment -s").unwrap();
+        assert_eq!(
+            output,
+            "API_URL=\"http://x/\\$y\"; export API_URL;\nunset DISPLAY;"
+        );
+        let output = run_list(&mut ctx, "show-environment -h TOKEN").unwrap();
+        assert_eq!(output, "TOKEN=secret");
+
+        // New panes get the session environment.
+        let env = ctx.spawn_env("env-show-test", &["EXTRA=1".to_string()]);
+        assert!(env.contains(&("API_URL".to_string(), Some("http://x/$y".to_string()))));
+        assert!(env.contains(&("TOKEN".to_string(), Some("secret".to_string()))));
+        assert!(env.contains(&("DISPLAY".to_string(), None)));
+        assert_eq!(
+            env.last(),
+            Some(&("EXTRA".to_string(), Some("1".to_string())))
+        );
+
+        run_list(&mut ctx, "setenv -u API_URL").unwrap();
+        assert_eq!(
+            run_list(&mut ctx, "showenv API_URL"),
+            Err("unknown variable: API_URL".to_string())
+        );
+    }
+
+    #[test]
+    fn set_environment_errors() {
+        let mut ctx = HandlerContext::new("env-error-test".to_string());
+        assert_eq!(
+            run_list(&mut ctx, "setenv NAME"),
+            Err("no value specified".to_string())
+        );
+        assert_eq!(
+            run_list(&mut ctx, "setenv -u NAME value"),
+            Err("can't specify a value with -u".to_string())
+        );
+        assert_eq!(
+            run_list(&mut ctx, "setenv A=B c"),
+            Err("variable name contains =".to_string())
+        );
+    }
+
+    #[test]
+    fn bind_and_list_keys() {
+        let mut ctx = HandlerContext::new("keys-test".to_string());
+        run_list(
+            &mut ctx,
+            "bind -T keys-test | split-window -h ; \
+             bind -r -T keys-test M-Up select-pane -U ; \
+             bind -N 'Kill the pane' -T keys-test '#' kill-pane",
+        )
+        .unwrap();
+
+        let output = run_list(&mut ctx, "list-keys -T keys-test").unwrap();
+        assert_eq!(
+            output,
+            "bind-key    -T keys-test \\#   kill-pane\n\
+             bind-key -r -T keys-test M-Up select-pane -U\n\
+             bind-key    -T keys-test |    split-window -h"
+        );
+        let output = run_list(&mut ctx, "list-keys -N -T keys-test").unwrap();
+        assert_eq!(output, "# Kill the pane");
+        let output = run_list(&mut ctx, "list-keys -T keys-test |").unwrap();
+        assert_eq!(output, "bind-key -T keys-test | split-window -h");
+
+        run_list(&mut ctx, "unbind -T keys-test M-up").unwrap();
+        assert_eq!(
+            run_list(&mut ctx, "list-keys -T keys-test M-Up"),
+            Err("unknown key: M-Up".to_string())
+        );
+        run_list(&mut ctx, "unbind -a -T keys-test").unwrap();
+        assert_eq!(
+            run_list(&mut ctx, "list-keys -T keys-test"),
+            Err("table keys-test doesn't exist".to_string())
+        );
+        run_list(&mut ctx, "unbind -q -T keys-test x").unwrap();
+    }
+
+    #[test]
+    fn bind_key_errors() {
+        let mut ctx = HandlerContext::new("keys-error-test".to_string());
+        assert_eq!(
+            run_list(&mut ctx, "bind -T keys-error Hyper-x kill-pane"),
+            Err("unknown key: Hyper-x".to_string())
+        );
+        assert!(run_list(&mut ctx, "bind -T keys-error x no-such-command").is_err());
+        assert_eq!(
+            run_list(&mut ctx, "switch-client -T keys-error"),
+            Err("table keys-error doesn't exist".to_string())
+        );
+    }
+
+    #[test]
+    fn history_limit_must_be_a_number() {
+        let mut ctx = HandlerContext::new("history-limit-test".to_string());
+        assert_eq!(
+            run_list(&mut ctx, "set history-limit lots"),
+            Err("value is invalid: lots".to_string())
+        );
+        run_list(&mut ctx, "set history-limit 100").unwrap();
+        assert_eq!(
+            run_list(&mut ctx, "show -v history-limit"),
+            Ok("100".to_string())
+        );
+        assert_eq!(session_history_limit("history-limit-test"), Some(100));
+        assert_eq!(
+            run_list(&mut ctx, "set history-limit 99999999999"),
+            Err("value is too large: 99999999999".to_string())
+        );
+        assert_eq!(session_history_limit("history-limit-test"), Some(100));
+    }
+
+    #[test]
+    fn monitor_silence_must_be_a_number() {
+        let mut ctx = HandlerContext::new("monitor-silence-test".to_string());
+        assert_eq!(
+            run_list(&mut ctx, "set -gw monitor-silence soon"),
+            Err("value is invalid: soon".to_string())
+        );
+        assert_eq!(
+            run_list(&mut ctx, "show -gwv monitor-silence"),
+            Ok("0".to_string())
+        );
+    }
+
+    // --- command list / if-shell / source-file tests ---
+
+    fn run_list(ctx: &mut HandlerContext, text: &str) -> Result<String, String> {
+        let list = parse_command_list(text).map_err(|e| e.to_string())?;
+        smol::block_on(dispatch_command_list(ctx, &list))
+    }
+
+    #[test]
+    fn named_servers_have_their_own_state() {
+        let mut default = HandlerContext::new("default".to_string());
+        let mut named = HandlerContext::new("0".to_string());
+        named.server = Some("/tmp/handlers-test-isolation".to_string());
+        run_list(
+            &mut named,
+            "set -g @iso-test on ; set-hook -g after-iso-test 'display-message hi' ; \
+             set-buffer -b iso-test data ; bind-key -T iso-test x display-message hi ; \
+             setenv -g ISO_TEST 1",
+        )
+        .unwrap();
+
+        assert_eq!(run_list(&mut named, "show -gv @iso-test").unwrap(), "on");
+        assert_eq!(
+            run_list(&mut named, "show-buffer -b iso-test").unwrap(),
+            "data"
+        );
+        assert!(run_list(&mut named, "show-hooks -g")
+            .unwrap()
+            .contains("after-iso-test"));
+        assert!(run_list(&mut named, "list-keys -T iso-test").is_ok());
+        assert_eq!(
+            run_list(&mut named, "showenv -g ISO_TEST").unwrap(),
+            "ISO_TEST=1"
+        );
+
+        // None of it reaches the default server.
+        assert!(run_list(&mut default, "show -gv @iso-test").is_err());
+        assert!(run_list(&mut default, "show-buffer -b iso-test").is_err());
+        assert!(!run_list(&mut default, "show-hooks -g")
+            .unwrap()
+            .contains("after-iso-test"));
+        assert!(run_list(&mut default, "list-keys -T iso-test").is_err());
+        assert!(run_list(&mut default, "showenv -g ISO_TEST").is_err());
+
+        // The server's state goes when it stops.
+        crate::tmux_compat_server::server::stop_named_server("/tmp/handlers-test-isolation");
+        assert!(run_list(&mut named, "show -gv @iso-test").is_err());
+    }
+
+    #[test]
+    fn wait_for_rejects_bad_timeouts() {
+        let ctx = HandlerContext::new("default".to_string());
+        for timeout in ["1e300", "-1", "NaN"] {
+            assert_eq!(
+                smol::block_on(handle_wait_for(
+                    &ctx,
+                    false,
+                    false,
+                    false,
+                    Some(timeout),
+                    "wf-handler-test"
+                )),
+                Err("invalid timeout".to_string())
+            );
+        }
+        assert_eq!(
+            smol::block_on(handle_wait_for(&ctx, true, false, false, None, "")),
+            Err("missing channel".to_string())
+        );
+    }
+
+    #[test]
+    fn command_list_joins_output() {
+        let mut ctx = HandlerContext::new("default".to_string());
+        run_list(&mut ctx, "set -g @list-a 1 ; set -g @list-b 2").unwrap();
+        let output = run_list(&mut ctx, "show -gv @list-a ; show -gv @list-b").unwrap();
+        assert_eq!(output, "1\n2");
+    }
+
+    #[test]
+    fn command_list_stops_at_first_error() {
+        let mut ctx = HandlerContext::new("default".to_string());
+        let result = run_list(
+            &mut ctx,
+            "set -g @list-before 1 ; set-hook -g not-a-hook x ; set -g @list-after 1",
+        );
+        assert_eq!(result, Err("invalid option: not-a-hook".to_string()));
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@list-before")),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            show(GLOBAL, true, true, Some("@list-after")),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn command_list_keeps_output_apart_from_error() {
+        let mut ctx = HandlerContext::new("default".to_string());
+        let list =
+            parse_command_list("set -g @split-a 1 ; show -gv @split-a ; set-hook -g not-a-hook x")
+                .unwrap();
+        let (output, error) = smol::block_on(run_command_list(&mut ctx, &list));
+        assert_eq!(output, "1");
+        assert_eq!(error, Some("invalid option: not-a-hook".to_string()));
+        assert_eq!(
+            smol::block_on(dispatch_command_list(&mut ctx, &list)),
+            Err("1\ninvalid option: not-a-hook".to_string())
+        );
+    }
+
+    #[test]
+    fn if_shell_format_picks_command() {
+        let mut ctx = HandlerContext::new("default".to_string());
+        run_list(
+            &mut ctx,
+            "if -F '#{==:a,a}' 'set -g @if-f yes' 'set -g @if-f no'",
+        )
+        .unwrap();
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@if-f")),
+            Ok("yes".to_string())
+        );
+        run_list(&mut ctx, "if -F 0 { set -g @if-f yes } { set -g @if-f no }").unwrap();
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@if-f")),
+            Ok("no".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn if_shell_uses_exit_status() {
+        let mut ctx = HandlerContext::new("default".to_string());
+        run_list(
+            &mut ctx,
+            "if-shell true 'set -g @if-sh yes' 'set -g @if-sh no'",
+        )
+        .unwrap();
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@if-sh")),
+            Ok("yes".to_string())
+        );
+        run_list(
+            &mut ctx,
+            "if-shell 'exit 3' 'set -g @if-sh yes' 'set -g @if-sh no'",
+        )
+        .unwrap();
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@if-sh")),
+            Ok("no".to_string())
+        );
+    }
+
+    fn write_config(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.conf", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn source_file_fills_stores() {
+        let path = write_config(
+            "tmux-compat-source",
+            "\
+# test config
+set -g @source-a one
+%if #{==:x,x}
+set -g @source-b yes
+%else
+set -g @source-b no
+%endif
+clock-mode
+bind-key -T source-test x kill-pane
+set-hook -g after-test-source-file 'display hi'
+",
+        );
+        let mut ctx = HandlerContext::new("default".to_string());
+        let result = run_list(&mut ctx, &format!("source-file '{}'", path));
+        std::fs::remove_file(&path).unwrap();
+
+        // The unsupported clock-mode is reported, but the rest of the file ran.
+        let error = result.unwrap_err();
+        assert!(error.contains(&format!("{}:8:", path)), "{}", error);
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@source-a")),
+            Ok("one".to_string())
+        );
+        assert_eq!(
+            show(GLOBAL, true, false, Some("@source-b")),
+            Ok("yes".to_string())
+        );
+        assert_eq!(
+            hooks(None).commands("default", "after-test-source-file"),
+            vec!["display hi"]
+        );
+        assert_eq!(
+            run_list(&mut ctx, "list-keys -T source-test"),
+            Ok("bind-key -T source-test x kill-pane".to_string())
+        );
     }
 
+    #[test]
+    fn source_file_flags_and_missing_files() {
+        let path = write_config("tmux-compat-source-n", "set -g @source-n ran\n");
+        let mut ctx = HandlerContext::new("default".to_string());
+        run_list(&mut ctx, &format!("source-file -n '{}'", path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            show(GLOBAL, true, true, Some("@source-n")),
+            Ok(String::new())
+        );
+
+        let missing = std::env::temp_dir().join("tmux-compat-no-such-file.conf");
+        let missing = missing.to_string_lossy();
+        assert!(run_list(&mut ctx, &format!("source-file '{}'", missing)).is_err());
+        assert_eq!(
+            run_list(&mut ctx, &format!("source -q '{}'", missing)),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn source_file_stops_recursion() {
+        let path =
+            std::env::temp_dir().join(format!("tmux-compat-loop-{}.conf", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        std::fs::write(&path, format!("source-file '{}'\n", path)).unwrap();
+        let mut ctx = HandlerContext::new("default".to_string());
+        let result = run_list(&mut ctx, &format!("source-file '{}'", path));
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.unwrap_err().contains("too many nested files"));
+        assert_eq!(ctx.source_depth, 0);
+    }
     // --- Phase 8: detach-client tests ---
 
     #[test]
@@ -4486,12 +8027,14 @@ mod tests {
 
     #[test]
     fn copy_mode_quit_succeeds() {
-        assert_eq!(handle_copy_mode(true), Ok(String::new()));
+        let mut ctx = HandlerContext::new("default".to_string());
+        assert_eq!(handle_copy_mode(&mut ctx, true, &None), Ok(String::new()));
     }
 
     #[test]
-    fn copy_mode_enter_succeeds() {
-        assert_eq!(handle_copy_mode(false), Ok(String::new()));
+    fn copy_mode_enter_needs_a_pane() {
+        let mut ctx = HandlerContext::new("default".to_string());
+        assert!(handle_copy_mode(&mut ctx, false, &None).is_err());
     }
 
     // --- Phase 17: new command tests ---
@@ -4533,34 +8076,35 @@ mod tests {
 
     #[test]
     fn phase18_resolve_named_key_control_chars() {
-        assert_eq!(resolve_named_key("C-c"), Some(vec![3]));
-        assert_eq!(resolve_named_key("C-d"), Some(vec![4]));
-        assert_eq!(resolve_named_key("C-z"), Some(vec![26]));
-        assert_eq!(resolve_named_key("C-a"), Some(vec![1]));
+        assert_eq!(named_key_bytes("C-c"), Some(vec![3]));
+        assert_eq!(named_key_bytes("C-d"), Some(vec![4]));
+        assert_eq!(named_key_bytes("C-z"), Some(vec![26]));
+        assert_eq!(named_key_bytes("C-a"), Some(vec![1]));
     }
 
     #[test]
     fn phase18_resolve_named_key_special_keys() {
-        assert_eq!(resolve_named_key("Enter"), Some(b"\r".to_vec()));
-        assert_eq!(resolve_named_key("Space"), Some(b" ".to_vec()));
-        assert_eq!(resolve_named_key("Tab"), Some(b"\t".to_vec()));
-        assert_eq!(resolve_named_key("Escape"), Some(b"\x1b".to_vec()));
-        assert_eq!(resolve_named_key("BSpace"), Some(b"\x7f".to_vec()));
+        assert_eq!(named_key_bytes("Enter"), Some(b"\r".to_vec()));
+        assert_eq!(named_key_bytes("Space"), Some(b" ".to_vec()));
+        assert_eq!(named_key_bytes("Tab"), Some(b"\t".to_vec()));
+        assert_eq!(named_key_bytes("Escape"), Some(b"\x1b".to_vec()));
+        assert_eq!(named_key_bytes("BSpace"), Some(b"\x7f".to_vec()));
     }
 
     #[test]
     fn phase18_resolve_named_key_arrow_keys() {
-        assert_eq!(resolve_named_key("Up"), Some(b"\x1b[A".to_vec()));
-        assert_eq!(resolve_named_key("Down"), Some(b"\x1b[B".to_vec()));
-        assert_eq!(resolve_named_key("Right"), Some(b"\x1b[C".to_vec()));
-        assert_eq!(resolve_named_key("Left"), Some(b"\x1b[D".to_vec()));
+        assert_eq!(named_key_bytes("Up"), Some(b"\x1b[A".to_vec()));
+        assert_eq!(named_key_bytes("Down"), Some(b"\x1b[B".to_vec()));
+        assert_eq!(named_key_bytes("Right"), Some(b"\x1b[C".to_vec()));
+        assert_eq!(named_key_bytes("Left"), Some(b"\x1b[D".to_vec()));
     }
 
     #[test]
     fn phase18_resolve_named_key_unknown() {
         assert_eq!(resolve_named_key("C-"), None);
         assert_eq!(resolve_named_key("FooBar"), None);
-        assert_eq!(resolve_named_key("C-1"), None);
+        // tmux accepts a modifier on any character, not just letters
+        assert_eq!(named_key_bytes("C-1"), Some(b"1".to_vec()));
     }
 
     #[test]
@@ -4603,7 +8147,7 @@ mod tests {
     fn phase19_list_commands_count() {
         let output = handle_list_commands();
         let commands: Vec<&str> = output.lines().collect();
-        assert_eq!(commands.len(), 46);
+        assert_eq!(commands.len(), 71);
     }
 
     #[test]
@@ -4617,4 +8161,170 @@ mod tests {
         // Last line should be the expanded value
         assert!(verbose.lines().last().unwrap().starts_with('%'));
     }
+
+    // --- capture-pane rendering tests ---
+
+    /// Build a real `LocalPane` of the given size and feed `text` through its
+    /// terminal emulator, so captures exercise the actual line model.
+    fn capture_test_pane(cols: usize, rows: usize, text: &str) -> crate::localpane::LocalPane {
+        config::use_test_configuration();
+        let size = TerminalSize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+            dpi: 0,
+        };
+        let pair = portable_pty::native_pty_system()
+            .openpty(portable_pty::PtySize {
+                rows: rows as u16,
+                cols: cols as u16,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .expect("openpty");
+        let writer = pair.master.take_writer().expect("take_writer");
+        let terminal = wezterm_term::Terminal::new(
+            size,
+            Arc::new(config::TermConfig::new()),
+            "WezTerm",
+            config::wezterm_version(),
+            Box::new(Vec::<u8>::new()),
+        );
+        let pane = crate::localpane::LocalPane::new(
+            0,
+            terminal,
+            Box::new(crate::domain::FailedProcessSpawn {}),
+            pair.master,
+            crate::domain::WriterWrapper::new(writer),
+            0,
+            "capture-pane test".to_string(),
+        );
+
+        let mut parser = termwiz::escape::parser::Parser::new();
+        let mut actions = vec![];
+        parser.parse(text.as_bytes(), |action| actions.push(action));
+        pane.perform_actions(actions);
+        pane
+    }
+
+    fn capture(pane: &dyn Pane, opts: CaptureOptions) -> String {
+        capture_pane_contents(pane, opts, Some(0), Some(2)).unwrap()
+    }
+
+    #[test]
+    fn capture_plain_trims_trailing_spaces() {
+        let pane = capture_test_pane(20, 4, "ab   \r\ncd");
+        assert_eq!(capture(&pane, CaptureOptions::default()), "ab\ncd\n\n");
+    }
+
+    #[test]
+    fn capture_preserve_trailing_keeps_spaces() {
+        let pane = capture_test_pane(20, 4, "ab   \r\ncd");
+        let opts = CaptureOptions {
+            preserve_trailing: true,
+            ..Default::default()
+        };
+        let out = capture(&pane, opts);
+        assert_eq!(out.lines().next(), Some("ab   "));
+    }
+
+    #[test]
+    fn capture_without_join_splits_wrapped_lines() {
+        let pane = capture_test_pane(10, 4, "0123456789abc");
+        let out = capture(&pane, CaptureOptions::default());
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "0123456789");
+        assert_eq!(lines[1], "abc");
+    }
+
+    #[test]
+    fn capture_join_merges_wrapped_lines() {
+        let pane = capture_test_pane(10, 4, "0123456789abc\r\nnext");
+        let opts = CaptureOptions {
+            join: true,
+            ..Default::default()
+        };
+        let out = capture(&pane, opts);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "0123456789abc");
+        assert_eq!(lines[1], "next");
+    }
+
+    #[test]
+    fn capture_escape_emits_sgr() {
+        let pane = capture_test_pane(20, 4, "\x1b[1;31mred\x1b[0m plain");
+        let opts = CaptureOptions {
+            escape: true,
+            ..Default::default()
+        };
+        let out = capture(&pane, opts);
+        let first = out.lines().next().unwrap();
+        assert!(first.contains("\x1b["), "expected SGR in {:?}", first);
+        assert!(first.contains("red"));
+        assert!(first.contains(" plain"));
+        assert!(!out.contains("\r\n"));
+    }
+
+    #[test]
+    fn capture_without_escape_has_no_sgr() {
+        let pane = capture_test_pane(20, 4, "\x1b[1;31mred\x1b[0m plain");
+        let out = capture(&pane, CaptureOptions::default());
+        assert_eq!(out.lines().next(), Some("red plain"));
+    }
+
+    #[test]
+    fn capture_octal_escapes_backslash() {
+        let pane = capture_test_pane(20, 4, "a\\b");
+        let opts = CaptureOptions {
+            octal_escape: true,
+            ..Default::default()
+        };
+        let out = capture(&pane, opts);
+        assert_eq!(out.lines().next(), Some("a\\\\b"));
+    }
+
+    #[test]
+    fn capture_octal_escapes_sgr_introducer() {
+        let pane = capture_test_pane(20, 4, "\x1b[1mbold");
+        let opts = CaptureOptions {
+            escape: true,
+            octal_escape: true,
+            ..Default::default()
+        };
+        let out = capture(&pane, opts);
+        assert!(out.contains("\\033["), "expected octal ESC in {:?}", out);
+        assert!(!out.contains('\x1b'));
+    }
+
+    #[test]
+    fn capture_whole_history_and_trim_it() {
+        let pane = capture_test_pane(10, 2, "1\r\n2\r\n3\r\n4\r\n5");
+        let everything = |pane: &dyn Pane| {
+            let opts = CaptureOptions::default();
+            capture_pane_contents(pane, opts, Some(i64::MIN), Some(i64::MAX)).unwrap()
+        };
+        assert_eq!(everything(&pane), "1\n2\n3\n4\n5\n");
+        assert_eq!(
+            capture_pane_contents(&pane, CaptureOptions::default(), Some(-2), Some(0)).unwrap(),
+            "2\n3\n4\n"
+        );
+
+        pane.set_scrollback_limit(Some(1));
+        assert_eq!(pane.scrollback_limit(), Some(1));
+        assert_eq!(everything(&pane), "3\n4\n5\n");
+
+        pane.erase_scrollback(ScrollbackEraseMode::ScrollbackOnly);
+        assert_eq!(everything(&pane), "4\n5\n");
+
+        pane.set_scrollback_limit(None);
+        assert_eq!(pane.scrollback_limit(), Some(3500));
+    }
+
+    #[test]
+    fn push_capture_row_octal_control_chars() {
+        let mut out = String::new();
+        push_capture_row(&mut out, "a\tb\x07", true);
+        assert_eq!(out, "a\\011b\\007");
+    }
 }
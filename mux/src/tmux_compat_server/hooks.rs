@@ -0,0 +1,627 @@
+//! tmux hooks (`set-hook` / `show-hooks`).
+//!
+//! A hook is a list of commands run when something happens: a pane exits, a
+//! window is linked into a session, a command such as `split-window`
+//! completes (`after-split-window`). Hooks are set globally (`-g`) or on a
+//! session (WezTerm workspace); like tmux, a session's own hooks replace the
+//! global hook of the same name.
+//!
+//! Event hooks are fired from the mux notification stream (see
+//! [`hook_event_for_notification`]), except for the `alert-*` hooks and
+//! `pane-died`, which the window alert timer fires (see [`super::alerts`]
+//! and [`check_died_panes`]); `after-*` hooks are fired by
+//! `dispatch_command_list` once a command succeeds. Either way the
+//! commands run on the main thread as a command list, after `#{hook}`,
+//! `#{hook_pane}` and the other format variables in the command text have
+//! been expanded.
+//!
+//! As in tmux, `pane-exited` fires when a pane closes because its program
+//! exited, not when a command such as `kill-pane` closes it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+
+use parking_lot::{MappedMutexGuard, Mutex};
+
+use crate::pane::PaneId;
+use crate::tab::TabId;
+use crate::{Mux, MuxNotification};
+
+use super::command_parser::parse_command_list;
+use super::format::{expand_format, FormatContext};
+use super::handlers::{dispatch_command_list, format_context_for_pane, id_map_key, HandlerContext};
+use super::id_map::IdMap;
+use super::named_server::{self, PerServer};
+use super::options::OptionScope;
+
+/// Hook names tmux accepts besides `after-<command>`.
+pub const HOOK_NAMES: &[&str] = &[
+    "alert-activity",
+    "alert-bell",
+    "alert-silence",
+    "client-active",
+    "client-attached",
+    "client-detached",
+    "client-focus-in",
+    "client-focus-out",
+    "client-resized",
+    "client-session-changed",
+    "command-error",
+    "pane-died",
+    "pane-exited",
+    "pane-focus-in",
+    "pane-focus-out",
+    "pane-mode-changed",
+    "pane-set-clipboard",
+    "pane-title-changed",
+    "session-closed",
+    "session-created",
+    "session-renamed",
+    "session-window-changed",
+    "window-layout-changed",
+    "window-linked",
+    "window-pane-changed",
+    "window-renamed",
+    "window-resized",
+    "window-unlinked",
+];
+
+/// Whether `name` is a hook tmux knows about.
+pub fn is_valid_hook(name: &str) -> bool {
+    HOOK_NAMES.contains(&name)
+        || matches!(name.strip_prefix("after-"), Some(cmd) if !cmd.is_empty())
+}
+
+/// Hook commands, keyed by scope and then by hook name. Only
+/// [`OptionScope::GlobalSession`] and [`OptionScope::Session`] are used.
+#[derive(Debug, Default)]
+pub struct HookStore {
+    hooks: HashMap<OptionScope, BTreeMap<String, Vec<String>>>,
+}
+
+impl HookStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hook to `command`, or add `command` to the end of the hook
+    /// when `append` is true.
+    pub fn set(&mut self, scope: &OptionScope, name: &str, command: String, append: bool) {
+        let commands = self
+            .hooks
+            .entry(scope.clone())
+            .or_default()
+            .entry(name.to_string())
+            .or_default();
+        if !append {
+            commands.clear();
+        }
+        commands.push(command);
+    }
+
+    /// Remove the hook. Returns `true` if it was set.
+    pub fn unset(&mut self, scope: &OptionScope, name: &str) -> bool {
+        self.hooks
+            .get_mut(scope)
+            .and_then(|hooks| hooks.remove(name))
+            .is_some()
+    }
+
+    /// The commands to run for `name` in `workspace`: the session's own
+    /// hook if it has one, otherwise the global hook.
+    pub fn commands(&self, workspace: &str, name: &str) -> Vec<String> {
+        [
+            OptionScope::Session(workspace.to_string()),
+            OptionScope::GlobalSession,
+        ]
+        .iter()
+        .find_map(|scope| self.hooks.get(scope).and_then(|hooks| hooks.get(name)))
+        .cloned()
+        .unwrap_or_default()
+    }
+
+    /// Whether `name` is set in any scope. Cheap enough to check before
+    /// doing the work of firing a hook.
+    pub fn is_set(&self, name: &str) -> bool {
+        self.hooks.values().any(|hooks| hooks.contains_key(name))
+    }
+
+    /// The hooks set at exactly `scope`, one `name[index] command` line per
+    /// command, as printed by `show-hooks`.
+    pub fn list(&self, scope: &OptionScope) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(hooks) = self.hooks.get(scope) {
+            for (name, commands) in hooks {
+                for (idx, command) in commands.iter().enumerate() {
+                    lines.push(format!("{}[{}] {}", name, idx, command));
+                }
+            }
+        }
+        lines
+    }
+
+    /// Move a session's hooks when its workspace is renamed.
+    pub fn rename_session(&mut self, old_workspace: &str, new_workspace: &str) {
+        let old = OptionScope::Session(old_workspace.to_string());
+        if let Some(hooks) = self.hooks.remove(&old) {
+            self.hooks
+                .insert(OptionScope::Session(new_workspace.to_string()), hooks);
+        }
+    }
+}
+
+/// The hook store of each server.
+pub(super) static HOOKS: LazyLock<PerServer<HookStore>> = LazyLock::new(Default::default);
+
+/// Lock the hook store of `server`, or of the default server for `None`.
+pub fn hooks(server: Option<&str>) -> MappedMutexGuard<'static, HookStore> {
+    HOOKS.lock(server)
+}
+
+/// Something that happened, and where, for running a hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookEvent {
+    pub name: String,
+    pub pane_id: Option<PaneId>,
+    pub tab_id: Option<TabId>,
+    /// The session the event belongs to. The session of a pane or tab that
+    /// still exists is looked up when the hook runs instead.
+    pub workspace: Option<String>,
+    /// The client name (`/dev/pts/N`) for `client-*` hooks.
+    pub client: Option<String>,
+    /// The tmux IDs of the pane and window, for `#{hook_pane}` and
+    /// `#{hook_window}`, recorded when the event was fired.
+    pub tmux_pane_id: Option<u64>,
+    pub tmux_window_id: Option<u64>,
+}
+
+impl HookEvent {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            pane_id: None,
+            tab_id: None,
+            workspace: None,
+            client: None,
+            tmux_pane_id: None,
+            tmux_window_id: None,
+        }
+    }
+
+    pub fn pane(name: &str, pane_id: PaneId) -> Self {
+        Self {
+            pane_id: Some(pane_id),
+            ..Self::new(name)
+        }
+    }
+
+    pub fn tab(name: &str, tab_id: TabId) -> Self {
+        Self {
+            tab_id: Some(tab_id),
+            ..Self::new(name)
+        }
+    }
+}
+
+/// Panes being closed by a command such as `kill-pane` rather than by their
+/// program exiting.
+static KILLED_PANES: LazyLock<Mutex<HashSet<PaneId>>> = LazyLock::new(Default::default);
+
+/// Record that a command is about to close `pane_id`, so that its removal
+/// doesn't fire `pane-exited`.
+pub fn note_pane_killed(pane_id: PaneId) {
+    KILLED_PANES.lock().insert(pane_id);
+}
+
+/// The hook fired by a mux notification, if any.
+pub fn hook_event_for_notification(notif: &MuxNotification) -> Option<HookEvent> {
+    match notif {
+        MuxNotification::PaneRemoved(pane_id) => {
+            if KILLED_PANES.lock().remove(pane_id) {
+                return None;
+            }
+            Some(HookEvent::pane("pane-exited", *pane_id))
+        }
+        MuxNotification::PaneFocused(pane_id) => {
+            Some(HookEvent::pane("window-pane-changed", *pane_id))
+        }
+        MuxNotification::AssignClipboard { pane_id, .. } => {
+            Some(HookEvent::pane("pane-set-clipboard", *pane_id))
+        }
+        MuxNotification::Alert {
+            pane_id,
+            alert:
+                wezterm_term::Alert::WindowTitleChanged(_) | wezterm_term::Alert::IconTitleChanged(_),
+        } => Some(HookEvent::pane("pane-title-changed", *pane_id)),
+        MuxNotification::TabAddedToWindow { tab_id, .. } => {
+            Some(HookEvent::tab("window-linked", *tab_id))
+        }
+        MuxNotification::TabTitleChanged { tab_id, .. } => {
+            Some(HookEvent::tab("window-renamed", *tab_id))
+        }
+        MuxNotification::TabResized(tab_id) => {
+            Some(HookEvent::tab("window-layout-changed", *tab_id))
+        }
+        MuxNotification::WorkspaceRenamed { new_workspace, .. } => Some(HookEvent {
+            workspace: Some(new_workspace.clone()),
+            ..HookEvent::new("session-renamed")
+        }),
+        _ => None,
+    }
+}
+
+/// Where the panes and windows were when [`refresh_homes`] last looked, so
+/// that an event for one that has just gone away can still be placed.
+#[derive(Default)]
+struct Homes {
+    /// The session and window of each pane.
+    panes: HashMap<PaneId, (String, TabId)>,
+    /// The session of each window.
+    tabs: HashMap<TabId, String>,
+}
+
+static HOMES: LazyLock<Mutex<Homes>> = LazyLock::new(Default::default);
+
+/// Record where every pane and window is now. Runs on the main thread.
+fn refresh_homes() {
+    let Some(mux) = Mux::try_get() else {
+        return;
+    };
+    let mut homes = Homes::default();
+    for window_id in mux.iter_windows() {
+        let Some(window) = mux.get_window(window_id) else {
+            continue;
+        };
+        let workspace = window.get_workspace().to_string();
+        for tab in window.iter() {
+            for pp in tab.iter_panes_ignoring_zoom() {
+                homes
+                    .panes
+                    .insert(pp.pane.pane_id(), (workspace.clone(), tab.tab_id()));
+            }
+            homes.tabs.insert(tab.tab_id(), workspace.clone());
+        }
+    }
+    *HOMES.lock() = homes;
+}
+
+/// Bring the record of where panes and windows are up to date. The mux
+/// can't be looked up from inside a subscriber, so this happens later on the
+/// main thread, once for a burst of changes.
+pub fn refresh_homes_later() {
+    static PENDING: AtomicBool = AtomicBool::new(false);
+    if PENDING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    promise::spawn::spawn_into_main_thread(async {
+        PENDING.store(false, Ordering::SeqCst);
+        refresh_homes();
+    })
+    .detach();
+}
+
+/// Whether `notif` may have added or moved panes or windows.
+pub fn moves_panes(notif: &MuxNotification) -> bool {
+    matches!(
+        notif,
+        MuxNotification::PaneAdded(_)
+            | MuxNotification::TabAddedToWindow { .. }
+            | MuxNotification::TabResized(_)
+            | MuxNotification::WindowCreated(_)
+            | MuxNotification::WindowWorkspaceChanged(_)
+            | MuxNotification::WorkspaceRenamed { .. }
+    )
+}
+
+/// Fill in the session, window and tmux IDs of `event` while they are still
+/// known: by the time the hook runs, a pane that has exited is gone, and a
+/// client may have pruned its tmux ID.
+fn capture(event: &mut HookEvent) {
+    {
+        let homes = HOMES.lock();
+        if let Some((workspace, tab_id)) = event.pane_id.and_then(|id| homes.panes.get(&id)) {
+            event.workspace.get_or_insert_with(|| workspace.clone());
+            event.tab_id.get_or_insert(*tab_id);
+        }
+        if let Some(workspace) = event.tab_id.and_then(|id| homes.tabs.get(&id)) {
+            event.workspace.get_or_insert_with(|| workspace.clone());
+        }
+    }
+    let Some(workspace) = &event.workspace else {
+        return;
+    };
+    if event.pane_id.is_none() && event.tab_id.is_none() {
+        return;
+    }
+    let server = named_server::owner(workspace);
+    let id_map = IdMap::load(&id_map_key(workspace, server.as_deref()));
+    event.tmux_pane_id = event.pane_id.and_then(|id| id_map.tmux_pane_id(id));
+    event.tmux_window_id = event.tab_id.and_then(|id| id_map.tmux_window_id(id));
+}
+
+/// The process generation of each pane `pane-died` last fired for.
+static DIED_PANES: LazyLock<Mutex<HashMap<PaneId, usize>>> = LazyLock::new(Default::default);
+
+/// Fire `pane-died` once for each pane kept open after its program exited,
+/// by `remain-on-exit`, and again if the program of a respawned pane exits.
+/// Called by the alert timer, so that it fires whether or not a client is
+/// connected.
+pub fn check_died_panes() {
+    let Some(mux) = Mux::try_get() else {
+        return;
+    };
+    let mut died = Vec::new();
+    {
+        let mut fired = DIED_PANES.lock();
+        let mut live = HashSet::new();
+        for pane in mux.iter_panes() {
+            let pane_id = pane.pane_id();
+            live.insert(pane_id);
+            // is_dead() notices the program exiting and records its status.
+            if pane.is_dead() || pane.exit_code().is_none() {
+                continue;
+            }
+            let generation = pane.process_generation();
+            if fired.insert(pane_id, generation) != Some(generation) {
+                died.push(pane_id);
+            }
+        }
+        fired.retain(|pane_id, _| live.contains(pane_id));
+    }
+    for pane_id in died {
+        fire_hook(HookEvent::pane("pane-died", pane_id));
+    }
+}
+
+/// Run the hook for `event` if one is set. Can be called from any thread,
+/// including from inside a mux subscriber: the commands are dispatched later
+/// on the main thread.
+pub fn fire_hook(mut event: HookEvent) {
+    if !HOOKS.any(|hooks| hooks.is_set(&event.name)) {
+        return;
+    }
+    capture(&mut event);
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            run_hook(event).await;
+        })
+        .detach();
+    })
+    .detach();
+}
+
+/// Fire `after-<command>` once `command` has succeeded for a client. The
+/// hook's pane is the client's current pane after the command, so for
+/// `split-window` or `new-window` it is the new pane.
+pub fn fire_after_hook(ctx: &HandlerContext, command: &str) {
+    let name = format!("after-{}", command);
+    if !hooks(ctx.server.as_deref()).is_set(&name) {
+        return;
+    }
+    let pane_id = ctx.resolve_target(&None).ok().and_then(|r| r.pane_id);
+    fire_hook(HookEvent {
+        pane_id,
+        workspace: Some(ctx.workspace.clone()),
+        client: Some(ctx.client_name.clone()),
+        ..HookEvent::new(name)
+    });
+}
+
+/// Find the session an event belongs to and, for pane and tab events, the
+/// pane whose format variables describe it.
+fn locate_event(mux: &Mux, event: &HookEvent) -> (Option<String>, Option<PaneId>) {
+    let workspace_of = |window_id| {
+        mux.get_window(window_id)
+            .map(|win| win.get_workspace().to_string())
+    };
+    if let Some(pane_id) = event.pane_id {
+        if let Some((_, window_id, _)) = mux.resolve_pane_id(pane_id) {
+            return (workspace_of(window_id), Some(pane_id));
+        }
+    }
+    if let Some(tab_id) = event.tab_id {
+        let pane = mux
+            .get_tab(tab_id)
+            .and_then(|tab| tab.get_active_pane())
+            .map(|pane| pane.pane_id());
+        if let Some(window_id) = mux.window_containing_tab(tab_id) {
+            return (workspace_of(window_id), pane);
+        }
+    }
+    (None, None)
+}
+
+/// Run the commands of the hook for `event` in a context of their own.
+async fn run_hook(event: HookEvent) {
+    let Some(mux) = Mux::try_get() else {
+        return;
+    };
+    let (located, context_pane) = locate_event(&mux, &event);
+    let workspace = match located.or_else(|| event.workspace.clone()) {
+        Some(workspace) => workspace,
+        None if event.pane_id.is_some() || event.tab_id.is_some() => {
+            log::debug!("hook {}: can't tell which session it is for", event.name);
+            return;
+        }
+        None => mux.active_workspace(),
+    };
+
+    let server = named_server::owner(&workspace);
+    let commands = hooks(server.as_deref()).commands(&workspace, &event.name);
+    if commands.is_empty() {
+        return;
+    }
+
+    // The persisted IDs are loaded without pruning, so that the hook's
+    // commands can still refer to a pane that has just exited.
+    let mut ctx = HandlerContext::new(workspace.clone());
+    ctx.server = server;
+    ctx.id_map = IdMap::load(&ctx.id_map_key());
+    ctx.fire_after_hooks = false;
+
+    let mut fctx = context_pane
+        .and_then(|pane_id| format_context_for_pane(&mut ctx, pane_id))
+        .unwrap_or_else(|| FormatContext {
+            session_id: ctx.id_map.get_or_create_tmux_session_id(&workspace),
+            session_name: workspace.clone(),
+            ..FormatContext::default()
+        });
+    fctx.hook = event.name.clone();
+    let tmux_pane_id = event
+        .tmux_pane_id
+        .or_else(|| event.pane_id.and_then(|id| ctx.id_map.tmux_pane_id(id)));
+    if let Some(tmux_id) = tmux_pane_id {
+        fctx.hook_pane = format!("%{}", tmux_id);
+    }
+    if context_pane.is_some() {
+        fctx.hook_window = format!("@{}", fctx.window_id);
+        fctx.hook_window_name = fctx.window_name.clone();
+    } else if let Some(tmux_id) = event.tmux_window_id {
+        fctx.hook_window = format!("@{}", tmux_id);
+    }
+    fctx.hook_session = format!("${}", fctx.session_id);
+    fctx.hook_session_name = fctx.session_name.clone();
+    fctx.hook_client = event.client.clone().unwrap_or_default();
+
+    for command in commands {
+        let line = expand_format(&command, &fctx);
+        log::debug!("hook {}: {}", event.name, line);
+        let result = match parse_command_list(&line) {
+            Ok(list) => dispatch_command_list(&mut ctx, &list).await,
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            log::warn!("hook {}: {:?} failed: {}", event.name, line, e);
+        }
+    }
+    ctx.save_id_map();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_hook_names() {
+        assert!(is_valid_hook("pane-exited"));
+        assert!(is_valid_hook("after-split-window"));
+        assert!(!is_valid_hook("after-"));
+        assert!(!is_valid_hook("not-a-hook"));
+    }
+
+    #[test]
+    fn set_replaces_and_append_adds() {
+        let mut store = HookStore::new();
+        let global = OptionScope::GlobalSession;
+        store.set(&global, "pane-exited", "a".to_string(), false);
+        store.set(&global, "pane-exited", "b".to_string(), false);
+        assert_eq!(store.commands("any", "pane-exited"), vec!["b"]);
+        store.set(&global, "pane-exited", "c".to_string(), true);
+        assert_eq!(store.commands("any", "pane-exited"), vec!["b", "c"]);
+        assert_eq!(
+            store.list(&global),
+            vec!["pane-exited[0] b", "pane-exited[1] c"]
+        );
+    }
+
+    #[test]
+    fn session_hook_replaces_global() {
+        let mut store = HookStore::new();
+        store.set(
+            &OptionScope::GlobalSession,
+            "window-linked",
+            "global".to_string(),
+            false,
+        );
+        store.set(
+            &OptionScope::Session("work".to_string()),
+            "window-linked",
+            "work".to_string(),
+            false,
+        );
+        assert_eq!(store.commands("work", "window-linked"), vec!["work"]);
+        assert_eq!(store.commands("other", "window-linked"), vec!["global"]);
+        assert!(store.commands("work", "pane-exited").is_empty());
+    }
+
+    #[test]
+    fn unset_and_is_set() {
+        let mut store = HookStore::new();
+        let scope = OptionScope::Session("work".to_string());
+        assert!(!store.is_set("client-attached"));
+        store.set(&scope, "client-attached", "x".to_string(), false);
+        assert!(store.is_set("client-attached"));
+        assert!(store.unset(&scope, "client-attached"));
+        assert!(!store.unset(&scope, "client-attached"));
+        assert!(!store.is_set("client-attached"));
+    }
+
+    #[test]
+    fn rename_session_moves_hooks() {
+        let mut store = HookStore::new();
+        let old = OptionScope::Session("old".to_string());
+        store.set(&old, "session-renamed", "x".to_string(), false);
+        store.rename_session("old", "new");
+        assert!(store.list(&old).is_empty());
+        assert_eq!(store.commands("new", "session-renamed"), vec!["x"]);
+    }
+
+    #[test]
+    fn killed_panes_do_not_exit() {
+        note_pane_killed(7);
+        assert!(hook_event_for_notification(&MuxNotification::PaneRemoved(7)).is_none());
+        // Only the removal the kill caused is skipped.
+        let event = hook_event_for_notification(&MuxNotification::PaneRemoved(7)).unwrap();
+        assert_eq!(event, HookEvent::pane("pane-exited", 7));
+    }
+
+    #[test]
+    fn events_are_placed_while_their_pane_is_known() {
+        HOMES
+            .lock()
+            .panes
+            .insert(PaneId::MAX - 2, ("hooks-home-test".to_string(), 5));
+        let mut event = HookEvent::pane("pane-exited", PaneId::MAX - 2);
+        capture(&mut event);
+        assert_eq!(event.workspace.as_deref(), Some("hooks-home-test"));
+        assert_eq!(event.tab_id, Some(5));
+
+        // A session given by the caller is kept.
+        let mut event = HookEvent {
+            workspace: Some("given".to_string()),
+            ..HookEvent::pane("pane-exited", PaneId::MAX - 2)
+        };
+        capture(&mut event);
+        assert_eq!(event.workspace.as_deref(), Some("given"));
+        HOMES.lock().panes.remove(&(PaneId::MAX - 2));
+    }
+
+    #[test]
+    fn notifications_map_to_hooks() {
+        let event = hook_event_for_notification(&MuxNotification::PaneRemoved(3)).unwrap();
+        assert_eq!(event, HookEvent::pane("pane-exited", 3));
+
+        let notif = MuxNotification::TabAddedToWindow {
+            tab_id: 2,
+            window_id: 1,
+        };
+        let event = hook_event_for_notification(&notif).unwrap();
+        assert_eq!(event, HookEvent::tab("window-linked", 2));
+
+        let notif = MuxNotification::WorkspaceRenamed {
+            old_workspace: "a".to_string(),
+            new_workspace: "b".to_string(),
+        };
+        let event = hook_event_for_notification(&notif).unwrap();
+        assert_eq!(event.name, "session-renamed");
+        assert_eq!(event.workspace.as_deref(), Some("b"));
+
+        assert!(hook_event_for_notification(&MuxNotification::PaneOutput(1)).is_none());
+        let bell = MuxNotification::Alert {
+            pane_id: 1,
+            alert: wezterm_term::Alert::Bell,
+        };
+        assert!(hook_event_for_notification(&bell).is_none());
+    }
+}
@@ -0,0 +1,610 @@
+//! Key bindings (`bind-key` / `unbind-key` / `list-keys`).
+//!
+//! As in tmux, bindings live in named key tables: `root` holds the keys that
+//! work on their own (`bind-key -n`), `prefix` the keys pressed after the
+//! prefix key, and any other table can be made current with
+//! `switch-client -T`. The GUI consults the tables as dynamic WezTerm key
+//! tables (see [`lookup_key`]) and runs the command of a binding that matches
+//! with [`run_binding`].
+//!
+//! Unlike tmux, the tables start out empty, so WezTerm's own key assignments
+//! apply until something is bound. The prefix key only starts a key sequence
+//! once the `prefix` table has bindings.
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use parking_lot::MappedMutexGuard;
+use wezterm_input_types::{KeyCode as EventKey, Modifiers};
+use wezterm_term::{KeyCode, KeyModifiers};
+
+use crate::pane::PaneId;
+use crate::Mux;
+
+use super::command_parser::parse_command_list;
+use super::handlers::{dispatch_command_list, resolve_named_key, HandlerContext};
+use super::named_server::{self, PerServer};
+use super::options::{options, OptionScope};
+
+/// Tables tmux always has, even when nothing is bound in them.
+const BUILTIN_TABLES: &[&str] = &["copy-mode", "copy-mode-vi", "prefix", "root"];
+
+/// A key bound to a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// The command list run when the key is pressed.
+    pub command: String,
+    /// `-r`: the key can be pressed again within `repeat-time` without
+    /// going through the prefix key.
+    pub repeat: bool,
+    /// `-N`: a description shown by `list-keys -N`.
+    pub note: Option<String>,
+}
+
+/// What the GUI should do for a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Run a bound command. For a repeatable binding, `repeat_time` is how
+    /// long, in milliseconds, the table stays current afterwards.
+    Run {
+        command: String,
+        repeat_time: Option<u64>,
+    },
+    /// The prefix key was pressed: look the next key up in `prefix`.
+    Prefix,
+}
+
+/// Key bindings, keyed by table and then by canonical key name.
+#[derive(Debug, Default)]
+pub struct KeyBindingStore {
+    tables: BTreeMap<String, BTreeMap<String, Binding>>,
+    /// A table set by `switch-client -T` that the GUI has yet to switch to.
+    switched_table: Option<String>,
+}
+
+impl KeyBindingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind `key` in `table`. Without a command, an existing binding only
+    /// has its note and repeat flag updated, as in tmux.
+    pub fn bind(
+        &mut self,
+        table: &str,
+        key: &str,
+        command: Option<String>,
+        repeat: bool,
+        note: Option<String>,
+    ) {
+        let bindings = self.tables.entry(table.to_string()).or_default();
+        match (bindings.get_mut(key), command) {
+            (Some(binding), None) => {
+                binding.repeat |= repeat;
+                if note.is_some() {
+                    binding.note = note;
+                }
+            }
+            (_, command) => {
+                bindings.insert(
+                    key.to_string(),
+                    Binding {
+                        command: command.unwrap_or_default(),
+                        repeat,
+                        note,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Remove the binding of `key` in `table`, if there is one.
+    pub fn unbind(&mut self, table: &str, key: &str) -> Result<(), String> {
+        let Some(bindings) = self.tables.get_mut(table) else {
+            if BUILTIN_TABLES.contains(&table) {
+                return Ok(());
+            }
+            return Err(format!("table {} doesn't exist", table));
+        };
+        bindings.remove(key);
+        if bindings.is_empty() {
+            self.tables.remove(table);
+        }
+        Ok(())
+    }
+
+    /// Remove every binding in `table`.
+    pub fn unbind_all(&mut self, table: &str) -> Result<(), String> {
+        if self.tables.remove(table).is_none() && !BUILTIN_TABLES.contains(&table) {
+            return Err(format!("table {} doesn't exist", table));
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, table: &str, key: &str) -> Option<&Binding> {
+        self.tables
+            .get(table)
+            .and_then(|bindings| bindings.get(key))
+    }
+
+    pub fn has_table(&self, table: &str) -> bool {
+        self.tables.contains_key(table) || BUILTIN_TABLES.contains(&table)
+    }
+
+    /// The bindings in `table`, or in every table, in table and key order.
+    pub fn bindings(&self, table: Option<&str>) -> Vec<(&str, &str, &Binding)> {
+        self.tables
+            .iter()
+            .filter(|(name, _)| table.is_none_or(|t| t == name.as_str()))
+            .flat_map(|(name, bindings)| {
+                bindings
+                    .iter()
+                    .map(move |(key, binding)| (name.as_str(), key.as_str(), binding))
+            })
+            .collect()
+    }
+
+    /// Make `table` current for the next key press (`switch-client -T`).
+    pub fn switch_table(&mut self, table: &str) {
+        self.switched_table = Some(table.to_string());
+    }
+
+    /// The table set by `switch-client -T` since the last call, if any.
+    pub fn take_switched_table(&mut self) -> Option<String> {
+        self.switched_table.take()
+    }
+
+    /// Decide what pressing `key` does in `table`. `prefixes` are the
+    /// canonical names of the prefix keys and `repeat_time` the
+    /// `repeat-time` option.
+    pub fn resolve(
+        &self,
+        table: &str,
+        key: &str,
+        prefixes: &[String],
+        repeat_time: u64,
+    ) -> Option<KeyAction> {
+        if let Some(binding) = self.lookup(table, key) {
+            // Keys in `root` need no prefix, so there is nothing to repeat.
+            let repeat = binding.repeat && table != "root";
+            return Some(KeyAction::Run {
+                command: binding.command.clone(),
+                repeat_time: repeat.then_some(repeat_time),
+            });
+        }
+        let has_prefix_bindings = self.tables.get("prefix").is_some_and(|b| !b.is_empty());
+        if table == "root" && has_prefix_bindings && prefixes.iter().any(|p| p == key) {
+            return Some(KeyAction::Prefix);
+        }
+        None
+    }
+}
+
+/// The key binding store of each server.
+pub(super) static KEY_BINDINGS: LazyLock<PerServer<KeyBindingStore>> =
+    LazyLock::new(Default::default);
+
+/// Lock the key binding store of `server`, or of the default server for
+/// `None`.
+pub fn key_bindings(server: Option<&str>) -> MappedMutexGuard<'static, KeyBindingStore> {
+    KEY_BINDINGS.lock(server)
+}
+
+/// Lock the key binding store of the server `workspace` belongs to, for
+/// key presses in the GUI.
+pub fn key_bindings_for(workspace: &str) -> MappedMutexGuard<'static, KeyBindingStore> {
+    key_bindings(named_server::owner(workspace).as_deref())
+}
+
+/// The name tmux prints for a key, or `None` if tmux has no name for it.
+fn key_string(key: KeyCode, mut mods: KeyModifiers) -> Option<String> {
+    let name = match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Tab if mods.contains(KeyModifiers::SHIFT) => {
+            mods -= KeyModifiers::SHIFT;
+            "BTab".to_string()
+        }
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Escape => "Escape".to_string(),
+        KeyCode::Backspace => "BSpace".to_string(),
+        KeyCode::Delete => "DC".to_string(),
+        KeyCode::Insert => "IC".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PPage".to_string(),
+        KeyCode::PageDown => "NPage".to_string(),
+        KeyCode::UpArrow => "Up".to_string(),
+        KeyCode::DownArrow => "Down".to_string(),
+        KeyCode::LeftArrow => "Left".to_string(),
+        KeyCode::RightArrow => "Right".to_string(),
+        KeyCode::Function(n) => format!("F{}", n),
+        _ => return None,
+    };
+    Some(format!("{}{}", modifier_prefix(mods), name))
+}
+
+/// The `C-`, `M-` and `S-` prefixes for `mods`, in tmux's order.
+fn modifier_prefix(mods: KeyModifiers) -> String {
+    let mut prefix = String::new();
+    if mods.contains(KeyModifiers::CTRL) {
+        prefix.push_str("C-");
+    }
+    if mods.contains(KeyModifiers::ALT) {
+        prefix.push_str("M-");
+    }
+    if mods.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("S-");
+    }
+    prefix
+}
+
+/// Whether `name`, without its modifiers, is one of tmux's mouse keys.
+/// Those are accepted so that a tmux.conf binding them can be loaded, but
+/// the GUI never presses them.
+fn is_mouse_key(name: &str) -> bool {
+    let mut rest = name;
+    while rest.len() > 2 && matches!(&rest[..2], "C-" | "M-" | "S-") {
+        rest = &rest[2..];
+    }
+    [
+        "Mouse",
+        "Wheel",
+        "SecondClick",
+        "DoubleClick",
+        "TripleClick",
+    ]
+    .iter()
+    .any(|prefix| rest.starts_with(prefix))
+}
+
+/// The canonical name of a key given to `bind-key` and friends, so that
+/// `C-B`, `^b` and `c-b` all refer to the same binding.
+pub fn normalize_key(name: &str) -> Result<String, String> {
+    let unknown = || format!("unknown key: {}", name);
+    if is_mouse_key(name) {
+        return Ok(name.to_string());
+    }
+    let (key, mods) = match resolve_named_key(name) {
+        Some(key) => key,
+        None => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => (KeyCode::Char(c), KeyModifiers::NONE),
+                _ => return Err(unknown()),
+            }
+        }
+    };
+    key_string(key, mods).ok_or_else(unknown)
+}
+
+/// The canonical name of a key pressed in the GUI, or `None` if tmux has no
+/// name for it.
+pub fn key_event_name(key: &EventKey, mods: Modifiers) -> Option<String> {
+    let mut mods = mods.remove_positional_mods();
+    if mods.intersects(Modifiers::SUPER | Modifiers::LEADER) {
+        return None;
+    }
+    let key = match key {
+        EventKey::Char('\r') => KeyCode::Enter,
+        EventKey::Char('\t') => KeyCode::Tab,
+        EventKey::Char('\x1b') => KeyCode::Escape,
+        EventKey::Char('\x08') => KeyCode::Backspace,
+        EventKey::Char('\x7f') => KeyCode::Delete,
+        EventKey::Char(c) => {
+            // The shifted character says it all: `|`, not `S-\`.
+            mods -= Modifiers::SHIFT;
+            if mods.contains(Modifiers::CTRL) {
+                KeyCode::Char(c.to_ascii_lowercase())
+            } else {
+                KeyCode::Char(*c)
+            }
+        }
+        EventKey::UpArrow => KeyCode::UpArrow,
+        EventKey::DownArrow => KeyCode::DownArrow,
+        EventKey::LeftArrow => KeyCode::LeftArrow,
+        EventKey::RightArrow => KeyCode::RightArrow,
+        EventKey::Home => KeyCode::Home,
+        EventKey::End => KeyCode::End,
+        EventKey::PageUp => KeyCode::PageUp,
+        EventKey::PageDown => KeyCode::PageDown,
+        EventKey::Insert => KeyCode::Insert,
+        EventKey::Function(n) => KeyCode::Function(*n),
+        _ => return None,
+    };
+    key_string(key, mods)
+}
+
+/// A key name as `list-keys` prints it: single characters that mean
+/// something to the command parser are escaped with `\`.
+fn escape_key(key: &str) -> String {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if "\"#';${}%~".contains(c) => format!("\\{}", c),
+        _ => key.to_string(),
+    }
+}
+
+/// The lines `list-keys` prints for `bindings`, in tmux's `bind-key` form.
+pub fn format_bindings(bindings: &[(&str, &str, &Binding)]) -> Vec<String> {
+    let table_width = bindings.iter().map(|(t, _, _)| t.len()).max().unwrap_or(0);
+    let key_width = bindings
+        .iter()
+        .map(|(_, k, _)| escape_key(k).len())
+        .max()
+        .unwrap_or(0);
+    let any_repeat = bindings.iter().any(|(_, _, b)| b.repeat);
+    bindings
+        .iter()
+        .map(|(table, key, binding)| {
+            let repeat = match (binding.repeat, any_repeat) {
+                (true, _) => "-r ",
+                (false, true) => "   ",
+                (false, false) => "",
+            };
+            format!(
+                "bind-key {}-T {:tw$} {:kw$} {}",
+                repeat,
+                table,
+                escape_key(key),
+                binding.command,
+                tw = table_width,
+                kw = key_width,
+            )
+            .trim_end()
+            .to_string()
+        })
+        .collect()
+}
+
+/// The lines `list-keys -N` prints for `bindings`: each key, after `prefix`
+/// for keys in the `prefix` table, and its note. With `all`, keys without a
+/// note are listed too, with their command.
+pub fn format_notes(bindings: &[(&str, &str, &Binding)], prefix: &str, all: bool) -> Vec<String> {
+    let entries: Vec<(String, &str)> = bindings
+        .iter()
+        .filter_map(|(table, key, binding)| {
+            let text = match &binding.note {
+                Some(note) => note.as_str(),
+                None if all => binding.command.as_str(),
+                None => return None,
+            };
+            let key = if *table == "prefix" {
+                format!("{}{}", prefix, key)
+            } else {
+                key.to_string()
+            };
+            Some((key, text))
+        })
+        .collect();
+    let key_width = entries.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    entries
+        .into_iter()
+        .map(|(key, text)| format!("{:kw$} {}", key, text, kw = key_width))
+        .collect()
+}
+
+/// The canonical names of the prefix keys (`prefix` and `prefix2`) for
+/// `workspace`.
+pub fn prefix_keys(workspace: &str) -> Vec<String> {
+    let chain = OptionScope::Session(workspace.to_string()).chain(None);
+    let store = options(named_server::owner(workspace).as_deref());
+    ["prefix", "prefix2"]
+        .iter()
+        .filter_map(|name| store.lookup(&chain, name))
+        .filter(|key| *key != "None")
+        .filter_map(|key| normalize_key(key).ok())
+        .collect()
+}
+
+/// Decide what pressing the key named `key` does in `table` for a pane in
+/// `workspace`.
+pub fn lookup_key(table: &str, key: &str, workspace: &str) -> Option<KeyAction> {
+    let prefixes = prefix_keys(workspace);
+    let server = named_server::owner(workspace);
+    let repeat_time = options(server.as_deref())
+        .lookup(
+            &OptionScope::Session(workspace.to_string()).chain(None),
+            "repeat-time",
+        )
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(500);
+    key_bindings(server.as_deref()).resolve(table, key, &prefixes, repeat_time)
+}
+
+/// Run the command of a key binding pressed in `pane_id`, with that pane as
+/// the current pane. Can be called from any thread; the commands are
+/// dispatched on the main thread.
+pub fn run_binding(pane_id: PaneId, command: String) {
+    run_in_pane(pane_id, move |_| command);
+}
+
+/// Run the command returned by `command` with `pane_id` as the current pane,
+/// on behalf of the GUI. `command` is given the context the command runs in,
+/// for looking up IDs. Can be called from any thread.
+pub fn run_in_pane<F>(pane_id: PaneId, command: F)
+where
+    F: FnOnce(&mut HandlerContext) -> String + Send + 'static,
+{
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            run_bound_command(pane_id, command).await;
+        })
+        .detach();
+    })
+    .detach();
+}
+
+async fn run_bound_command<F>(pane_id: PaneId, command: F)
+where
+    F: FnOnce(&mut HandlerContext) -> String,
+{
+    let Some(mux) = Mux::try_get() else {
+        return;
+    };
+    let Some((_, window_id, tab_id)) = mux.resolve_pane_id(pane_id) else {
+        return;
+    };
+    let Some(workspace) = mux
+        .get_window(window_id)
+        .map(|win| win.get_workspace().to_string())
+    else {
+        return;
+    };
+
+    let server = named_server::owner(&workspace);
+    let mut ctx = HandlerContext::with_persistent_ids(workspace, server);
+    ctx.for_gui = true;
+    ctx.active_window_id = Some(ctx.id_map.get_or_create_tmux_window_id(tab_id));
+    ctx.active_pane_id = Some(ctx.id_map.get_or_create_tmux_pane_id(pane_id));
+    let command = command(&mut ctx);
+    let result = match parse_command_list(&command) {
+        Ok(list) => dispatch_command_list(&mut ctx, &list).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = result {
+        log::warn!("{:?} failed: {}", command, e);
+    }
+    ctx.save_id_map();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(command: &str, repeat: bool) -> Binding {
+        Binding {
+            command: command.to_string(),
+            repeat,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn key_names_are_normalized() {
+        assert_eq!(normalize_key("C-B").unwrap(), "C-b");
+        assert_eq!(normalize_key("^b").unwrap(), "C-b");
+        assert_eq!(normalize_key("m-up").unwrap(), "M-Up");
+        assert_eq!(normalize_key("PageUp").unwrap(), "PPage");
+        assert_eq!(normalize_key("|").unwrap(), "|");
+        assert_eq!(normalize_key("C-Space").unwrap(), "C-Space");
+        assert_eq!(normalize_key("BTab").unwrap(), "BTab");
+        assert_eq!(normalize_key("MouseDown1Pane").unwrap(), "MouseDown1Pane");
+        assert_eq!(
+            normalize_key("bogus"),
+            Err("unknown key: bogus".to_string())
+        );
+    }
+
+    #[test]
+    fn key_events_are_named() {
+        let name = |key, mods| key_event_name(&key, mods);
+        assert_eq!(
+            name(EventKey::Char('b'), Modifiers::CTRL).as_deref(),
+            Some("C-b")
+        );
+        assert_eq!(
+            name(EventKey::Char('B'), Modifiers::CTRL).as_deref(),
+            Some("C-b")
+        );
+        assert_eq!(
+            name(EventKey::Char('|'), Modifiers::SHIFT).as_deref(),
+            Some("|")
+        );
+        assert_eq!(
+            name(EventKey::LeftArrow, Modifiers::ALT | Modifiers::LEFT_ALT).as_deref(),
+            Some("M-Left")
+        );
+        assert_eq!(
+            name(EventKey::Char('\r'), Modifiers::NONE).as_deref(),
+            Some("Enter")
+        );
+        assert_eq!(name(EventKey::Char('c'), Modifiers::SUPER), None);
+    }
+
+    #[test]
+    fn bind_and_unbind() {
+        let mut store = KeyBindingStore::new();
+        store.bind("prefix", "|", Some("split-window -h".into()), false, None);
+        store.bind("prefix", "|", None, true, Some("Split".into()));
+        assert_eq!(
+            store.lookup("prefix", "|"),
+            Some(&Binding {
+                command: "split-window -h".into(),
+                repeat: true,
+                note: Some("Split".into()),
+            })
+        );
+
+        store.bind("resize", "h", Some("resize-pane -L".into()), true, None);
+        store.unbind("resize", "h").unwrap();
+        assert!(!store.has_table("resize"));
+        assert_eq!(
+            store.unbind("resize", "h"),
+            Err("table resize doesn't exist".to_string())
+        );
+
+        store.unbind_all("prefix").unwrap();
+        assert!(store.lookup("prefix", "|").is_none());
+        assert!(store.has_table("prefix"));
+        assert!(store.unbind_all("resize").is_err());
+    }
+
+    #[test]
+    fn prefix_starts_a_sequence_once_bound() {
+        let mut store = KeyBindingStore::new();
+        let prefixes = vec!["C-b".to_string()];
+        assert_eq!(store.resolve("root", "C-b", &prefixes, 500), None);
+
+        store.bind("prefix", "r", Some("resize-pane -R".into()), true, None);
+        store.bind("root", "M-h", Some("select-pane -L".into()), false, None);
+        assert_eq!(
+            store.resolve("root", "C-b", &prefixes, 500),
+            Some(KeyAction::Prefix)
+        );
+        assert_eq!(
+            store.resolve("prefix", "r", &prefixes, 500),
+            Some(KeyAction::Run {
+                command: "resize-pane -R".into(),
+                repeat_time: Some(500),
+            })
+        );
+        assert_eq!(
+            store.resolve("root", "M-h", &prefixes, 500),
+            Some(KeyAction::Run {
+                command: "select-pane -L".into(),
+                repeat_time: None,
+            })
+        );
+        assert_eq!(store.resolve("prefix", "C-b", &prefixes, 500), None);
+    }
+
+    #[test]
+    fn list_formats() {
+        let split = binding("split-window -h", false);
+        let resize = binding("resize-pane -U 5", true);
+        let lines = format_bindings(&[("prefix", "\"", &split), ("prefix", "M-Up", &resize)]);
+        assert_eq!(
+            lines,
+            vec![
+                "bind-key    -T prefix \\\"   split-window -h",
+                "bind-key -r -T prefix M-Up resize-pane -U 5",
+            ]
+        );
+
+        let noted = Binding {
+            note: Some("Split horizontally".into()),
+            ..split.clone()
+        };
+        let bindings = [("prefix", "|", &noted), ("root", "M-Up", &resize)];
+        assert_eq!(
+            format_notes(&bindings, "C-b ", false),
+            vec!["C-b | Split horizontally"]
+        );
+        assert_eq!(
+            format_notes(&bindings, "C-b ", true),
+            vec!["C-b | Split horizontally", "M-Up  resize-pane -U 5"]
+        );
+    }
+}
@@ -1,8 +1,8 @@
-//! Tmux layout string generator.
+//! Tmux layout strings.
 //!
 //! Generates tmux-format layout description strings from a tree of pane
 //! geometry nodes. These strings are sent in `%layout-change` notifications
-//! to tmux control mode clients.
+//! to tmux control mode clients, and parsed back by `select-layout`.
 //!
 //! ## Layout string format
 //!
@@ -16,13 +16,20 @@
 //! output as four lowercase hex digits.
 
 use std::fmt::Write;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use wezterm_term::TerminalSize;
+
+use crate::pane::{Pane, PaneId};
+use crate::tab::{SplitDirection, SplitDirectionAndSize, Tree};
 
 /// A tree node describing the geometry of a pane or a split container.
 ///
 /// Leaf nodes are [`LayoutNode::Pane`] entries with a concrete pane ID and
 /// position. Interior nodes are either horizontal splits (children arranged
 /// side by side) or vertical splits (children stacked top to bottom).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LayoutNode {
     /// A single terminal pane.
     Pane {
@@ -52,6 +59,74 @@ pub enum LayoutNode {
     },
 }
 
+impl LayoutNode {
+    pub fn width(&self) -> u64 {
+        match self {
+            Self::Pane { width, .. }
+            | Self::HorizontalSplit { width, .. }
+            | Self::VerticalSplit { width, .. } => *width,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        match self {
+            Self::Pane { height, .. }
+            | Self::HorizontalSplit { height, .. }
+            | Self::VerticalSplit { height, .. } => *height,
+        }
+    }
+
+    /// The pane IDs of the leaves, left to right and top to bottom.
+    pub fn pane_ids(&self) -> Vec<u64> {
+        let mut ids = Vec::new();
+        self.collect_pane_ids(&mut ids);
+        ids
+    }
+
+    fn collect_pane_ids(&self, ids: &mut Vec<u64>) {
+        match self {
+            Self::Pane { pane_id, .. } => ids.push(*pane_id),
+            Self::HorizontalSplit { children, .. } | Self::VerticalSplit { children, .. } => {
+                for child in children {
+                    child.collect_pane_ids(ids);
+                }
+            }
+        }
+    }
+
+    /// The size along one axis: the width if `horizontal`, else the height.
+    fn size_along(&self, horizontal: bool) -> u64 {
+        if horizontal {
+            self.width()
+        } else {
+            self.height()
+        }
+    }
+
+    fn size_along_mut(&mut self, horizontal: bool) -> &mut u64 {
+        match self {
+            Self::Pane { width, height, .. }
+            | Self::HorizontalSplit { width, height, .. }
+            | Self::VerticalSplit { width, height, .. } => {
+                if horizontal {
+                    width
+                } else {
+                    height
+                }
+            }
+        }
+    }
+
+    /// For a split, whether its children are side by side, and the children.
+    fn split_mut(&mut self) -> Option<(bool, &mut Vec<LayoutNode>)> {
+        match self {
+            Self::Pane { .. } => None,
+            Self::HorizontalSplit { children, .. } => Some((true, children)),
+            Self::VerticalSplit { children, .. } => Some((false, children)),
+        }
+    }
+}
+
 /// Compute the tmux layout checksum over a description string.
 ///
 /// This implements the same algorithm used by tmux itself: a 16-bit rotating
@@ -123,6 +198,448 @@ fn write_node(node: &LayoutNode, out: &mut String) {
     }
 }
 
+/// Parse a tmux layout string, `<checksum>,<description>`, into a
+/// [`LayoutNode`] tree.
+///
+/// The checksum must match the description, and the children of each split
+/// must fill it exactly, with one cell between neighbours. As in tmux, the
+/// pane IDs of the leaves are kept but the offsets are not checked.
+pub fn parse_layout_string(layout: &str) -> Result<LayoutNode> {
+    let (csum, desc) = layout
+        .split_once(',')
+        .ok_or_else(|| anyhow!("invalid layout"))?;
+    let csum = u16::from_str_radix(csum, 16).map_err(|_| anyhow!("invalid layout"))?;
+    if csum != layout_checksum(desc) {
+        bail!("layout checksum mismatch");
+    }
+
+    let mut parser = LayoutParser {
+        desc: desc.as_bytes(),
+        pos: 0,
+    };
+    let root = parser.node()?;
+    if parser.pos != desc.len() {
+        bail!("invalid layout: unexpected {:?}", &desc[parser.pos..]);
+    }
+    check_sizes(&root)?;
+    Ok(root)
+}
+
+/// Recursive descent over a layout description.
+struct LayoutParser<'a> {
+    desc: &'a [u8],
+    pos: usize,
+}
+
+impl LayoutParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.desc.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, ch: u8) -> Result<()> {
+        if self.peek() != Some(ch) {
+            bail!(
+                "invalid layout: expected {:?} at offset {}",
+                ch as char,
+                self.pos
+            );
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<u64> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.desc[start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| anyhow!("invalid layout: expected a number at offset {}", start))
+    }
+
+    /// `WxH,L,T` followed by `,ID` for a pane or `{...}` / `[...]` for a split.
+    fn node(&mut self) -> Result<LayoutNode> {
+        let width = self.number()?;
+        self.expect(b'x')?;
+        let height = self.number()?;
+        self.expect(b',')?;
+        let left = self.number()?;
+        self.expect(b',')?;
+        let top = self.number()?;
+        if width == 0 || height == 0 {
+            bail!("invalid layout: empty cell {}x{}", width, height);
+        }
+
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                let pane_id = self.number()?;
+                Ok(LayoutNode::Pane {
+                    pane_id,
+                    width,
+                    height,
+                    left,
+                    top,
+                })
+            }
+            Some(open @ b'{') | Some(open @ b'[') => {
+                self.pos += 1;
+                let mut children = vec![self.node()?];
+                while self.peek() == Some(b',') {
+                    self.pos += 1;
+                    children.push(self.node()?);
+                }
+                if open == b'{' {
+                    self.expect(b'}')?;
+                    Ok(LayoutNode::HorizontalSplit {
+                        width,
+                        height,
+                        left,
+                        top,
+                        children,
+                    })
+                } else {
+                    self.expect(b']')?;
+                    Ok(LayoutNode::VerticalSplit {
+                        width,
+                        height,
+                        left,
+                        top,
+                        children,
+                    })
+                }
+            }
+            _ => bail!(
+                "invalid layout: expected a pane or split at offset {}",
+                self.pos
+            ),
+        }
+    }
+}
+
+/// Check that the children of every split exactly fill it.
+fn check_sizes(node: &LayoutNode) -> Result<()> {
+    let (horizontal, children) = match node {
+        LayoutNode::Pane { .. } => return Ok(()),
+        LayoutNode::HorizontalSplit { children, .. } => (true, children),
+        LayoutNode::VerticalSplit { children, .. } => (false, children),
+    };
+    let mut total = children.len() as u64 - 1;
+    for child in children {
+        if child.size_along(!horizontal) != node.size_along(!horizontal) {
+            bail!("size mismatch after applying layout");
+        }
+        total += child.size_along(horizontal);
+        check_sizes(child)?;
+    }
+    if total != node.size_along(horizontal) {
+        bail!("size mismatch after applying layout");
+    }
+    Ok(())
+}
+
+/// Spread the panes of the split containing `pane_id` out evenly, as
+/// `select-layout -E` does. If they already are, the split containing that
+/// one is tried, and so on up to the root. Returns whether anything changed.
+pub fn spread_out(root: &mut LayoutNode, pane_id: u64) -> bool {
+    let path = match path_to_pane(root, pane_id) {
+        Some(path) => path,
+        None => return false,
+    };
+    for depth in (0..path.len()).rev() {
+        let mut parent = &mut *root;
+        for &idx in &path[..depth] {
+            parent = match parent.split_mut() {
+                Some((_, children)) => &mut children[idx],
+                None => return false,
+            };
+        }
+        if spread_split(parent) {
+            let (left, top) = match root {
+                LayoutNode::Pane { left, top, .. }
+                | LayoutNode::HorizontalSplit { left, top, .. }
+                | LayoutNode::VerticalSplit { left, top, .. } => (*left, *top),
+            };
+            fix_offsets(root, left, top);
+            return true;
+        }
+    }
+    false
+}
+
+/// The child indices leading from `node` to the pane `pane_id`.
+fn path_to_pane(node: &LayoutNode, pane_id: u64) -> Option<Vec<usize>> {
+    match node {
+        LayoutNode::Pane { pane_id: id, .. } => (*id == pane_id).then(Vec::new),
+        LayoutNode::HorizontalSplit { children, .. }
+        | LayoutNode::VerticalSplit { children, .. } => {
+            children.iter().enumerate().find_map(|(idx, child)| {
+                let mut path = path_to_pane(child, pane_id)?;
+                path.insert(0, idx);
+                Some(path)
+            })
+        }
+    }
+}
+
+/// Give the children of a split equal sizes, the last taking any remainder.
+fn spread_split(node: &mut LayoutNode) -> bool {
+    let (horizontal, size) = match node {
+        LayoutNode::Pane { .. } => return false,
+        LayoutNode::HorizontalSplit { width, .. } => (true, *width),
+        LayoutNode::VerticalSplit { height, .. } => (false, *height),
+    };
+    let children = match node.split_mut() {
+        Some((_, children)) => children,
+        None => return false,
+    };
+    let number = children.len() as u64;
+    if number <= 1 || size < number - 1 {
+        return false;
+    }
+    let mut each = (size - (number - 1)) / number;
+    if each == 0 {
+        return false;
+    }
+
+    let mut changed = false;
+    let last = children.len() - 1;
+    for (idx, child) in children.iter_mut().enumerate() {
+        if idx == last {
+            each = size - (each + 1) * (number - 1);
+        }
+        let change = each as i64 - child.size_along(horizontal) as i64;
+        if change != 0 {
+            resize_node(child, horizontal, change);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Grow or shrink `node` by `change` cells along one axis. A split along
+/// the same axis shares the change out between its children a cell at a
+/// time; a split across it passes the whole change to every child.
+fn resize_node(node: &mut LayoutNode, horizontal: bool, change: i64) {
+    let size = node.size_along_mut(horizontal);
+    *size = (*size as i64 + change).max(1) as u64;
+
+    let (side_by_side, children) = match node.split_mut() {
+        Some(split) => split,
+        None => return,
+    };
+    if side_by_side != horizontal {
+        for child in children.iter_mut() {
+            resize_node(child, horizontal, change);
+        }
+        return;
+    }
+
+    let mut remaining = change;
+    while remaining != 0 {
+        let before = remaining;
+        for child in children.iter_mut() {
+            if remaining > 0 {
+                resize_node(child, horizontal, 1);
+                remaining -= 1;
+            } else if remaining < 0 && child.size_along(horizontal) > 1 {
+                resize_node(child, horizontal, -1);
+                remaining += 1;
+            }
+            if remaining == 0 {
+                break;
+            }
+        }
+        if remaining == before {
+            break;
+        }
+    }
+}
+
+/// Recompute the offsets of `node` and its children from its own position.
+fn fix_offsets(node: &mut LayoutNode, new_left: u64, new_top: u64) {
+    match node {
+        LayoutNode::Pane { left, top, .. }
+        | LayoutNode::HorizontalSplit { left, top, .. }
+        | LayoutNode::VerticalSplit { left, top, .. } => {
+            *left = new_left;
+            *top = new_top;
+        }
+    }
+    if let Some((horizontal, children)) = node.split_mut() {
+        let (mut left, mut top) = (new_left, new_top);
+        for child in children.iter_mut() {
+            fix_offsets(child, left, top);
+            if horizontal {
+                left += child.width() + 1;
+            } else {
+                top += child.height() + 1;
+            }
+        }
+    }
+}
+
+/// Describe a tab's split tree, filling `size`, as a [`LayoutNode`].
+/// Chains of splits in the same direction become a single tmux split.
+pub fn layout_from_tree(
+    tree: &Tree,
+    size: &TerminalSize,
+    tmux_pane_id: &mut dyn FnMut(PaneId) -> u64,
+) -> LayoutNode {
+    node_from_tree(tree, size.cols as u64, size.rows as u64, 0, 0, tmux_pane_id)
+}
+
+fn node_from_tree(
+    tree: &Tree,
+    width: u64,
+    height: u64,
+    left: u64,
+    top: u64,
+    tmux_pane_id: &mut dyn FnMut(PaneId) -> u64,
+) -> LayoutNode {
+    let (first, second, data) = match tree {
+        Tree::Leaf(pane) => {
+            return LayoutNode::Pane {
+                pane_id: tmux_pane_id(pane.pane_id()),
+                width,
+                height,
+                left,
+                top,
+            }
+        }
+        Tree::Node {
+            left: first,
+            right: second,
+            data: Some(data),
+        } => (first, second, data),
+        // An empty tab still has a size to report.
+        Tree::Empty | Tree::Node { data: None, .. } => {
+            return LayoutNode::Pane {
+                pane_id: 0,
+                width,
+                height,
+                left,
+                top,
+            }
+        }
+    };
+
+    let horizontal = data.direction == SplitDirection::Horizontal;
+    let first_width = data.first.cols as u64;
+    let first_height = data.first.rows as u64;
+    let first = node_from_tree(first, first_width, first_height, left, top, tmux_pane_id);
+    let (second_left, second_top) = if horizontal {
+        (left + first_width + 1, top)
+    } else {
+        (left, top + first_height + 1)
+    };
+    let second = node_from_tree(
+        second,
+        data.second.cols as u64,
+        data.second.rows as u64,
+        second_left,
+        second_top,
+        tmux_pane_id,
+    );
+
+    let mut children = Vec::new();
+    for child in [first, second] {
+        match child {
+            LayoutNode::HorizontalSplit {
+                children: nested, ..
+            } if horizontal => children.extend(nested),
+            LayoutNode::VerticalSplit {
+                children: nested, ..
+            } if !horizontal => children.extend(nested),
+            other => children.push(other),
+        }
+    }
+    if horizontal {
+        LayoutNode::HorizontalSplit {
+            width,
+            height,
+            left,
+            top,
+            children,
+        }
+    } else {
+        LayoutNode::VerticalSplit {
+            width,
+            height,
+            left,
+            top,
+            children,
+        }
+    }
+}
+
+/// Build a tab split tree from a [`LayoutNode`]. `pane_for` is called for
+/// each leaf in order and supplies its pane; `cell` is the size of a single
+/// cell. Returns `None` if `pane_for` runs out of panes.
+pub fn tree_from_layout(
+    node: &LayoutNode,
+    cell: &TerminalSize,
+    pane_for: &mut dyn FnMut(u64) -> Option<Arc<dyn Pane>>,
+) -> Option<Tree> {
+    match node {
+        LayoutNode::Pane { pane_id, .. } => Some(Tree::Leaf(pane_for(*pane_id)?)),
+        LayoutNode::HorizontalSplit { children, .. } => {
+            chain_from_layout(children, SplitDirection::Horizontal, cell, pane_for)
+        }
+        LayoutNode::VerticalSplit { children, .. } => {
+            chain_from_layout(children, SplitDirection::Vertical, cell, pane_for)
+        }
+    }
+}
+
+/// Build a right-leaning chain of splits holding `children`.
+fn chain_from_layout(
+    children: &[LayoutNode],
+    direction: SplitDirection,
+    cell: &TerminalSize,
+    pane_for: &mut dyn FnMut(u64) -> Option<Arc<dyn Pane>>,
+) -> Option<Tree> {
+    let (first, rest) = children.split_first()?;
+    let first_tree = tree_from_layout(first, cell, pane_for)?;
+    if rest.is_empty() {
+        return Some(first_tree);
+    }
+
+    let gaps = rest.len() as u64 - 1;
+    let (second_width, second_height) = match direction {
+        SplitDirection::Horizontal => (
+            rest.iter().map(LayoutNode::width).sum::<u64>() + gaps,
+            first.height(),
+        ),
+        SplitDirection::Vertical => (
+            first.width(),
+            rest.iter().map(LayoutNode::height).sum::<u64>() + gaps,
+        ),
+    };
+    let second_tree = chain_from_layout(rest, direction, cell, pane_for)?;
+    Some(Tree::Node {
+        left: Box::new(first_tree),
+        right: Box::new(second_tree),
+        data: Some(SplitDirectionAndSize {
+            direction,
+            first: cells_to_size(first.width(), first.height(), cell),
+            second: cells_to_size(second_width, second_height, cell),
+        }),
+    })
+}
+
+fn cells_to_size(cols: u64, rows: u64, cell: &TerminalSize) -> TerminalSize {
+    TerminalSize {
+        cols: cols as usize,
+        rows: rows as usize,
+        pixel_width: cols as usize * cell.pixel_width,
+        pixel_height: rows as usize * cell.pixel_height,
+        dpi: cell.dpi,
+    }
+}
+
 /// Write a comma-separated list of child node descriptions.
 fn write_children(children: &[LayoutNode], out: &mut String) {
     for (i, child) in children.iter().enumerate() {
@@ -585,4 +1102,102 @@ mod tests {
         let comma_pos = result.find(',').unwrap();
         assert_eq!(comma_pos, 4);
     }
+
+    // -------------------------------------------------------------------
+    // Parsing tests
+    // -------------------------------------------------------------------
+
+    /// Prefix a layout description with its checksum.
+    fn with_checksum(desc: &str) -> String {
+        format!("{:04x},{}", layout_checksum(desc), desc)
+    }
+
+    #[test]
+    fn parse_single_pane() {
+        let node = parse_layout_string("b25d,80x24,0,0,0").unwrap();
+        assert_eq!(
+            node,
+            LayoutNode::Pane {
+                pane_id: 0,
+                width: 80,
+                height: 24,
+                left: 0,
+                top: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_round_trips_nested_layout() {
+        let full = with_checksum("159x48,0,0{79x48,0,0,0,79x48,80,0[79x24,80,0,1,79x23,80,25,2]}");
+        let node = parse_layout_string(&full).unwrap();
+        assert_eq!(node.pane_ids(), vec![0, 1, 2]);
+        assert_eq!(generate_layout_string(&node), full);
+    }
+
+    #[test]
+    fn parse_rejects_checksum_mismatch() {
+        let err = parse_layout_string("0000,80x24,0,0,0").unwrap_err();
+        assert_eq!(err.to_string(), "layout checksum mismatch");
+    }
+
+    #[test]
+    fn parse_rejects_bad_sizes() {
+        // The children are one cell too wide for their parent.
+        let full = with_checksum("80x24,0,0{40x24,0,0,0,40x24,41,0,1}");
+        let err = parse_layout_string(&full).unwrap_err();
+        assert_eq!(err.to_string(), "size mismatch after applying layout");
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        for layout in [
+            "80x24,0,0,0junk",
+            "80x24,0,0",
+            "80x24,0,0{40x24,0,0,0",
+            "x24,0,0,0",
+        ] {
+            assert!(
+                parse_layout_string(&with_checksum(layout)).is_err(),
+                "accepted {}",
+                layout
+            );
+        }
+        assert!(parse_layout_string("main-vertical").is_err());
+    }
+
+    // -------------------------------------------------------------------
+    // Spreading tests
+    // -------------------------------------------------------------------
+
+    #[test]
+    fn spread_out_evens_the_split_holding_the_pane() {
+        let full = with_checksum("80x24,0,0{20x24,0,0,0,59x24,21,0,1}");
+        let mut node = parse_layout_string(&full).unwrap();
+        assert!(spread_out(&mut node, 1));
+        assert_eq!(
+            generate_layout_string(&node),
+            with_checksum("80x24,0,0{39x24,0,0,0,40x24,40,0,1}")
+        );
+        // Already even, so nothing changes a second time.
+        assert!(!spread_out(&mut node, 1));
+    }
+
+    #[test]
+    fn spread_out_moves_up_to_the_parent() {
+        // The inner vertical split is already even, the outer one is not.
+        let full = with_checksum("80x24,0,0{20x24,0,0,0,59x24,21,0[59x11,21,0,1,59x12,21,12,2]}");
+        let mut node = parse_layout_string(&full).unwrap();
+        assert!(spread_out(&mut node, 2));
+        match &node {
+            LayoutNode::HorizontalSplit { children, .. } => {
+                assert_eq!(children[0].width(), 39);
+                assert_eq!(children[1].width(), 40);
+                assert_eq!(children[1].pane_ids(), vec![1, 2]);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(check_sizes(&node).is_ok());
+        assert!(!spread_out(&mut node, 99));
+    }
 }
@@ -4,12 +4,25 @@
 //! Agent Teams can use their existing tmux integration (split-window, send-keys,
 //! capture-pane, list-panes) natively in WezTerm.
 
+pub mod alerts;
+pub mod auth;
+pub mod choose;
 pub mod command_parser;
+pub mod copy_mode;
+pub mod environment;
 pub mod format;
 pub mod handlers;
+pub mod hooks;
 pub mod id_map;
+pub mod key_bindings;
 pub mod layout;
+pub mod named_server;
+pub mod options;
 pub mod paste_buffer;
+pub mod pipe_pane;
+pub mod popup;
 pub mod response;
 pub mod server;
+pub mod split_drag;
 pub mod target;
+pub mod wait_for;
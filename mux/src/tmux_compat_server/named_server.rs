@@ -0,0 +1,178 @@
+//! Named servers for `tmux -L` and `tmux -S`.
+//!
+//! tmux runs one server per socket, and tools that want to keep out of the
+//! user's sessions start one of their own with `-L name` or `-S path`. Here
+//! every server shares the one mux: a named server is an extra CC listener
+//! on the socket tmux would use, and its clients only see the sessions that
+//! were created through it. All other sessions belong to the default
+//! server, the one in `WEZTERM_TMUX_CC`.
+//!
+//! Named servers are identified by their socket path. Like tmux, each
+//! server has its own options, hooks, buffers, environment, key bindings and
+//! `wait-for` channels, kept in a [`PerServer`] store.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::LazyLock;
+
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// The sessions (workspace names) of each running named server.
+static SERVERS: LazyLock<Mutex<HashMap<String, HashSet<String>>>> = LazyLock::new(Default::default);
+
+/// Whether the named server at `path` is running.
+pub fn is_running(path: &str) -> bool {
+    SERVERS.lock().contains_key(path)
+}
+
+/// Record that the named server at `path` has started, with no sessions.
+pub fn register(path: &str) {
+    SERVERS.lock().entry(path.to_string()).or_default();
+}
+
+/// Forget the named server at `path`, returning its sessions.
+pub fn unregister(path: &str) -> HashSet<String> {
+    SERVERS.lock().remove(path).unwrap_or_default()
+}
+
+/// Make `workspace` a session of `server`, or of the default server for
+/// `None`, taking it from whichever server had it before.
+pub fn add_session(server: Option<&str>, workspace: &str) {
+    let mut servers = SERVERS.lock();
+    for sessions in servers.values_mut() {
+        sessions.remove(workspace);
+    }
+    if let Some(sessions) = server.and_then(|path| servers.get_mut(path)) {
+        sessions.insert(workspace.to_string());
+    }
+}
+
+/// Follow a session to its new name.
+pub fn rename_session(old: &str, new: &str) {
+    for sessions in SERVERS.lock().values_mut() {
+        if sessions.remove(old) {
+            sessions.insert(new.to_string());
+        }
+    }
+}
+
+/// The named server `workspace` belongs to, or `None` for the default
+/// server.
+pub fn owner(workspace: &str) -> Option<String> {
+    SERVERS
+        .lock()
+        .iter()
+        .find(|(_, sessions)| sessions.contains(workspace))
+        .map(|(path, _)| path.clone())
+}
+
+/// Pick the sessions of `server` out of `workspaces`, keeping their order.
+pub fn sessions(server: Option<&str>, workspaces: Vec<String>) -> Vec<String> {
+    let servers = SERVERS.lock();
+    workspaces
+        .into_iter()
+        .filter(|workspace| match server {
+            Some(path) => servers
+                .get(path)
+                .is_some_and(|sessions| sessions.contains(workspace)),
+            None => !servers
+                .values()
+                .any(|sessions| sessions.contains(workspace)),
+        })
+        .collect()
+}
+
+/// State kept separately for the default server and each named server.
+pub struct PerServer<T>(Mutex<BTreeMap<Option<String>, T>>);
+
+impl<T> Default for PerServer<T> {
+    fn default() -> Self {
+        Self(Mutex::new(BTreeMap::new()))
+    }
+}
+
+impl<T: Default> PerServer<T> {
+    /// Lock the state of `server`, or of the default server for `None`.
+    pub fn lock(&self, server: Option<&str>) -> MappedMutexGuard<'_, T> {
+        MutexGuard::map(self.0.lock(), |servers| {
+            servers.entry(server.map(str::to_string)).or_default()
+        })
+    }
+
+    /// Run `f` on the state of every server, e.g. to follow a session or
+    /// pane that could belong to any of them.
+    pub fn for_each(&self, f: impl FnMut(&mut T)) {
+        self.0.lock().values_mut().for_each(f);
+    }
+
+    /// Whether `f` holds for the state of any server.
+    pub fn any(&self, f: impl FnMut(&T) -> bool) -> bool {
+        self.0.lock().values().any(f)
+    }
+
+    /// Drop the state of the named server at `path`, once it has stopped.
+    pub fn remove(&self, path: &str) {
+        self.0.lock().remove(&Some(path.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn sessions_are_split_between_servers() {
+        let path = "/tmp/named-server-test-split";
+        register(path);
+        assert!(is_running(path));
+        add_session(Some(path), "ns-agents");
+
+        let all = names(&["ns-main", "ns-agents", "ns-other"]);
+        assert_eq!(sessions(Some(path), all.clone()), names(&["ns-agents"]));
+        assert_eq!(sessions(None, all), names(&["ns-main", "ns-other"]));
+        assert_eq!(owner("ns-agents").as_deref(), Some(path));
+        assert_eq!(owner("ns-main"), None);
+
+        assert_eq!(unregister(path), ["ns-agents".to_string()].into());
+        assert!(!is_running(path));
+        assert_eq!(owner("ns-agents"), None);
+    }
+
+    #[test]
+    fn sessions_move_and_rename() {
+        let path = "/tmp/named-server-test-move";
+        register(path);
+        add_session(Some(path), "nm-work");
+        rename_session("nm-work", "nm-renamed");
+        assert_eq!(owner("nm-renamed").as_deref(), Some(path));
+
+        // A session of the same name made on the default server takes
+        // the name back.
+        add_session(None, "nm-renamed");
+        assert_eq!(owner("nm-renamed"), None);
+
+        // Nothing is recorded for a server that isn't running.
+        add_session(Some("/tmp/named-server-test-absent"), "nm-lost");
+        assert_eq!(owner("nm-lost"), None);
+        unregister(path);
+    }
+
+    #[test]
+    fn per_server_state_is_separate() {
+        let store: PerServer<Vec<u32>> = PerServer::default();
+        store.lock(None).push(1);
+        store.lock(Some("/tmp/a")).push(2);
+        assert_eq!(*store.lock(None), vec![1]);
+        assert_eq!(*store.lock(Some("/tmp/a")), vec![2]);
+        assert!(store.any(|state| state.contains(&2)));
+
+        store.for_each(|state| state.push(3));
+        assert_eq!(*store.lock(None), vec![1, 3]);
+
+        store.remove("/tmp/a");
+        assert!(store.lock(Some("/tmp/a")).is_empty());
+    }
+}
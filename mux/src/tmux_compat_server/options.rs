@@ -0,0 +1,460 @@
+//! Option store for `set-option` / `show-options`.
+//!
+//! Mirrors tmux's option tree. Server options stand alone; each session
+//! (WezTerm workspace) inherits from the global session options; each window
+//! (WezTerm tab) inherits from the global window options, and each pane
+//! inherits from its window. A lookup walks the chain from the most specific
+//! scope to the global one and returns the first value found.
+//!
+//! Built-in options belong to one table (server, session or window) and are
+//! seeded with tmux's defaults. User options (`@name`) can be set at any
+//! scope and have no default.
+//!
+//! There is one store per server, shared by every CC session of that server,
+//! returned by [`options`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+
+use parking_lot::MappedMutexGuard;
+
+use crate::pane::PaneId;
+use crate::tab::TabId;
+
+use super::named_server::PerServer;
+
+/// The table a built-in option lives in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionTable {
+    Server,
+    Session,
+    Window,
+}
+
+/// A place an option value can be stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OptionScope {
+    Server,
+    GlobalSession,
+    Session(String),
+    GlobalWindow,
+    Window(TabId),
+    Pane(PaneId),
+}
+
+impl OptionScope {
+    /// The scopes searched when reading an option at this scope, most
+    /// specific first. `window` is the tab containing a pane; it is ignored
+    /// for every other scope.
+    pub fn chain(&self, window: Option<TabId>) -> Vec<OptionScope> {
+        match self {
+            OptionScope::Server => vec![OptionScope::Server],
+            OptionScope::GlobalSession => vec![OptionScope::GlobalSession],
+            OptionScope::Session(_) => vec![self.clone(), OptionScope::GlobalSession],
+            OptionScope::GlobalWindow => vec![OptionScope::GlobalWindow],
+            OptionScope::Window(_) => vec![self.clone(), OptionScope::GlobalWindow],
+            OptionScope::Pane(_) => {
+                let mut chain = vec![self.clone()];
+                chain.extend(window.map(OptionScope::Window));
+                chain.push(OptionScope::GlobalWindow);
+                chain
+            }
+        }
+    }
+
+    /// Whether this is one of the global scopes, which hold the defaults.
+    pub fn is_global(&self) -> bool {
+        matches!(
+            self,
+            OptionScope::Server | OptionScope::GlobalSession | OptionScope::GlobalWindow
+        )
+    }
+}
+
+/// Built-in options and their defaults, by table.
+const DEFAULTS: &[(OptionTable, &str, &str)] = &[
+    (OptionTable::Server, "buffer-limit", "50"),
+    (OptionTable::Server, "copy-command", ""),
+    (OptionTable::Server, "escape-time", "500"),
+    (OptionTable::Server, "exit-empty", "on"),
+    (OptionTable::Server, "focus-events", "on"),
+    (OptionTable::Server, "set-clipboard", "on"),
+    (OptionTable::Session, "activity-action", "other"),
+    (OptionTable::Session, "base-index", "0"),
+    (OptionTable::Session, "bell-action", "any"),
+    (OptionTable::Session, "default-shell", "/bin/sh"),
+    (OptionTable::Session, "default-terminal", "screen-256color"),
+    (OptionTable::Session, "display-time", "750"),
+    (OptionTable::Session, "history-limit", "2000"),
+    (OptionTable::Session, "mouse", "off"),
+    (OptionTable::Session, "prefix", "C-b"),
+    (OptionTable::Session, "prefix2", "None"),
+    (OptionTable::Session, "renumber-windows", "off"),
+    (OptionTable::Session, "repeat-time", "500"),
+    (OptionTable::Session, "set-titles", "off"),
+    (OptionTable::Session, "silence-action", "other"),
+    (OptionTable::Session, "status", "off"),
+    (OptionTable::Session, "status-keys", "emacs"),
+    (
+        OptionTable::Session,
+        "update-environment",
+        "DISPLAY KRB5CCNAME SSH_ASKPASS SSH_AUTH_SOCK SSH_AGENT_PID SSH_CONNECTION WINDOWID \
+         XAUTHORITY",
+    ),
+    (OptionTable::Session, "visual-activity", "off"),
+    (OptionTable::Session, "visual-bell", "off"),
+    (OptionTable::Session, "visual-silence", "off"),
+    (OptionTable::Window, "aggressive-resize", "off"),
+    (OptionTable::Window, "allow-rename", "on"),
+    (OptionTable::Window, "automatic-rename", "on"),
+    (OptionTable::Window, "mode-keys", "emacs"),
+    (OptionTable::Window, "monitor-activity", "off"),
+    (OptionTable::Window, "monitor-bell", "on"),
+    (OptionTable::Window, "monitor-silence", "0"),
+    (OptionTable::Window, "pane-base-index", "0"),
+    (
+        OptionTable::Window,
+        "pane-border-format",
+        "#{?pane_active,#[reverse],}#{pane_index}#[default] \"#{pane_title}\"",
+    ),
+    (OptionTable::Window, "pane-border-status", "off"),
+    (OptionTable::Window, "remain-on-exit", "off"),
+    (OptionTable::Window, "synchronize-panes", "off"),
+];
+
+/// The table a built-in option belongs to, or `None` for user options and
+/// names tmux doesn't know.
+pub fn option_table(name: &str) -> Option<OptionTable> {
+    DEFAULTS
+        .iter()
+        .find(|(_, n, _)| *n == name)
+        .map(|(table, _, _)| *table)
+}
+
+/// The default value of a built-in option.
+pub fn default_value(name: &str) -> Option<&'static str> {
+    DEFAULTS
+        .iter()
+        .find(|(_, n, _)| *n == name)
+        .map(|(_, _, value)| *value)
+}
+
+/// Whether `name` is a user option (`@name`).
+pub fn is_user_option(name: &str) -> bool {
+    name.starts_with('@')
+}
+
+/// Option values, keyed by scope and then by option name.
+#[derive(Debug, Clone)]
+pub struct OptionStore {
+    values: HashMap<OptionScope, BTreeMap<String, String>>,
+}
+
+impl Default for OptionStore {
+    fn default() -> Self {
+        let mut store = Self {
+            values: HashMap::new(),
+        };
+        for (table, name, value) in DEFAULTS {
+            store.set(&global_scope(*table), name, value.to_string());
+        }
+        store
+    }
+}
+
+/// The global scope holding the defaults for `table`.
+pub fn global_scope(table: OptionTable) -> OptionScope {
+    match table {
+        OptionTable::Server => OptionScope::Server,
+        OptionTable::Session => OptionScope::GlobalSession,
+        OptionTable::Window => OptionScope::GlobalWindow,
+    }
+}
+
+impl OptionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value set at exactly `scope`, ignoring inheritance.
+    pub fn get_local(&self, scope: &OptionScope, name: &str) -> Option<&str> {
+        self.values
+            .get(scope)
+            .and_then(|opts| opts.get(name))
+            .map(|v| v.as_str())
+    }
+
+    /// The first value found along `chain`.
+    pub fn lookup(&self, chain: &[OptionScope], name: &str) -> Option<&str> {
+        chain.iter().find_map(|scope| self.get_local(scope, name))
+    }
+
+    pub fn set(&mut self, scope: &OptionScope, name: &str, value: String) {
+        self.values
+            .entry(scope.clone())
+            .or_default()
+            .insert(name.to_string(), value);
+    }
+
+    /// Remove the value set at `scope`. Like tmux, unsetting a built-in
+    /// option at its global scope restores the default rather than removing
+    /// it. Returns `true` if anything was set.
+    pub fn unset(&mut self, scope: &OptionScope, name: &str) -> bool {
+        let removed = self
+            .values
+            .get_mut(scope)
+            .and_then(|opts| opts.remove(name))
+            .is_some();
+        if let (Some(table), Some(value)) = (option_table(name), default_value(name)) {
+            if global_scope(table) == *scope {
+                self.set(scope, name, value.to_string());
+            }
+        }
+        removed
+    }
+
+    /// Every option set at exactly `scope`, sorted by name.
+    pub fn local(&self, scope: &OptionScope) -> Vec<(&str, &str)> {
+        self.values
+            .get(scope)
+            .map(|opts| opts.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect())
+            .unwrap_or_default()
+    }
+
+    /// Every option visible along `chain`, sorted by name. The flag is `true`
+    /// when the value was inherited from a scope other than the first.
+    pub fn merged(&self, chain: &[OptionScope]) -> Vec<(&str, &str, bool)> {
+        let mut merged: BTreeMap<&str, (&str, bool)> = BTreeMap::new();
+        for (depth, scope) in chain.iter().enumerate() {
+            for (name, value) in self.local(scope) {
+                merged.entry(name).or_insert((value, depth > 0));
+            }
+        }
+        merged
+            .into_iter()
+            .map(|(name, (value, inherited))| (name, value, inherited))
+            .collect()
+    }
+
+    /// User options visible to a pane, for `#{@name}` in formats. tmux
+    /// searches the pane's options, then its window's, then its session's,
+    /// then the server's.
+    pub fn format_user_options(
+        &self,
+        pane: Option<PaneId>,
+        window: Option<TabId>,
+        session: &str,
+    ) -> HashMap<String, String> {
+        let mut chain = Vec::new();
+        chain.extend(pane.map(OptionScope::Pane));
+        chain.extend(window.map(OptionScope::Window));
+        chain.push(OptionScope::GlobalWindow);
+        chain.push(OptionScope::Session(session.to_string()));
+        chain.push(OptionScope::GlobalSession);
+        chain.push(OptionScope::Server);
+
+        self.merged(&chain)
+            .into_iter()
+            .filter(|(name, _, _)| is_user_option(name))
+            .map(|(name, value, _)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Drop everything set at `scope`, e.g. when its pane or tab goes away.
+    pub fn remove_scope(&mut self, scope: &OptionScope) {
+        if !scope.is_global() {
+            self.values.remove(scope);
+        }
+    }
+}
+
+/// The option store of each server.
+pub(super) static OPTIONS: LazyLock<PerServer<OptionStore>> = LazyLock::new(Default::default);
+
+/// Lock the option store of `server`, or of the default server for `None`.
+pub fn options(server: Option<&str>) -> MappedMutexGuard<'static, OptionStore> {
+    OPTIONS.lock(server)
+}
+
+/// Quote an option value the way `show-options` prints it: bare when it is
+/// a single word, otherwise in double quotes with `"` and `\` escaped.
+pub fn quote_option_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || "\"'#;$\\{}".contains(c));
+    if !needs_quotes {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' || c == '$' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_seeded_in_global_scopes() {
+        let store = OptionStore::new();
+        assert_eq!(
+            store.get_local(&OptionScope::Server, "escape-time"),
+            Some("500")
+        );
+        assert_eq!(
+            store.get_local(&OptionScope::GlobalSession, "base-index"),
+            Some("0")
+        );
+        assert_eq!(
+            store.get_local(&OptionScope::GlobalWindow, "mode-keys"),
+            Some("emacs")
+        );
+        assert_eq!(
+            store.get_local(&OptionScope::GlobalSession, "escape-time"),
+            None
+        );
+    }
+
+    #[test]
+    fn option_tables() {
+        assert_eq!(option_table("buffer-limit"), Some(OptionTable::Server));
+        assert_eq!(option_table("status"), Some(OptionTable::Session));
+        assert_eq!(option_table("remain-on-exit"), Some(OptionTable::Window));
+        assert_eq!(option_table("@mine"), None);
+        assert_eq!(option_table("not-an-option"), None);
+    }
+
+    #[test]
+    fn session_inherits_from_global() {
+        let mut store = OptionStore::new();
+        let session = OptionScope::Session("work".to_string());
+        let chain = session.chain(None);
+        assert_eq!(store.lookup(&chain, "status"), Some("off"));
+
+        store.set(&session, "status", "on".to_string());
+        assert_eq!(store.lookup(&chain, "status"), Some("on"));
+        // Other sessions still see the global value.
+        let other = OptionScope::Session("other".to_string()).chain(None);
+        assert_eq!(store.lookup(&other, "status"), Some("off"));
+    }
+
+    #[test]
+    fn pane_inherits_from_window_then_global() {
+        let mut store = OptionStore::new();
+        let chain = OptionScope::Pane(7).chain(Some(3));
+        assert_eq!(
+            chain,
+            vec![
+                OptionScope::Pane(7),
+                OptionScope::Window(3),
+                OptionScope::GlobalWindow
+            ]
+        );
+        assert_eq!(store.lookup(&chain, "remain-on-exit"), Some("off"));
+
+        store.set(&OptionScope::Window(3), "remain-on-exit", "on".to_string());
+        assert_eq!(store.lookup(&chain, "remain-on-exit"), Some("on"));
+
+        store.set(
+            &OptionScope::Pane(7),
+            "remain-on-exit",
+            "failed".to_string(),
+        );
+        assert_eq!(store.lookup(&chain, "remain-on-exit"), Some("failed"));
+    }
+
+    #[test]
+    fn unset_global_restores_default() {
+        let mut store = OptionStore::new();
+        store.set(&OptionScope::Server, "escape-time", "10".to_string());
+        assert!(store.unset(&OptionScope::Server, "escape-time"));
+        assert_eq!(
+            store.get_local(&OptionScope::Server, "escape-time"),
+            Some("500")
+        );
+
+        store.set(&OptionScope::GlobalSession, "@theme", "dark".to_string());
+        assert!(store.unset(&OptionScope::GlobalSession, "@theme"));
+        assert_eq!(store.get_local(&OptionScope::GlobalSession, "@theme"), None);
+    }
+
+    #[test]
+    fn unset_local_falls_back_to_inherited() {
+        let mut store = OptionStore::new();
+        let window = OptionScope::Window(1);
+        store.set(&window, "mode-keys", "vi".to_string());
+        assert!(store.unset(&window, "mode-keys"));
+        assert!(!store.unset(&window, "mode-keys"));
+        assert_eq!(
+            store.lookup(&window.chain(None), "mode-keys"),
+            Some("emacs")
+        );
+    }
+
+    #[test]
+    fn merged_marks_inherited_values() {
+        let mut store = OptionStore::new();
+        let window = OptionScope::Window(1);
+        store.set(&window, "mode-keys", "vi".to_string());
+        let merged = store.merged(&window.chain(None));
+        assert!(merged.contains(&("mode-keys", "vi", false)));
+        assert!(merged.contains(&("aggressive-resize", "off", true)));
+    }
+
+    #[test]
+    fn format_user_options_most_specific_wins() {
+        let mut store = OptionStore::new();
+        store.set(&OptionScope::Server, "@who", "server".to_string());
+        store.set(
+            &OptionScope::Session("work".to_string()),
+            "@who",
+            "session".to_string(),
+        );
+        store.set(
+            &OptionScope::GlobalSession,
+            "@only-global",
+            "yes".to_string(),
+        );
+        store.set(&OptionScope::Pane(4), "@who", "pane".to_string());
+
+        let opts = store.format_user_options(Some(4), Some(2), "work");
+        assert_eq!(opts.get("@who").map(String::as_str), Some("pane"));
+        assert_eq!(opts.get("@only-global").map(String::as_str), Some("yes"));
+        assert!(!opts.contains_key("status"));
+
+        let opts = store.format_user_options(Some(5), Some(2), "work");
+        assert_eq!(opts.get("@who").map(String::as_str), Some("session"));
+    }
+
+    #[test]
+    fn remove_scope_keeps_globals() {
+        let mut store = OptionStore::new();
+        store.set(&OptionScope::Pane(1), "@x", "1".to_string());
+        store.remove_scope(&OptionScope::Pane(1));
+        assert!(store.local(&OptionScope::Pane(1)).is_empty());
+        store.remove_scope(&OptionScope::Server);
+        assert_eq!(
+            store.get_local(&OptionScope::Server, "escape-time"),
+            Some("500")
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote_option_value("screen-256color"), "screen-256color");
+        assert_eq!(quote_option_value(""), "\"\"");
+        assert_eq!(quote_option_value("a b"), "\"a b\"");
+        assert_eq!(
+            quote_option_value("#{pane_title} \"x\""),
+            "\"#{pane_title} \\\"x\\\"\""
+        );
+    }
+}
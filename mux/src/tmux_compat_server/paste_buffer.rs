@@ -1,11 +1,32 @@
 //! In-process paste buffer store for tmux CC compatibility.
 //!
 //! Models tmux's named paste buffer stack. Auto-named buffers (`buffer0`,
-//! `buffer1`, ...) are capped at `BUFFER_LIMIT`; user-named buffers are
-//! unlimited. Buffers are ordered by insertion time (most recent first).
+//! `buffer1`, ...) are capped at the `buffer-limit` option (50 by default);
+//! user-named buffers are unlimited. Buffers are ordered by insertion time
+//! (most recent first).
+//!
+//! Like tmux, there is one set of buffers per server: the store returned by
+//! [`paste_buffers`] is shared by every CC session of the server, so a
+//! `set-buffer` from one shim invocation is visible to the next.
+
+use std::sync::LazyLock;
+
+use parking_lot::MappedMutexGuard;
+
+use super::named_server::PerServer;
+
+/// Default number of auto-named buffers kept before the oldest is evicted.
+pub const DEFAULT_BUFFER_LIMIT: usize = 50;
 
-/// Maximum number of auto-named buffers before the oldest is evicted.
-const BUFFER_LIMIT: usize = 50;
+/// The paste buffer store of each server.
+pub(super) static PASTE_BUFFERS: LazyLock<PerServer<PasteBufferStore>> =
+    LazyLock::new(Default::default);
+
+/// Lock the paste buffer store of `server`, or of the default server for
+/// `None`.
+pub fn paste_buffers(server: Option<&str>) -> MappedMutexGuard<'static, PasteBufferStore> {
+    PASTE_BUFFERS.lock(server)
+}
 
 /// A single paste buffer entry.
 #[derive(Debug, Clone)]
@@ -18,11 +39,23 @@ pub struct PasteBuffer {
 }
 
 /// Ordered collection of paste buffers, keyed by name.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct PasteBufferStore {
     buffers: Vec<PasteBuffer>,
     next_order: u64,
     next_auto_index: u64,
+    limit: usize,
+}
+
+impl Default for PasteBufferStore {
+    fn default() -> Self {
+        Self {
+            buffers: Vec::new(),
+            next_order: 0,
+            next_auto_index: 0,
+            limit: DEFAULT_BUFFER_LIMIT,
+        }
+    }
 }
 
 impl PasteBufferStore {
@@ -30,6 +63,18 @@ impl PasteBufferStore {
         Self::default()
     }
 
+    /// The current `buffer-limit`.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Change the `buffer-limit`, evicting old automatic buffers if the new
+    /// limit is lower than the number currently stored.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.enforce_limit();
+    }
+
     /// Insert or replace a buffer. If `name` is `None`, auto-assign a name.
     /// Returns the buffer name used.
     pub fn set(&mut self, name: Option<&str>, data: String) -> String {
@@ -61,6 +106,19 @@ impl PasteBufferStore {
         buf_name
     }
 
+    /// Store text that was copied to the system clipboard as a new automatic
+    /// buffer and return its name.
+    ///
+    /// Every CC session sees the same `AssignClipboard` notification, and
+    /// `set-buffer -w` echoes its own buffer back through it, so nothing is
+    /// stored when the text already matches the most recent buffer.
+    pub fn set_from_clipboard(&mut self, data: String) -> String {
+        match self.most_recent() {
+            Some(buf) if buf.data == data => buf.name.clone(),
+            _ => self.set(None, data),
+        }
+    }
+
     /// Append data to an existing buffer. Returns `Err` if the buffer doesn't
     /// exist.
     pub fn append(&mut self, name: &str, data: &str) -> Result<(), String> {
@@ -117,7 +175,7 @@ impl PasteBufferStore {
     /// Evict oldest automatic buffers when limit is exceeded.
     fn enforce_limit(&mut self) {
         let auto_count = self.buffers.iter().filter(|b| b.automatic).count();
-        if auto_count <= BUFFER_LIMIT {
+        if auto_count <= self.limit {
             return;
         }
         // Find oldest automatic buffers to evict.
@@ -129,7 +187,7 @@ impl PasteBufferStore {
             .map(|(i, b)| (i, b.order))
             .collect();
         auto_bufs.sort_by_key(|&(_, order)| order);
-        let to_remove = auto_count - BUFFER_LIMIT;
+        let to_remove = auto_count - self.limit;
         let remove_indices: Vec<usize> =
             auto_bufs.iter().take(to_remove).map(|&(i, _)| i).collect();
         // Remove in reverse index order to preserve indices.
@@ -273,6 +331,34 @@ mod tests {
         assert!(store.get("keep_me").is_some());
     }
 
+    #[test]
+    fn set_limit_evicts_oldest_automatic() {
+        let mut store = PasteBufferStore::new();
+        store.set(Some("keep_me"), "important".to_string());
+        for i in 0..5 {
+            store.set(None, i.to_string());
+        }
+        store.set_limit(2);
+        assert_eq!(store.limit(), 2);
+        let names: Vec<&str> = store.list().iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["buffer4", "buffer3", "keep_me"]);
+
+        store.set(None, "x".to_string());
+        assert!(store.get("buffer3").is_none());
+        assert!(store.get("buffer5").is_some());
+    }
+
+    #[test]
+    fn set_from_clipboard_skips_duplicate() {
+        let mut store = PasteBufferStore::new();
+        assert_eq!(store.set_from_clipboard("hello".to_string()), "buffer0");
+        // A second session handling the same notification reuses the buffer.
+        assert_eq!(store.set_from_clipboard("hello".to_string()), "buffer0");
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.set_from_clipboard("world".to_string()), "buffer1");
+        assert_eq!(store.len(), 2);
+    }
+
     #[test]
     fn buffer_sample_short() {
         assert_eq!(buffer_sample("hello"), "hello");
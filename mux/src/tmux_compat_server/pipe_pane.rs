@@ -0,0 +1,400 @@
+//! `pipe-pane`: a shell command that is sent what a pane prints (`-O`),
+//! whose own output is typed into the pane (`-I`), or both.
+//!
+//! A pipe belongs to its pane rather than to the client that opened it, so
+//! logging a pane to a file carries on once a one-shot `tmux` command has
+//! exited. It lasts until `pipe-pane` closes or replaces it, its command
+//! exits, or the pane goes away. Closing a pipe first writes what the pane
+//! printed before then to the command and ends its input, and gives the
+//! command [`PIPE_CLOSE_TIMEOUT`] to exit, so that a transcript is complete
+//! by the time `kill-pane` returns. Killing a window, session or server
+//! closes the pipes of its panes together, within the same timeout. The
+//! waiting is done on a thread of its own, which the command awaits, so the
+//! main thread carries on meanwhile.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::pane::PaneId;
+use crate::Mux;
+
+/// How long a closed pipe's command has to finish with its input and exit
+/// before it is killed.
+pub const PIPE_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often the thread feeding a command checks whether its pipe has been
+/// closed while the pane is quiet.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where the output of a pane comes from: the pane's output tap.
+type OutputSource = Receiver<(Vec<u8>, Instant)>;
+
+/// Where a `-I` command's output goes: returns `false` once it can't be
+/// written any more.
+type InputSink = Box<dyn FnMut(&[u8]) -> bool + Send>;
+
+/// A running `pipe-pane` command.
+struct PanePipe {
+    child: Child,
+    /// Set when the pipe is closed, so that the threads stop feeding it.
+    closing: Arc<AtomicBool>,
+    /// Signalled by the `-O` thread once it has written everything and
+    /// closed the command's input.
+    output_done: Option<Receiver<()>>,
+}
+
+impl PanePipe {
+    /// Run `command` with a shell, sending it what arrives from `output`,
+    /// and passing what it prints to `input`.
+    fn spawn(
+        command: &str,
+        output: Option<OutputSource>,
+        input: Option<InputSink>,
+    ) -> std::io::Result<Self> {
+        #[cfg(windows)]
+        let (shell, shell_flag) = ("cmd.exe", "/C");
+        #[cfg(not(windows))]
+        let (shell, shell_flag) = ("sh", "-c");
+
+        let piped_if = |piped: bool| {
+            if piped {
+                std::process::Stdio::piped()
+            } else {
+                std::process::Stdio::null()
+            }
+        };
+        let mut child = std::process::Command::new(shell)
+            .arg(shell_flag)
+            .arg(command)
+            .stdin(piped_if(output.is_some()))
+            .stdout(piped_if(input.is_some()))
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+
+        let closing = Arc::new(AtomicBool::new(false));
+        let mut output_done = None;
+        if let (Some(source), Some(stdin)) = (output, child.stdin.take()) {
+            let (done_tx, done_rx) = std::sync::mpsc::channel();
+            let closing = Arc::clone(&closing);
+            std::thread::Builder::new()
+                .name("pipe-pane-out".to_string())
+                .spawn(move || {
+                    feed_output(source, stdin, &closing);
+                    let _ = done_tx.send(());
+                })?;
+            output_done = Some(done_rx);
+        }
+        if let (Some(sink), Some(stdout)) = (input, child.stdout.take()) {
+            let closing = Arc::clone(&closing);
+            std::thread::Builder::new()
+                .name("pipe-pane-in".to_string())
+                .spawn(move || feed_input(stdout, sink, &closing))?;
+        }
+
+        Ok(Self {
+            child,
+            closing,
+            output_done,
+        })
+    }
+
+    /// Whether the command is still running.
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+/// Close `pipes` together: their commands get [`PIPE_CLOSE_TIMEOUT`] in all
+/// to finish with their input and exit, rather than one after another.
+fn close_all(mut pipes: Vec<PanePipe>) {
+    let deadline = Instant::now() + PIPE_CLOSE_TIMEOUT;
+    for pipe in &pipes {
+        pipe.closing.store(true, Ordering::SeqCst);
+    }
+    for pipe in &mut pipes {
+        match pipe.output_done.take() {
+            // A command that doesn't read its input can't be waited for.
+            Some(done) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if done.recv_timeout(left).is_err() {
+                    let _ = pipe.child.kill();
+                }
+            }
+            // An input-only command has nothing left to finish, and is
+            // killed straight away.
+            None => {
+                let _ = pipe.child.kill();
+            }
+        }
+    }
+    while Instant::now() < deadline && pipes.iter_mut().any(PanePipe::is_running) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    for pipe in &mut pipes {
+        if pipe.is_running() {
+            let _ = pipe.child.kill();
+        }
+        let _ = pipe.child.wait();
+    }
+}
+
+/// Write what the pane prints to the command until the pipe is closed,
+/// then close the command's input.
+fn feed_output(source: OutputSource, mut stdin: ChildStdin, closing: &AtomicBool) {
+    loop {
+        match source.recv_timeout(POLL_INTERVAL) {
+            Ok((data, _)) => {
+                if stdin.write_all(&data).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if closing.load(Ordering::SeqCst) {
+            // What the pane printed before the pipe was closed still goes
+            // to the command.
+            while let Ok((data, _)) = source.try_recv() {
+                if stdin.write_all(&data).is_err() {
+                    return;
+                }
+            }
+            return;
+        }
+    }
+}
+
+/// Pass what the command prints to `sink` until the command exits or the
+/// pipe is closed.
+fn feed_input(mut stdout: ChildStdout, mut sink: InputSink, closing: &AtomicBool) {
+    let mut buf = [0u8; 4096];
+    loop {
+        match stdout.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => {
+                if closing.load(Ordering::SeqCst) || !sink(&buf[..n]) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The open pipes, by pane.
+static PIPES: LazyLock<Mutex<HashMap<PaneId, PanePipe>>> = LazyLock::new(Default::default);
+
+/// Open a pipe from (`output`) and/or into (`input`) the pane `pane_id`,
+/// running `command`. The pane must not have a pipe already.
+pub fn open_pipe_pane(
+    pane_id: PaneId,
+    command: &str,
+    output: bool,
+    input: bool,
+) -> Result<(), String> {
+    let source = output.then(|| crate::register_unbounded_output_tap(pane_id));
+    let sink: Option<InputSink> = input.then(|| {
+        Box::new(move |data: &[u8]| {
+            Mux::try_get()
+                .and_then(|mux| mux.get_pane(pane_id))
+                .is_some_and(|pane| pane.writer().write_all(data).is_ok())
+        }) as InputSink
+    });
+    let pipe = PanePipe::spawn(command, source, sink)
+        .map_err(|e| format!("pipe-pane: failed to spawn: {}", e))?;
+    PIPES.lock().insert(pane_id, pipe);
+    Ok(())
+}
+
+/// Close `pipes` on a thread of their own. The returned channel is closed
+/// once they are.
+fn close_in_background(pipes: Vec<PanePipe>) -> async_channel::Receiver<()> {
+    let (done_tx, done_rx) = async_channel::bounded::<()>(1);
+    let spawned = std::thread::Builder::new()
+        .name("pipe-pane-close".to_string())
+        .spawn(move || {
+            close_all(pipes);
+            drop(done_tx);
+        });
+    if let Err(err) = spawned {
+        log::warn!("pipe-pane: can't close pipes: {}", err);
+    }
+    done_rx
+}
+
+/// Close the pipe of `pane_id`, waiting for its command to finish with what
+/// it was sent. Returns `false` if the pane had no pipe, or its command had
+/// already exited.
+pub async fn close_pipe_pane(pane_id: PaneId) -> bool {
+    let pipe = PIPES.lock().remove(&pane_id);
+    match pipe {
+        Some(mut pipe) => {
+            let running = pipe.is_running();
+            let _ = close_in_background(vec![pipe]).recv().await;
+            running
+        }
+        None => false,
+    }
+}
+
+/// Close the pipes of `pane_ids`, which are about to be killed, all at once:
+/// this waits at most [`PIPE_CLOSE_TIMEOUT`] however many there are.
+pub async fn close_pipe_panes(pane_ids: impl IntoIterator<Item = PaneId>) {
+    let pipes: Vec<PanePipe> = {
+        let mut open = PIPES.lock();
+        pane_ids
+            .into_iter()
+            .filter_map(|pane_id| open.remove(&pane_id))
+            .collect()
+    };
+    if !pipes.is_empty() {
+        let _ = close_in_background(pipes).recv().await;
+    }
+}
+
+/// Close the pipe of a pane that has gone away, without holding up the
+/// caller.
+pub fn pane_removed(pane_id: PaneId) {
+    if let Some(pipe) = PIPES.lock().remove(&pane_id) {
+        close_in_background(vec![pipe]);
+    }
+}
+
+/// Whether `pane_id` has a pipe open, for `#{pane_pipe}`. A pipe whose
+/// command has exited is closed.
+pub fn is_piped(pane_id: PaneId) -> bool {
+    let mut pipes = PIPES.lock();
+    let Some(pipe) = pipes.get_mut(&pane_id) else {
+        return false;
+    };
+    if pipe.is_running() {
+        return true;
+    }
+    if let Some(pipe) = pipes.remove(&pane_id) {
+        pipe.closing.store(true, Ordering::SeqCst);
+    }
+    false
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pipe-pane-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn closing_flushes_output_to_the_command() {
+        let path = temp_path("out");
+        let (tx, rx) = std::sync::mpsc::sync_channel(256);
+        let command = format!("cat > '{}'", path.display());
+        let pipe = PanePipe::spawn(&command, Some(rx), None).unwrap();
+        for chunk in ["hello ", "from ", "the pane\n"] {
+            tx.send((chunk.as_bytes().to_vec(), Instant::now()))
+                .unwrap();
+        }
+        close_all(vec![pipe]);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "hello from the pane\n"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn input_goes_to_the_pane() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let sink: InputSink = Box::new(move |data: &[u8]| tx.send(data.to_vec()).is_ok());
+        let mut pipe = PanePipe::spawn("printf 'ls\\n'", None, Some(sink)).unwrap();
+        let typed = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(typed, b"ls\n");
+        let _ = pipe.child.wait();
+        assert!(!pipe.is_running());
+        close_all(vec![pipe]);
+    }
+
+    #[test]
+    fn both_directions() {
+        let (out_tx, out_rx) = std::sync::mpsc::sync_channel(256);
+        let (in_tx, in_rx) = std::sync::mpsc::channel();
+        let sink: InputSink = Box::new(move |data: &[u8]| in_tx.send(data.to_vec()).is_ok());
+        let pipe = PanePipe::spawn("cat", Some(out_rx), Some(sink)).unwrap();
+        out_tx.send((b"echo\n".to_vec(), Instant::now())).unwrap();
+        assert_eq!(
+            in_rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            b"echo\n"
+        );
+        close_all(vec![pipe]);
+    }
+
+    #[test]
+    fn commands_that_ignore_their_input_are_killed() {
+        let (_tx, rx) = std::sync::mpsc::sync_channel(256);
+        let pipe = PanePipe::spawn("sleep 30", Some(rx), None).unwrap();
+        let start = Instant::now();
+        close_all(vec![pipe]);
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let sink: InputSink = Box::new(|_: &[u8]| true);
+        let pipe = PanePipe::spawn("sleep 30", None, Some(sink)).unwrap();
+        let start = Instant::now();
+        close_all(vec![pipe]);
+        assert!(start.elapsed() < PIPE_CLOSE_TIMEOUT);
+    }
+
+    #[test]
+    fn the_pipe_gets_all_of_a_busy_panes_output() {
+        let pane_id = PaneId::MAX - 2;
+        let path = temp_path("busy");
+        // The pane prints far more chunks than a bounded tap holds before
+        // the command reads any of them.
+        let source = crate::register_unbounded_output_tap(pane_id);
+        for i in 0..1000 {
+            crate::notify_output_taps(pane_id, format!("{}\n", i).as_bytes(), Instant::now());
+        }
+        let command = format!("cat > '{}'", path.display());
+        let pipe = PanePipe::spawn(&command, Some(source), None).unwrap();
+        crate::remove_output_taps(pane_id);
+        close_all(vec![pipe]);
+        let written = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let expected: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn pipes_are_closed_together() {
+        let pane_ids = [PaneId::MAX - 3, PaneId::MAX - 4, PaneId::MAX - 5];
+        for &pane_id in &pane_ids {
+            let (_tx, rx) = std::sync::mpsc::sync_channel(256);
+            let pipe = PanePipe::spawn("sleep 30", Some(rx), None).unwrap();
+            PIPES.lock().insert(pane_id, pipe);
+        }
+        let start = Instant::now();
+        let mut closing = Box::pin(close_pipe_panes(pane_ids));
+        // The caller isn't held up while the commands are given time to exit.
+        assert!(futures::FutureExt::now_or_never(&mut closing).is_none());
+        assert!(start.elapsed() < PIPE_CLOSE_TIMEOUT);
+        smol::block_on(closing);
+        assert!(start.elapsed() < PIPE_CLOSE_TIMEOUT * 2);
+        assert!(pane_ids.iter().all(|pane_id| !is_piped(*pane_id)));
+    }
+
+    #[test]
+    fn exited_commands_are_not_piped() {
+        let pane_id = PaneId::MAX - 1;
+        let sink: InputSink = Box::new(|_: &[u8]| true);
+        let mut pipe = PanePipe::spawn("true", None, Some(sink)).unwrap();
+        let _ = pipe.child.wait();
+        PIPES.lock().insert(pane_id, pipe);
+        assert!(!is_piped(pane_id));
+        assert!(!smol::block_on(close_pipe_pane(pane_id)));
+    }
+}
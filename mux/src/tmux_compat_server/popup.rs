@@ -0,0 +1,172 @@
+//! Popup geometry for `display-popup`.
+//!
+//! tmux sizes a popup from `-w`/`-h`, given in cells or as a percentage of
+//! the window, and places it from `-x`/`-y`. A position is either a number
+//! or one of these letters:
+//!
+//! | Letter | `-x`                         | `-y`                           |
+//! |--------|------------------------------|--------------------------------|
+//! | `C`    | centre of the window         | centre of the window           |
+//! | `R`    | right edge of the target pane| —                              |
+//! | `P`    | left edge of the target pane | bottom edge of the target pane |
+//! | `M`    | mouse position               | mouse position                 |
+//! | `W`    | window in the status line    | status line                    |
+//! | `S`    | —                            | status line                    |
+//!
+//! As in tmux, a numeric `-y` gives the line *below* the popup rather than
+//! its top line, so `-y 0` and `-y 10 -h 10` both put the popup at the top.
+//! There is no mouse position or status line to go by here, so `M` is
+//! treated as the centre and `W`/`S` as the bottom of the window.
+
+use anyhow::{bail, Result};
+
+/// A rectangle of cells within a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Default popup size, in percent of the window.
+const DEFAULT_SIZE: &str = "50%";
+
+/// A popup smaller than this has no room inside its border.
+const MIN_SIZE: usize = 3;
+
+/// Computes the rectangle, border included, of a popup opened over a window
+/// of `cols` by `rows` cells for the pane at `pane`.
+pub fn popup_rect(
+    cols: usize,
+    rows: usize,
+    pane: Rect,
+    width: Option<&str>,
+    height: Option<&str>,
+    x: Option<&str>,
+    y: Option<&str>,
+) -> Result<Rect> {
+    let width = parse_size(width.unwrap_or(DEFAULT_SIZE), cols)?
+        .max(MIN_SIZE)
+        .min(cols);
+    let height = parse_size(height.unwrap_or(DEFAULT_SIZE), rows)?
+        .max(MIN_SIZE)
+        .min(rows);
+
+    let left = match x.unwrap_or("C") {
+        "C" | "M" => (cols - width) / 2,
+        "R" => (pane.left + pane.width).saturating_sub(width),
+        "P" => pane.left,
+        "W" => 0,
+        other => parse_number(other, "x")?,
+    };
+
+    let top = match y.unwrap_or("C") {
+        "C" | "M" => (rows - height) / 2,
+        "P" => (pane.top + pane.height).saturating_sub(height),
+        "S" | "W" => rows - height,
+        other => parse_number(other, "y")?.saturating_sub(height),
+    };
+
+    Ok(Rect {
+        left: left.min(cols - width),
+        top: top.min(rows - height),
+        width,
+        height,
+    })
+}
+
+/// Parses a `-w`/`-h` value: a number of cells, or a percentage of `total`.
+fn parse_size(value: &str, total: usize) -> Result<usize> {
+    let size = match value.strip_suffix('%') {
+        Some(percent) => match percent.parse::<usize>() {
+            Ok(percent) if percent <= 100 => total * percent / 100,
+            _ => bail!("invalid size: {}", value),
+        },
+        None => match value.parse::<usize>() {
+            Ok(cells) => cells,
+            Err(_) => bail!("invalid size: {}", value),
+        },
+    };
+    if size == 0 {
+        bail!("invalid size: {}", value);
+    }
+    Ok(size)
+}
+
+fn parse_number(value: &str, axis: &str) -> Result<usize> {
+    match value.parse::<usize>() {
+        Ok(n) => Ok(n),
+        Err(_) => bail!("invalid {} position: {}", axis, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PANE: Rect = Rect {
+        left: 40,
+        top: 0,
+        width: 40,
+        height: 12,
+    };
+
+    fn rect(left: usize, top: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn default_is_centred_half_size() {
+        let r = popup_rect(80, 24, PANE, None, None, None, None).unwrap();
+        assert_eq!(r, rect(20, 6, 40, 12));
+    }
+
+    #[test]
+    fn sizes_in_cells_and_percent() {
+        let r = popup_rect(80, 24, PANE, Some("60"), Some("75%"), None, None).unwrap();
+        assert_eq!(r, rect(10, 3, 60, 18));
+    }
+
+    #[test]
+    fn size_is_clamped_to_window() {
+        let r = popup_rect(80, 24, PANE, Some("200"), Some("1"), None, None).unwrap();
+        assert_eq!(r, rect(0, 10, 80, 3));
+    }
+
+    #[test]
+    fn numeric_y_is_the_line_below() {
+        let r = popup_rect(80, 24, PANE, Some("10"), Some("5"), Some("0"), Some("0")).unwrap();
+        assert_eq!(r, rect(0, 0, 10, 5));
+        let r = popup_rect(80, 24, PANE, Some("10"), Some("5"), Some("3"), Some("12")).unwrap();
+        assert_eq!(r, rect(3, 7, 10, 5));
+    }
+
+    #[test]
+    fn position_is_kept_inside_window() {
+        let r = popup_rect(80, 24, PANE, Some("10"), Some("5"), Some("79"), Some("99")).unwrap();
+        assert_eq!(r, rect(70, 19, 10, 5));
+    }
+
+    #[test]
+    fn pane_relative_positions() {
+        let r = popup_rect(80, 24, PANE, Some("10"), Some("5"), Some("P"), Some("P")).unwrap();
+        assert_eq!(r, rect(40, 7, 10, 5));
+        let r = popup_rect(80, 24, PANE, Some("10"), Some("5"), Some("R"), Some("S")).unwrap();
+        assert_eq!(r, rect(70, 19, 10, 5));
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(popup_rect(80, 24, PANE, Some("0"), None, None, None).is_err());
+        assert!(popup_rect(80, 24, PANE, Some("150%"), None, None, None).is_err());
+        assert!(popup_rect(80, 24, PANE, None, Some("tall"), None, None).is_err());
+        assert!(popup_rect(80, 24, PANE, None, None, Some("Q"), None).is_err());
+        assert!(popup_rect(80, 24, PANE, None, None, None, Some("-1")).is_err());
+    }
+}
@@ -228,6 +228,36 @@ pub fn exit_notification(reason: Option<&str>) -> String {
     }
 }
 
+// ---------------------------------------------------------------------------
+// One-shot requests
+// ---------------------------------------------------------------------------
+
+/// The reply to a one-shot request, which has no guard lines:
+/// `%result <status> <stdout length> <stderr length>` followed by the output
+/// of the command list and the error that stopped it, each ending in a
+/// newline unless empty. Lengths are in bytes and the status is the exit
+/// status for the client.
+pub fn request_result(output: &str, error: Option<&str>) -> String {
+    fn terminated(text: &str) -> String {
+        if text.is_empty() || text.ends_with('\n') {
+            text.to_string()
+        } else {
+            format!("{}\n", text)
+        }
+    }
+    let stdout = terminated(output);
+    let stderr = terminated(error.unwrap_or(""));
+    let status = if error.is_some() { 1 } else { 0 };
+    format!(
+        "%result {} {} {}\n{}{}",
+        status,
+        stdout.len(),
+        stderr.len(),
+        stdout,
+        stderr
+    )
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -524,6 +554,21 @@ mod tests {
         assert_eq!(exit_notification(Some("detached")), "%exit detached\n");
     }
 
+    #[test]
+    fn request_result_success() {
+        assert_eq!(request_result("", None), "%result 0 0 0\n");
+        assert_eq!(request_result("%1\n%2", None), "%result 0 6 0\n%1\n%2\n");
+    }
+
+    #[test]
+    fn request_result_error() {
+        assert_eq!(
+            request_result("ok", Some("can't find pane: %9")),
+            "%result 1 3 20\nok\ncan't find pane: %9\n"
+        );
+        assert_eq!(request_result("", Some("")), "%result 1 0 0\n");
+    }
+
     #[test]
     fn paste_buffer_changed_notification_basic() {
         assert_eq!(
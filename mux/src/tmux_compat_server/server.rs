@@ -2,26 +2,50 @@
 //!
 //! Accepts connections on a Unix domain socket, reads tmux commands as text
 //! lines, dispatches them through Phase 2's `dispatch_command`, and writes
-//! `%begin`/`%end` response blocks.  Mux notifications are forwarded as
-//! CC-style `%`-prefixed notification lines.
-
-use std::sync::Arc;
+//! `%begin`/`%end` response blocks.  A line may hold a whole command list
+//! (`cmd1 ; cmd2`), which gets a single response block.  Mux notifications
+//! are forwarded as CC-style `%`-prefixed notification lines.
+//!
+//! A client says which it is with its first line. One that sends
+//! [`REQUEST_LINE`] makes a one-shot request: it gets no handshake or
+//! notifications, just the result of the one command list it sends, with its
+//! output and error apart. Any other client attaches in control mode, and
+//! gets the handshake once it sends [`CONTROL_LINE`] or a command, or has
+//! said nothing for [`REQUEST_WAIT`].
+
+use std::sync::{Arc, LazyLock};
 use std::time::Instant;
 
-use crate::tab::{PositionedPane, Tab};
+use parking_lot::Mutex;
+
+use crate::tab::Tab;
 use crate::{Mux, MuxNotification};
 
-use super::command_parser::parse_command;
-use super::handlers::{dispatch_command, HandlerContext};
-use super::layout::{generate_layout_string, LayoutNode};
+use super::alerts::{self, start_alert_timer, ALERTS};
+use super::auth;
+use super::command_parser::parse_command_list;
+use super::environment::ENVIRONMENTS;
+use super::handlers::{
+    forget_closed_layout_presets_later, new_session_name, run_command_list, HandlerContext,
+};
+use super::hooks::{
+    fire_hook, hook_event_for_notification, moves_panes, refresh_homes_later, HookEvent, HOOKS,
+};
+use super::key_bindings::KEY_BINDINGS;
+use super::layout::{generate_layout_string, layout_from_tree, LayoutNode};
+use super::named_server;
+use super::options::{OptionScope, OPTIONS};
+use super::paste_buffer::{paste_buffers, PASTE_BUFFERS};
+use super::pipe_pane::pane_removed;
 use super::response::{
     exit_notification, extended_output_notification, layout_change_notification,
-    output_notification, paste_buffer_changed_notification, pause_notification,
+    output_notification, paste_buffer_changed_notification, pause_notification, request_result,
     session_changed_notification, session_renamed_notification,
     session_window_changed_notification, sessions_changed_notification, window_add_notification,
     window_close_notification, window_pane_changed_notification, window_renamed_notification,
     ResponseWriter,
 };
+use super::wait_for::WAIT_CHANNELS;
 
 // ---------------------------------------------------------------------------
 // TmuxCompatSession
@@ -36,8 +60,14 @@ pub struct TmuxCompatSession {
 
 impl TmuxCompatSession {
     pub fn new(workspace: String) -> Self {
+        Self::for_server(workspace, None)
+    }
+
+    /// A session for a client of the named server at `server`, or of the
+    /// default server for `None`.
+    pub fn for_server(workspace: String, server: Option<String>) -> Self {
         Self {
-            ctx: HandlerContext::with_persistent_ids(workspace),
+            ctx: HandlerContext::with_persistent_ids(workspace, server),
             writer: ResponseWriter::new(),
             line_buffer: String::new(),
         }
@@ -101,91 +131,29 @@ pub fn build_initial_handshake(session: &mut TmuxCompatSession) -> String {
 // Layout builder
 // ---------------------------------------------------------------------------
 
-/// Convert a tab's positioned panes into a `LayoutNode` tree for
-/// `%layout-change` notifications.
-///
-/// Simplified approach: single pane → `LayoutNode::Pane`.  Multiple panes
-/// sharing the same `top` → `HorizontalSplit`.  Multiple panes sharing the
-/// same `left` → `VerticalSplit`.  Otherwise fall back to a flat horizontal
-/// arrangement.
-pub fn build_layout_for_tab(ctx: &mut HandlerContext, tab: &Arc<Tab>) -> String {
-    let panes = tab.iter_panes();
+/// Describe a tab's panes as a `LayoutNode` tree. A zoomed tab is
+/// described as its zoomed pane filling the window.
+pub fn build_layout_node_for_tab(ctx: &mut HandlerContext, tab: &Arc<Tab>) -> LayoutNode {
     let tab_size = tab.get_size();
-
-    let root = if panes.len() <= 1 {
-        // Single pane (or empty tab)
-        if let Some(pp) = panes.first() {
-            pane_to_layout_node(ctx, pp)
-        } else {
-            LayoutNode::Pane {
-                pane_id: 0,
-                width: tab_size.cols as u64,
-                height: tab_size.rows as u64,
-                left: 0,
-                top: 0,
-            }
-        }
-    } else {
-        build_split_node(ctx, &panes, tab_size.cols as u64, tab_size.rows as u64)
-    };
-
-    generate_layout_string(&root)
-}
-
-fn pane_to_layout_node(ctx: &mut HandlerContext, pp: &PositionedPane) -> LayoutNode {
-    let tmux_pid = ctx.id_map.get_or_create_tmux_pane_id(pp.pane.pane_id());
-    LayoutNode::Pane {
-        pane_id: tmux_pid,
-        width: pp.width as u64,
-        height: pp.height as u64,
-        left: pp.left as u64,
-        top: pp.top as u64,
-    }
-}
-
-fn build_split_node(
-    ctx: &mut HandlerContext,
-    panes: &[PositionedPane],
-    width: u64,
-    height: u64,
-) -> LayoutNode {
-    let children: Vec<LayoutNode> = panes
-        .iter()
-        .map(|pp| pane_to_layout_node(ctx, pp))
-        .collect();
-
-    // Detect split orientation from positions
-    let all_same_top = panes.windows(2).all(|w| w[0].top == w[1].top);
-    let all_same_left = panes.windows(2).all(|w| w[0].left == w[1].left);
-
-    if all_same_top {
-        LayoutNode::HorizontalSplit {
-            width,
-            height,
-            left: 0,
-            top: 0,
-            children,
-        }
-    } else if all_same_left {
-        LayoutNode::VerticalSplit {
-            width,
-            height,
-            left: 0,
-            top: 0,
-            children,
-        }
-    } else {
-        // Complex nested splits — fall back to flat horizontal arrangement
-        LayoutNode::HorizontalSplit {
-            width,
-            height,
+    match tab.get_zoomed_pane() {
+        Some(pane) => LayoutNode::Pane {
+            pane_id: ctx.id_map.get_or_create_tmux_pane_id(pane.pane_id()),
+            width: tab_size.cols as u64,
+            height: tab_size.rows as u64,
             left: 0,
             top: 0,
-            children,
-        }
+        },
+        None => layout_from_tree(&tab.get_split_tree(), &tab_size, &mut |pane_id| {
+            ctx.id_map.get_or_create_tmux_pane_id(pane_id)
+        }),
     }
 }
 
+/// Build the layout string of a tab for `%layout-change` notifications.
+pub fn build_layout_for_tab(ctx: &mut HandlerContext, tab: &Arc<Tab>) -> String {
+    generate_layout_string(&build_layout_node_for_tab(ctx, tab))
+}
+
 // ---------------------------------------------------------------------------
 // Notification translation
 // ---------------------------------------------------------------------------
@@ -321,14 +289,16 @@ pub fn translate_notification(
         }
 
         MuxNotification::PaneRemoved(pane_id) => {
-            // Clean up pipe-pane, spawned-pane tracking, and id_map, but don't
-            // emit a separate notification.
-            // The layout-change from TabResized covers the visual change.
+            // Clean up copy mode, spawned-pane tracking, pane options and
+            // id_map, but don't emit a separate notification. The
+            // layout-change from TabResized covers the visual change.
             // Note: currently used from tests; production cleanup is handled
             // by reap_dead_cc_panes (periodic) and cleanup_cc_spawned_panes
-            // (disconnect).
-            super::handlers::close_pipe_pane(pane_id);
+            // (disconnect). Pipes belong to the pane rather than to a client,
+            // and are closed by the hook subscriber.
+            super::copy_mode::copy_modes().remove(&pane_id);
             super::handlers::cc_global_remove_spawned(&session.ctx.workspace, pane_id);
+            OPTIONS.for_each(|options| options.remove_scope(&OptionScope::Pane(pane_id)));
             session.ctx.id_map.remove_pane(pane_id);
             None
         }
@@ -380,11 +350,13 @@ pub fn translate_notification(
         }
 
         MuxNotification::AssignClipboard { clipboard, .. } => {
-            // Clipboard content changed → store in paste buffer and notify
-            if let Some(content) = clipboard {
-                session.ctx.paste_buffers.set(None, content);
-            }
-            Some(paste_buffer_changed_notification("buffer0"))
+            // Clipboard content changed → store in the paste buffers of the
+            // session's server and notify. Each session handles this
+            // notification, but only the first one of a server creates a
+            // buffer.
+            let content = clipboard?;
+            let name = paste_buffers(session.ctx.server.as_deref()).set_from_clipboard(content);
+            Some(paste_buffer_changed_notification(&name))
         }
 
         // Notifications with no CC equivalent — silently ignore.
@@ -395,10 +367,71 @@ pub fn translate_notification(
         | MuxNotification::Alert { .. }
         | MuxNotification::Empty
         | MuxNotification::SaveToDownloads { .. }
+        | MuxNotification::PerformAssignment { .. }
         | MuxNotification::WindowTitleChanged { .. } => None,
     }
 }
 
+/// Subscribe to mux notifications to fire hooks, keep track of where panes
+/// are for them, record output and bells for window alerts and close the
+/// pipes and forget the layout presets of panes and windows that have gone
+/// away. Hooks belong to the server rather than to a client, so there is a
+/// single subscriber however many clients are connected, and each hook runs
+/// once per event.
+fn start_hook_subscriber() {
+    static STARTED: std::sync::Once = std::sync::Once::new();
+    STARTED.call_once(|| {
+        let Some(mux) = Mux::try_get() else {
+            return;
+        };
+        mux.subscribe(|notif| {
+            if let MuxNotification::WorkspaceRenamed {
+                old_workspace,
+                new_workspace,
+            } = &notif
+            {
+                HOOKS.for_each(|hooks| hooks.rename_session(old_workspace, new_workspace));
+                ENVIRONMENTS.for_each(|environments| {
+                    environments.rename_session(old_workspace, new_workspace)
+                });
+            }
+            match &notif {
+                MuxNotification::PaneRemoved(pane_id) => {
+                    pane_removed(*pane_id);
+                    forget_closed_layout_presets_later();
+                }
+                MuxNotification::WindowRemoved(_) => forget_closed_layout_presets_later(),
+                MuxNotification::PaneOutput(pane_id) => {
+                    alerts::pane_output(*pane_id, Instant::now());
+                }
+                MuxNotification::Alert {
+                    pane_id,
+                    alert: wezterm_term::Alert::Bell,
+                } => alerts::pane_bell(*pane_id),
+                _ => {}
+            }
+            if let Some(event) = hook_event_for_notification(&notif) {
+                fire_hook(event);
+            }
+            if moves_panes(&notif) {
+                refresh_homes_later();
+            }
+            true
+        });
+        refresh_homes_later();
+        start_alert_timer();
+    });
+}
+
+/// Fire a `client-*` hook for the client of `session`.
+fn fire_client_hook(session: &TmuxCompatSession, name: &str) {
+    fire_hook(HookEvent {
+        workspace: Some(session.ctx.workspace.clone()),
+        client: Some(session.ctx.client_name.clone()),
+        ..HookEvent::new(name)
+    });
+}
+
 // ---------------------------------------------------------------------------
 // Line extraction
 // ---------------------------------------------------------------------------
@@ -523,33 +556,265 @@ fn start_output_forwarder(
     rx
 }
 
+/// The session a new client starts in: the active workspace if it belongs to
+/// the client's server, or else the server's first session. A named server
+/// may have no sessions yet, and then the client starts in the session
+/// `new-session` would create.
+fn initial_workspace(server: Option<&str>) -> String {
+    let mux = match Mux::try_get() {
+        Some(mux) => mux,
+        None => return "default".to_string(),
+    };
+    let active = mux.active_workspace();
+    let sessions = named_server::sessions(server, mux.iter_workspaces());
+    if sessions.contains(&active) || (server.is_none() && sessions.is_empty()) {
+        return active;
+    }
+    sessions
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| new_session_name(server, &mux.iter_workspaces()))
+}
+
+/// A client connection. Its reads can be given a timeout, so that it can be
+/// watched for the client going away while a command runs.
+trait CcStream: std::io::Read + std::io::Write {
+    fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()>;
+}
+
+impl CcStream for std::net::TcpStream {
+    fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()> {
+        std::net::TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl CcStream for wezterm_uds::UnixStream {
+    fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+}
+
+/// Read whatever the client has sent within a short time into `accum`.
+/// Returns `false` once the client has closed the connection.
+fn poll_client(
+    stream: &mut impl CcStream,
+    accum: &mut String,
+    read_buf: &mut [u8],
+) -> anyhow::Result<bool> {
+    stream.set_read_timeout(Some(std::time::Duration::from_millis(10)))?;
+    let result = std::io::Read::read(stream, read_buf);
+    stream.set_read_timeout(None)?;
+    match result {
+        Ok(0) => Ok(false),
+        Ok(n) => {
+            accum.push_str(&String::from_utf8_lossy(&read_buf[..n]));
+            Ok(true)
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::Interrupted
+            ) =>
+        {
+            Ok(true)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The line a client sends first to make a one-shot request instead of
+/// attaching in control mode. The command list follows on the next line.
+pub const REQUEST_LINE: &str = "request";
+
+/// The line a control mode client may send first, to be sent the
+/// handshake without waiting for [`REQUEST_WAIT`].
+pub const CONTROL_LINE: &str = "control";
+
+/// How long a client has to send its first line before it is taken for a
+/// control mode client that waits for the handshake, as `tmux -CC` clients
+/// do.
+const REQUEST_WAIT: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Wait up to [`REQUEST_WAIT`] for the first line the client sends, reading
+/// into `accum`. Returns whether it asks for a one-shot request, or `None`
+/// if it went away before sending a whole line. [`REQUEST_LINE`] and
+/// [`CONTROL_LINE`] are consumed; anything else is left for the control mode
+/// loop, as the first command of a client that doesn't wait for the
+/// handshake.
+fn wants_request(
+    stream: &mut impl CcStream,
+    accum: &mut String,
+    read_buf: &mut [u8],
+) -> anyhow::Result<Option<bool>> {
+    let deadline = Instant::now() + REQUEST_WAIT;
+    let mut closed = false;
+    let mut result = Ok(());
+    while !accum.contains('\n') {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        stream.set_read_timeout(Some(deadline - now))?;
+        match std::io::Read::read(stream, read_buf) {
+            Ok(0) => {
+                closed = true;
+                break;
+            }
+            Ok(n) => accum.push_str(&String::from_utf8_lossy(&read_buf[..n])),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::TimedOut
+                        | std::io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    stream.set_read_timeout(None)?;
+    result?;
+    let Some(pos) = accum.find('\n') else {
+        // A client that says nothing is waiting for the handshake.
+        return Ok((!closed).then_some(false));
+    };
+    let first = accum[..pos].trim();
+    let request = first == REQUEST_LINE;
+    if request || first == CONTROL_LINE {
+        accum.drain(..=pos);
+    }
+    Ok(Some(request))
+}
+
+/// Run `cmd_line` with `ctx` on the main thread, which `Mux::get()` needs.
+/// The output of the command list, the error that stopped it and `ctx`
+/// come back on the returned channel.
+fn spawn_command_list(
+    mut ctx: HandlerContext,
+    cmd_line: String,
+) -> std::sync::mpsc::Receiver<((String, Option<String>), HandlerContext)> {
+    let (resp_tx, resp_rx) = std::sync::mpsc::sync_channel(1);
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            log::info!("tmux CC cmd: {:?}", cmd_line);
+            let resp = match parse_command_list(&cmd_line) {
+                Ok(list) => run_command_list(&mut ctx, &list).await,
+                Err(e) => {
+                    log::warn!("tmux compat: command parse error: {e} (input: {cmd_line:?})");
+                    (String::new(), Some(format!("{}", e)))
+                }
+            };
+            let _ = resp_tx.send((resp, ctx));
+        })
+        .detach();
+    })
+    .detach();
+    resp_rx
+}
+
+/// Serve a one-shot request: run the command list on the line the client
+/// sends and reply with [`request_result`], without the handshake, hooks or
+/// notifications of a control mode client.
+fn process_request(
+    mut stream: impl CcStream,
+    mut session: TmuxCompatSession,
+    mut accum: String,
+    mut read_buf: Vec<u8>,
+) -> anyhow::Result<()> {
+    let line = loop {
+        if let Some(pos) = accum.find('\n') {
+            let line = accum[..pos].trim().to_string();
+            accum.drain(..=pos);
+            break line;
+        }
+        let n = std::io::Read::read(&mut stream, &mut read_buf)?;
+        if n == 0 {
+            log::trace!("tmux CC: request client disconnected before its request");
+            return Ok(());
+        }
+        accum.push_str(&String::from_utf8_lossy(&read_buf[..n]));
+    };
+
+    let (client_gone_tx, client_gone) = async_channel::bounded::<()>(1);
+    let mut client_gone_tx = Some(client_gone_tx);
+    session.ctx.client_gone = Some(client_gone);
+    let ctx = std::mem::replace(&mut session.ctx, HandlerContext::new(String::new()));
+    let resp_rx = spawn_command_list(ctx, line);
+    let ((output, error), ctx_back) = loop {
+        match resp_rx.recv_timeout(std::time::Duration::from_millis(10)) {
+            Ok(result) => break result,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                if client_gone_tx.is_some() && !poll_client(&mut stream, &mut accum, &mut read_buf)?
+                {
+                    log::trace!("tmux CC: request client disconnected while waiting");
+                    client_gone_tx = None;
+                }
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow::anyhow!("failed to receive command response"));
+            }
+        }
+    };
+    session.ctx = ctx_back;
+    if error.is_none() {
+        session.ctx.save_id_map();
+    }
+    if client_gone_tx.is_none() {
+        return Ok(());
+    }
+    let reply = request_result(&output, error.as_deref());
+    std::io::Write::write_all(&mut stream, reply.as_bytes())?;
+    std::io::Write::flush(&mut stream)?;
+    Ok(())
+}
+
 fn process_cc_connection_sync(
-    mut stream: impl std::io::Read + std::io::Write,
+    mut stream: impl CcStream,
     listen_addr: &str,
+    server: Option<&str>,
 ) -> anyhow::Result<()> {
+    // Manual line-buffered read loop.  We avoid BufReader because we need
+    // to alternate reads and writes on the same stream, and writes through
+    // BufReader::get_mut() don't work reliably on Windows.
+    let mut read_buf = vec![0u8; 4096];
+    let mut accum = String::new();
+    let Some(request) = wants_request(&mut stream, &mut accum, &mut read_buf)? else {
+        log::trace!("tmux CC: client disconnected before saying what it is");
+        return Ok(());
+    };
+
     // Build session and handshake directly on this thread.
     // Mux::try_get() uses a global Arc and works from any thread.
-    let workspace = Mux::try_get()
-        .map(|mux| mux.active_workspace().to_string())
-        .unwrap_or_else(|| "default".to_string());
-    let mut session = TmuxCompatSession::new(workspace.clone());
+    let workspace = initial_workspace(server);
+    let mut session = TmuxCompatSession::for_server(workspace.clone(), server.map(str::to_string));
     let client_num = CLIENT_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
     session.ctx.client_name = format!("/dev/pts/{}", client_num);
     session.ctx.socket_path = listen_addr.to_string();
+    // Hooks set by one-shot requests fire as well as those of attached
+    // clients.
+    start_hook_subscriber();
+    if request {
+        return process_request(stream, session, accum, read_buf);
+    }
+    // Dropped, closing the channel, when the client goes away or this
+    // function returns.
+    let (client_gone_tx, client_gone) = async_channel::bounded::<()>(1);
+    let mut client_gone_tx = Some(client_gone_tx);
+    session.ctx.client_gone = Some(client_gone);
     let handshake = build_initial_handshake(&mut session);
 
     std::io::Write::write_all(&mut stream, handshake.as_bytes())?;
     std::io::Write::flush(&mut stream)?;
     log::info!("tmux CC: handshake sent ({} bytes)", handshake.len());
+    fire_client_hook(&session, "client-attached");
 
     // Start output forwarder for all panes in the workspace.
     let output_rx = start_output_forwarder(&workspace);
 
-    // Manual line-buffered read loop.  We avoid BufReader because we need
-    // to alternate reads and writes on the same stream, and writes through
-    // BufReader::get_mut() don't work reliably on Windows.
-    let mut read_buf = vec![0u8; 4096];
-    let mut accum = String::new();
     let mut last_subscription_check = std::time::Instant::now();
     loop {
         // Drain any pending output before blocking on read.
@@ -562,6 +827,12 @@ fn process_cc_connection_sync(
                 accum.drain(..=pos);
                 break line;
             }
+            // The client went away while a command was running; what it
+            // sent before that has been dealt with.
+            if client_gone_tx.is_none() {
+                fire_client_hook(&session, "client-detached");
+                return Ok(());
+            }
             // Need more data — read with a short timeout so we can also
             // drain output taps periodically.
             let n = std::io::Read::read(&mut stream, &mut read_buf)?;
@@ -572,6 +843,7 @@ fn process_cc_connection_sync(
                 // separate TCP connection), the panes are still in use.
                 // Cleanup happens on explicit detach-client or via the
                 // periodic reap_dead_cc_panes reaper.
+                fire_client_hook(&session, "client-detached");
                 return Ok(());
             }
             accum.push_str(&String::from_utf8_lossy(&read_buf[..n]));
@@ -585,35 +857,25 @@ fn process_cc_connection_sync(
             continue;
         }
 
-        // Dispatch the command on the main thread via spawn_local.
-        let cmd_line = trimmed;
-        let mut ctx = std::mem::replace(&mut session.ctx, HandlerContext::new(String::new()));
-        let (resp_tx, resp_rx) = std::sync::mpsc::sync_channel(1);
-        promise::spawn::spawn_into_main_thread(async move {
-            promise::spawn::spawn(async move {
-                log::info!("tmux CC cmd: {:?}", cmd_line);
-                let resp = match parse_command(&cmd_line) {
-                    Ok(cmd) => match dispatch_command(&mut ctx, cmd).await {
-                        Ok(body) => Ok(body),
-                        Err(e) => Err(e),
-                    },
-                    Err(e) => {
-                        log::warn!("tmux compat: command parse error: {e} (input: {cmd_line:?})");
-                        Err(format!("{}", e))
-                    }
-                };
-                let _ = resp_tx.send((resp, ctx));
-            })
-            .detach();
-        })
-        .detach();
+        // Dispatch the command on the main thread.
+        let ctx = std::mem::replace(&mut session.ctx, HandlerContext::new(String::new()));
+        let resp_rx = spawn_command_list(ctx, trimmed);
 
         // While waiting for the command response, keep draining output.
-        let (response, ctx_back) = loop {
+        // A command that blocks, such as `wait-for`, gives up if the client
+        // goes away, so watch for that too; any commands the client sends
+        // meanwhile are buffered.
+        let ((output, error), ctx_back) = loop {
             match resp_rx.recv_timeout(std::time::Duration::from_millis(10)) {
                 Ok(result) => break result,
                 Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                     drain_output_taps(&mut session, &output_rx, &mut stream)?;
+                    if client_gone_tx.is_some()
+                        && !poll_client(&mut stream, &mut accum, &mut read_buf)?
+                    {
+                        log::trace!("CC client disconnected while a command was running");
+                        client_gone_tx = None;
+                    }
                 }
                 Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
                     return Err(anyhow::anyhow!("failed to receive command response"));
@@ -623,18 +885,25 @@ fn process_cc_connection_sync(
         session.ctx = ctx_back;
 
         // Persist ID mappings after each command (best-effort).
-        if response.is_ok() {
+        if error.is_none() {
             session.ctx.save_id_map();
         }
 
-        let formatted = match response {
-            Ok(ref body) if body.is_empty() => session.writer.empty_success(),
-            Ok(body) => session.writer.success(&body),
-            Err(e) => session.writer.error(&e),
+        // A failed command's error follows the output of those before it.
+        let formatted = match error {
+            None if output.is_empty() => session.writer.empty_success(),
+            None => session.writer.success(&output),
+            Some(e) if output.is_empty() => session.writer.error(&e),
+            Some(e) => session.writer.error(&format!("{}\n{}", output, e)),
         };
         // Write response directly — no BufReader::get_mut().
-        std::io::Write::write_all(&mut stream, formatted.as_bytes())?;
-        std::io::Write::flush(&mut stream)?;
+        let written = std::io::Write::write_all(&mut stream, formatted.as_bytes())
+            .and_then(|()| std::io::Write::flush(&mut stream));
+        if written.is_err() && client_gone_tx.is_none() {
+            fire_client_hook(&session, "client-detached");
+            return Ok(());
+        }
+        written?;
 
         // Drain any pending notifications queued by the handler
         // (e.g. %session-changed after attach-session).
@@ -665,6 +934,7 @@ fn process_cc_connection_sync(
             std::io::Write::write_all(&mut stream, exit.as_bytes())?;
             std::io::Write::flush(&mut stream)?;
             log::info!("tmux CC: client detached");
+            fire_client_hook(&session, "client-detached");
             super::handlers::cleanup_cc_spawned_panes(&session.ctx.workspace);
             return Ok(());
         }
@@ -675,6 +945,16 @@ fn process_cc_connection_sync(
 // Listener
 // ---------------------------------------------------------------------------
 
+/// Addresses of the listeners started by this process, in the order they
+/// were started.
+static LISTENER_ADDRESSES: LazyLock<Mutex<Vec<String>>> = LazyLock::new(Default::default);
+
+/// The addresses of this process's CC listeners, for clients that want to
+/// find them (`wezterm cli tmux-socket`).
+pub fn listener_addresses() -> Vec<String> {
+    LISTENER_ADDRESSES.lock().clone()
+}
+
 /// Start the tmux CC compatibility listener.
 ///
 /// On Windows, binds a TCP listener on `127.0.0.1:0` (random port) because
@@ -686,25 +966,89 @@ fn process_cc_connection_sync(
 /// - Unix: the socket file path
 /// - Windows: `tcp:127.0.0.1:PORT`
 ///
+/// The address is also recorded for [`listener_addresses`]. The TCP
+/// listener only accepts clients presenting the token it writes to
+/// `socket_path` with a `.token` extension; see [`auth`].
+///
 /// Spawns a background thread that accepts connections.  Each connection is
 /// handled synchronously on its own thread.
-pub fn start_tmux_compat_listener(_socket_path: &std::path::Path) -> anyhow::Result<String> {
+pub fn start_tmux_compat_listener(socket_path: &std::path::Path) -> anyhow::Result<String> {
     #[cfg(windows)]
-    {
-        start_tmux_compat_listener_tcp()
-    }
+    let addr = start_tmux_compat_listener_tcp(&socket_path.with_extension("token"))?;
     #[cfg(not(windows))]
-    {
-        start_tmux_compat_listener_uds(_socket_path)
+    let addr = start_tmux_compat_listener_uds(socket_path, None)?;
+
+    LISTENER_ADDRESSES.lock().push(addr.clone());
+    Ok(addr)
+}
+
+/// Start the named server (`tmux -L`/`-S`) with its socket at `socket_path`,
+/// unless it is already running.
+///
+/// A socket that something else is still listening on, such as a real tmux
+/// server, is left alone.
+#[cfg(not(windows))]
+pub fn start_named_server(socket_path: &std::path::Path) -> anyhow::Result<()> {
+    let path = socket_path.to_string_lossy().to_string();
+    if named_server::is_running(&path) {
+        return Ok(());
     }
+    if wezterm_uds::UnixStream::connect(socket_path).is_ok() {
+        anyhow::bail!("server already running on {}", path);
+    }
+
+    // Like tmux, keep the socket directory private.
+    if let Some(dir) = socket_path.parent() {
+        use std::os::unix::fs::DirBuilderExt;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+
+    named_server::register(&path);
+    if let Err(err) = start_tmux_compat_listener_uds(socket_path, Some(path.clone())) {
+        named_server::unregister(&path);
+        return Err(err);
+    }
+    Ok(())
 }
 
-/// TCP-based listener for Windows.
 #[cfg(windows)]
-fn start_tmux_compat_listener_tcp() -> anyhow::Result<String> {
+pub fn start_named_server(socket_path: &std::path::Path) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "can't start a server on {}: named servers need unix sockets",
+        socket_path.display()
+    )
+}
+
+/// Stop the named server at `path`: its listener stops accepting clients
+/// and its socket is removed. The caller deals with its sessions.
+pub fn stop_named_server(path: &str) {
+    named_server::unregister(path);
+    // Its options, hooks and so on go with it.
+    OPTIONS.remove(path);
+    HOOKS.remove(path);
+    PASTE_BUFFERS.remove(path);
+    ENVIRONMENTS.remove(path);
+    KEY_BINDINGS.remove(path);
+    WAIT_CHANNELS.remove(path);
+    ALERTS.remove(path);
+    // Wake the listener thread so that it sees the server has stopped.
+    #[cfg(not(windows))]
+    drop(wezterm_uds::UnixStream::connect(path));
+    let _ = std::fs::remove_file(path);
+}
+
+/// TCP-based listener for Windows, whose token is written to `token_path`.
+#[cfg(windows)]
+fn start_tmux_compat_listener_tcp(token_path: &std::path::Path) -> anyhow::Result<String> {
+    let token = auth::new_token()?;
+    auth::write_token_file(token_path, &token)?;
     let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
     let addr = listener.local_addr()?;
     let addr_str = format!("tcp:{}", addr);
+    auth::register_token_file(&addr_str, token_path.to_path_buf());
     log::info!("tmux CC compat listener started on {}", addr_str);
 
     let addr_for_thread = addr_str.clone();
@@ -713,17 +1057,22 @@ fn start_tmux_compat_listener_tcp() -> anyhow::Result<String> {
         .spawn(move || {
             for stream in listener.incoming() {
                 match stream {
-                    Ok(stream) => {
+                    Ok(mut stream) => {
                         // Disable Nagle — without this, writes after the
                         // initial handshake stall due to delayed-ACK
                         // interaction on Windows localhost TCP.
                         let _ = stream.set_nodelay(true);
                         log::info!("tmux CC: accepted new TCP connection");
                         let addr = addr_for_thread.clone();
+                        let token = token.clone();
                         std::thread::Builder::new()
                             .name("tmux-cc-conn".to_string())
                             .spawn(move || {
-                                if let Err(e) = process_cc_connection_sync(stream, &addr) {
+                                if let Err(e) = authenticate_tcp_client(&mut stream, &token) {
+                                    log::warn!("tmux CC: rejected TCP connection: {}", e);
+                                    return;
+                                }
+                                if let Err(e) = process_cc_connection_sync(stream, &addr, None) {
                                     log::error!("tmux CC connection error: {}", e);
                                 }
                             })
@@ -739,9 +1088,24 @@ fn start_tmux_compat_listener_tcp() -> anyhow::Result<String> {
     Ok(addr_str)
 }
 
-/// UDS-based listener for Unix.
+/// Check that a client of the TCP listener presents its `token`, within
+/// [`auth::AUTH_TIMEOUT`].
+#[cfg(windows)]
+fn authenticate_tcp_client(stream: &mut std::net::TcpStream, token: &str) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(auth::AUTH_TIMEOUT))?;
+    auth::authenticate(stream, token)?;
+    stream.set_read_timeout(None)?;
+    Ok(())
+}
+
+/// UDS-based listener for Unix, for the named server `server` or for the
+/// default server. Only clients running as the same user, or as root, are
+/// accepted.
 #[cfg(not(windows))]
-fn start_tmux_compat_listener_uds(socket_path: &std::path::Path) -> anyhow::Result<String> {
+fn start_tmux_compat_listener_uds(
+    socket_path: &std::path::Path,
+    server: Option<String>,
+) -> anyhow::Result<String> {
     if socket_path.exists() {
         let _ = std::fs::remove_file(socket_path);
     }
@@ -755,14 +1119,31 @@ fn start_tmux_compat_listener_uds(socket_path: &std::path::Path) -> anyhow::Resu
         .name("tmux-cc-listener".to_string())
         .spawn(move || {
             for stream in listener.incoming() {
+                if server
+                    .as_deref()
+                    .is_some_and(|path| !named_server::is_running(path))
+                {
+                    log::info!("tmux CC: server {} stopped", addr_for_thread);
+                    break;
+                }
                 match stream {
-                    Ok(stream) => {
+                    Ok(mut stream) => {
                         log::info!("tmux CC: accepted new connection");
+                        if !auth::peer_is_allowed(&stream) {
+                            log::warn!("tmux CC: rejected connection from another user");
+                            let _ = std::io::Write::write_all(
+                                &mut stream,
+                                auth::ACCESS_DENIED.as_bytes(),
+                            );
+                            continue;
+                        }
                         let addr = addr_for_thread.clone();
+                        let server = server.clone();
                         std::thread::Builder::new()
                             .name("tmux-cc-conn".to_string())
                             .spawn(move || {
-                                if let Err(e) = process_cc_connection_sync(stream, &addr) {
+                                let server = server.as_deref();
+                                if let Err(e) = process_cc_connection_sync(stream, &addr, server) {
                                     log::error!("tmux CC connection error: {}", e);
                                 }
                             })
@@ -786,6 +1167,122 @@ fn start_tmux_compat_listener_uds(socket_path: &std::path::Path) -> anyhow::Resu
 mod tests {
     use super::*;
 
+    #[cfg(not(windows))]
+    #[test]
+    fn listener_address_is_recorded() {
+        let path = std::env::temp_dir().join(format!("tmux-cc-test-{}", std::process::id()));
+        let addr = start_tmux_compat_listener(&path).unwrap();
+        assert_eq!(addr, path.to_string_lossy());
+        assert!(listener_addresses().contains(&addr));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// A client that has sent `input` and then closed its end.
+    struct SentClient(std::io::Cursor<Vec<u8>>);
+
+    impl std::io::Read for SentClient {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl std::io::Write for SentClient {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CcStream for SentClient {
+        fn set_read_timeout(&self, _: Option<std::time::Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn first_line(input: &str) -> (Option<bool>, String) {
+        let mut client = SentClient(std::io::Cursor::new(input.as_bytes().to_vec()));
+        let mut accum = String::new();
+        let mut read_buf = vec![0u8; 4096];
+        let request = wants_request(&mut client, &mut accum, &mut read_buf).unwrap();
+        (request, accum)
+    }
+
+    #[test]
+    fn request_line_selects_one_shot_mode() {
+        assert_eq!(
+            first_line("request\nlist-sessions\n"),
+            (Some(true), "list-sessions\n".to_string())
+        );
+        assert_eq!(
+            first_line("list-sessions\n"),
+            (Some(false), "list-sessions\n".to_string())
+        );
+        assert_eq!(
+            first_line("control\nlist-sessions\n"),
+            (Some(false), "list-sessions\n".to_string())
+        );
+        assert_eq!(first_line(""), (None, String::new()));
+        assert_eq!(first_line("contr"), (None, "contr".to_string()));
+    }
+
+    /// A client that is still connected but sends nothing.
+    struct SilentClient;
+
+    impl std::io::Read for SilentClient {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    impl std::io::Write for SilentClient {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CcStream for SilentClient {
+        fn set_read_timeout(&self, _: Option<std::time::Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn silent_client_is_taken_for_control_mode() {
+        let mut accum = String::new();
+        let mut read_buf = vec![0u8; 4096];
+        let request = wants_request(&mut SilentClient, &mut accum, &mut read_buf).unwrap();
+        assert_eq!(request, Some(false));
+        assert_eq!(accum, "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_without_a_preamble_gets_the_handshake() {
+        let path = std::env::temp_dir().join(format!("tmux-cc-greet-{}", std::process::id()));
+        let addr = start_tmux_compat_listener(&path).unwrap();
+        let mut client = std::os::unix::net::UnixStream::connect(&addr).unwrap();
+        client
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        let mut greeting = String::new();
+        let mut read_buf = [0u8; 4096];
+        while !greeting.contains("%end") {
+            let n = std::io::Read::read(&mut client, &mut read_buf).unwrap();
+            assert_ne!(n, 0, "the server hung up after {:?}", greeting);
+            greeting.push_str(&String::from_utf8_lossy(&read_buf[..n]));
+        }
+        assert!(greeting.starts_with("%begin "), "{:?}", greeting);
+        let _ = std::fs::remove_file(&path);
+    }
+
     // --- extract_lines tests ---
 
     #[test]
@@ -850,30 +1347,6 @@ mod tests {
         assert_eq!(buf, "");
     }
 
-    // --- build_layout tests ---
-
-    #[test]
-    fn build_split_node_horizontal() {
-        let mut ctx = HandlerContext::new("test".to_string());
-        // Two panes side by side (same top=0)
-        let node = build_split_node(
-            &mut ctx,
-            &[], // We test via build_layout_for_tab indirectly, but
-            // for the node builder we need PositionedPane which
-            // requires Pane trait objects, so test the logic at a
-            // higher level.
-            160,
-            40,
-        );
-        // Empty panes → empty children
-        match node {
-            LayoutNode::HorizontalSplit { children, .. } => {
-                assert!(children.is_empty());
-            }
-            _ => panic!("expected HorizontalSplit for empty panes"),
-        }
-    }
-
     // --- translate_notification tests ---
 
     #[test]
@@ -1037,8 +1510,12 @@ mod tests {
             selection: wezterm_term::ClipboardSelection::Clipboard,
             clipboard: Some("hello".to_string()),
         };
-        let result = translate_notification(&mut session, notif);
-        assert_eq!(result, Some("%paste-buffer-changed buffer0\n".to_string()));
+        let result = translate_notification(&mut session, notif).unwrap();
+        let name = result
+            .strip_prefix("%paste-buffer-changed ")
+            .and_then(|rest| rest.strip_suffix('\n'))
+            .unwrap();
+        assert_eq!(paste_buffers(None).get(name).unwrap().data, "hello");
     }
 
     #[test]
@@ -1049,8 +1526,8 @@ mod tests {
             selection: wezterm_term::ClipboardSelection::PrimarySelection,
             clipboard: None,
         };
-        let result = translate_notification(&mut session, notif);
-        assert_eq!(result, Some("%paste-buffer-changed buffer0\n".to_string()));
+        // Clearing the selection doesn't touch any buffer.
+        assert!(translate_notification(&mut session, notif).is_none());
     }
 
     // --- Phase 9: %session-window-changed tests ---
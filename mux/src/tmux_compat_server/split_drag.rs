@@ -0,0 +1,170 @@
+//! `resize-pane -M`: dragging the borders between panes with the mouse.
+//!
+//! In tmux, `resize-pane -M` starts dragging the border under the mouse
+//! event that triggered it, and the border then follows the mouse until the
+//! button is released. A command sent by a client has no mouse event of its
+//! own, so the drag starts from the last event `send-keys -M` delivered to a
+//! pane of the window. While the drag lasts, the events `send-keys -M`
+//! delivers to that window move the border rather than going to the pane.
+
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use wezterm_term::{MouseEvent, MouseEventKind};
+
+use crate::tab::{PositionedSplit, SplitDirection, Tab, TabId};
+
+/// Where a mouse event happened, in cells from the top left corner of the
+/// window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    tab_id: TabId,
+    x: isize,
+    y: isize,
+    /// Whether a button was held down.
+    pressed: bool,
+}
+
+/// The borders being dragged, by the index of their split.
+struct Drag {
+    tab_id: TabId,
+    splits: Vec<(usize, SplitDirection)>,
+}
+
+#[derive(Default)]
+struct MouseState {
+    /// The last event `send-keys -M` delivered.
+    last: Option<Position>,
+    drag: Option<Drag>,
+}
+
+static MOUSE: LazyLock<Mutex<MouseState>> = LazyLock::new(Default::default);
+
+/// The splits whose border runs through the cell at `x`, `y`. Where two
+/// borders meet, both are dragged, as in tmux.
+fn splits_at(splits: &[PositionedSplit], x: isize, y: isize) -> Vec<(usize, SplitDirection)> {
+    splits
+        .iter()
+        .filter(|split| {
+            let (left, top, size) = (split.left as isize, split.top as isize, split.size as isize);
+            match split.direction {
+                SplitDirection::Horizontal => x == left && (top..top + size).contains(&y),
+                SplitDirection::Vertical => y == top && (left..left + size).contains(&x),
+            }
+        })
+        .map(|split| (split.index, split.direction))
+        .collect()
+}
+
+/// Note the mouse `event` that `send-keys -M` is delivering to the pane at
+/// `left`, `top` of `tab`. Returns whether the event moved or ended a drag,
+/// in which case it isn't for the pane.
+pub fn mouse_event(tab: &Tab, left: usize, top: usize, event: &MouseEvent) -> bool {
+    let position = Position {
+        tab_id: tab.tab_id(),
+        x: (left + event.x) as isize,
+        y: top as isize + event.y as isize,
+        pressed: event.kind != MouseEventKind::Release,
+    };
+    let mut state = MOUSE.lock();
+    state.last = Some(position);
+    let Some(drag) = state.drag.take() else {
+        return false;
+    };
+    // The mouse has moved on to another window, leaving the border behind.
+    if drag.tab_id != position.tab_id {
+        return false;
+    }
+    if !position.pressed {
+        return true;
+    }
+    // Like the GUI, move each border to the mouse rather than by how far
+    // the mouse moved, so that it catches up after being held at a limit.
+    let current = tab.iter_splits();
+    for &(index, direction) in &drag.splits {
+        let Some(split) = current.iter().find(|split| split.index == index) else {
+            continue;
+        };
+        let delta = match direction {
+            SplitDirection::Horizontal => position.x - split.left as isize,
+            SplitDirection::Vertical => position.y - split.top as isize,
+        };
+        if delta != 0 {
+            tab.resize_split_by(index, delta);
+        }
+    }
+    state.drag = Some(drag);
+    true
+}
+
+/// Start dragging the borders under the last mouse event, if a button was
+/// held down over a border of `tab`. As in tmux, there is nothing to do
+/// otherwise.
+pub fn start_drag(tab: &Tab) {
+    let mut state = MOUSE.lock();
+    let Some(last) = state
+        .last
+        .filter(|last| last.tab_id == tab.tab_id() && last.pressed)
+    else {
+        return;
+    };
+    let splits = splits_at(&tab.iter_splits(), last.x, last.y);
+    if !splits.is_empty() {
+        state.drag = Some(Drag {
+            tab_id: last.tab_id,
+            splits,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(index: usize, direction: SplitDirection, left: usize, top: usize) -> PositionedSplit {
+        PositionedSplit {
+            index,
+            direction,
+            left,
+            top,
+            size: 10,
+            first_cells: 0,
+            second_cells: 0,
+        }
+    }
+
+    #[test]
+    fn borders_under_the_mouse() {
+        // Two panes side by side, the right one split top and bottom.
+        let splits = [
+            split(0, SplitDirection::Horizontal, 20, 0),
+            split(1, SplitDirection::Vertical, 21, 5),
+        ];
+        assert_eq!(
+            splits_at(&splits, 20, 3),
+            vec![(0, SplitDirection::Horizontal)]
+        );
+        assert_eq!(
+            splits_at(&splits, 25, 5),
+            vec![(1, SplitDirection::Vertical)]
+        );
+        assert_eq!(splits_at(&splits, 20, 10), vec![]);
+        assert_eq!(splits_at(&splits, 19, 3), vec![]);
+        assert_eq!(splits_at(&splits, 10, 5), vec![]);
+    }
+
+    #[test]
+    fn where_borders_meet_both_are_dragged() {
+        let splits = [
+            split(0, SplitDirection::Vertical, 0, 5),
+            split(1, SplitDirection::Horizontal, 4, 0),
+        ];
+        assert_eq!(
+            splits_at(&splits, 4, 5),
+            vec![
+                (0, SplitDirection::Vertical),
+                (1, SplitDirection::Horizontal)
+            ]
+        );
+    }
+}
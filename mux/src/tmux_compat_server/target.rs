@@ -63,7 +63,7 @@ pub fn parse_target(target: &str) -> Result<TmuxTarget> {
 
     // Handle bare pane ID: `%N` with no colon or dot.
     if target.starts_with('%') && !target.contains(':') && !target.contains('.') {
-        let id = parse_id_number(&target[1..])?;
+        let id = parse_id(target, "pane")?;
         return Ok(TmuxTarget {
             pane: Some(PaneRef::Id(id)),
             ..Default::default()
@@ -72,7 +72,7 @@ pub fn parse_target(target: &str) -> Result<TmuxTarget> {
 
     // Handle bare window ID: `@N` with no colon or dot.
     if target.starts_with('@') && !target.contains(':') && !target.contains('.') {
-        let id = parse_id_number(&target[1..])?;
+        let id = parse_id(target, "window")?;
         return Ok(TmuxTarget {
             window: Some(WindowRef::Id(id)),
             ..Default::default()
@@ -81,7 +81,7 @@ pub fn parse_target(target: &str) -> Result<TmuxTarget> {
 
     // Handle bare session ID: `$N` with no colon or dot.
     if target.starts_with('$') && !target.contains(':') && !target.contains('.') {
-        let id = parse_id_number(&target[1..])?;
+        let id = parse_id(target, "session")?;
         return Ok(TmuxTarget {
             session: Some(SessionRef::Id(id)),
             ..Default::default()
@@ -111,14 +111,25 @@ pub fn parse_target(target: &str) -> Result<TmuxTarget> {
     })
 }
 
+/// Parse the target of a command that takes a session, such as
+/// `has-session`. As with tmux, a bare name is a session's rather than a
+/// window's.
+pub fn parse_session_target(target: &str) -> Result<TmuxTarget> {
+    if target.contains(':') || target.starts_with(['@', '%']) {
+        parse_target(target)
+    } else {
+        parse_target(&format!("{}:", target))
+    }
+}
+
 /// Parse a session reference from the text before the first `:`.
 fn parse_session_ref(s: &str) -> Result<Option<SessionRef>> {
     if s.is_empty() {
         return Ok(None);
     }
 
-    if let Some(rest) = s.strip_prefix('$') {
-        let id = parse_id_number(rest)?;
+    if s.starts_with('$') {
+        let id = parse_id(s, "session")?;
         Ok(Some(SessionRef::Id(id)))
     } else {
         Ok(Some(SessionRef::Name(s.to_string())))
@@ -168,8 +179,8 @@ fn parse_window_ref(s: &str) -> Result<Option<WindowRef>> {
         return Ok(None);
     }
 
-    if let Some(rest) = s.strip_prefix('@') {
-        let id = parse_id_number(rest)?;
+    if s.starts_with('@') {
+        let id = parse_id(s, "window")?;
         Ok(Some(WindowRef::Id(id)))
     } else if let Ok(index) = s.parse::<u64>() {
         Ok(Some(WindowRef::Index(index)))
@@ -180,23 +191,23 @@ fn parse_window_ref(s: &str) -> Result<Option<WindowRef>> {
 
 /// Parse a pane reference token.
 fn parse_pane_ref(s: &str) -> Result<PaneRef> {
-    if let Some(rest) = s.strip_prefix('%') {
-        let id = parse_id_number(rest)?;
+    if s.starts_with('%') {
+        let id = parse_id(s, "pane")?;
         Ok(PaneRef::Id(id))
     } else if let Ok(index) = s.parse::<u64>() {
         Ok(PaneRef::Index(index))
     } else {
-        bail!("invalid pane reference: {s:?}");
+        bail!("can't find pane: {}", s);
     }
 }
 
-/// Parse the numeric portion after a sigil (`$`, `@`, or `%`).
-fn parse_id_number(s: &str) -> Result<u64> {
-    if s.is_empty() {
-        bail!("expected a number after sigil");
-    }
-    s.parse::<u64>()
-        .map_err(|_| anyhow::anyhow!("invalid numeric id: {s:?}"))
+/// Parse the number after the sigil (`$`, `@`, or `%`) of `token`, an ID of
+/// a `kind` of object. Like tmux, a bad ID is reported as one that can't be
+/// found.
+fn parse_id(token: &str, kind: &str) -> Result<u64> {
+    token[1..]
+        .parse::<u64>()
+        .map_err(|_| anyhow::anyhow!("can't find {}: {}", kind, token))
 }
 
 #[cfg(test)]
@@ -404,24 +415,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn session_targets() {
+        assert_eq!(
+            parse_session_target("work").unwrap(),
+            TmuxTarget {
+                session: Some(SessionRef::Name("work".to_string())),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parse_session_target("$3").unwrap(),
+            TmuxTarget {
+                session: Some(SessionRef::Id(3)),
+                ..Default::default()
+            }
+        );
+        assert_eq!(parse_session_target("work:1").unwrap(), parse("work:1"));
+        assert_eq!(parse_session_target("%2").unwrap(), parse("%2"));
+    }
+
+    fn error(target: &str) -> String {
+        parse_target(target).unwrap_err().to_string()
+    }
+
     #[test]
     fn invalid_pane_ref() {
-        assert!(parse_target(":0.abc").is_err());
+        assert_eq!(error(":0.abc"), "can't find pane: abc");
     }
 
     #[test]
     fn invalid_session_id() {
-        assert!(parse_target("$abc").is_err());
+        assert_eq!(error("$abc"), "can't find session: $abc");
+        assert_eq!(error("$:0"), "can't find session: $");
     }
 
     #[test]
     fn invalid_window_id() {
-        assert!(parse_target(":@abc").is_err());
+        assert_eq!(error(":@abc"), "can't find window: @abc");
     }
 
     #[test]
     fn invalid_pane_id() {
-        assert!(parse_target("%xyz").is_err());
+        assert_eq!(error("%xyz"), "can't find pane: %xyz");
+        assert_eq!(error("%"), "can't find pane: %");
     }
 
     #[test]
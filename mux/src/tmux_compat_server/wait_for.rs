@@ -0,0 +1,281 @@
+//! Channels for `wait-for`.
+//!
+//! A client waits on a channel until another client signals it (`-S`); a
+//! signal sent while nobody is waiting is kept for the next waiter. A
+//! channel can also be used as a lock: `-L` takes it, queueing behind the
+//! other clients that want it while it is held, and `-U` releases it to the
+//! next client in the queue. As in tmux, a lock isn't owned by the client
+//! that took it, so any client may release it.
+//!
+//! A waiting client leaves the queue when its connection closes or, with our
+//! `-T` extension, when its timeout runs out.
+//!
+//! Each server has its own channels, so a channel of a named server never
+//! wakes a client of the default server.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use async_channel::{Receiver, Sender};
+
+use super::named_server::PerServer;
+
+#[derive(Debug, Default)]
+pub(super) struct WaitChannel {
+    /// Whether a signal has been sent but no one has consumed it yet.
+    woken: bool,
+    /// Whether the channel is locked.
+    locked: bool,
+    /// Clients waiting for a signal. When signaled, we send `()` to all.
+    waiters: Vec<Sender<()>>,
+    /// Clients waiting for the lock, in the order they asked for it.
+    lockers: VecDeque<Sender<()>>,
+}
+
+impl WaitChannel {
+    /// Whether the channel holds no state and can be forgotten.
+    fn is_idle(&self) -> bool {
+        !self.woken && !self.locked && self.waiters.is_empty() && self.lockers.is_empty()
+    }
+}
+
+/// The wait channels of each server, keyed by channel name.
+pub(super) static WAIT_CHANNELS: LazyLock<PerServer<HashMap<String, WaitChannel>>> =
+    LazyLock::new(Default::default);
+
+/// Run `f` on the channel `name` of `server`, forgetting the channel
+/// afterwards if it was left idle.
+fn with_channel<R>(server: Option<&str>, name: &str, f: impl FnOnce(&mut WaitChannel) -> R) -> R {
+    let mut channels = WAIT_CHANNELS.lock(server);
+    let channel = channels.entry(name.to_string()).or_default();
+    let result = f(channel);
+    if channel.is_idle() {
+        channels.remove(name);
+    }
+    result
+}
+
+/// Signal the channel `name`, waking everyone waiting on it, or the next
+/// client to wait if there is nobody yet.
+pub fn signal(server: Option<&str>, name: &str) {
+    with_channel(server, name, |channel| {
+        let mut woke = false;
+        for waiter in channel.waiters.drain(..) {
+            woke |= waiter.try_send(()).is_ok();
+        }
+        if !woke {
+            channel.woken = true;
+        }
+    });
+}
+
+/// Release the lock on the channel `name`, handing it to the next client
+/// still waiting for it.
+pub fn unlock(server: Option<&str>, name: &str) -> Result<(), String> {
+    with_channel(server, name, |channel| {
+        if !channel.locked {
+            return Err(format!("channel {} not locked", name));
+        }
+        while let Some(locker) = channel.lockers.pop_front() {
+            if locker.try_send(()).is_ok() {
+                return Ok(());
+            }
+        }
+        channel.locked = false;
+        Ok(())
+    })
+}
+
+/// Wait for the channel `name` to be signaled.
+pub async fn wait(
+    server: Option<&str>,
+    name: &str,
+    client_gone: Option<Receiver<()>>,
+    timeout: Option<Duration>,
+) -> Result<(), String> {
+    let receiver = with_channel(server, name, |channel| {
+        if channel.woken {
+            // Already signaled — consume the signal.
+            channel.woken = false;
+            return None;
+        }
+        let (sender, receiver) = async_channel::bounded(1);
+        channel.waiters.push(sender);
+        Some(receiver)
+    });
+    let Some(receiver) = receiver else {
+        return Ok(());
+    };
+
+    let result = wake(&receiver, client_gone, timeout, name).await;
+    drop(receiver);
+    if result.is_err() {
+        // Leave the queue.
+        with_channel(server, name, |channel| {
+            channel.waiters.retain(|w| !w.is_closed())
+        });
+    }
+    result
+}
+
+/// Take the lock on the channel `name`, waiting for it if it is held.
+pub async fn lock(
+    server: Option<&str>,
+    name: &str,
+    client_gone: Option<Receiver<()>>,
+    timeout: Option<Duration>,
+) -> Result<(), String> {
+    let receiver = with_channel(server, name, |channel| {
+        if !channel.locked {
+            channel.locked = true;
+            return None;
+        }
+        let (sender, receiver) = async_channel::bounded(1);
+        channel.lockers.push_back(sender);
+        Some(receiver)
+    });
+    let Some(receiver) = receiver else {
+        return Ok(());
+    };
+
+    let result = wake(&receiver, client_gone, timeout, name).await;
+    if result.is_err() {
+        // The lock may have been handed over just as we gave up, in which
+        // case it goes to the next in line.
+        let handed_over = receiver.try_recv().is_ok();
+        drop(receiver);
+        with_channel(server, name, |channel| {
+            channel.lockers.retain(|l| !l.is_closed())
+        });
+        if handed_over {
+            unlock(server, name)?;
+        }
+    }
+    result
+}
+
+/// Wait for `receiver` to be woken, giving up when the client's connection
+/// closes (`client_gone` is closed) or `timeout` runs out.
+async fn wake(
+    receiver: &Receiver<()>,
+    client_gone: Option<Receiver<()>>,
+    timeout: Option<Duration>,
+    name: &str,
+) -> Result<(), String> {
+    let woken = async {
+        let _ = receiver.recv().await;
+        Ok(())
+    };
+    let gone = async {
+        match client_gone {
+            Some(gone) => {
+                let _ = gone.recv().await;
+                Err("client detached".to_string())
+            }
+            None => smol::future::pending().await,
+        }
+    };
+    let timed_out = async {
+        match timeout {
+            Some(timeout) => {
+                smol::Timer::after(timeout).await;
+                Err(format!("timed out waiting for channel {}", name))
+            }
+            None => smol::future::pending().await,
+        }
+    };
+    smol::future::or(woken, smol::future::or(gone, timed_out)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_known(name: &str) -> bool {
+        WAIT_CHANNELS.lock(None).contains_key(name)
+    }
+
+    #[test]
+    fn signal_before_wait_is_kept() {
+        signal(None, "wf-test-early");
+        smol::block_on(wait(None, "wf-test-early", None, None)).unwrap();
+        assert!(!is_known("wf-test-early"));
+    }
+
+    #[test]
+    fn servers_have_their_own_channels() {
+        let server = Some("/tmp/wf-test-server");
+        signal(server, "wf-test-server");
+        assert!(!is_known("wf-test-server"));
+        smol::block_on(wait(server, "wf-test-server", None, None)).unwrap();
+    }
+
+    #[test]
+    fn signal_wakes_waiter() {
+        smol::block_on(async {
+            let waiter = smol::spawn(wait(None, "wf-test-wake", None, None));
+            while WAIT_CHANNELS
+                .lock(None)
+                .get("wf-test-wake")
+                .is_none_or(|c| c.waiters.is_empty())
+            {
+                smol::future::yield_now().await;
+            }
+            signal(None, "wf-test-wake");
+            waiter.await.unwrap();
+        });
+        assert!(!is_known("wf-test-wake"));
+    }
+
+    #[test]
+    fn lock_is_handed_on_in_order() {
+        smol::block_on(async {
+            lock(None, "wf-test-lock", None, None).await.unwrap();
+            let second = smol::spawn(lock(None, "wf-test-lock", None, None));
+            while WAIT_CHANNELS
+                .lock(None)
+                .get("wf-test-lock")
+                .is_none_or(|c| c.lockers.is_empty())
+            {
+                smol::future::yield_now().await;
+            }
+
+            // Releasing hands the lock over rather than freeing it.
+            unlock(None, "wf-test-lock").unwrap();
+            second.await.unwrap();
+            assert!(WAIT_CHANNELS.lock(None)["wf-test-lock"].locked);
+
+            unlock(None, "wf-test-lock").unwrap();
+            assert_eq!(
+                unlock(None, "wf-test-lock"),
+                Err("channel wf-test-lock not locked".to_string())
+            );
+        });
+        assert!(!is_known("wf-test-lock"));
+    }
+
+    #[test]
+    fn waiters_leave_on_timeout_and_disconnect() {
+        smol::block_on(async {
+            lock(None, "wf-test-leave", None, None).await.unwrap();
+            let result = lock(None, "wf-test-leave", None, Some(Duration::from_millis(10))).await;
+            assert_eq!(
+                result,
+                Err("timed out waiting for channel wf-test-leave".to_string())
+            );
+
+            let (gone_tx, gone_rx) = async_channel::bounded::<()>(1);
+            drop(gone_tx);
+            let result = wait(None, "wf-test-leave", Some(gone_rx), None).await;
+            assert_eq!(result, Err("client detached".to_string()));
+
+            let channels = WAIT_CHANNELS.lock(None);
+            let channel = &channels["wf-test-leave"];
+            assert!(channel.lockers.is_empty());
+            assert!(channel.waiters.is_empty());
+        });
+        unlock(None, "wf-test-leave").unwrap();
+        assert!(!is_known("wf-test-leave"));
+    }
+}
@@ -35,6 +35,10 @@ pub struct Screen {
     /// that we're the primary rather than the alternate screen.
     allow_scrollback: bool,
 
+    /// Overrides the configured scrollback size for this screen,
+    /// eg: when a mux client adjusts the history limit of a pane.
+    scrollback_limit: Option<usize>,
+
     pub(crate) keyboard_stack: Vec<KeyboardEncoding>,
 
     /// Physical, visible height of the screen (not including scrollback)
@@ -80,6 +84,7 @@ impl Screen {
             lines,
             config: Arc::clone(config),
             allow_scrollback,
+            scrollback_limit: None,
             physical_rows,
             physical_cols,
             stable_row_index_offset: 0,
@@ -93,8 +98,29 @@ impl Screen {
         self.keyboard_stack.clear();
     }
 
-    fn scrollback_size(&self) -> usize {
-        scrollback_size(&self.config, self.allow_scrollback)
+    pub(crate) fn scrollback_size(&self) -> usize {
+        match self.scrollback_limit {
+            Some(limit) if self.allow_scrollback => limit,
+            _ => scrollback_size(&self.config, self.allow_scrollback),
+        }
+    }
+
+    /// Override the maximum number of lines of scrollback, or revert to
+    /// the configured size with `None`.  Lines beyond the new limit are
+    /// discarded from the top of the scrollback and spare line storage
+    /// is released; a larger limit lets the storage grow as lines arrive.
+    pub fn set_scrollback_limit(&mut self, limit: Option<usize>) {
+        self.scrollback_limit = limit;
+        let capacity = self.physical_rows.saturating_add(self.scrollback_size());
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+            if self.allow_scrollback {
+                self.stable_row_index_offset += 1;
+            }
+        }
+        if capacity < self.lines.capacity() {
+            self.lines.shrink_to(capacity);
+        }
     }
 
     fn rewrap_lines(
@@ -179,7 +205,7 @@ impl Screen {
         // if the bottom line(s) are whitespace, we'll prune those
         // out first in the rewrap case so that we don't lose any
         // real information off the top of the scrollback
-        let capacity = physical_rows + self.scrollback_size();
+        let capacity = physical_rows.saturating_add(self.scrollback_size());
         while self.lines.len() > capacity
             && self.lines.back().map(Line::is_whitespace).unwrap_or(false)
         {
@@ -248,10 +274,14 @@ impl Screen {
             (cursor.x, cursor_phys)
         };
 
-        let capacity = physical_rows + self.scrollback_size();
-        let current_capacity = self.lines.capacity();
-        if capacity > current_capacity {
-            self.lines.reserve(capacity - current_capacity);
+        // A scrollback limit set on the screen isn't reserved up front;
+        // the storage grows as lines arrive.
+        if self.scrollback_limit.is_none() {
+            let capacity = physical_rows + self.scrollback_size();
+            let current_capacity = self.lines.capacity();
+            if capacity > current_capacity {
+                self.lines.reserve(capacity - current_capacity);
+            }
         }
 
         // If we resized wider and the rewrap resulted in fewer
@@ -678,7 +708,7 @@ impl Screen {
             // Remove the scrolled lines
             num_rows
         } else {
-            let max_allowed = self.physical_rows + self.scrollback_size();
+            let max_allowed = self.physical_rows.saturating_add(self.scrollback_size());
             if self.lines.len() + num_rows >= max_allowed {
                 (self.lines.len() + num_rows) - max_allowed
             } else {
@@ -759,6 +789,24 @@ impl Screen {
         }
     }
 
+    /// Remove the hyperlinks set by the application from every line,
+    /// leaving the text and the other attributes alone.  Implicit
+    /// hyperlinks are made again from the hyperlink rules.
+    pub fn erase_hyperlinks(&mut self, seqno: SequenceNo) {
+        for line in self.lines.iter_mut() {
+            if !line.has_hyperlink() {
+                continue;
+            }
+            for cell in line.cells_mut_for_attr_changes_only() {
+                if matches!(cell.attrs().hyperlink(), Some(link) if !link.is_implicit()) {
+                    cell.attrs_mut().set_hyperlink(None);
+                }
+            }
+            line.invalidate_implicit_hyperlinks(seqno);
+            line.update_last_change_seqno(seqno);
+        }
+    }
+
     pub fn erase_scrollback(&mut self) {
         let len = self.lines.len();
         let to_clear = len - self.physical_rows;
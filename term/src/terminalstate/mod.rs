@@ -744,6 +744,28 @@ impl TerminalState {
         self.screen_mut().erase_scrollback();
     }
 
+    /// Removes the hyperlinks from the text of the current screen and its
+    /// scrollback, as tmux's `clear-history -H` does. Text printed later
+    /// still gets the hyperlink the application has set, if any.
+    pub fn erase_hyperlinks(&mut self) {
+        self.increment_seqno();
+        let seqno = self.seqno;
+        self.screen_mut().erase_hyperlinks(seqno);
+    }
+
+    /// Override the scrollback size of the primary screen, or revert to
+    /// the configured `scrollback_size` with `None`.
+    pub fn set_scrollback_limit(&mut self, limit: Option<usize>) {
+        self.increment_seqno();
+        self.screen.screen.set_scrollback_limit(limit);
+    }
+
+    /// Returns the maximum number of lines of scrollback retained by
+    /// the primary screen.
+    pub fn scrollback_limit(&self) -> usize {
+        self.screen.screen.scrollback_size()
+    }
+
     /// Returns true if the associated application has enabled any of the
     /// supported mouse reporting modes.
     /// This is useful for the hosting GUI application to decide how best
@@ -1289,6 +1289,113 @@ fn test_region_scrollback_limit() {
     assert_eq!(term.screen().visible_row_to_stable_row(4), 7);
 }
 
+#[test]
+fn test_set_scrollback_limit() {
+    let mut term = TestTerm::new(2, 1, 10);
+    term.print("1\n2\n3\n4\n5");
+    assert_eq!(term.screen().visible_row_to_stable_row(0), 3);
+
+    let seqno = term.current_seqno();
+    term.set_scrollback_limit(Some(1));
+    assert!(term.current_seqno() > seqno);
+    assert_eq!(term.scrollback_limit(), 1);
+    assert_all_contents(&term, file!(), line!(), &["3", "4", "5"]);
+    // The lines that are left keep their stable row indices.
+    assert_eq!(term.screen().visible_row_to_stable_row(0), 3);
+
+    term.print("\n6");
+    assert_all_contents(&term, file!(), line!(), &["4", "5", "6"]);
+
+    // Without a limit, the configured scrollback size applies again.
+    term.set_scrollback_limit(None);
+    assert_eq!(term.scrollback_limit(), 10);
+    term.print("\n7\n8");
+    assert_all_contents(&term, file!(), line!(), &["4", "5", "6", "7", "8"]);
+}
+
+#[test]
+fn test_set_scrollback_limit_applies_to_the_primary_screen() {
+    let mut term = TestTerm::new(2, 1, 10);
+    term.print("1\n2\n3\n4");
+    term.set_mode("?1049", true);
+    term.set_scrollback_limit(Some(0));
+    assert_eq!(term.scrollback_limit(), 0);
+    term.set_mode("?1049", false);
+    assert_all_contents(&term, file!(), line!(), &["3", "4"]);
+}
+
+#[test]
+fn test_screen_set_scrollback_limit() {
+    let mut term = TestTerm::new(2, 1, 10);
+    term.print("1\n2\n3\n4");
+    let screen = term.screen_mut();
+    screen.set_scrollback_limit(Some(1));
+    assert_eq!(screen.scrollback_size(), 1);
+    assert_eq!(screen.all_lines().len(), 3);
+    screen.set_scrollback_limit(None);
+    assert_eq!(screen.scrollback_size(), 10);
+    assert_eq!(screen.all_lines().len(), 3);
+
+    // A huge limit neither overflows nor allocates the lines up front.
+    screen.set_scrollback_limit(Some(usize::MAX));
+    assert_eq!(screen.scrollback_size(), usize::MAX);
+    assert_eq!(screen.all_lines().len(), 3);
+    term.print("\n5");
+    assert_eq!(term.screen().all_lines().len(), 4);
+    term.resize(TerminalSize {
+        rows: 3,
+        cols: 1,
+        pixel_width: 0,
+        pixel_height: 0,
+        dpi: 0,
+    });
+    assert_eq!(term.screen().all_lines().len(), 4);
+
+    // The alternate screen has no scrollback to limit.
+    term.set_mode("?1049", true);
+    let screen = term.screen_mut();
+    screen.set_scrollback_limit(Some(5));
+    assert_eq!(screen.scrollback_size(), 0);
+}
+
+#[test]
+fn test_erase_hyperlinks() {
+    let mut term = TestTerm::new(2, 5, 0);
+    let link = Arc::new(Hyperlink::new("http://example.com"));
+    let mut bold = CellAttributes::default();
+    bold.set_intensity(Intensity::Bold);
+    let mut linked = bold.clone();
+    linked.set_hyperlink(Some(Arc::clone(&link)));
+
+    term.print("\x1b[1m");
+    term.hyperlink(&link);
+    term.print("hello");
+    assert_lines_equal(
+        file!(),
+        line!(),
+        &term.screen().visible_lines(),
+        &[Line::from_text("hello", &linked, SEQ_ZERO, None), "".into()],
+        Compare::TEXT | Compare::ATTRS,
+    );
+
+    let seqno = term.current_seqno();
+    term.erase_hyperlinks();
+    term.assert_dirty_lines(seqno, &[0], None);
+    assert_lines_equal(
+        file!(),
+        line!(),
+        &term.screen().visible_lines(),
+        &[Line::from_text("hello", &bold, SEQ_ZERO, None), "".into()],
+        Compare::TEXT | Compare::ATTRS,
+    );
+
+    // The application's hyperlink still applies to what it prints next.
+    term.print("hi");
+    let lines = term.screen().visible_lines();
+    let cell = lines[1].visible_cells().next().unwrap();
+    assert_eq!(cell.attrs().hyperlink(), Some(&link));
+}
+
 #[test]
 fn test_hyperlinks() {
     let mut term = TestTerm::new(3, 5, 0);
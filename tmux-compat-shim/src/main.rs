@@ -2,10 +2,14 @@
 //!
 //! This binary is named `tmux` and placed on `$PATH` ahead of the real tmux
 //! when WezTerm's tmux-compat mode is enabled.  It connects to the CC protocol
-//! server (Phase 3) over a Unix domain socket, sends one command, reads the
-//! `%begin`/`%end` response, prints the body to stdout, and exits.
+//! server (Phase 3) over a Unix domain socket, sends one command as a one-shot
+//! request, prints the output and error it gets back to stdout and stderr,
+//! and exits with the command's status.  With `WEZTERM_TMUX_CC_ATTACH` set it
+//! attaches in control mode instead, reading the `%begin`/`%end` response
+//! after the handshake.
 
-// std::io::{Read, Write} used via UFCS in run_cc_exchange and raw_read_line.
+// std::io::{Read, Write} used via UFCS in run_cc_exchange, run_request and
+// raw_read_line.
 
 // ---------------------------------------------------------------------------
 // Command modes
@@ -15,8 +19,15 @@
 enum Action {
     /// Print version string and exit 0.
     Version,
-    /// Session management command (new-session, attach-session) — no-op, exit 0.
+    /// Bare `tmux` — no-op, exit 0.
     SessionNoOp,
+    /// `attach-session` — we're already in a session, so this only copies
+    /// the `update-environment` variables into the target session, unless
+    /// `-E` was given.
+    Attach {
+        target: Option<String>,
+        update_environment: bool,
+    },
     /// Forward a one-shot command to the CC server.
     Command(String),
 }
@@ -28,9 +39,10 @@ enum Action {
 /// Parse the process's command-line arguments into an [`Action`].
 ///
 /// The binary is invoked as `tmux [flags] [command] [args...]`.  We strip
-/// connection-mode flags (`-C`, `-CC`, `-L`, `-S`, `-f`) and detect special
+/// connection-mode flags (`-C`, `-CC`, `-L`, `-S`, `-f`; see
+/// [`named_server_socket`] for `-L` and `-S`) and detect special
 /// cases (version, session commands).  Everything else is reconstructed into
-/// the command text that the CC server's `command_parser::parse_command()`
+/// the command text that the CC server's `command_parser::parse_command_list()`
 /// expects.
 fn parse_args(args: &[String]) -> Action {
     // Skip argv[0] (the binary name).
@@ -42,10 +54,95 @@ fn parse_args(args: &[String]) -> Action {
     }
 
     // Strip global flags that precede the command name.
+    let i = skip_global_flags(args);
+    if args.get(i).map(String::as_str) == Some("-V") {
+        return Action::Version;
+    }
+
+    let rest = &args[i.min(args.len())..];
+    if rest.is_empty() {
+        return Action::SessionNoOp;
+    }
+
+    // Detect session management commands.
+    // attach-session/attach/a don't switch sessions (we're already in one).
+    // new-session/new are forwarded to the server so they can create workspaces
+    // and return pane IDs when -P is specified.
+    match rest[0].as_str() {
+        "attach-session" | "attach" | "a" => {
+            let target = rest
+                .iter()
+                .position(|arg| arg == "-t")
+                .and_then(|i| rest.get(i + 1))
+                .cloned();
+            return Action::Attach {
+                target,
+                update_environment: !rest.iter().any(|arg| arg == "-E"),
+            };
+        }
+        _ => {}
+    }
+
+    // Everything else: reconstruct the command text.
+    // Like tmux, an argument ending in `;` ends the command, so
+    // `tmux neww \; splitw` sends a command list; `\;` keeps a literal `;`.
+    let mut words = Vec::with_capacity(rest.len());
+    for arg in rest {
+        match arg.strip_suffix(';') {
+            Some(word) if word.ends_with('\\') => {
+                words.push(quote_arg(&format!("{};", &word[..word.len() - 1])));
+            }
+            Some(word) => {
+                if !word.is_empty() {
+                    words.push(quote_arg(word));
+                }
+                words.push(";".to_string());
+            }
+            None => words.push(quote_arg(arg)),
+        }
+    }
+
+    Action::Command(words.join(" "))
+}
+
+/// Quote one argument for the command text.
+///
+/// We need to re-quote arguments that contain shell-special characters
+/// so the server's shell_words-based parser can split them correctly.
+/// Notably, `#` is treated as a comment character by POSIX shell parsing,
+/// so format strings like `#{pane_id}` must be quoted.
+fn quote_arg(a: &str) -> String {
+    if a.is_empty()
+        || a.contains(' ')
+        || a.contains('"')
+        || a.contains('\'')
+        || a.contains('#')
+        || a.contains('{')
+        || a.contains('}')
+        || a.contains('(')
+        || a.contains(')')
+        || a.contains('$')
+        || a.contains('`')
+        || a.contains('\\')
+        || a.contains(';')
+        || a.contains('&')
+        || a.contains('|')
+        || a.contains('>')
+        || a.contains('<')
+    {
+        // Shell-quote: wrap in single quotes, escaping existing single quotes.
+        format!("'{}'", a.replace('\'', "'\\''"))
+    } else {
+        a.to_string()
+    }
+}
+
+/// Return the index of the first argument after the global flags, i.e. the
+/// command name (or `-V`).  `args` must not include argv[0].
+fn skip_global_flags(args: &[String]) -> usize {
     let mut i = 0;
     while i < args.len() {
         match args[i].as_str() {
-            "-V" => return Action::Version,
             // Connection-mode flags — skip.
             "-C" | "-CC" => {
                 i += 1;
@@ -57,59 +154,205 @@ fn parse_args(args: &[String]) -> Action {
             _ => break,
         }
     }
+    i
+}
 
-    let rest = &args[i..];
-    if rest.is_empty() {
-        return Action::SessionNoOp;
+/// The socket of the named server chosen with `-L name` or `-S path`, if any.
+///
+/// Like tmux, `-S` wins over `-L`, and `-L name` is the socket `name` in
+/// `$TMUX_TMPDIR/tmux-UID` (`tmpdir` is `$TMUX_TMPDIR`, defaulting to `/tmp`).
+/// `args` must not include argv[0].
+#[cfg(unix)]
+fn named_server_socket(
+    args: &[String],
+    cwd: &std::path::Path,
+    tmpdir: Option<&str>,
+    uid: u32,
+) -> Option<std::path::PathBuf> {
+    let mut label = None;
+    let mut path = None;
+    let end = skip_global_flags(args).min(args.len());
+    let mut i = 0;
+    while i < end {
+        match args[i].as_str() {
+            "-L" => label = args.get(i + 1),
+            "-S" => path = args.get(i + 1),
+            _ => {}
+        }
+        i += if matches!(args[i].as_str(), "-L" | "-S" | "-f") {
+            2
+        } else {
+            1
+        };
     }
 
-    // Detect session management commands.
-    // attach-session/attach/a are no-ops (we're already in a session).
-    // new-session/new are forwarded to the server so they can create workspaces
-    // and return pane IDs when -P is specified.
-    match rest[0].as_str() {
-        "attach-session" | "attach" | "a" => {
-            return Action::SessionNoOp;
-        }
-        _ => {}
+    if let Some(path) = path {
+        return Some(cwd.join(path));
     }
+    let tmpdir = tmpdir.filter(|dir| !dir.is_empty()).unwrap_or("/tmp");
+    label.map(|label| {
+        std::path::Path::new(tmpdir)
+            .join(format!("tmux-{}", uid))
+            .join(label)
+    })
+}
 
-    // Everything else: reconstruct the command text.
-    // We need to re-quote arguments that contain shell-special characters
-    // so the server's shell_words-based parser can split them correctly.
-    // Notably, `#` is treated as a comment character by POSIX shell parsing,
-    // so format strings like `#{pane_id}` must be quoted.
-    let command_text = rest
+/// Whether the command text starts a server if there is none, as
+/// `new-session` and `start-server` do in tmux.
+fn starts_server(command: &str) -> bool {
+    matches!(
+        command.split_whitespace().next(),
+        Some("new-session") | Some("new") | Some("start-server") | Some("start")
+    )
+}
+
+/// The variables named by the server's `update-environment` option, which
+/// like tmux we copy from our environment into the session's when creating
+/// or attaching to a session.
+fn update_environment_names(socket_path: &str) -> Vec<String> {
+    match execute_command(socket_path, "show-options -gqv update-environment") {
+        Ok(reply) if reply.status == 0 => reply
+            .stdout
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Our values of the variables `names`, `None` for those that aren't set.
+fn client_environment(names: &[String]) -> Vec<(String, Option<String>)> {
+    names
         .iter()
-        .map(|a| {
-            if a.is_empty()
-                || a.contains(' ')
-                || a.contains('"')
-                || a.contains('\'')
-                || a.contains('#')
-                || a.contains('{')
-                || a.contains('}')
-                || a.contains('(')
-                || a.contains(')')
-                || a.contains('$')
-                || a.contains('`')
-                || a.contains('\\')
-                || a.contains(';')
-                || a.contains('&')
-                || a.contains('|')
-                || a.contains('>')
-                || a.contains('<')
-            {
-                // Shell-quote: wrap in single quotes, escaping existing single quotes.
-                format!("'{}'", a.replace('\'', "'\\''"))
-            } else {
-                a.clone()
-            }
+        .map(|name| (name.clone(), std::env::var(name).ok()))
+        .collect()
+}
+
+/// Whether the command text starts with `new-session`.
+fn starts_new_session(command: &str) -> bool {
+    matches!(
+        command.split_whitespace().next(),
+        Some("new-session") | Some("new")
+    )
+}
+
+/// Add `-e` flags for the variables in `vars` that are set to the
+/// `new-session` at the start of `command`. Unless `-E` is given: then, as
+/// in tmux, the session doesn't get them.
+fn add_new_session_env(command: &str, vars: &[(String, Option<String>)]) -> String {
+    let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+    if !matches!(name, "new-session" | "new") || rest.split(' ').any(|word| word == "-E") {
+        return command.to_string();
+    }
+    let mut words = vec![name.to_string()];
+    for (var, value) in vars {
+        if let Some(value) = value {
+            words.push("-e".to_string());
+            words.push(quote_arg(&format!("{}={}", var, value)));
+        }
+    }
+    if !rest.is_empty() {
+        words.push(rest.to_string());
+    }
+    words.join(" ")
+}
+
+/// The commands that copy `vars` into the session `target` on attach: those
+/// that aren't set in our environment are marked as removed.
+fn attach_env_commands(target: Option<&str>, vars: &[(String, Option<String>)]) -> String {
+    let target = match target {
+        Some(target) => format!(" -t {}", quote_arg(target)),
+        None => String::new(),
+    };
+    vars.iter()
+        .map(|(var, value)| match value {
+            Some(value) => format!(
+                "set-environment{} {} {}",
+                target,
+                quote_arg(var),
+                quote_arg(value)
+            ),
+            None => format!("set-environment{} -r {}", target, quote_arg(var)),
         })
         .collect::<Vec<_>>()
-        .join(" ");
+        .join(" ; ")
+}
 
-    Action::Command(command_text)
+/// Rewrite the path argument of `load-buffer`/`save-buffer` so that the
+/// server, which has its own working directory and no access to our stdin,
+/// can use it.
+///
+/// Relative paths are resolved against `cwd`.  For `load-buffer -`, `stdin`
+/// is copied into a new temporary file, readable only by us, and its path
+/// is returned; the file is removed when the path is dropped, once the
+/// command has run.  `save-buffer -` is left alone: the server returns the
+/// buffer as the command output, which we print.
+fn localize_buffer_path(
+    args: &mut [String],
+    cwd: &std::path::Path,
+    stdin: &mut dyn std::io::Read,
+) -> anyhow::Result<Option<tempfile::TempPath>> {
+    if args.len() < 2 {
+        return Ok(None);
+    }
+    let start = 1 + skip_global_flags(&args[1..]);
+    let is_load = match args.get(start).map(String::as_str) {
+        Some("load-buffer") | Some("loadb") => true,
+        Some("save-buffer") | Some("saveb") => false,
+        _ => return Ok(None),
+    };
+
+    // The path is the final argument; make sure it isn't a flag's value.
+    let last = args.len() - 1;
+    if last <= start || matches!(args[last - 1].as_str(), "-b" | "-t") {
+        return Ok(None);
+    }
+
+    if args[last] == "-" {
+        if !is_load {
+            return Ok(None);
+        }
+        let mut temp = tempfile::Builder::new()
+            .prefix("wezterm-tmux-load-buffer-")
+            .tempfile()?;
+        std::io::copy(stdin, &mut temp)?;
+        let temp = temp.into_temp_path();
+        args[last] = temp.to_string_lossy().into_owned();
+        return Ok(Some(temp));
+    }
+
+    let path = std::path::Path::new(&args[last]);
+    if path.is_relative() {
+        args[last] = cwd.join(path).to_string_lossy().into_owned();
+    }
+    Ok(None)
+}
+
+/// Make the relative paths given to `source-file` absolute, since the server
+/// has its own working directory.
+fn localize_source_paths(args: &mut [String], cwd: &std::path::Path) {
+    if args.len() < 2 {
+        return;
+    }
+    let start = 1 + skip_global_flags(&args[1..]);
+    if !matches!(
+        args.get(start).map(String::as_str),
+        Some("source-file") | Some("source")
+    ) {
+        return;
+    }
+
+    let mut i = start + 1;
+    // Flags stop at the first path; `-t` takes a value.
+    while i < args.len() && args[i].starts_with('-') && args[i].len() > 1 {
+        i += if args[i] == "-t" { 2 } else { 1 };
+    }
+    for arg in args.iter_mut().skip(i) {
+        let path = std::path::Path::new(arg.as_str());
+        if path.is_relative() && !arg.starts_with('~') && !arg.contains("#{") {
+            *arg = cwd.join(path).to_string_lossy().into_owned();
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -124,6 +367,35 @@ struct CcResponse {
     is_error: bool,
 }
 
+/// What the server sent back for a command.
+struct Reply {
+    /// The exit status: 0 unless a command failed.
+    status: i32,
+    /// The output of the commands that ran.
+    stdout: String,
+    /// The error that stopped the command list, if any.
+    stderr: String,
+}
+
+impl From<CcResponse> for Reply {
+    /// A control mode response has the error in place of the output.
+    fn from(response: CcResponse) -> Self {
+        if response.is_error {
+            Reply {
+                status: 1,
+                stdout: String::new(),
+                stderr: response.body,
+            }
+        } else {
+            Reply {
+                status: 0,
+                stdout: response.body,
+                stderr: String::new(),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 /// Skip the initial handshake the CC server sends when a client connects.
 ///
@@ -147,6 +419,9 @@ fn skip_handshake(reader: &mut impl std::io::BufRead) -> anyhow::Result<()> {
         if line.trim().starts_with("%end ") {
             break;
         }
+        if let Some(reason) = line.trim().strip_prefix("%exit") {
+            anyhow::bail!("server exited: {}", reason.trim());
+        }
     }
 
     // Phase 2: drain any %-prefixed notification lines that follow.
@@ -258,6 +533,9 @@ fn run_cc_exchange(
     let mut accum = String::new();
     let mut buf = [0u8; 4096];
 
+    // The server waits for us to say that we attach before its handshake.
+    std::io::Write::write_all(&mut stream, format!("{}\n", CONTROL_LINE).as_bytes())?;
+    std::io::Write::flush(&mut stream)?;
     if verbose {
         eprintln!("[tmux-shim] waiting for handshake...");
     }
@@ -270,6 +548,9 @@ fn run_cc_exchange(
         if line.trim().starts_with("%end ") {
             break;
         }
+        if let Some(reason) = line.trim().strip_prefix("%exit") {
+            anyhow::bail!("server exited: {}", reason.trim());
+        }
     }
     // Drain %-prefixed notification lines.
     // Peek at accumulated data to see if more %-lines follow.
@@ -337,37 +618,184 @@ fn run_cc_exchange(
     }
 }
 
-/// Connect to the CC server, send a command, and return the response.
+/// Read the token of the TCP listener at `address` from the file named by the
+/// fourth field of `tmux_env`, the value of `$TMUX` that WezTerm gave us.
+fn listener_token(address: &str, tmux_env: Option<&str>) -> anyhow::Result<String> {
+    let mut fields = tmux_env.unwrap_or("").splitn(4, ',');
+    let token_file = match (fields.next(), fields.nth(2)) {
+        (Some(tmux_address), Some(path)) if tmux_address == address && !path.is_empty() => path,
+        _ => anyhow::bail!(
+            "can't authenticate to {}: $TMUX doesn't name its token file",
+            address
+        ),
+    };
+    let token = std::fs::read_to_string(token_file)
+        .map_err(|e| anyhow::anyhow!("can't read the token file {}: {}", token_file, e))?;
+    Ok(token.trim().to_string())
+}
+
+/// The first line of a one-shot request; the command list follows.
+const REQUEST_LINE: &str = "request";
+
+/// The first line of a control mode client, before the server's handshake.
+const CONTROL_LINE: &str = "control";
+
+/// Parse the `%result <status> <stdout length> <stderr length>` line that
+/// starts the reply to a one-shot request.
+fn parse_result_header(header: &str) -> anyhow::Result<(i32, usize, usize)> {
+    let fields: Vec<&str> = header.split(' ').collect();
+    let parsed = match fields.as_slice() {
+        ["%result", status, stdout, stderr] => status
+            .parse()
+            .ok()
+            .zip(stdout.parse().ok())
+            .zip(stderr.parse().ok())
+            .map(|((status, stdout), stderr)| (status, stdout, stderr)),
+        _ => None,
+    };
+    parsed.ok_or_else(|| anyhow::anyhow!("unexpected reply from the server: {}", header))
+}
+
+/// Run a one-shot request on an already-connected stream.
+///
+/// The server sends no handshake or notifications: just a `%result` line
+/// followed by the output and error it announces. Reads are raw for the
+/// same reason as in [`run_cc_exchange`].
+fn run_request(
+    mut stream: impl std::io::Read + std::io::Write,
+    command: &str,
+    verbose: bool,
+) -> anyhow::Result<Reply> {
+    if verbose {
+        eprintln!("[tmux-shim] sending request: {}", command);
+    }
+    let request = format!("{}\n{}\n", REQUEST_LINE, command);
+    std::io::Write::write_all(&mut stream, request.as_bytes())?;
+    std::io::Write::flush(&mut stream)?;
+
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut read_more = |data: &mut Vec<u8>| -> anyhow::Result<()> {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            anyhow::bail!("connection closed");
+        }
+        data.extend_from_slice(&buf[..n]);
+        Ok(())
+    };
+    let header_len = loop {
+        if let Some(pos) = data.iter().position(|&b| b == b'\n') {
+            break pos;
+        }
+        read_more(&mut data)?;
+    };
+    let header = String::from_utf8_lossy(&data[..header_len])
+        .trim()
+        .to_string();
+    data.drain(..=header_len);
+    if verbose {
+        eprintln!("[tmux-shim] reply: {:?}", header);
+    }
+    if let Some(reason) = header.strip_prefix("%exit") {
+        anyhow::bail!("server exited: {}", reason.trim());
+    }
+    let (status, stdout_len, stderr_len) = parse_result_header(&header)?;
+    while data.len() < stdout_len + stderr_len {
+        read_more(&mut data)?;
+    }
+    Ok(Reply {
+        status,
+        stdout: String::from_utf8_lossy(&data[..stdout_len]).into_owned(),
+        stderr: String::from_utf8_lossy(&data[stdout_len..stdout_len + stderr_len]).into_owned(),
+    })
+}
+
+/// Send `command` on a connected stream: as a one-shot request, or with a
+/// control mode attach if `WEZTERM_TMUX_CC_ATTACH` is set.
+fn run_exchange(
+    stream: impl std::io::Read + std::io::Write,
+    command: &str,
+    verbose: bool,
+) -> anyhow::Result<Reply> {
+    if std::env::var("WEZTERM_TMUX_CC_ATTACH").is_ok() {
+        run_cc_exchange(stream, command, verbose).map(Reply::from)
+    } else {
+        run_request(stream, command, verbose)
+    }
+}
+
+/// The error for a failed connection to the CC server at `address`, in
+/// tmux's words.
+fn connect_error(address: &str, err: std::io::Error) -> anyhow::Error {
+    match err.kind() {
+        std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => {
+            anyhow::anyhow!("no server running on {}", address)
+        }
+        _ => anyhow::anyhow!("error connecting to {} ({})", address, err),
+    }
+}
+
+/// Connect to the CC server, send a command, and return the reply.
 ///
 /// Supports two address formats in `WEZTERM_TMUX_CC`:
 /// - `tcp:HOST:PORT` — connect via TCP (used on Windows)
 /// - anything else — treat as a Unix domain socket path
-fn execute_command(socket_path: &str, command: &str) -> anyhow::Result<CcResponse> {
+fn execute_command(socket_path: &str, command: &str) -> anyhow::Result<Reply> {
     let verbose = std::env::var("WEZTERM_TMUX_CC_VERBOSE").is_ok();
 
     if let Some(addr) = socket_path.strip_prefix("tcp:") {
-        let stream = std::net::TcpStream::connect(addr).map_err(|e| {
-            anyhow::anyhow!("failed to connect to WezTerm CC server at {}: {}", addr, e)
-        })?;
+        let token = listener_token(socket_path, std::env::var("TMUX").ok().as_deref())?;
+        let mut stream =
+            std::net::TcpStream::connect(addr).map_err(|e| connect_error(socket_path, e))?;
         // Disable Nagle to avoid delayed-ACK stalls on Windows localhost.
         let _ = stream.set_nodelay(true);
         if verbose {
             eprintln!("[tmux-shim] connected via TCP (nodelay)");
         }
-        run_cc_exchange(stream, command, verbose)
+        // The TCP listener only talks to clients that know its token.
+        std::io::Write::write_all(&mut stream, format!("auth-token {}\n", token).as_bytes())?;
+        run_exchange(stream, command, verbose)
     } else {
-        let stream = wezterm_uds::UnixStream::connect(socket_path).map_err(|e| {
-            anyhow::anyhow!(
-                "failed to connect to WezTerm CC server at {}: {}",
-                socket_path,
-                e
-            )
-        })?;
+        let stream = wezterm_uds::UnixStream::connect(socket_path)
+            .map_err(|e| connect_error(socket_path, e))?;
         if verbose {
             eprintln!("[tmux-shim] connected via UDS");
         }
-        run_cc_exchange(stream, command, verbose)
+        run_exchange(stream, command, verbose)
+    }
+}
+
+/// The socket to send a command to: that of the named server chosen with
+/// `-L` or `-S` in `args` (which include argv[0]), or else `default_socket`,
+/// that of the default server.
+///
+/// A named server that isn't running is started by the default server when
+/// `start` is set, as for `new-session`; otherwise it is an error.
+fn server_socket(args: &[String], default_socket: String, start: bool) -> anyhow::Result<String> {
+    #[cfg(unix)]
+    if let Some(server) = named_server_socket(
+        &args[1..],
+        &std::env::current_dir()?,
+        std::env::var("TMUX_TMPDIR").ok().as_deref(),
+        unsafe { libc::getuid() },
+    ) {
+        let server = server.to_string_lossy().into_owned();
+        if start {
+            // The default server starts named servers; this does nothing if
+            // it is already running.
+            let start = format!("start-server -S {}", quote_arg(&server));
+            let reply = execute_command(&default_socket, &start)?;
+            if reply.status != 0 {
+                anyhow::bail!("{}", reply.stderr.trim_end());
+            }
+        } else if !std::path::Path::new(&server).exists() {
+            anyhow::bail!("no server running on {}", server);
+        }
+        return Ok(server);
     }
+    #[cfg(not(unix))]
+    let _ = (args, start);
+    Ok(default_socket)
 }
 
 // ---------------------------------------------------------------------------
@@ -421,7 +849,23 @@ fn main() {
 }
 
 fn run(args: &[String]) -> anyhow::Result<()> {
-    let action = parse_args(args);
+    let socket_path = std::env::var("WEZTERM_TMUX_CC")
+        .ok()
+        .filter(|p| !p.is_empty());
+
+    // Buffer file paths only need rewriting when talking to the CC server;
+    // the real tmux can read our stdin and working directory itself.
+    let mut args = args.to_vec();
+    let stdin_copy = match socket_path {
+        Some(_) => {
+            let cwd = std::env::current_dir()?;
+            localize_source_paths(&mut args, &cwd);
+            localize_buffer_path(&mut args, &cwd, &mut std::io::stdin())?
+        }
+        None => None,
+    };
+
+    let action = parse_args(&args);
 
     match action {
         Action::Version => {
@@ -434,30 +878,60 @@ fn run(args: &[String]) -> anyhow::Result<()> {
             Ok(())
         }
 
+        Action::Attach {
+            target,
+            update_environment,
+        } => {
+            let socket_path = match socket_path {
+                Some(p) if update_environment => server_socket(&args, p, false)?,
+                _ => return Ok(()),
+            };
+            let vars = client_environment(&update_environment_names(&socket_path));
+            if vars.is_empty() {
+                return Ok(());
+            }
+            let reply =
+                execute_command(&socket_path, &attach_env_commands(target.as_deref(), &vars))?;
+            if reply.status != 0 {
+                anyhow::bail!("{}", reply.stderr.trim_end());
+            }
+            Ok(())
+        }
+
         Action::Command(command_text) => {
             // Find the CC server socket.
-            let socket_path = match std::env::var("WEZTERM_TMUX_CC") {
-                Ok(p) if !p.is_empty() => p,
-                _ => {
+            let socket_path = match socket_path {
+                Some(p) => p,
+                None => {
                     // No CC socket — try to fall through to real tmux.
-                    return exec_real_tmux(args);
+                    return exec_real_tmux(&args);
                 }
             };
+            let socket_path = server_socket(&args, socket_path, starts_server(&command_text))?;
 
-            let response = execute_command(&socket_path, &command_text)?;
+            let command_text = if starts_new_session(&command_text) {
+                let vars = client_environment(&update_environment_names(&socket_path));
+                add_new_session_env(&command_text, &vars)
+            } else {
+                command_text
+            };
 
-            if response.is_error {
-                // Print error body to stderr (strip trailing newline for cleaner output).
-                let msg = response.body.trim_end();
-                if !msg.is_empty() {
-                    eprintln!("{}", msg);
-                }
-                std::process::exit(1);
-            }
+            let reply = execute_command(&socket_path, &command_text);
+            drop(stdin_copy);
+            let reply = reply?;
 
-            // Print success body to stdout verbatim.
-            // The body already has appropriate newlines from the server.
-            print!("{}", response.body);
+            // Print the output verbatim; it already has appropriate newlines
+            // from the server. Then the error, if a command failed.
+            print!("{}", reply.stdout);
+            let msg = reply.stderr.trim_end();
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            if reply.status != 0 {
+                use std::io::Write;
+                let _ = std::io::stdout().flush();
+                std::process::exit(reply.status);
+            }
             Ok(())
         }
     }
@@ -496,11 +970,52 @@ mod tests {
     #[test]
     fn parse_session_attach() {
         match parse_args(&args(&["tmux", "attach-session"])) {
-            Action::SessionNoOp => {}
-            _ => panic!("expected SessionNoOp"),
+            Action::Attach {
+                target: None,
+                update_environment: true,
+            } => {}
+            _ => panic!("expected Attach"),
+        }
+        match parse_args(&args(&["tmux", "a", "-E", "-t", "work"])) {
+            Action::Attach {
+                target: Some(target),
+                update_environment: false,
+            } => assert_eq!(target, "work"),
+            _ => panic!("expected Attach"),
         }
     }
 
+    #[test]
+    fn update_environment_for_new_session() {
+        let vars = vec![
+            ("DISPLAY".to_string(), Some(":0".to_string())),
+            ("SSH_AUTH_SOCK".to_string(), None),
+            ("API".to_string(), Some("http://a b".to_string())),
+        ];
+        assert_eq!(
+            add_new_session_env("new-session -d -s work", &vars),
+            "new-session -e DISPLAY=:0 -e 'API=http://a b' -d -s work"
+        );
+        assert_eq!(add_new_session_env("new", &vars[..1]), "new -e DISPLAY=:0");
+        assert_eq!(add_new_session_env("new -E -d", &vars), "new -E -d");
+    }
+
+    #[test]
+    fn update_environment_on_attach() {
+        let vars = vec![
+            ("DISPLAY".to_string(), Some(":0".to_string())),
+            ("SSH_AUTH_SOCK".to_string(), None),
+        ];
+        assert_eq!(
+            attach_env_commands(Some("work"), &vars),
+            "set-environment -t work DISPLAY :0 ; set-environment -t work -r SSH_AUTH_SOCK"
+        );
+        assert_eq!(
+            attach_env_commands(None, &vars[1..]),
+            "set-environment -r SSH_AUTH_SOCK"
+        );
+    }
+
     #[test]
     fn parse_bare_tmux() {
         match parse_args(&args(&["tmux"])) {
@@ -556,6 +1071,120 @@ mod tests {
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn named_server_socket_paths() {
+        let cwd = std::path::Path::new("/home/user");
+        let socket = |argv: &[&str], tmpdir| named_server_socket(&args(argv), cwd, tmpdir, 1000);
+
+        assert_eq!(socket(&["list-panes"], None), None);
+        assert_eq!(
+            socket(&["-L", "agents", "new-session"], None).unwrap(),
+            std::path::Path::new("/tmp/tmux-1000/agents")
+        );
+        assert_eq!(
+            socket(&["-L", "agents", "ls"], Some("/run/user/1000")).unwrap(),
+            std::path::Path::new("/run/user/1000/tmux-1000/agents")
+        );
+        // -S wins over -L, and is relative to the working directory.
+        assert_eq!(
+            socket(&["-S", "sock", "-L", "agents", "ls"], None).unwrap(),
+            std::path::Path::new("/home/user/sock")
+        );
+        // Only global flags count.
+        assert_eq!(socket(&["capture-pane", "-S", "-50"], None), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_that_start_a_server() {
+        assert!(starts_server("new-session -d -s work"));
+        assert!(starts_server("start-server"));
+        assert!(!starts_server("kill-server"));
+        assert!(!starts_server("list-sessions ; new-session"));
+    }
+
+    #[test]
+    fn localize_relative_buffer_path() {
+        let mut argv = args(&["tmux", "save-buffer", "-b", "notes", "out.txt"]);
+        let cwd = std::path::Path::new("/home/user/project");
+        let temp = localize_buffer_path(&mut argv, cwd, &mut std::io::empty()).unwrap();
+        assert!(temp.is_none());
+        assert_eq!(argv[4], cwd.join("out.txt").to_string_lossy().into_owned());
+    }
+
+    #[test]
+    fn localize_leaves_absolute_and_stdout_paths() {
+        let cwd = std::path::Path::new("/home/user");
+        let mut argv = args(&["tmux", "-C", "load-buffer", "/tmp/in.txt"]);
+        localize_buffer_path(&mut argv, cwd, &mut std::io::empty()).unwrap();
+        assert_eq!(argv[3], "/tmp/in.txt");
+
+        let mut argv = args(&["tmux", "save-buffer", "-"]);
+        localize_buffer_path(&mut argv, cwd, &mut std::io::empty()).unwrap();
+        assert_eq!(argv[2], "-");
+
+        // No path given: `notes` is the value of -b, not a path.
+        let mut argv = args(&["tmux", "loadb", "-b", "notes"]);
+        localize_buffer_path(&mut argv, cwd, &mut std::io::empty()).unwrap();
+        assert_eq!(argv[3], "notes");
+    }
+
+    #[test]
+    fn localize_load_buffer_stdin() {
+        let mut argv = args(&["tmux", "load-buffer", "-w", "-"]);
+        let mut stdin = "from stdin\n".as_bytes();
+        let temp = localize_buffer_path(&mut argv, std::path::Path::new("/"), &mut stdin)
+            .unwrap()
+            .unwrap();
+        assert_eq!(argv[3], temp.to_string_lossy());
+        assert_eq!(std::fs::read_to_string(&temp).unwrap(), "from stdin\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&temp).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // The file goes with the path.
+        let path = temp.to_path_buf();
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn parse_semicolon_separates_commands() {
+        match parse_args(&args(&["tmux", "new-window", ";", "split-window", "-h"])) {
+            Action::Command(cmd) => assert_eq!(cmd, "new-window ; split-window -h"),
+            _ => panic!("expected Command"),
+        }
+        match parse_args(&args(&["tmux", "send-keys", "q;", "kill-pane"])) {
+            Action::Command(cmd) => assert_eq!(cmd, "send-keys q ; kill-pane"),
+            _ => panic!("expected Command"),
+        }
+        match parse_args(&args(&["tmux", "send-keys", "-l", "a\\;"])) {
+            Action::Command(cmd) => assert_eq!(cmd, "send-keys -l 'a;'"),
+            _ => panic!("expected Command"),
+        }
+    }
+
+    #[test]
+    fn localize_relative_source_paths() {
+        let cwd = std::path::Path::new("/home/user");
+        let mut argv = args(&[
+            "tmux",
+            "source-file",
+            "-q",
+            "a.conf",
+            "/etc/b.conf",
+            "~/c.conf",
+        ]);
+        localize_source_paths(&mut argv, cwd);
+        assert_eq!(argv[3], "/home/user/a.conf");
+        assert_eq!(argv[4], "/etc/b.conf");
+        assert_eq!(argv[5], "~/c.conf");
+    }
+
     #[test]
     fn extract_response_success() {
         let data = "%begin 1234567890 1 1\nhello world\n%end 1234567890 1 1\n";
@@ -628,4 +1257,122 @@ some unexpected body\n\
         assert!(!resp.is_error);
         assert_eq!(resp.body, "line1\nline2\nline3\n");
     }
+
+    #[test]
+    fn handshake_refused() {
+        let mut reader = std::io::BufReader::new("%exit access denied\n".as_bytes());
+        let err = skip_handshake(&mut reader).unwrap_err();
+        assert_eq!(err.to_string(), "server exited: access denied");
+
+        // A client of the real exchange sees the same error.
+        let mut server = FakeServer::new("%exit access denied\n");
+        match run_cc_exchange(&mut server, "list-sessions", false) {
+            Err(err) => assert_eq!(err.to_string(), "server exited: access denied"),
+            Ok(_) => panic!("expected the exchange to fail"),
+        }
+        let mut server = FakeServer::new("%exit access denied\n");
+        match run_request(&mut server, "list-sessions", false) {
+            Err(err) => assert_eq!(err.to_string(), "server exited: access denied"),
+            Ok(_) => panic!("expected the request to fail"),
+        }
+    }
+
+    /// A server that sends `reply` and records what it is sent.
+    struct FakeServer {
+        reply: std::io::Cursor<Vec<u8>>,
+        received: Vec<u8>,
+    }
+
+    impl FakeServer {
+        fn new(reply: &str) -> Self {
+            Self {
+                reply: std::io::Cursor::new(reply.as_bytes().to_vec()),
+                received: Vec::new(),
+            }
+        }
+    }
+
+    impl std::io::Read for FakeServer {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reply.read(buf)
+        }
+    }
+
+    impl std::io::Write for FakeServer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.received.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn request_reply_keeps_output_and_error_apart() {
+        let mut server = FakeServer::new("%result 1 3 20\nok\ncan't find pane: %9\n");
+        let reply = run_request(&mut server, "list-panes ; kill-pane -t %9", false).unwrap();
+        assert_eq!(server.received, b"request\nlist-panes ; kill-pane -t %9\n");
+        assert_eq!(reply.status, 1);
+        assert_eq!(reply.stdout, "ok\n");
+        assert_eq!(reply.stderr, "can't find pane: %9\n");
+
+        let mut server = FakeServer::new("%result 0 0 0\n");
+        let reply = run_request(&mut server, "select-pane -t %1", false).unwrap();
+        assert_eq!(
+            (reply.status, reply.stdout.as_str(), reply.stderr.as_str()),
+            (0, "", "")
+        );
+    }
+
+    #[test]
+    fn control_exchange_says_it_attaches() {
+        // The server goes away after its handshake.
+        let mut server = FakeServer::new("%begin 1 0 1\n%end 1 0 1\n");
+        assert!(run_cc_exchange(&mut server, "list-sessions", false).is_err());
+        assert_eq!(server.received, b"control\nlist-sessions\n");
+    }
+
+    #[test]
+    fn request_reply_errors() {
+        // The server went away before sending everything it announced.
+        let mut server = FakeServer::new("%result 0 10 0\nshort\n");
+        assert!(run_request(&mut server, "list-panes", false).is_err());
+        assert!(parse_result_header("%begin 1 1 1").is_err());
+        assert!(parse_result_header("%result 0 x 0").is_err());
+        assert_eq!(parse_result_header("%result 0 6 0").unwrap(), (0, 6, 0));
+    }
+
+    #[test]
+    fn connect_errors_read_like_tmux() {
+        let err = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(
+            connect_error("/tmp/wezterm-cc", err).to_string(),
+            "no server running on /tmp/wezterm-cc"
+        );
+        let err = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(
+            connect_error("/tmp/wezterm-cc", err).to_string(),
+            "error connecting to /tmp/wezterm-cc (denied)"
+        );
+    }
+
+    #[test]
+    fn listener_token_from_tmux_env() {
+        let path =
+            std::env::temp_dir().join(format!("wezterm-tmux-token-test-{}", std::process::id()));
+        std::fs::write(&path, "secret\n").unwrap();
+        let tmux = format!("tcp:127.0.0.1:1,42,0,{}", path.display());
+
+        assert_eq!(
+            listener_token("tcp:127.0.0.1:1", Some(&tmux)).unwrap(),
+            "secret"
+        );
+        // The token is only presented to the listener it belongs to.
+        assert!(listener_token("tcp:127.0.0.1:2", Some(&tmux)).is_err());
+        assert!(listener_token("tcp:127.0.0.1:1", Some("tcp:127.0.0.1:1,42,0")).is_err());
+        assert!(listener_token("tcp:127.0.0.1:1", None).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,350 @@
+//! Runs the `tmux` shim against a CC listener started in this process, and
+//! checks that its exit status, stdout and stderr follow tmux's contract.
+
+use mux::client::ClientId;
+use mux::domain::{Domain, LocalDomain};
+use mux::tmux_compat_server::{auth, server};
+use mux::Mux;
+use std::process::{Command, Output};
+use std::sync::{Arc, OnceLock};
+
+/// The workspace the shim's commands run in, so that the ID mappings the
+/// server saves never replace those of a real session.
+const WORKSPACE: &str = "tmux-shim-test";
+
+/// Start the listener, and a thread standing in for the GUI thread that
+/// runs the commands, once for all the tests. Panes run in a local domain.
+fn listener() -> &'static str {
+    static ADDRESS: OnceLock<String> = OnceLock::new();
+    ADDRESS.get_or_init(|| {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let executor = promise::spawn::SimpleExecutor::new();
+            ready_tx.send(()).unwrap();
+            while executor.tick().is_ok() {}
+        });
+        ready_rx.recv().unwrap();
+
+        // Keep the ID mappings the server saves out of the user's cache
+        // where the platform allows.
+        let cache =
+            std::env::temp_dir().join(format!("tmux-shim-cli-cache-{}", std::process::id()));
+        std::env::set_var("XDG_CACHE_HOME", &cache);
+
+        config::use_test_configuration();
+        let domain: Arc<dyn Domain> = Arc::new(LocalDomain::new("local").unwrap());
+        let mux = Arc::new(Mux::new(Some(domain)));
+        Mux::set_mux(&mux);
+        let client = Arc::new(ClientId::new());
+        mux.register_client(client.clone());
+        mux.replace_identity(Some(client));
+        mux.set_active_workspace(WORKSPACE);
+
+        let path = std::env::temp_dir().join(format!("tmux-shim-cli-{}", std::process::id()));
+        server::start_tmux_compat_listener(&path).unwrap()
+    })
+}
+
+/// Run the shim with `args` against the listener at `address`.
+fn tmux_at(address: &str, args: &[&str], attach: bool) -> Output {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_tmux"));
+    cmd.args(args)
+        .env("WEZTERM_TMUX_CC", address)
+        .env("TMUX", auth::tmux_env_value(address, std::process::id()))
+        .env_remove("WEZTERM_TMUX_CC_ATTACH")
+        .env_remove("WEZTERM_TMUX_CC_VERBOSE");
+    if attach {
+        cmd.env("WEZTERM_TMUX_CC_ATTACH", "1");
+    }
+    cmd.output().unwrap()
+}
+
+fn tmux(args: &[&str]) -> Output {
+    tmux_at(listener(), args, false)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn output_goes_to_stdout() {
+    let output = tmux(&[
+        "set", "-g", "@cli-out", "yes", ";", "show", "-gv", "@cli-out",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "yes\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn missing_session_fails() {
+    let output = tmux(&["has-session", "-t", "no-such-session"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "can't find session: no-such-session\n");
+}
+
+#[test]
+fn target_errors_read_like_tmux() {
+    let output = tmux(&["kill-pane", "-t", "%9999"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "can't find pane: %9999\n");
+
+    let output = tmux(&["select-window", "-t", "@abc"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "can't find window: @abc\n");
+}
+
+#[test]
+fn output_before_an_error_is_kept() {
+    let args: Vec<&str> = "set -g @cli-kept 1 ; show -gv @cli-kept ; kill-pane -t %9999"
+        .split(' ')
+        .collect();
+    let output = tmux(&args);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "1\n");
+    assert_eq!(stderr(&output), "can't find pane: %9999\n");
+}
+
+#[test]
+fn attached_client_fails_the_same_way() {
+    let output = tmux_at(listener(), &["kill-pane", "-t", "%9999"], true);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "can't find pane: %9999\n");
+}
+
+#[test]
+fn no_server_running() {
+    let path = std::env::temp_dir().join(format!("tmux-shim-cli-gone-{}", std::process::id()));
+    let address = path.to_string_lossy();
+    let output = tmux_at(&address, &["list-sessions"], false);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        format!("no server running on {}\n", address)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn attach_goes_to_the_named_server() {
+    let path = std::env::temp_dir().join(format!("tmux-shim-cli-named-{}", std::process::id()));
+    let socket = path.to_string_lossy().into_owned();
+    let named = |args: &[&str]| {
+        let mut all = vec!["-S", socket.as_str()];
+        all.extend_from_slice(args);
+        Command::new(env!("CARGO_BIN_EXE_tmux"))
+            .args(&all)
+            .env("WEZTERM_TMUX_CC", listener())
+            .env("CLI_ATTACH_VAR", "named")
+            .env_remove("WEZTERM_TMUX_CC_ATTACH")
+            .output()
+            .unwrap()
+    };
+
+    let output = named(&["attach"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!("no server running on {}\n", socket)
+    );
+
+    assert_eq!(named(&["start-server"]).status.code(), Some(0));
+    let output = named(&["set", "-g", "update-environment", "CLI_ATTACH_VAR"]);
+    assert_eq!(output.status.code(), Some(0));
+    let output = named(&["attach"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let output = named(&["show-environment", "CLI_ATTACH_VAR"]);
+    assert_eq!(stdout(&output), "CLI_ATTACH_VAR=named\n");
+
+    // The default server's environment is left alone.
+    let output = tmux(&["show-environment", "CLI_ATTACH_VAR"]);
+    assert_eq!(stdout(&output), "");
+    let _ = named(&["kill-server"]);
+}
+
+/// Run the shim with the space separated `args`, and check that it
+/// succeeds.
+#[cfg(unix)]
+fn ok(args: &str) -> String {
+    let args: Vec<&str> = args.split(' ').collect();
+    let output = tmux(&args);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    stdout(&output)
+}
+
+/// Start the detached session `name` with `count` panes side by side, and
+/// return the process IDs of their programs from left to right. The last
+/// pane is active. Each client keeps its own tmux IDs for the windows of
+/// other sessions, so the tests find panes by index and tell them apart by
+/// process.
+#[cfg(unix)]
+fn session_with_panes(name: &str, count: usize) -> Vec<String> {
+    let mut pids = vec![ok(&format!(
+        "new-session -d -s {} -P -F #{{pane_pid}} cat",
+        name
+    ))];
+    while pids.len() < count {
+        let last = pids.len() - 1;
+        pids.push(ok(&format!(
+            "split-window -h -t {}:0.{} -P -F #{{pane_pid}} cat",
+            name, last
+        )));
+    }
+    pids.iter().map(|pid| pid.trim().to_string()).collect()
+}
+
+/// The process IDs of the panes of the session `name` from left to right,
+/// with a `*` after the active one.
+#[cfg(unix)]
+fn pane_order(name: &str) -> String {
+    let panes = ok(&format!(
+        "list-panes -t {}: -F #{{pane_pid}}#{{?pane_active,*,}}",
+        name
+    ));
+    panes.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(unix)]
+#[test]
+fn swap_pane_moves_the_target() {
+    let pids = session_with_panes("cli-swap", 3);
+    let (a, b, c) = (&pids[0], &pids[1], &pids[2]);
+
+    ok("swap-pane -s cli-swap:0.0 -t cli-swap:0.2");
+    assert_eq!(pane_order("cli-swap"), format!("{}* {} {}", c, b, a));
+
+    ok("swap-pane -D -t cli-swap:0.1");
+    assert_eq!(pane_order("cli-swap"), format!("{} {} {}*", c, a, b));
+
+    // -d keeps the active position rather than the active pane.
+    ok("swap-pane -d -s cli-swap:0.0 -t cli-swap:0.2");
+    assert_eq!(pane_order("cli-swap"), format!("{} {} {}*", b, a, c));
+    ok("kill-session -t cli-swap:");
+}
+
+#[cfg(unix)]
+#[test]
+fn rotate_window_moves_the_active_pane() {
+    let pids = session_with_panes("cli-rotate", 3);
+    let (a, b, c) = (&pids[0], &pids[1], &pids[2]);
+    ok("select-pane -t cli-rotate:0.0");
+
+    ok("rotate-window -t cli-rotate:");
+    assert_eq!(pane_order("cli-rotate"), format!("{} {} {}*", b, c, a));
+
+    ok("rotate-window -D -t cli-rotate:");
+    assert_eq!(pane_order("cli-rotate"), format!("{}* {} {}", a, b, c));
+    ok("kill-session -t cli-rotate:");
+}
+
+#[cfg(unix)]
+#[test]
+fn last_pane_goes_back() {
+    let pids = session_with_panes("cli-last-pane", 3);
+    let (a, b, c) = (&pids[0], &pids[1], &pids[2]);
+    ok("select-pane -t cli-last-pane:0.0");
+
+    ok("last-pane -t cli-last-pane:");
+    assert_eq!(pane_order("cli-last-pane"), format!("{} {} {}*", a, b, c));
+    ok("last-pane -t cli-last-pane:");
+    assert_eq!(pane_order("cli-last-pane"), format!("{}* {} {}", a, b, c));
+    ok("kill-session -t cli-last-pane:");
+}
+
+#[cfg(unix)]
+#[test]
+fn window_stepping_wraps_around() {
+    // The process IDs of the windows' programs tell the windows apart.
+    let pids = [
+        ok("new-session -d -s cli-step -P -F #{pane_pid} cat"),
+        ok("new-window -t cli-step: -P -F #{pane_pid} cat"),
+        ok("new-window -t cli-step: -P -F #{pane_pid} cat"),
+    ];
+    let current = || ok("display -p -t cli-step: #{pane_pid}");
+    let start = pids.iter().position(|pid| *pid == current()).unwrap();
+    let at = |step: usize| pids[(start + step) % pids.len()].clone();
+
+    let step = |command: &str| {
+        ok(&format!("{} -t cli-step:", command));
+        current()
+    };
+    assert_eq!(step("next-window"), at(1));
+    assert_eq!(step("next-window"), at(2));
+    assert_eq!(step("previous-window"), at(1));
+    assert_eq!(step("previous-window"), at(0));
+    assert_eq!(step("previous-window"), at(2));
+    assert_eq!(step("last-window"), at(0));
+    ok("kill-session -t cli-step:");
+}
+
+#[cfg(unix)]
+#[test]
+fn send_keys_repeats_the_keys() {
+    session_with_panes("cli-send-keys", 1);
+    ok("send-keys -N 3 -t cli-send-keys: -l ab");
+
+    // cat's terminal echoes the keys as they arrive.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut screen = String::new();
+    while !screen.contains("ababab") && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        screen = ok("capture-pane -p -t cli-send-keys:");
+    }
+    assert_eq!(screen.trim(), "ababab");
+    ok("kill-session -t cli-send-keys:");
+}
+
+#[cfg(unix)]
+#[test]
+fn copy_mode_enters_and_leaves() {
+    session_with_panes("cli-copy-mode", 1);
+    let in_mode = || ok("display -p -t cli-copy-mode: #{pane_in_mode}");
+    assert_eq!(in_mode(), "0\n");
+
+    ok("copy-mode -t cli-copy-mode:");
+    assert_eq!(in_mode(), "1\n");
+    ok("send-keys -X -t cli-copy-mode: cancel");
+    assert_eq!(in_mode(), "0\n");
+
+    ok("copy-mode -t cli-copy-mode:");
+    ok("copy-mode -q -t cli-copy-mode:");
+    assert_eq!(in_mode(), "0\n");
+    ok("kill-session -t cli-copy-mode:");
+}
+
+#[cfg(unix)]
+#[test]
+fn resize_pane_drags_the_border_with_the_mouse() {
+    session_with_panes("cli-drag", 2);
+    let widths = || ok("list-panes -t cli-drag: -F #{pane_width}");
+    let mouse = |report: String| ok(&format!("send-keys -M -t cli-drag:0.0 {}", report));
+    let width: usize = widths().lines().next().unwrap().parse().unwrap();
+    // The border is the column after the left pane; reports count from 1.
+    let border = width + 1;
+
+    mouse(format!("<0;{};1M", border));
+    ok("resize-pane -M -t cli-drag:0.0");
+    mouse(format!("<32;{};2M", border - 5));
+    let moved = widths();
+    assert_eq!(moved.lines().next(), Some((width - 5).to_string().as_str()));
+
+    // Once the button is released, the mouse goes to the pane again.
+    mouse(format!("<0;{};2m", border - 5));
+    mouse("<35;2;2M".to_string());
+    assert_eq!(widths(), moved);
+
+    // Without a button held over a border, there is nothing to drag.
+    mouse("<0;2;2M".to_string());
+    ok("resize-pane -M -t cli-drag:0.0");
+    mouse("<32;3;2M".to_string());
+    assert_eq!(widths(), moved);
+    ok("kill-session -t cli-drag:");
+}
@@ -1390,4 +1390,9 @@ impl Client {
     );
     rpc!(adjust_pane_size, AdjustPaneSize, UnitResponse);
     rpc!(set_tab_layout, SetTabLayout, UnitResponse);
+    rpc!(
+        get_tmux_compat_socket,
+        GetTmuxCompatSocket = (),
+        GetTmuxCompatSocketResponse
+    );
 }
@@ -1988,6 +1988,14 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &["Window", "Set Tab Layout"],
             icon: Some("md_grid_view"),
         },
+        TmuxCommand(command) => CommandDef {
+            brief: format!("Run tmux command `{command}`").into(),
+            doc: format!("Run `{command}` in the tmux compat server").into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &[],
+            icon: None,
+        },
         SplitPane(split) => {
             let direction = split.direction;
             CommandDef {
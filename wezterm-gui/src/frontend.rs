@@ -94,6 +94,9 @@ impl GuiFrontEnd {
                 MuxNotification::WindowInvalidated(_) => {}
                 MuxNotification::PaneOutput(_) => {}
                 MuxNotification::PaneAdded(_) => {}
+                MuxNotification::PerformAssignment { .. } => {
+                    // Handled via TermWindowNotif; NOP it here.
+                }
                 MuxNotification::Alert {
                     pane_id,
                     alert:
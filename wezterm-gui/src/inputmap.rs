@@ -4,6 +4,7 @@ use config::keyassignment::{
     MouseEventTrigger, SelectionMode,
 };
 use config::{ConfigHandle, MouseEventAltScreen, MouseEventTriggerMods};
+use mux::tmux_compat_server::key_bindings::{self, KeyAction};
 use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use wezterm_dynamic::{ToDynamic, Value};
@@ -14,6 +15,8 @@ pub struct InputMap {
     pub keys: KeyTables,
     pub mouse: HashMap<(MouseEventTrigger, MouseEventTriggerMods), KeyAssignment>,
     leader: Option<(KeyCode, Modifiers, Duration)>,
+    /// Whether tmux compat is on, and with it the tmux key tables.
+    tmux_compat: bool,
 }
 
 impl InputMap {
@@ -386,6 +389,7 @@ impl InputMap {
             keys,
             leader,
             mouse,
+            tmux_compat: config.enable_tmux_compat,
         }
     }
 
@@ -436,6 +440,10 @@ impl InputMap {
 
     pub fn has_table(&self, name: &str) -> bool {
         self.keys.by_name.contains_key(name)
+            || (self.tmux_compat
+                && mux::Mux::try_get().is_some_and(|mux| {
+                    key_bindings::key_bindings_for(&mux.active_workspace()).has_table(name)
+                }))
     }
 
     pub fn lookup_key(
@@ -445,8 +453,18 @@ impl InputMap {
         table_name: Option<&str>,
     ) -> Option<KeyTableEntry> {
         let table = match table_name {
-            Some(name) => self.keys.by_name.get(name)?,
-            None => &self.keys.default,
+            Some(name) => match self.keys.by_name.get(name) {
+                Some(table) => table,
+                None => return tmux_key_table_entry(name, key, mods),
+            },
+            None => {
+                // Keys bound with `bind-key -n` take precedence over the
+                // default table, as they were bound for this session.
+                if let Some(entry) = tmux_key_table_entry("root", key, mods) {
+                    return Some(entry);
+                }
+                &self.keys.default
+            }
         };
 
         table
@@ -566,6 +584,45 @@ impl InputMap {
     }
 }
 
+/// Look a key up in a key table of the tmux compat server, made with
+/// `bind-key`. A binding becomes an action that runs its command, and the
+/// tmux prefix key one that activates the `prefix` table for the next key.
+fn tmux_key_table_entry(table: &str, key: &KeyCode, mods: Modifiers) -> Option<KeyTableEntry> {
+    let (key, mods) = key.normalize_shift(mods);
+    let name = key_bindings::key_event_name(&key, mods)?;
+    let workspace = mux::Mux::try_get()?.active_workspace();
+    let action = match key_bindings::lookup_key(table, &name, &workspace)? {
+        KeyAction::Prefix => KeyAssignment::ActivateKeyTable {
+            name: "prefix".to_string(),
+            timeout_milliseconds: None,
+            replace_current: false,
+            one_shot: true,
+            until_unknown: false,
+            prevent_fallback: true,
+        },
+        KeyAction::Run {
+            command,
+            repeat_time: None,
+        } => KeyAssignment::TmuxCommand(command),
+        KeyAction::Run {
+            command,
+            repeat_time: Some(timeout),
+        } => KeyAssignment::Multiple(vec![
+            KeyAssignment::TmuxCommand(command),
+            // Keep the table for `repeat-time`, letting other keys through.
+            KeyAssignment::ActivateKeyTable {
+                name: table.to_string(),
+                timeout_milliseconds: Some(timeout),
+                replace_current: false,
+                one_shot: true,
+                until_unknown: false,
+                prevent_fallback: false,
+            },
+        ]),
+    };
+    Some(KeyTableEntry { action })
+}
+
 fn section_header(title: &str) {
     let dash = "-".repeat(title.len());
     println!("{title}");
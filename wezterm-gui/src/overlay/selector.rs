@@ -37,6 +37,14 @@ pub fn matcher_pattern(s: &str) -> Pattern {
     )
 }
 
+/// What to do with the chosen entry
+enum SelectorAction {
+    /// Emit the event registered by `wezterm.action_callback`
+    Event(String),
+    /// Run a tmux command with `%%` replaced by the id of the entry
+    TmuxCommand(String),
+}
+
 struct SelectorState {
     active_idx: usize,
     max_items: usize,
@@ -48,7 +56,7 @@ struct SelectorState {
     filtering: bool,
     always_fuzzy: bool,
     args: InputSelector,
-    event_name: String,
+    action: SelectorAction,
     selection: String,
     labels: Vec<String>,
 }
@@ -205,7 +213,16 @@ impl SelectorState {
     }
 
     fn trigger_event(&self, entry: Option<InputSelectorEntry>) {
-        let name = self.event_name.clone();
+        let name = match &self.action {
+            SelectorAction::Event(name) => name.clone(),
+            SelectorAction::TmuxCommand(template) => {
+                if let Some(entry) = entry {
+                    let choice = entry.id.unwrap_or(entry.label);
+                    mux::tmux_compat_server::choose::run_choice(self.pane.0, template, &choice);
+                }
+                return;
+            }
+        };
         let window = self.window.clone();
         let pane = self.pane.clone();
 
@@ -418,8 +435,9 @@ pub fn selector(
     window: GuiWin,
     pane: MuxPane,
 ) -> anyhow::Result<()> {
-    let event_name = match *args.action {
-        KeyAssignment::EmitEvent(ref id) => id.to_string(),
+    let action = match *args.action {
+        KeyAssignment::EmitEvent(ref id) => SelectorAction::Event(id.to_string()),
+        KeyAssignment::TmuxCommand(ref template) => SelectorAction::TmuxCommand(template.clone()),
         _ => {
             anyhow::bail!("InputSelector requires action to be defined by wezterm.action_callback")
         }
@@ -435,7 +453,7 @@ pub fn selector(
         filtering: args.fuzzy,
         always_fuzzy: args.fuzzy,
         args,
-        event_name,
+        action,
         selection: String::new(),
         labels: vec![],
     };
@@ -5,6 +5,7 @@ use ::window::{
 use anyhow::Context;
 use config::keyassignment::{KeyAssignment, KeyTableEntry};
 use mux::pane::{Pane, PerformAssignmentResult};
+use mux::tmux_compat_server::key_bindings::key_bindings_for;
 use smol::Timer;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -215,6 +216,20 @@ impl super::TermWindow {
         mods: Modifiers,
         only_key_bindings: OnlyKeyBindings,
     ) -> Option<(KeyTableEntry, Option<String>)> {
+        // `switch-client -T` in the tmux compat server picks the table for
+        // the next key press.
+        let workspace = mux::Mux::get().active_workspace();
+        let switched_table = key_bindings_for(&workspace).take_switched_table();
+        if let Some(name) = switched_table.filter(|name| name != "root") {
+            self.key_table_state.activate(KeyTableArgs {
+                name: &name,
+                timeout_milliseconds: None,
+                replace_current: false,
+                one_shot: true,
+                until_unknown: false,
+                prevent_fallback: true,
+            });
+        }
         if let Some(overlay) = self.pane_state(pane.pane_id()).overlay.as_mut() {
             if let Some((entry, table_name)) = overlay.key_table_state.lookup_key(
                 &self.input_map,
@@ -596,6 +611,23 @@ impl super::TermWindow {
         }
     }
 
+    /// Once the program in a popup has exited, any key press closes the
+    /// popup. Returns true if `pane` was such a popup and is now closed.
+    fn close_exited_popup(&self, pane: &Arc<dyn Pane>) -> bool {
+        if pane.exit_code().is_none() {
+            return false;
+        }
+        let mux = mux::Mux::get();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return false,
+        };
+        match tab.get_popup() {
+            Some(popup) if popup.pane.pane_id() == pane.pane_id() => mux.close_popup(tab.tab_id()),
+            _ => false,
+        }
+    }
+
     pub fn key_event_impl(&mut self, window_key: KeyEvent, context: &dyn WindowOps) {
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
@@ -671,6 +703,10 @@ impl super::TermWindow {
                     return;
                 }
 
+                if window_key.key_is_down && !key.is_modifier() && self.close_exited_popup(&pane) {
+                    return;
+                }
+
                 let res = if let Some(encoded) = self.encode_win32_input(&pane, &window_key) {
                     if self.config.debug_key_events {
                         log::info!("win32: Encoded input as {:?}", encoded);
@@ -46,7 +46,7 @@ use mux::pane::{
 use mux::renderable::RenderableDimensions;
 use mux::tab::{
     PositionedPane, PositionedSplit, SplitDirection, SplitRequest, SplitSize as MuxSplitSize, Tab,
-    TabId,
+    TabId, TabPopup,
 };
 use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
@@ -1311,6 +1311,28 @@ impl TermWindow {
                 MuxNotification::TabTitleChanged { .. } => {
                     self.update_title_post_status();
                 }
+                MuxNotification::PerformAssignment {
+                    pane_id,
+                    assignment,
+                } => {
+                    if !self.window_contains_pane(pane_id) {
+                        return Ok(());
+                    }
+                    let mux = Mux::get();
+                    mux.focus_pane_and_containing_tab(pane_id)?;
+                    // As for TermWindowNotif::PerformAssignment, prefer the
+                    // pane's overlay, so that a tmux client can drive the
+                    // copy overlay it opened.
+                    let pane = match self.get_active_pane_or_overlay() {
+                        Some(active) if active.pane_id() == pane_id => active,
+                        _ => mux
+                            .get_pane(pane_id)
+                            .ok_or_else(|| anyhow!("pane id {} is not valid", pane_id))?,
+                    };
+                    self.perform_key_assignment(&pane, &assignment)
+                        .context("perform_key_assignment")?;
+                    window.invalidate();
+                }
                 MuxNotification::PaneAdded(_)
                 | MuxNotification::WorkspaceRenamed { .. }
                 | MuxNotification::PaneRemoved(_)
@@ -1431,6 +1453,12 @@ impl TermWindow {
             return tab_overlay.pane_id() == pane_id;
         }
 
+        if let Some(popup) = tab.get_popup() {
+            if popup.pane.pane_id() == pane_id {
+                return true;
+            }
+        }
+
         tab.contains_pane(pane_id)
     }
 
@@ -1469,7 +1497,8 @@ impl TermWindow {
             }
             | MuxNotification::PaneFocused(pane_id)
             | MuxNotification::PaneRemoved(pane_id)
-            | MuxNotification::PaneOutput(pane_id) => {
+            | MuxNotification::PaneOutput(pane_id)
+            | MuxNotification::PerformAssignment { pane_id, .. } => {
                 // Ideally we'd check to see if pane_id is part of this window,
                 // but overlays may not be 100% associated with the window
                 // in the mux and we don't want to lose the invalidation
@@ -3121,6 +3150,9 @@ impl TermWindow {
                     log::error!("apply_layout({layout_name}): {err}");
                 }
             }
+            TmuxCommand(command) => {
+                mux::tmux_compat_server::key_bindings::run_binding(pane.pane_id(), command.clone());
+            }
             SplitPane(split) => {
                 log::trace!("SplitPane {:?}", split);
                 self.spawn_command(
@@ -3421,6 +3453,8 @@ impl TermWindow {
             .map(|overlay| overlay.pane.clone())
         {
             Some(tab_overlay)
+        } else if let Some(popup) = tab.get_popup() {
+            Some(popup.pane)
         } else {
             let pane = tab.get_active_pane()?;
             let pane_id = pane.pane_id();
@@ -3541,6 +3575,31 @@ impl TermWindow {
         }
     }
 
+    /// Returns the popup shown over the active tab, if any, along with
+    /// the position of the area inside its border.
+    fn get_popup_to_render(&self) -> Option<(TabPopup, PositionedPane)> {
+        let mux = Mux::get();
+        let tab = mux.get_active_tab_for_window(self.mux_window_id)?;
+        if self.tab_state(tab.tab_id()).overlay.is_some() {
+            return None;
+        }
+        let popup = tab.get_popup()?;
+        let size = TabPopup::content_size(popup.width, popup.height, &tab.get_size());
+        let pos = PositionedPane {
+            index: 0,
+            is_active: true,
+            is_zoomed: false,
+            left: popup.left + 1,
+            top: popup.top + 1,
+            width: size.cols,
+            height: size.rows,
+            pixel_width: size.cols * self.render_metrics.cell_size.width as usize,
+            pixel_height: size.rows * self.render_metrics.cell_size.height as usize,
+            pane: Arc::clone(&popup.pane),
+        };
+        Some((popup, pos))
+    }
+
     fn get_panes_to_render(&self) -> Vec<PositionedPane> {
         let mux = Mux::get();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -667,7 +667,22 @@ impl super::TermWindow {
             Some(MouseCapture::TerminalPane(_))
         );
 
-        for pos in self.get_panes_to_render() {
+        // A popup has the mouse to itself while it is shown
+        let panes = match self.get_popup_to_render() {
+            Some((_, pos)) => {
+                let outside = row < pos.top as i64
+                    || row >= (pos.top + pos.height) as i64
+                    || column < pos.left
+                    || column >= pos.left + pos.width;
+                if outside && !is_already_captured {
+                    return;
+                }
+                vec![pos]
+            }
+            None => self.get_panes_to_render(),
+        };
+
+        for pos in panes {
             if !is_already_captured
                 && row >= pos.top as i64
                 && row <= (pos.top + pos.height) as i64
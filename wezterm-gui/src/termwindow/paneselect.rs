@@ -21,6 +21,7 @@ pub struct PaneSelector {
     mode: PaneSelectMode,
     was_zoomed: bool,
     show_pane_ids: bool,
+    tmux_command: Option<String>,
 }
 
 impl PaneSelector {
@@ -47,6 +48,7 @@ impl PaneSelector {
             mode: args.mode,
             was_zoomed,
             show_pane_ids: args.show_pane_ids,
+            tmux_command: args.tmux_command.clone(),
         }
     }
 
@@ -174,6 +176,19 @@ impl PaneSelector {
             let panes = tab.iter_panes();
 
             match self.mode {
+                _ if self.tmux_command.is_some() => {
+                    let active = tab.get_active_pane();
+                    let chosen = panes.iter().find(|p| p.index == pane_index);
+                    if let (Some(template), Some(active), Some(chosen)) =
+                        (&self.tmux_command, active, chosen)
+                    {
+                        mux::tmux_compat_server::choose::run_pane_choice(
+                            active.pane_id(),
+                            template,
+                            chosen.pane.pane_id(),
+                        );
+                    }
+                }
                 PaneSelectMode::Activate => {
                     if panes.iter().position(|p| p.index == pane_index).is_some() {
                         tab.set_active_idx(pane_index);
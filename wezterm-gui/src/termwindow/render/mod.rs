@@ -39,6 +39,7 @@ pub mod corners;
 pub mod draw;
 pub mod fancy_tab_bar;
 pub mod paint;
+pub mod popup;
 pub mod pane;
 pub mod screen_line;
 pub mod split;
@@ -246,7 +246,12 @@ impl crate::TermWindow {
             .context("filled_rectangle for window background")?;
         }
 
-        for pos in panes {
+        let popup = self.get_popup_to_render();
+        for mut pos in panes {
+            // A popup takes the focus from the panes beneath it
+            if popup.is_some() {
+                pos.is_active = false;
+            }
             if pos.is_active {
                 self.update_text_cursor(&pos);
                 if focused {
@@ -265,6 +270,10 @@ impl crate::TermWindow {
             }
         }
 
+        if let Some((popup, pos)) = popup {
+            self.paint_popup(&popup, &pos).context("paint_popup")?;
+        }
+
         if self.show_tab_bar {
             self.paint_tab_bar(&mut layers).context("paint_tab_bar")?;
         }
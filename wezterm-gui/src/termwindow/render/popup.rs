@@ -0,0 +1,117 @@
+use crate::termwindow::box_model::*;
+use crate::utilsprites::RenderMetrics;
+use anyhow::Context;
+use config::DimensionContext;
+use mux::tab::{PositionedPane, TabPopup};
+
+impl crate::TermWindow {
+    /// Paint a popup over the top of the panes of the active tab.
+    /// `pos` is the area inside the popup border.
+    pub fn paint_popup(&mut self, popup: &TabPopup, pos: &PositionedPane) -> anyhow::Result<()> {
+        let palette = pos.pane.palette();
+        let cell_width = self.render_metrics.cell_size.width as f32;
+        let cell_height = self.render_metrics.cell_size.height as f32;
+
+        let border = self.get_os_border();
+        let first_row_offset = if self.show_tab_bar && !self.config.tab_bar_at_bottom {
+            self.tab_bar_pixel_height()?
+        } else {
+            0.
+        } + border.top.get() as f32;
+        let (padding_left, padding_top) = self.padding_left_top();
+
+        let left = padding_left + border.left.get() as f32 + popup.left as f32 * cell_width;
+        let top = first_row_offset + padding_top + popup.top as f32 * cell_height;
+        let width = popup.width as f32 * cell_width;
+        let height = popup.height as f32 * cell_height;
+
+        // The popup has a layer of its own so that it hides the panes
+        // beneath it, rather than interleaving with them.
+        let gl_state = self.render_state.as_ref().unwrap();
+        let layer = gl_state
+            .layer_for_zindex(2)
+            .context("layer_for_zindex(2)")?;
+        let mut layers = layer.quad_allocator();
+
+        self.filled_rectangle(
+            &mut layers,
+            0,
+            euclid::rect(left, top, width, height),
+            palette.background.to_linear(),
+        )
+        .context("filled_rectangle for popup background")?;
+
+        // Draw the border through the middle of the outermost cells,
+        // in the same way as the splits between panes.
+        let foreground = palette.split.to_linear();
+        let thickness = self.render_metrics.underline_height as f32;
+        let x1 = left + cell_width / 2.;
+        let y1 = top + cell_height / 2.;
+        let x2 = left + width - cell_width / 2.;
+        let y2 = top + height - cell_height / 2.;
+        for rect in [
+            euclid::rect(x1, y1, x2 - x1 + thickness, thickness),
+            euclid::rect(x1, y2, x2 - x1 + thickness, thickness),
+            euclid::rect(x1, y1, thickness, y2 - y1),
+            euclid::rect(x2, y1, thickness, y2 - y1),
+        ] {
+            self.filled_rectangle(&mut layers, 2, rect, foreground)?;
+        }
+
+        self.update_text_cursor(pos);
+        if self.focused.is_some() {
+            pos.pane.advise_focus();
+            mux::Mux::get().record_focus_for_current_identity(pos.pane.pane_id());
+        }
+        self.paint_pane(pos, &mut layers).context("paint_pane")?;
+        drop(layers);
+
+        if popup.title.is_empty() {
+            return Ok(());
+        }
+
+        let font = self.fonts.title_font()?;
+        let metrics = RenderMetrics::with_font_metrics(&font.metrics());
+        let element = Element::new(&font, ElementContent::Text(format!(" {} ", popup.title)))
+            .colors(ElementColors {
+                border: BorderColor::default(),
+                bg: palette.background.to_linear().into(),
+                text: palette.foreground.to_linear().into(),
+            });
+
+        let dimensions = self.dimensions;
+        let mut computed = self.compute_element(
+            &LayoutContext {
+                height: DimensionContext {
+                    dpi: dimensions.dpi as f32,
+                    pixel_max: dimensions.pixel_height as f32,
+                    pixel_cell: metrics.cell_size.height as f32,
+                },
+                width: DimensionContext {
+                    dpi: dimensions.dpi as f32,
+                    pixel_max: dimensions.pixel_width as f32,
+                    pixel_cell: metrics.cell_size.width as f32,
+                },
+                bounds: euclid::rect(
+                    left + 2. * cell_width,
+                    top,
+                    (width - 4. * cell_width).max(0.),
+                    cell_height,
+                ),
+                metrics: &metrics,
+                gl_state: self.render_state.as_ref().unwrap(),
+                zindex: 3,
+            },
+            &element,
+        )?;
+
+        // Centre the title on the top border
+        let element_height = computed.bounds.height();
+        computed.translate(euclid::vec2(0., (cell_height - element_height) / 2.));
+
+        let gl_state = self.render_state.as_ref().unwrap();
+        self.render_element(&computed, gl_state, None)?;
+
+        Ok(())
+    }
+}
@@ -92,3 +92,18 @@ fn safely_create_sock_path(unix_dom: &UnixDomain) -> anyhow::Result<UnixListener
 
     Ok(listener)
 }
+
+/// Start the tmux compat (CC) listener for a unix domain.
+/// Its socket sits next to the domain's own socket, in the directory
+/// that `safely_create_sock_path` has already checked, so it must be
+/// called after `LocalListener::with_domain`.
+/// Returns the address to export as `WEZTERM_TMUX_CC`.
+pub fn start_tmux_compat_listener(unix_dom: &UnixDomain) -> anyhow::Result<String> {
+    let sock_path = unix_dom.socket_path();
+    let file_name = sock_path
+        .file_name()
+        .ok_or_else(|| anyhow!("sock_path {} has no file name", sock_path.display()))?;
+    let cc_path = sock_path.with_file_name(format!("{}-tmux-cc", file_name.to_string_lossy()));
+    mux::tmux_compat_server::server::start_tmux_compat_listener(&cc_path)
+        .with_context(|| format!("tmux compat listener for domain {}", unix_dom.name))
+}
@@ -385,6 +385,12 @@ impl SessionHandler {
                 })
                 .detach();
             }
+            Pdu::GetTmuxCompatSocket(GetTmuxCompatSocket) => {
+                let addresses = mux::tmux_compat_server::server::listener_addresses();
+                send_response(Ok(Pdu::GetTmuxCompatSocketResponse(
+                    GetTmuxCompatSocketResponse { addresses },
+                )))
+            }
             Pdu::ListPanes(ListPanes {}) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -1035,6 +1041,7 @@ impl SessionHandler {
             | Pdu::WindowWorkspaceChanged { .. }
             | Pdu::GetTlsCredsResponse { .. }
             | Pdu::GetClientListResponse { .. }
+            | Pdu::GetTmuxCompatSocketResponse { .. }
             | Pdu::PaneRemoved { .. }
             | Pdu::PaneFocused { .. }
             | Pdu::TabResized { .. }
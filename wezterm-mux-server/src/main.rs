@@ -194,7 +194,9 @@ fn run() -> anyhow::Result<()> {
         "OLDPWD",
         "PWD",
         "SHLVL",
+        "TMUX",
         "WEZTERM_PANE",
+        "WEZTERM_TMUX_CC",
         "WEZTERM_UNIX_SOCKET",
         "_",
     ] {
@@ -309,12 +311,31 @@ mod ossl;
 
 pub fn spawn_listener() -> anyhow::Result<()> {
     let config = configuration();
+    let mut tmux_cc_addr = None;
     for unix_dom in &config.unix_domains {
         std::env::set_var("WEZTERM_UNIX_SOCKET", unix_dom.socket_path());
         let mut listener = wezterm_mux_server_impl::local::LocalListener::with_domain(unix_dom)?;
         thread::spawn(move || {
             listener.run();
         });
+
+        if config.enable_tmux_compat {
+            match wezterm_mux_server_impl::local::start_tmux_compat_listener(unix_dom) {
+                Ok(addr) => {
+                    log::info!("tmux CC listener for {}: {}", unix_dom.name, addr);
+                    tmux_cc_addr.get_or_insert(addr);
+                }
+                Err(err) => log::warn!("Failed to start tmux CC compat server: {:#}", err),
+            }
+        }
+    }
+
+    // Every listener serves the same mux, so panes spawned by this server
+    // pick up the address of the first as WEZTERM_TMUX_CC, and with it TMUX
+    // and the shim on PATH; see LocalDomain::build_command.
+    if let Some(addr) = tmux_cc_addr {
+        std::env::set_var("WEZTERM_TMUX_CC", &addr);
+        log::info!("WEZTERM_TMUX_CC={}", addr);
     }
 
     for tls_server in &config.tls_servers {
@@ -22,6 +22,7 @@ mod set_window_title;
 mod spawn_command;
 mod split_pane;
 mod tls_creds;
+mod tmux_socket;
 mod zoom_pane;
 
 #[derive(Debug, Parser, Clone, Copy)]
@@ -168,6 +169,13 @@ Outputs the pane-id for the newly created pane on success"
     /// Zoom, unzoom, or toggle zoom state
     #[command(name = "zoom-pane", rename_all = "kebab")]
     ZoomPane(zoom_pane::ZoomPane),
+
+    /// Print the address of the tmux compat server.
+    ///
+    /// Prints one line per listener. Point WEZTERM_TMUX_CC at one of
+    /// them to use the tmux shim from outside a WezTerm pane.
+    #[command(name = "tmux-socket", rename_all = "kebab")]
+    TmuxSocket(tmux_socket::TmuxSocket),
 }
 
 async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()> {
@@ -205,6 +213,7 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
         CliSubCommand::SetWindowTitle(cmd) => cmd.run(client).await,
         CliSubCommand::RenameWorkspace(cmd) => cmd.run(client).await,
         CliSubCommand::ZoomPane(cmd) => cmd.run(client).await,
+        CliSubCommand::TmuxSocket(cmd) => cmd.run(client).await,
     }
 }
 
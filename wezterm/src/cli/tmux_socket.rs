@@ -0,0 +1,18 @@
+use clap::Parser;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct TmuxSocket {}
+
+impl TmuxSocket {
+    pub async fn run(&self, client: Client) -> anyhow::Result<()> {
+        let response = client.get_tmux_compat_socket().await?;
+        if response.addresses.is_empty() {
+            anyhow::bail!("the tmux compat server is not running; set enable_tmux_compat = true");
+        }
+        for address in response.addresses {
+            println!("{address}");
+        }
+        Ok(())
+    }
+}